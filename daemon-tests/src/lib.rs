@@ -709,6 +709,8 @@ impl Maker {
             identities.clone(),
             endpoint_listen.clone(),
             config.blocked_peers.clone(),
+            None,
+            false,
         )
         .unwrap();
 
@@ -883,9 +885,12 @@ impl Taker {
         let mut monitor_mock = None;
         tracing::info!("Connecting to maker {maker_multiaddr}");
 
+        let (_, wallet_info) = watch::channel(Some(mocks::wallet::wallet_info()));
+
         let taker = daemon::TakerActorSystem::new(
             db.clone(),
             wallet_addr,
+            wallet_info,
             config.oracle_pk,
             identities.clone(),
             |executor| {
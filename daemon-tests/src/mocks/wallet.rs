@@ -11,6 +11,8 @@ use daemon::maia_core::PartyParams;
 use daemon::maia_core::TxBuilderExt;
 use daemon::wallet;
 use mockall::*;
+use model::Timestamp;
+use model::WalletInfo;
 use rand::thread_rng;
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -73,6 +75,23 @@ pub trait Wallet {
     }
 }
 
+/// Wallet info as published by a wallet which has just been synced.
+///
+/// The balance matches the funds available to [`build_party_params`], which is plenty for the
+/// orders placed in the tests.
+pub fn wallet_info() -> WalletInfo {
+    let mut rng = thread_rng();
+    let wallet = new_test_wallet(&mut rng, Amount::from_btc(1.4).unwrap(), 5).unwrap();
+
+    WalletInfo {
+        network: wallet.network(),
+        balance: Amount::from_sat(wallet.get_balance().unwrap()),
+        address: wallet.get_address(AddressIndex::New).unwrap().address,
+        last_updated_at: Timestamp::now(),
+        transactions: Vec::new(),
+    }
+}
+
 pub fn build_party_params(msg: wallet::BuildPartyParams) -> Result<PartyParams> {
     let mut rng = thread_rng();
     let wallet = new_test_wallet(&mut rng, Amount::from_btc(1.4).unwrap(), 5).unwrap();
@@ -25,6 +25,7 @@ use model::OfferId;
 use model::OrderId;
 use model::Price;
 use model::Role;
+use model::WalletInfo;
 use online_status::ConnectionStatus;
 use parse_display::Display;
 use ping_pong::ping;
@@ -79,6 +80,7 @@ pub const N_PAYOUTS: usize = 200;
 pub struct TakerActorSystem<O, W, P> {
     pub cfd_actor: Address<taker_cfd::Actor>,
     wallet_actor: Address<W>,
+    projection_actor: Address<projection::Actor>,
     _oracle_actor: Address<O>,
     pub auto_rollover_actor: Address<auto_rollover::Actor>,
     pub price_feed_actor: Address<P>,
@@ -126,6 +128,7 @@ where
     pub fn new<M>(
         db: sqlite_db::Connection,
         wallet_actor_addr: Address<W>,
+        wallet_info: watch::Receiver<Option<WalletInfo>>,
         oracle_pk: XOnlyPublicKey,
         identity: Identities,
         oracle_constructor: impl FnOnce(command::Executor) -> O,
@@ -212,7 +215,7 @@ where
 
         let cfd_actor_addr = taker_cfd::Actor::new(
             db.clone(),
-            projection_actor,
+            projection_actor.clone(),
             collab_settlement_addr,
             order,
             maker_identity,
@@ -222,6 +225,8 @@ where
                     .extract_peer_id()
                     .context("Unable to extract peer id from maker address")?,
             ),
+            taker_cfd::MAX_RETAINED_OFFERS,
+            wallet_info,
         )
         .create(None)
         .spawn(&mut tasks);
@@ -274,6 +279,11 @@ where
             move || offer::taker::Actor::new(cfd_actor_addr.clone().into())
         });
 
+        let (offer_withdrawal_supervisor, offer_withdrawal_addr) = Supervisor::new({
+            let cfd_actor_addr = cfd_actor_addr.clone();
+            move || offer::taker::WithdrawalActor::new(cfd_actor_addr.clone().into())
+        });
+
         let (identify_listener_supervisor, identify_listener_actor) = Supervisor::new({
             let identity = identity.libp2p.clone();
             move || {
@@ -304,8 +314,8 @@ where
             TAKER_LISTEN_PROTOCOLS.inbound_substream_handlers(
                 pong_address.clone(),
                 identify_listener_actor,
-                offer_addr,
-            ),
+                (offer_addr, offer_withdrawal_addr),
+            )?,
             endpoint::Subscribers::new(
                 vec![
                     online_status_actor.clone().into(),
@@ -328,6 +338,7 @@ where
 
         tasks.add(dialer_supervisor.run_log_summary());
         tasks.add(offer_supervisor.run_log_summary());
+        tasks.add(offer_withdrawal_supervisor.run_log_summary());
         tasks.add(identify_listener_supervisor.run_log_summary());
 
         let close_cfds_actor = archive_closed_cfds::Actor::new(db.clone())
@@ -342,6 +353,7 @@ where
         Ok(Self {
             cfd_actor: cfd_actor_addr,
             wallet_actor: wallet_actor_addr,
+            projection_actor,
             _oracle_actor: oracle_addr,
             auto_rollover_actor: auto_rollover_addr,
             price_feed_actor,
@@ -445,6 +457,12 @@ where
         self.wallet_actor.send(wallet::Sync).await?;
         Ok(())
     }
+
+    #[instrument(skip(self), err)]
+    pub async fn resend_feeds(&self) -> Result<()> {
+        self.projection_actor.send(projection::ResendFeeds).await?;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Copy, Clone, Display, PartialEq, Eq)]
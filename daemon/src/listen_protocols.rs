@@ -7,6 +7,7 @@ use ping_pong::pong;
 use std::collections::HashSet;
 use xtra::message_channel::MessageChannel;
 use xtra::Address;
+use xtra_libp2p::InboundSubstreamHandlers;
 use xtra_libp2p::NewInboundSubstream;
 
 pub const MAKER_LISTEN_PROTOCOLS: MakerListenProtocols = MakerListenProtocols::new(
@@ -20,8 +21,11 @@ pub const MAKER_LISTEN_PROTOCOLS: MakerListenProtocols = MakerListenProtocols::n
     ),
 );
 
-pub const TAKER_LISTEN_PROTOCOLS: TakerListenProtocols =
-    TakerListenProtocols::new(ping_pong::PROTOCOL, identify::PROTOCOL, offer::PROTOCOL);
+pub const TAKER_LISTEN_PROTOCOLS: TakerListenProtocols = TakerListenProtocols::new(
+    ping_pong::PROTOCOL,
+    identify::PROTOCOL,
+    (offer::PROTOCOL, offer::WITHDRAW_PROTOCOL),
+);
 
 pub const REQUIRED_MAKER_LISTEN_PROTOCOLS: RequiredMakerListenProtocols =
     RequiredMakerListenProtocols::new(
@@ -116,7 +120,10 @@ impl MakerListenProtocols {
             Address<collab_settlement::maker::Actor>,
             Address<collab_settlement::deprecated::maker::Actor>,
         ),
-    ) -> [(&'static str, MessageChannel<NewInboundSubstream, ()>); Self::NR_OF_SUPPORTED_PROTOCOLS]
+    ) -> Result<
+        [(&'static str, MessageChannel<NewInboundSubstream, ()>); Self::NR_OF_SUPPORTED_PROTOCOLS],
+        xtra_libp2p::Error,
+    >
     where
         R: rollover::protocol::GetRates + Send + Sync + Clone + 'static,
         RD: rollover::deprecated::protocol::GetRates + Send + Sync + Clone + 'static,
@@ -133,22 +140,19 @@ impl MakerListenProtocols {
             collaborative_settlement_deprecated,
         } = self;
 
-        [
-            (ping, ping_handler.into()),
-            (identify, identify_handler.into()),
-            (order, order_handler.into()),
-            (order_deprecated, order_deprecated_handler.into()),
-            (rollover, rollover_handler.into()),
-            (rollover_deprecated, rollover_deprecated_handler.into()),
-            (
-                collaborative_settlement,
-                collaborative_settlement_handler.into(),
-            ),
-            (
+        InboundSubstreamHandlers::new()
+            .with(ping, ping_handler)
+            .with(identify, identify_handler)
+            .with(order, order_handler)
+            .with(order_deprecated, order_deprecated_handler)
+            .with(rollover, rollover_handler)
+            .with(rollover_deprecated, rollover_deprecated_handler)
+            .with(collaborative_settlement, collaborative_settlement_handler)
+            .with(
                 collaborative_settlement_deprecated,
-                collaborative_settlement_deprecated_handler.into(),
-            ),
-        ]
+                collaborative_settlement_deprecated_handler,
+            )
+            .build()
     }
 }
 
@@ -235,16 +239,22 @@ pub struct TakerListenProtocols {
     ping: &'static str,
     identify: &'static str,
     offer: &'static str,
+    offer_withdraw: &'static str,
 }
 
 impl TakerListenProtocols {
-    const NR_OF_SUPPORTED_PROTOCOLS: usize = 3;
+    const NR_OF_SUPPORTED_PROTOCOLS: usize = 4;
 
-    pub const fn new(ping: &'static str, identify: &'static str, offer: &'static str) -> Self {
+    pub const fn new(
+        ping: &'static str,
+        identify: &'static str,
+        (offer, offer_withdraw): (&'static str, &'static str),
+    ) -> Self {
         Self {
             ping,
             identify,
             offer,
+            offer_withdraw,
         }
     }
 
@@ -256,21 +266,28 @@ impl TakerListenProtocols {
         &self,
         ping_handler: Address<pong::Actor>,
         identify_handler: Address<identify::listener::Actor>,
-        offer_handler: Address<offer::taker::Actor>,
-    ) -> [(&'static str, MessageChannel<NewInboundSubstream, ()>); Self::NR_OF_SUPPORTED_PROTOCOLS]
-    {
+        (offer_handler, offer_withdraw_handler): (
+            Address<offer::taker::Actor>,
+            Address<offer::taker::WithdrawalActor>,
+        ),
+    ) -> Result<
+        [(&'static str, MessageChannel<NewInboundSubstream, ()>); Self::NR_OF_SUPPORTED_PROTOCOLS],
+        xtra_libp2p::Error,
+    > {
         // We deconstruct to ensure that all protocols are being used
         let TakerListenProtocols {
             ping,
             identify,
             offer,
+            offer_withdraw,
         } = self;
 
-        [
-            (ping, ping_handler.into()),
-            (identify, identify_handler.into()),
-            (offer, offer_handler.into()),
-        ]
+        InboundSubstreamHandlers::new()
+            .with(ping, ping_handler)
+            .with(identify, identify_handler)
+            .with(offer, offer_handler)
+            .with(offer_withdraw, offer_withdraw_handler)
+            .build()
     }
 }
 
@@ -281,9 +298,15 @@ impl From<TakerListenProtocols> for HashSet<String> {
             ping,
             identify,
             offer,
+            offer_withdraw,
         } = protocols;
 
-        HashSet::from_iter([ping.to_string(), identify.to_string(), offer.to_string()])
+        HashSet::from_iter([
+            ping.to_string(),
+            identify.to_string(),
+            offer.to_string(),
+            offer_withdraw.to_string(),
+        ])
     }
 }
 
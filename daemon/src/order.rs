@@ -1,4 +1,28 @@
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use xtra_libp2p::libp2p::PeerId;
+
 mod current;
 pub mod deprecated;
 
 pub use current::*;
+
+/// Ensure that a taker placing an order does not end up with more than `max_cfds` CFDs with us.
+async fn ensure_below_max_cfds(
+    db: &sqlite_db::Connection,
+    peer_id: PeerId,
+    max_cfds: usize,
+) -> Result<()> {
+    let cfds = db
+        .count_cfds_with_counterparty_peer_id(peer_id.into())
+        .await
+        .context("Failed to count CFDs of taker")?;
+
+    ensure!(
+        cfds < max_cfds,
+        "Taker already has {cfds} CFDs, at most {max_cfds} are allowed"
+    );
+
+    Ok(())
+}
@@ -3,4 +3,11 @@ pub mod maker;
 mod protocol;
 pub mod taker;
 
+use model::ProtocolVersion;
+
 pub const PROTOCOL: &str = "/itchysats/order/2.0.0";
+
+/// The version of [`PROTOCOL`], recorded for every CFD set up through it.
+fn protocol_version() -> ProtocolVersion {
+    ProtocolVersion::from_protocol(PROTOCOL).expect("protocol to have a valid version")
+}
@@ -214,7 +214,7 @@ pub async fn new(
         .context("Empty stream instead of Msg3")?
         .try_into_msg3()?;
 
-    Ok(Dlc {
+    let dlc = Dlc {
         identity: key_pairs.identity.private,
         identity_counterparty: params.counterparty.identity_pk,
         revocation: key_pairs.revoke.private,
@@ -232,7 +232,10 @@ pub async fn new(
         revoked_commit: Vec::new(),
         settlement_event_id,
         refund_timelock: setup_params.refund_timelock,
-    })
+    };
+    dlc.verify(own_role).context("Contract setup resulted in invalid DLC")?;
+
+    Ok(dlc)
 }
 
 fn stream_next_span() -> tracing::Span {
@@ -1,11 +1,13 @@
 use crate::command;
 use crate::oracle;
 use crate::oracle::NoAnnouncement;
+use crate::order::current;
 use crate::order::current::contract_setup;
 use crate::order::current::protocol;
 use crate::order::current::protocol::MakerMessage;
 use crate::order::current::protocol::SetupMsg;
 use crate::order::current::protocol::TakerMessage;
+use crate::order::ensure_below_max_cfds;
 use crate::process_manager;
 use crate::projection;
 use crate::wallet;
@@ -34,6 +36,7 @@ use std::time::Duration;
 use tokio_extras::FutureExt;
 use tracing::instrument;
 use xtra::prelude::MessageChannel;
+use xtra_libp2p::libp2p::PeerId;
 use xtra_libp2p::NewInboundSubstream;
 use xtra_libp2p::Substream;
 use xtra_productivity::xtra_productivity;
@@ -53,9 +56,11 @@ pub struct Actor {
     decision_senders: HashMap<OrderId, oneshot::Sender<protocol::Decision>>,
     db: sqlite_db::Connection,
     latest_offers: MessageChannel<offer::maker::GetLatestOffers, Vec<model::Offer>>,
+    max_cfds_per_taker: Option<usize>,
 }
 
 impl Actor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         n_payouts: usize,
         oracle_pk: XOnlyPublicKey,
@@ -70,6 +75,7 @@ impl Actor {
         ),
         projection: xtra::Address<projection::Actor>,
         latest_offers: MessageChannel<offer::maker::GetLatestOffers, Vec<model::Offer>>,
+        max_cfds_per_taker: Option<usize>,
     ) -> Self {
         Self {
             executor: command::Executor::new(db.clone(), process_manager),
@@ -82,6 +88,7 @@ impl Actor {
             decision_senders: HashMap::default(),
             db,
             latest_offers,
+            max_cfds_per_taker,
         }
     }
 
@@ -117,6 +124,16 @@ impl Actor {
 
         Ok(offer)
     }
+
+    /// Pick the offer the taker wants to take, unless the taker already has the maximum number of
+    /// CFDs with us.
+    async fn check_order(&self, peer_id: PeerId, offer_id: OfferId) -> Result<model::Offer> {
+        if let Some(max_cfds) = self.max_cfds_per_taker {
+            ensure_below_max_cfds(&self.db, peer_id, max_cfds).await?;
+        }
+
+        self.pick_offer(offer_id).await
+    }
 }
 
 #[xtra_productivity]
@@ -149,14 +166,12 @@ impl Actor {
 
         tracing::info!(%peer_id, %quantity, %order_id, %offer_id, "Taker wants to place an order");
 
-        // Reject the order if the offer cannot be found in the latest offers
-        let offer = match self.pick_offer(offer_id).await {
+        // Reject the order if the offer cannot be found in the latest offers or the taker has
+        // reached the maximum number of CFDs
+        let offer = match self.check_order(peer_id, offer_id).await {
             Ok(offer) => offer,
             Err(e) => {
-                tracing::warn!(
-                    %peer_id,
-                    "Rejecting taker order because unable to pick offer: {e:#}"
-                );
+                tracing::warn!(%peer_id, "Rejecting taker order: {e:#}");
 
                 let future = async move {
                     framed
@@ -190,6 +205,7 @@ impl Actor {
             Some(peer_id.into()),
             Role::Maker,
             leverage,
+            current::protocol_version(),
         );
 
         // If this fails we shouldn't try to append
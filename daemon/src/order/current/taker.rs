@@ -1,6 +1,7 @@
 use crate::command;
 use crate::oracle;
 use crate::oracle::NoAnnouncement;
+use crate::order::current;
 use crate::order::current::contract_setup;
 use crate::order::current::protocol;
 use crate::order::current::protocol::Decision;
@@ -116,6 +117,7 @@ impl Actor {
                     Some(maker_peer_id.into()),
                     Role::Taker,
                     leverage,
+                    current::protocol_version(),
                 );
 
                 // If this fails we shouldn't try to append
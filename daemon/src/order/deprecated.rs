@@ -2,4 +2,11 @@ mod contract_setup;
 pub mod maker;
 mod protocol;
 
+use model::ProtocolVersion;
+
 pub const PROTOCOL: &str = "/itchysats/order/1.0.0";
+
+/// The version of [`PROTOCOL`], recorded for every CFD set up through it.
+fn protocol_version() -> ProtocolVersion {
+    ProtocolVersion::from_protocol(PROTOCOL).expect("protocol to have a valid version")
+}
@@ -62,10 +62,21 @@ use xtra_bitmex_price_feed::GetLatestQuotes;
 use xtra_productivity::xtra_productivity;
 use xtras::SendAsyncNext;
 
+/// How often we try to reach the database before giving up on the initial load of the CFDs.
+const INITIAL_LOAD_ATTEMPTS: u32 = 5;
+
+/// Delay before the second attempt to reach the database, doubled for every further attempt.
+const INITIAL_LOAD_BACKOFF: Duration = Duration::from_millis(500);
+
 /// Store the latest state of `T` for display purposes
 /// (replaces previously stored values)
 pub struct Update<T>(pub T);
 
+/// Indicates that the offer with the given ID is no longer available
+/// and should no longer be displayed.
+#[derive(Clone, Copy)]
+pub struct WithdrawnOffer(pub OfferId);
+
 /// Indicates that the CFD with the given order ID changed.
 #[derive(Clone, Copy)]
 pub struct CfdChanged(pub OrderId);
@@ -74,12 +85,21 @@ pub struct CfdChanged(pub OrderId);
 #[derive(Clone, Copy)]
 struct Initialize;
 
+/// Reload all CFDs and push the current state of every feed again.
+///
+/// New subscribers already see the latest value of each feed. This is for consumers (e.g. a
+/// reconnecting UI) that suspect to have missed updates and want a fresh snapshot.
+#[derive(Clone, Copy)]
+pub struct ResendFeeds;
+
 pub struct Actor {
     db: sqlite_db::Connection,
     tx: Tx,
     state: State,
     price_feed: MessageChannel<GetLatestQuotes, xtra_bitmex_price_feed::LatestQuotes>,
     role: Role,
+    /// Feeds we already know have no receivers left.
+    closed_feeds: HashSet<&'static str>,
 }
 
 pub struct FeedReceivers {
@@ -127,6 +147,7 @@ impl Actor {
             state: State::new(network),
             price_feed,
             role,
+            closed_feeds: HashSet::new(),
         }
     }
 }
@@ -786,7 +807,11 @@ impl Cfd {
 struct Tx(Arc<FeedSenders>);
 
 impl Tx {
-    fn send_cfds_update(&self, cfds: HashMap<OrderId, Cfd>, quotes: &LatestQuotes) {
+    fn send_cfds_update(
+        &self,
+        cfds: HashMap<OrderId, Cfd>,
+        quotes: &LatestQuotes,
+    ) -> Result<(), FeedClosed> {
         let cfds_with_quote = cfds
             .into_iter()
             .map(|(_, cfd)| cfd.with_current_quote(Some(quotes)))
@@ -798,20 +823,26 @@ impl Tx {
             })
             .collect();
 
-        let _ = self.0.cfds.send(Some(cfds_with_quote));
+        self.0
+            .cfds
+            .send(Some(cfds_with_quote))
+            .map_err(|_| FeedClosed("CFD"))
     }
 
-    fn send_quotes_update(&self, quotes: LatestQuotes) {
-        let _ = self.0.quote.send(quotes);
+    fn send_quotes_update(&self, quotes: LatestQuotes) -> Result<(), FeedClosed> {
+        self.0.quote.send(quotes).map_err(|_| FeedClosed("Quote"))
     }
 
-    fn send_offer_update(&self, offers: MakerOffers) -> Result<()> {
-        self.0.offers.send(offers)?;
-
-        Ok(())
+    fn send_offer_update(&self, offers: MakerOffers) -> Result<(), FeedClosed> {
+        self.0.offers.send(offers).map_err(|_| FeedClosed("Offer"))
     }
 }
 
+/// All receivers of a feed have been dropped.
+#[derive(Debug, thiserror::Error)]
+#[error("{0} feed has no receivers left")]
+struct FeedClosed(&'static str);
+
 /// Internal struct to keep state in one place
 struct State {
     network: Network,
@@ -1088,6 +1119,21 @@ impl State {
         self.latest_quotes = quotes;
     }
 
+    fn remove_offer(&mut self, offer_id: OfferId) {
+        let MakerOffers {
+            btcusd_long,
+            btcusd_short,
+            ethusd_long,
+            ethusd_short,
+        } = &mut self.offers;
+
+        for offer in [btcusd_long, btcusd_short, ethusd_long, ethusd_short] {
+            if offer.as_ref().map(|offer| offer.id) == Some(offer_id) {
+                *offer = None;
+            }
+        }
+    }
+
     fn update_offers(&mut self, new_offers: Vec<CfdOffer>) {
         for new_offer in new_offers.into_iter() {
             match &new_offer {
@@ -1116,9 +1162,49 @@ impl State {
     }
 }
 
-#[xtra_productivity]
 impl Actor {
-    async fn handle(&mut self, _: Initialize) {
+    /// Log if one of the feeds has been closed, once per feed.
+    ///
+    /// This usually happens during shutdown, when the receivers are dropped before the actor. We
+    /// keep running regardless: the actor is supervised, so stopping would only get us restarted
+    /// with the same closed feeds over and over again.
+    fn log_if_feed_closed(&mut self, result: Result<(), FeedClosed>) {
+        if let Err(e) = result {
+            if self.closed_feeds.insert(e.0) {
+                tracing::info!("Not projecting to closed feed: {e}");
+            }
+        }
+    }
+}
+
+impl Actor {
+    /// Wait until the database can be queried, with bounded backoff.
+    ///
+    /// The actor may be started while the database is still being set up. Loading the CFDs
+    /// right away would then show an empty feed until the next CFD changes.
+    async fn wait_for_db(&self) -> Result<()> {
+        let mut backoff = INITIAL_LOAD_BACKOFF;
+
+        for attempt in 1.. {
+            match self.db.load_open_cfd_ids().await {
+                Ok(_) => break,
+                Err(e) if attempt < INITIAL_LOAD_ATTEMPTS => {
+                    tracing::warn!(%attempt, "Database not ready, retrying in {backoff:?}: {e:#}");
+                    tokio_extras::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => {
+                    return Err(e.context(format!(
+                        "Database not ready after {INITIAL_LOAD_ATTEMPTS} attempts"
+                    )))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn load_and_send_cfds(&mut self) {
         let mut stream = self.db.load_all_cfds::<Cfd>(self.state.network);
 
         let mut cfds = HashMap::new();
@@ -1137,13 +1223,38 @@ impl Actor {
 
         self.state.cfds = Some(cfds);
 
-        self.tx.send_cfds_update(
+        let result = self.tx.send_cfds_update(
             self.state
                 .cfds
                 .clone()
                 .expect("we initialized the state above; qed"),
             &self.state.latest_quotes,
         );
+        self.log_if_feed_closed(result);
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, _: Initialize, ctx: &mut xtra::Context<Self>) {
+        if let Err(e) = self.wait_for_db().await {
+            // Stopping allows the supervisor to start over with a fresh actor
+            tracing::error!("Stopping projection actor: {e:#}");
+            ctx.stop_self();
+            return;
+        }
+
+        self.load_and_send_cfds().await;
+    }
+
+    async fn handle(&mut self, _: ResendFeeds) {
+        self.load_and_send_cfds().await;
+
+        let result = self
+            .tx
+            .send_quotes_update(self.state.latest_quotes.clone())
+            .and_then(|()| self.tx.send_offer_update(self.state.offers.clone()));
+        self.log_if_feed_closed(result);
     }
 
     async fn handle(&mut self, msg: CfdChanged) {
@@ -1152,13 +1263,14 @@ impl Actor {
             return;
         };
 
-        self.tx.send_cfds_update(
+        let result = self.tx.send_cfds_update(
             self.state
                 .cfds
                 .clone()
                 .expect("update_cfd fails if the CFDs have not been initialized yet"),
             &self.state.latest_quotes,
         );
+        self.log_if_feed_closed(result);
     }
 
     fn handle(&mut self, msg: Update<Vec<model::Offer>>) {
@@ -1176,14 +1288,21 @@ impl Actor {
 
         self.state.update_offers(new_offers);
 
-        if let Err(e) = self.tx.send_offer_update(self.state.offers.clone()) {
-            tracing::error!("Failed to propagate offer update: {e:#}");
-        }
+        let result = self.tx.send_offer_update(self.state.offers.clone());
+        self.log_if_feed_closed(result);
+    }
+
+    fn handle(&mut self, msg: WithdrawnOffer) {
+        self.state.remove_offer(msg.0);
+
+        let result = self.tx.send_offer_update(self.state.offers.clone());
+        self.log_if_feed_closed(result);
     }
 
     fn handle(&mut self, msg: Update<LatestQuotes>) {
         self.state.update_quotes(msg.0.clone());
-        self.tx.send_quotes_update(msg.0.clone());
+        let result = self.tx.send_quotes_update(msg.0.clone());
+        self.log_if_feed_closed(result);
 
         let hydrated_cfds = match self.state.cfds.clone() {
             None => {
@@ -1193,7 +1312,8 @@ impl Actor {
             Some(cfds) => cfds,
         };
 
-        self.tx.send_cfds_update(hydrated_cfds, &msg.0);
+        let result = self.tx.send_cfds_update(hydrated_cfds, &msg.0);
+        self.log_if_feed_closed(result);
     }
 }
 
@@ -1712,6 +1832,7 @@ mod tests {
             FundingRate::default(),
             TxFeeRate::default(),
             ContractSymbol::BtcUsd,
+            None,
         )
     }
 
@@ -1753,6 +1874,7 @@ mod tests {
             FundingRate::default(),
             TxFeeRate::default(),
             ContractSymbol::BtcUsd,
+            None,
         );
 
         let contract_setup_completed =
@@ -6,6 +6,8 @@ use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
+use bdk::bitcoin::Amount;
+use model::calculate_margin;
 use model::libp2p::PeerId;
 use model::market_closing_price;
 use model::Cfd;
@@ -16,12 +18,27 @@ use model::OfferId;
 use model::OrderId;
 use model::Price;
 use model::Role;
+use model::WalletInfo;
 use sqlite_db;
 use std::collections::HashMap;
 use time::OffsetDateTime;
+use tokio::sync::watch;
 use xtra_productivity::xtra_productivity;
 use xtras::SendAsyncSafe;
 
+/// The maximum number of offers the taker retains by default.
+///
+/// The maker usually only announces a handful of offers at a time, so this leaves plenty of room
+/// while protecting us from a misbehaving maker flooding us with announcements.
+pub const MAX_RETAINED_OFFERS: usize = 100;
+
+/// Estimated virtual size of the taker's contribution to the lock transaction.
+///
+/// Used to reserve a buffer for transaction fees on top of the margin when checking whether the
+/// wallet balance suffices to place an order. This is generous on purpose, a few inputs fit
+/// comfortably.
+const LOCK_TX_FEE_BUFFER_VBYTES: u64 = 500;
+
 #[derive(Clone, Copy)]
 pub struct PlaceOrder {
     pub offer_id: OfferId,
@@ -37,6 +54,25 @@ pub struct ProposeSettlement {
     pub quote_timestamp: String,
 }
 
+/// The wallet balance does not cover the funds needed to place an order.
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Insufficient funds: placing this order requires {required} but the wallet balance is {available}")]
+pub struct InsufficientFunds {
+    pub required: Amount,
+    pub available: Amount,
+}
+
+/// The quantity of an order is not positive or outside of the bounds of the offer it takes.
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error(
+    "Invalid quantity: {quantity} is not within the offer's bounds of {min} to {max} contracts"
+)]
+pub struct InvalidQuantity {
+    pub quantity: Contracts,
+    pub min: Contracts,
+    pub max: Contracts,
+}
+
 pub struct Actor {
     db: sqlite_db::Connection,
     projection_actor: xtra::Address<projection::Actor>,
@@ -45,9 +81,11 @@ pub struct Actor {
     offers: Offers,
     maker_identity: Identity,
     maker_peer_id: PeerId,
+    wallet_info: watch::Receiver<Option<WalletInfo>>,
 }
 
 impl Actor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         db: sqlite_db::Connection,
         projection_actor: xtra::Address<projection::Actor>,
@@ -55,17 +93,75 @@ impl Actor {
         order_actor: xtra::Address<order::taker::Actor>,
         maker_identity: Identity,
         maker_peer_id: PeerId,
+        max_offers: usize,
+        wallet_info: watch::Receiver<Option<WalletInfo>>,
     ) -> Self {
         Self {
             db,
             projection_actor,
             collab_settlement_actor,
             order_actor,
-            offers: Offers::default(),
+            offers: Offers::new(max_offers),
             maker_identity,
             maker_peer_id,
+            wallet_info,
         }
     }
+
+    /// Ensure that our latest known wallet balance covers the funds needed for the order.
+    ///
+    /// We check this before the order is placed so that the taker learns about insufficient
+    /// funds right away instead of when building the lock transaction during contract setup.
+    fn ensure_sufficient_funds(
+        &self,
+        offer: &model::Offer,
+        quantity: Contracts,
+        leverage: Leverage,
+    ) -> Result<()> {
+        let available = self
+            .wallet_info
+            .borrow()
+            .as_ref()
+            .map(|wallet_info| wallet_info.balance)
+            .context("Wallet has not been synced yet, unable to verify balance")?;
+
+        check_funds(required_funds(offer, quantity, leverage), available)?;
+
+        Ok(())
+    }
+}
+
+/// The funds the taker needs to lock up for an order: their margin plus a fee buffer.
+fn required_funds(offer: &model::Offer, quantity: Contracts, leverage: Leverage) -> Amount {
+    let margin = calculate_margin(offer.contract_symbol, offer.price, quantity, leverage);
+    let fee_buffer =
+        Amount::from_sat(u64::from(offer.tx_fee_rate.to_u32()) * LOCK_TX_FEE_BUFFER_VBYTES);
+
+    margin + fee_buffer
+}
+
+fn check_funds(required: Amount, available: Amount) -> Result<(), InsufficientFunds> {
+    if available < required {
+        return Err(InsufficientFunds {
+            required,
+            available,
+        });
+    }
+
+    Ok(())
+}
+
+fn check_quantity(offer: &model::Offer, quantity: Contracts) -> Result<(), InvalidQuantity> {
+    if quantity <= Contracts::ZERO || quantity < offer.min_quantity || quantity > offer.max_quantity
+    {
+        return Err(InvalidQuantity {
+            quantity,
+            min: offer.min_quantity,
+            max: offer.max_quantity,
+        });
+    }
+
+    Ok(())
 }
 
 #[xtra_productivity]
@@ -78,6 +174,20 @@ impl Actor {
         };
     }
 
+    async fn handle_withdrawn_offer(&mut self, msg: offer::taker::WithdrawnOffer) {
+        let offer::taker::WithdrawnOffer(offer_id) = msg;
+
+        self.offers.remove(&offer_id);
+
+        if let Err(e) = self
+            .projection_actor
+            .send(projection::WithdrawnOffer(offer_id))
+            .await
+        {
+            tracing::warn!(%offer_id, "Failed to send withdrawn offer to projection actor: {e:#}");
+        };
+    }
+
     async fn handle_propose_settlement(&mut self, msg: ProposeSettlement) -> Result<()> {
         let ProposeSettlement {
             order_id,
@@ -122,6 +232,9 @@ impl Actor {
             bail!("The maker's offer appears to be outdated, refusing to place order");
         }
 
+        check_quantity(&offer, quantity)?;
+        self.ensure_sufficient_funds(&offer, quantity, leverage)?;
+
         let order_id = OrderId::default();
         let place_order = order::taker::PlaceOrder::new(
             order_id,
@@ -140,26 +253,67 @@ impl Actor {
     }
 }
 
-#[derive(Default)]
-struct Offers(HashMap<OfferId, model::Offer>);
+struct Offers {
+    inner: HashMap<OfferId, model::Offer>,
+    max_offers: usize,
+}
 
 impl Offers {
+    fn new(max_offers: usize) -> Self {
+        Self {
+            inner: HashMap::default(),
+            max_offers,
+        }
+    }
+
+    /// Insert the given offers, evicting the oldest ones if we exceed `max_offers`.
     fn insert(&mut self, offers: Vec<model::Offer>) {
         for offer in offers.into_iter() {
-            self.0.insert(offer.id, offer);
+            self.inner.insert(offer.id, offer);
+        }
+
+        self.evict_oldest_offers();
+    }
+
+    fn remove(&mut self, id: &OfferId) {
+        if self.inner.remove(id).is_some() {
+            tracing::info!(offer_id = %id, "Maker withdrew offer");
         }
     }
 
     fn get(&mut self, id: &OfferId) -> Option<model::Offer> {
         self.remove_old_offers();
 
-        self.0.get(id).cloned()
+        self.inner.get(id).cloned()
     }
 
     fn remove_old_offers(&mut self) {
-        self.0
+        self.inner
             .retain(|_, offer| offer.is_safe_to_take(OffsetDateTime::now_utc()));
     }
+
+    fn evict_oldest_offers(&mut self) {
+        if self.inner.len() <= self.max_offers {
+            return;
+        }
+        let excess = self.inner.len() - self.max_offers;
+
+        let mut offers_by_age = self
+            .inner
+            .values()
+            .map(|offer| (offer.creation_timestamp_maker, offer.id))
+            .collect::<Vec<_>>();
+        offers_by_age.sort_unstable_by_key(|(creation_timestamp, _)| *creation_timestamp);
+
+        tracing::warn!(
+            max_offers = %self.max_offers,
+            "Received too many offers from maker, evicting the {excess} oldest"
+        );
+
+        for (_, id) in offers_by_age.into_iter().take(excess) {
+            self.inner.remove(&id);
+        }
+    }
 }
 
 #[async_trait]
@@ -168,3 +322,113 @@ impl xtra::Actor for Actor {
 
     async fn stopped(self) -> Self::Stop {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::ContractSymbol;
+    use model::FundingRate;
+    use model::LotSize;
+    use model::OpeningFee;
+    use model::Position;
+    use model::Timestamp;
+    use model::TxFeeRate;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn given_more_offers_than_max_when_inserting_then_oldest_are_evicted() {
+        let mut offers = Offers::new(2);
+
+        let oldest = dummy_offer(Timestamp::new(1));
+        let older = dummy_offer(Timestamp::new(2));
+        let newest = dummy_offer(Timestamp::new(3));
+
+        offers.insert(vec![older.clone(), oldest.clone()]);
+        offers.insert(vec![newest.clone()]);
+
+        assert_eq!(offers.inner.len(), 2);
+        assert!(!offers.inner.contains_key(&oldest.id));
+        assert!(offers.inner.contains_key(&older.id));
+        assert!(offers.inner.contains_key(&newest.id));
+    }
+
+    #[test]
+    fn given_withdrawn_offer_then_offer_can_no_longer_be_taken() {
+        let mut offers = Offers::new(2);
+
+        let withdrawn = dummy_offer(Timestamp::now());
+        let remaining = dummy_offer(Timestamp::now());
+        offers.insert(vec![withdrawn.clone(), remaining.clone()]);
+
+        offers.remove(&withdrawn.id);
+
+        assert!(offers.get(&withdrawn.id).is_none());
+        assert!(offers.get(&remaining.id).is_some());
+    }
+
+    #[test]
+    fn given_balance_below_margin_and_fees_when_checking_funds_then_insufficient() {
+        let offer = dummy_offer(Timestamp::now());
+        let required = required_funds(&offer, Contracts::new(100), Leverage::TWO);
+
+        let available = required - Amount::from_sat(1);
+        let error = check_funds(required, available).unwrap_err();
+
+        assert_eq!(error.required, required);
+        assert_eq!(error.available, available);
+        assert!(check_funds(required, required).is_ok());
+    }
+
+    #[test]
+    fn given_quantity_outside_offer_bounds_when_checking_quantity_then_invalid() {
+        let offer = dummy_offer(Timestamp::now());
+
+        for quantity in [0, 99, 1001] {
+            let error = check_quantity(&offer, Contracts::new(quantity)).unwrap_err();
+
+            assert_eq!(error.quantity, Contracts::new(quantity));
+            assert_eq!(error.min, offer.min_quantity);
+            assert_eq!(error.max, offer.max_quantity);
+        }
+
+        for quantity in [100, 500, 1000] {
+            assert!(check_quantity(&offer, Contracts::new(quantity)).is_ok());
+        }
+    }
+
+    #[test]
+    fn required_funds_include_fee_buffer_on_top_of_margin() {
+        let offer = dummy_offer(Timestamp::now());
+
+        let required = required_funds(&offer, Contracts::new(100), Leverage::TWO);
+        let margin = calculate_margin(
+            offer.contract_symbol,
+            offer.price,
+            Contracts::new(100),
+            Leverage::TWO,
+        );
+
+        assert!(required > margin);
+    }
+
+    fn dummy_offer(creation_timestamp_maker: Timestamp) -> model::Offer {
+        let offer = model::Offer::new(
+            Position::Short,
+            Price::new(dec!(1000)).unwrap(),
+            Contracts::new(100),
+            Contracts::new(1000),
+            time::Duration::hours(24),
+            TxFeeRate::default(),
+            FundingRate::default(),
+            OpeningFee::default(),
+            vec![Leverage::TWO],
+            ContractSymbol::BtcUsd,
+            LotSize::new(100),
+        );
+
+        model::Offer {
+            creation_timestamp_maker,
+            ..offer
+        }
+    }
+}
@@ -105,10 +105,16 @@ pub struct Actor<B, DB> {
 }
 
 impl Actor<ElectrumBlockchain, sled::Tree> {
+    /// Spawn the wallet actor.
+    ///
+    /// The returned feed starts out with `last_wallet_info`, f.e. as loaded via
+    /// [`sqlite_db::Connection::load_last_wallet_info`], until the wallet is synced for the first
+    /// time.
     pub fn spawn(
         electrum_rpc_url: &str,
         ext_priv_key: ExtendedPrivKey,
         db_path: PathBuf,
+        last_wallet_info: Option<WalletInfo>,
     ) -> Result<(xtra::Address<Self>, watch::Receiver<Option<WalletInfo>>)> {
         let client = electrum_client::Client::new(electrum_rpc_url)
             .context("Failed to initialize Electrum RPC client")?;
@@ -144,7 +150,7 @@ impl Actor<ElectrumBlockchain, sled::Tree> {
         // then without incurring in double spend attempts.
         let time_to_lock = SYNC_INTERVAL * 4;
 
-        let (sender, receiver) = watch::channel(None);
+        let (sender, receiver) = watch::channel(last_wallet_info);
         let actor = Self {
             wallet,
             sender,
@@ -213,6 +219,25 @@ where
     }
 }
 
+/// Persist every wallet snapshot published on `wallet_info` to the database.
+///
+/// This allows seeding the wallet feed with the last known snapshot upon startup, see
+/// [`Actor::spawn`].
+pub async fn persist_wallet_info(
+    db: sqlite_db::Connection,
+    mut wallet_info: watch::Receiver<Option<WalletInfo>>,
+) {
+    while wallet_info.changed().await.is_ok() {
+        let latest = wallet_info.borrow().clone();
+
+        if let Some(latest) = latest {
+            if let Err(e) = db.insert_wallet_info(&latest).await {
+                tracing::warn!("Failed to persist wallet info: {e:#}");
+            }
+        }
+    }
+}
+
 #[xtra_productivity]
 impl<DB> Actor<ElectrumBlockchain, DB>
 where
@@ -30,6 +30,7 @@ use model::Contracts;
 use model::FundingRate;
 use model::Leverage;
 use model::LotSize;
+use model::OfferId;
 use model::OpeningFee;
 use model::OrderId;
 use model::Price;
@@ -37,6 +38,7 @@ use model::Role;
 use model::TxFeeRate;
 use ping_pong::ping;
 use ping_pong::pong;
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
@@ -50,6 +52,7 @@ use xtra_libp2p::libp2p::Multiaddr;
 use xtra_libp2p::libp2p::PeerId;
 use xtra_libp2p::listener;
 use xtra_libp2p::Endpoint;
+use xtra_libp2p::GetConnectionStats;
 use xtras::supervisor::always_restart_after;
 use xtras::supervisor::Supervisor;
 
@@ -80,6 +83,8 @@ pub struct ActorSystem<O: 'static, W: 'static> {
     executor: command::Executor,
     _tasks: Tasks,
     _pong_actor: Address<pong::Actor>,
+    endpoint: Address<Endpoint>,
+    db: sqlite_db::Connection,
 }
 
 impl<O, W> ActorSystem<O, W>
@@ -106,6 +111,8 @@ where
         identity: Identities,
         listen_multiaddr: Multiaddr,
         blocked_peers: HashSet<PeerId>,
+        max_cfds_per_taker: Option<usize>,
+        tcp_port_reuse: bool,
     ) -> Result<Self>
     where
         M: Handler<monitor::MonitorAfterContractSetup, Return = ()>
@@ -172,6 +179,7 @@ where
                     (wallet.clone().into(), wallet.clone().into()),
                     projection.clone(),
                     maker_offer_address.clone().into(),
+                    max_cfds_per_taker,
                 )
             }
         });
@@ -193,6 +201,7 @@ where
                     (wallet.clone().into(), wallet.clone().into()),
                     projection.clone(),
                     maker_offer_address.clone().into(),
+                    max_cfds_per_taker,
                 )
             }
         });
@@ -292,11 +301,16 @@ where
             }
         });
 
-        let (identify_dialer_supervisor, identify_dialer_actor) =
-            Supervisor::new(move || identify::dialer::Actor::new(endpoint_addr.clone()));
+        let (identify_dialer_supervisor, identify_dialer_actor) = Supervisor::new({
+            let endpoint_addr = endpoint_addr.clone();
+            move || identify::dialer::Actor::new(endpoint_addr.clone())
+        });
 
+        // All transports are clones of the same config, so that dials know the addresses we listen
+        // on when reusing the listen port
+        let tcp = TokioTcpConfig::new().port_reuse(tcp_port_reuse);
         let endpoint = Endpoint::new(
-            Box::new(TokioTcpConfig::new),
+            Box::new(move || tcp.clone()),
             identity.libp2p,
             ENDPOINT_CONNECTION_TIMEOUT,
             MAKER_LISTEN_PROTOCOLS.inbound_substream_handlers(
@@ -305,7 +319,7 @@ where
                 (order, order_deprecated),
                 (rollover_addr.clone(), rollover_deprecated_addr.clone()),
                 (collab_settlement_addr, collab_settlement_deprecated_addr),
-            ),
+            )?,
             endpoint::Subscribers::new(
                 vec![
                     ping_address.clone().into(),
@@ -344,7 +358,7 @@ where
             .create(None)
             .spawn(&mut tasks);
 
-        tasks.add(time_to_first_position_ctx.run(time_to_first_position::Actor::new(db)));
+        tasks.add(time_to_first_position_ctx.run(time_to_first_position::Actor::new(db.clone())));
 
         tracing::debug!("Maker actor system ready");
 
@@ -359,6 +373,8 @@ where
             _oracle_actor: oracle_addr,
             _tasks: tasks,
             _pong_actor: pong_address,
+            endpoint: endpoint_addr,
+            db,
         })
     }
 
@@ -399,6 +415,13 @@ where
         Ok(())
     }
 
+    pub async fn withdraw_offer(&self, offer_id: OfferId) -> Result<()> {
+        self.cfd_actor
+            .send(cfd::WithdrawOffer { offer_id })
+            .await??;
+        Ok(())
+    }
+
     pub async fn accept_order(&self, order_id: OrderId) -> Result<()> {
         self.cfd_actor.send(cfd::AcceptOrder { order_id }).await??;
         Ok(())
@@ -464,4 +487,33 @@ where
             .await?;
         Ok(())
     }
+
+    /// The currently connected takers and the number of CFDs we have with each of them.
+    ///
+    /// Connected takers without any CFDs are included with a count of zero.
+    pub async fn connected_takers_with_cfd_counts(&self) -> Result<HashMap<PeerId, usize>> {
+        let connected_peers = self
+            .endpoint
+            .send(GetConnectionStats)
+            .await?
+            .connected_peers;
+
+        let cfd_counts = self
+            .db
+            .count_cfds_by_counterparty_peer_id()
+            .await?
+            .into_iter()
+            .map(|(peer_id, count)| (peer_id.inner(), count))
+            .collect::<HashMap<_, _>>();
+
+        let takers = connected_peers
+            .into_iter()
+            .map(|peer_id| {
+                let count = cfd_counts.get(&peer_id).copied().unwrap_or_default();
+                (peer_id, count)
+            })
+            .collect();
+
+        Ok(takers)
+    }
 }
@@ -11,6 +11,7 @@ use model::FundingRate;
 use model::Identity;
 use model::Leverage;
 use model::LotSize;
+use model::OfferId;
 use model::OpeningFee;
 use model::OrderId;
 use model::Position;
@@ -32,6 +33,11 @@ pub struct NewOffers {
     pub params: OfferParams,
 }
 
+#[derive(Clone, Copy)]
+pub struct WithdrawOffer {
+    pub offer_id: OfferId,
+}
+
 #[derive(Clone, Copy)]
 pub struct AcceptOrder {
     pub order_id: OrderId,
@@ -416,6 +422,28 @@ impl Actor {
         Ok(())
     }
 
+    async fn handle(&mut self, msg: WithdrawOffer) -> Result<()> {
+        let WithdrawOffer { offer_id } = msg;
+
+        // Takers on the deprecated offer protocol are not notified, they only learn about the
+        // withdrawal once the offer is replaced
+        let withdrawn = self
+            .offer
+            .send(offer::maker::WithdrawOffer(offer_id))
+            .await
+            .context("Offer actor disconnected")?;
+
+        if !withdrawn {
+            bail!("Offer {offer_id} is not among the current offers");
+        }
+
+        self.projection
+            .send(projection::WithdrawnOffer(offer_id))
+            .await?;
+
+        Ok(())
+    }
+
     async fn handle(&mut self, msg: TakerConnected) -> Result<()> {
         self.handle_taker_connected(msg.id).await
     }
@@ -5,6 +5,7 @@ use shared_bin::cli::Network;
 use shared_bin::logger::LevelFilter;
 use shared_bin::logger::LOCAL_COLLECTOR_ENDPOINT;
 use std::convert::Infallible;
+use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 
@@ -44,6 +45,18 @@ pub struct Opts {
     #[clap(long, default_value = "10000")]
     pub p2p_port: u16,
 
+    /// The IP address to listen on for libp2p connections, f.e. to only accept connections on
+    /// one of several network interfaces.
+    #[clap(long, default_value = "0.0.0.0")]
+    pub p2p_listen_ip: IpAddr,
+
+    /// If enabled, outbound libp2p connections are dialed from the address and port we listen on
+    /// (via `SO_REUSEADDR` and `SO_REUSEPORT`), instead of from an ephemeral port on any
+    /// interface. Combine with `--p2p-listen-ip` to dial from a specific interface, f.e. to
+    /// satisfy firewall rules on the source address.
+    #[clap(long)]
+    pub p2p_port_reuse: bool,
+
     /// The IP address to listen on for the HTTP API.
     #[clap(long, default_value = "127.0.0.1:8001")]
     pub http_address: SocketAddr,
@@ -94,6 +107,20 @@ pub struct Opts {
     #[clap(short, long)]
     pub ignore_migration_errors: bool,
 
+    /// If enabled, the database syncs to disk less often, making writes faster. A power loss may
+    /// then lose the most recent writes, except for rollovers, which are always synced.
+    #[clap(long)]
+    pub db_synchronous_normal: bool,
+
+    /// If enabled, the persisted CFD data is checked for inconsistencies at startup and any
+    /// problems found are logged.
+    #[clap(long)]
+    pub db_integrity_checks: bool,
+
+    /// If provided, orders of takers which already have this many CFDs with us are rejected.
+    #[clap(long)]
+    pub max_cfds_per_taker: Option<usize>,
+
     /// If provided will be used for internal wallet instead of a random key. The keys will be
     /// derived according to Bip84
     #[clap(short, long)]
@@ -84,7 +84,7 @@ async fn main() -> Result<()> {
 
     wallet_dir.push(MAKER_WALLET_ID);
     let (wallet, wallet_feed_receiver) =
-        wallet::Actor::spawn(opts.network.electrum(), ext_priv_key, wallet_dir)?;
+        wallet::Actor::spawn(opts.network.electrum(), ext_priv_key, wallet_dir, None)?;
 
     if let Some(Withdraw::Withdraw {
         amount,
@@ -115,11 +115,28 @@ async fn main() -> Result<()> {
         .merge(("cli_colors", false))
         .merge(("secret_key", RandomSeed::default().seed()));
 
-    let p2p_port = opts.p2p_port;
-    let p2p_socket = format!("0.0.0.0:{p2p_port}").parse::<SocketAddr>().unwrap();
+    let p2p_socket = SocketAddr::new(opts.p2p_listen_ip, opts.p2p_port);
 
-    let db =
-        sqlite_db::connect(data_dir.join("maker.sqlite"), opts.ignore_migration_errors).await?;
+    let db_synchronous = if opts.db_synchronous_normal {
+        sqlite_db::Synchronous::Normal
+    } else {
+        sqlite_db::Synchronous::Full
+    };
+    let db = sqlite_db::connect(
+        data_dir.join("maker.sqlite"),
+        opts.ignore_migration_errors,
+        db_synchronous,
+    )
+    .await?;
+
+    if opts.db_integrity_checks {
+        let report = db.run_integrity_checks().await?;
+        if report.is_healthy() {
+            tracing::info!("Database integrity checks passed");
+        } else {
+            tracing::warn!(?report, "Database integrity checks found problems");
+        }
+    }
 
     let blocked_peers_path = data_dir.join("blocked_peers.toml");
     let blocked_peers = load_blocked_peers(&blocked_peers_path)
@@ -179,6 +196,8 @@ async fn main() -> Result<()> {
         identities,
         endpoint_listen,
         blocked_peers,
+        opts.max_cfds_per_taker,
+        opts.p2p_port_reuse,
     )?;
 
     if let Some(password) = opts.password {
@@ -204,6 +223,7 @@ async fn main() -> Result<()> {
                 routes::maker_feed,
                 routes::put_offer_params,
                 routes::put_offer_params_for_symbol,
+                routes::delete_offer,
                 routes::post_cfd_action,
                 routes::get_health_check,
                 routes::get_cfds,
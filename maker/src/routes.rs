@@ -14,6 +14,7 @@ use model::Contracts;
 use model::FundingRate;
 use model::Leverage;
 use model::LotSize;
+use model::OfferId;
 use model::OpeningFee;
 use model::OrderId;
 use model::Price;
@@ -235,6 +236,25 @@ pub async fn put_offer_params_for_symbol(
     Ok(())
 }
 
+#[rocket::delete("/offer/<offer_id>")]
+#[instrument(name = "DELETE /offer/<offer_id>", skip(maker, _user), err)]
+pub async fn delete_offer(
+    offer_id: Uuid,
+    maker: &State<Maker>,
+    _user: User,
+) -> Result<(), HttpApiProblem> {
+    maker
+        .withdraw_offer(OfferId::from(offer_id))
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Withdrawing offer failed")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
 #[rocket::post("/cfd/<order_id>/<action>")]
 #[instrument(name = "POST /cfd/<order_id>/<action>", skip(maker, _user), err)]
 pub async fn post_cfd_action(
@@ -10,6 +10,8 @@ use crate::payout_curve::Payouts;
 use crate::payout_curve::ETHUSD_MULTIPLIER;
 use crate::rollover::BaseDlcParams;
 use crate::rollover::RolloverParams;
+use crate::shared_protocol::verify_adaptor_signature;
+use crate::shared_protocol::verify_signature;
 use crate::CompleteFee;
 use crate::ContractSymbol;
 use crate::Contracts;
@@ -23,6 +25,7 @@ use crate::OpeningFee;
 use crate::Percent;
 use crate::Position;
 use crate::Price;
+use crate::ProtocolVersion;
 use crate::Timestamp;
 use crate::TxFeeRate;
 use crate::SETTLEMENT_INTERVAL;
@@ -290,6 +293,10 @@ pub enum CannotSettleCollaboratively {
     Attested,
     #[error("The CFD is already closed")]
     Closed,
+    #[error("The lock transaction is not final yet")]
+    NotLocked,
+    #[error("The CFD is already being settled collaboratively")]
+    InCollaborativeSettlement,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -508,6 +515,10 @@ pub struct Cfd {
     opening_fee: OpeningFee,
     initial_tx_fee_rate: TxFeeRate,
     contract_symbol: ContractSymbol,
+    /// The version of the protocol with which the CFD was set up.
+    ///
+    /// This is `None` for CFDs set up before we started recording the protocol version.
+    protocol_version: Option<ProtocolVersion>,
     // dynamic (based on events)
     fee_account: FeeAccount,
 
@@ -563,6 +574,7 @@ impl Cfd {
         initial_funding_rate: FundingRate,
         initial_tx_fee_rate: TxFeeRate,
         contract_symbol: ContractSymbol,
+        protocol_version: Option<ProtocolVersion>,
     ) -> Self {
         let (long_leverage, short_leverage) =
             long_and_short_leverage(taker_leverage, role, position);
@@ -595,6 +607,7 @@ impl Cfd {
             opening_fee,
             initial_tx_fee_rate,
             contract_symbol,
+            protocol_version,
             dlc: None,
             cet: None,
             commit_tx: None,
@@ -625,6 +638,7 @@ impl Cfd {
         counterparty_peer_id: Option<PeerId>,
         role: Role,
         taker_leverage: Leverage,
+        protocol_version: ProtocolVersion,
     ) -> Self {
         let position = match role {
             Role::Maker => offer.position_maker,
@@ -646,6 +660,7 @@ impl Cfd {
             offer.funding_rate,
             offer.tx_fee_rate,
             offer.contract_symbol,
+            Some(protocol_version),
         )
     }
 
@@ -758,6 +773,27 @@ impl Cfd {
         Ok(())
     }
 
+    /// Whether we can propose to settle the CFD collaboratively right now.
+    ///
+    /// Meant to decide whether to offer settling a CFD, f.e. in the UI. The same check guards
+    /// [`Cfd::start_collab_settlement_taker`].
+    ///
+    /// An ongoing rollover does not prevent collaborative settlement. The settlement takes
+    /// priority and the rollover fails upon completion.
+    pub fn can_propose_collab_settlement(&self) -> Result<(), CannotSettleCollaboratively> {
+        self.can_settle_collaboratively()?;
+
+        if !self.lock_finality {
+            return Err(CannotSettleCollaboratively::NotLocked);
+        }
+
+        if self.is_in_collaborative_settlement() {
+            return Err(CannotSettleCollaboratively::InCollaborativeSettlement);
+        }
+
+        Ok(())
+    }
+
     fn is_attested(&self) -> bool {
         self.cet.is_some()
     }
@@ -1017,9 +1053,8 @@ impl Cfd {
         current_price: Price,
         n_payouts: usize,
     ) -> Result<(CfdEvent, SettlementTransaction, SettlementProposal)> {
-        ensure!(!self.is_in_collaborative_settlement());
         ensure!(self.role == Role::Taker);
-        self.can_settle_collaboratively()
+        self.can_propose_collab_settlement()
             .context("Cannot collaboratively settle")?;
 
         let (collab_settlement_tx, proposal) =
@@ -1479,6 +1514,10 @@ impl Cfd {
         self.contract_symbol
     }
 
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        self.protocol_version
+    }
+
     pub fn opening_fee(&self) -> OpeningFee {
         self.opening_fee
     }
@@ -1914,6 +1953,19 @@ pub struct Cet {
 }
 
 impl Cet {
+    /// The amounts paid out to the maker and the taker, in this order, if this CET is published.
+    pub fn payouts(&self) -> (Amount, Amount) {
+        (self.maker_amount, self.taker_amount)
+    }
+
+    /// The amount paid out to the party with the given `role` if this CET is published.
+    pub fn payout(&self, role: Role) -> Amount {
+        match role {
+            Role::Maker => self.maker_amount,
+            Role::Taker => self.taker_amount,
+        }
+    }
+
     /// Build an actual `Transaction` out of the payout information
     /// stored in `Self`, together with the input and the output
     /// addresses.
@@ -1923,6 +1975,21 @@ impl Cet {
     /// We verify that the TXID of the resulting transaction matches
     /// the TXID with which `Self` was constructed.
     pub fn to_tx(
+        &self,
+        commit: (&Transaction, &Descriptor<PublicKey>),
+        maker_address: &Address,
+        taker_address: &Address,
+    ) -> Result<Transaction> {
+        let tx = self.build_tx(commit, maker_address, taker_address)?;
+
+        if tx.txid() != self.txid {
+            bail!("Reconstructed wrong CET");
+        }
+
+        Ok(tx)
+    }
+
+    fn build_tx(
         &self,
         (commit_tx, commit_descriptor): (&Transaction, &Descriptor<PublicKey>),
         maker_address: &Address,
@@ -1953,10 +2020,6 @@ impl Cet {
             output,
         };
 
-        if tx.txid() != self.txid {
-            bail!("Reconstructed wrong CET");
-        }
-
         Ok(tx)
     }
 }
@@ -1997,6 +2060,51 @@ pub struct Dlc {
     pub refund_timelock: u32,
 }
 
+/// The highest fee we accept for a commit transaction, see [`Dlc::verify_commit_fee`].
+///
+/// Commit transactions spend a single output into a single output, thus even at extreme fee rates
+/// their fee stays well below this.
+pub const MAX_COMMIT_TX_FEE_SAT: u64 = 100_000;
+
+/// Magic bytes identifying a [`Dlc`] backup.
+const DLC_BACKUP_MAGIC: &[u8; 6] = b"ISDLCB";
+
+/// Version of the [`Dlc`] backup format produced by [`Dlc::to_backup`].
+const DLC_BACKUP_VERSION: u8 = 1;
+
+impl Dlc {
+    /// Serialize the DLC into a portable, versioned backup.
+    ///
+    /// The backup consists of the magic bytes `ISDLCB`, a single version byte and the DLC
+    /// encoded as JSON.
+    ///
+    /// **The backup is sensitive**: it contains our identity, revocation and publication secret
+    /// keys. Anybody in possession of it can sign on our behalf for this CFD, so it must be
+    /// stored with the same care as the wallet seed.
+    pub fn to_backup(&self) -> Result<Vec<u8>> {
+        let mut backup = Vec::from(&DLC_BACKUP_MAGIC[..]);
+        backup.push(DLC_BACKUP_VERSION);
+        serde_json::to_writer(&mut backup, self).context("Failed to serialize DLC")?;
+
+        Ok(backup)
+    }
+
+    /// Deserialize a DLC from a backup produced by [`Dlc::to_backup`].
+    pub fn from_backup(backup: &[u8]) -> Result<Self> {
+        let payload = backup
+            .strip_prefix(&DLC_BACKUP_MAGIC[..])
+            .context("Not a DLC backup")?;
+        let (version, payload) = payload.split_first().context("DLC backup is truncated")?;
+
+        match *version {
+            DLC_BACKUP_VERSION => {
+                serde_json::from_slice(payload).context("Failed to deserialize DLC backup")
+            }
+            version => bail!("Unsupported DLC backup version {version}"),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SettlementTransaction {
     lock_desc: Descriptor<PublicKey>,
@@ -2180,6 +2288,166 @@ impl Dlc {
         Ok(signed_refund_tx)
     }
 
+    /// Verify that the counterparty's refund signature is valid for the refund transaction.
+    ///
+    /// The refund transaction is only ever signed and published if the oracle fails to attest,
+    /// which is the worst time to find out that the stored signature is unusable.
+    pub fn verify_refund(&self) -> Result<()> {
+        verify_signature(
+            &self.refund.0,
+            &self.commit.2,
+            Amount::from_sat(self.commit.0.output[0].value),
+            &self.refund.1,
+            &self.identity_counterparty,
+        )
+        .with_context(|| {
+            format!(
+                "Counterparty refund signature does not verify for refund transaction {}",
+                self.refund.0.txid()
+            )
+        })
+    }
+
+    /// Verify that the lock and commit descriptors correspond to the keys of the DLC.
+    ///
+    /// The descriptors are derived from the keys of both parties, in the order given by our
+    /// `role`. If the two ever diverge we would only find out when trying to sign.
+    pub fn verify_descriptors(&self, role: Role) -> Result<()> {
+        let to_public_key = |sk: &SecretKey| {
+            bitcoin::PublicKey::new(secp256k1_zkp::PublicKey::from_secret_key(SECP256K1, sk))
+        };
+
+        let own = (
+            to_public_key(&self.identity),
+            to_public_key(&self.revocation),
+            to_public_key(&self.publish),
+        );
+        let counterparty = (
+            self.identity_counterparty,
+            self.revocation_pk_counterparty,
+            self.publish_pk_counterparty,
+        );
+
+        let (maker, taker) = match role {
+            Role::Maker => (own, counterparty),
+            Role::Taker => (counterparty, own),
+        };
+
+        let expected_lock_descriptor = maia::lock_descriptor(maker.0, taker.0);
+        let lock_descriptor = &self.lock.1;
+        ensure!(
+            lock_descriptor == &expected_lock_descriptor,
+            "Lock descriptor {lock_descriptor} does not match {expected_lock_descriptor} derived \
+             from identity keys"
+        );
+
+        let expected_commit_descriptor = maia::commit_descriptor(maker, taker);
+        let commit_descriptor = &self.commit.2;
+        ensure!(
+            commit_descriptor == &expected_commit_descriptor,
+            "Commit descriptor {commit_descriptor} does not match {expected_commit_descriptor} \
+             derived from identity, revocation and publish keys"
+        );
+
+        Ok(())
+    }
+
+    /// Run all consistency checks on the DLC which do not depend on the oracle.
+    ///
+    /// Meant to be run whenever a DLC is created during contract setup or rollover, to reject a
+    /// bogus DLC before it is persisted.
+    pub fn verify(&self, role: Role) -> Result<()> {
+        self.verify_descriptors(role)?;
+        self.verify_refund()?;
+        self.verify_commit_fee()?;
+        self.verify_revoked_commits()?;
+
+        Ok(())
+    }
+
+    /// Verify all revoked commit transactions of the DLC, see [`RevokedCommit::verify`].
+    pub fn verify_revoked_commits(&self) -> Result<()> {
+        if self.revoked_commit.is_empty() {
+            return Ok(());
+        }
+
+        let (lock_tx, lock_desc) = &self.lock;
+        let lock_outpoint = lock_tx
+            .outpoint(&lock_desc.script_pubkey())
+            .context("Lock script not in lock transaction")?;
+        let lock_amount = Amount::from_sat(lock_tx.output[lock_outpoint.vout as usize].value);
+
+        for revoked_commit in self.revoked_commit.iter() {
+            revoked_commit.verify(lock_desc, lock_amount, &self.identity_counterparty)?;
+        }
+
+        Ok(())
+    }
+
+    /// The fee paid by the commit transaction: the value of the lock output it spends minus the
+    /// value of its outputs.
+    ///
+    /// Negative if the commit transaction's outputs are worth more than the lock output.
+    pub fn commit_fee(&self) -> Result<SignedAmount> {
+        let (lock_tx, _) = &self.lock;
+        let (commit_tx, _, _) = &self.commit;
+
+        let spent_outpoint = commit_tx
+            .input
+            .first()
+            .context("Commit transaction has no inputs")?
+            .previous_output;
+        ensure!(
+            spent_outpoint.txid == lock_tx.txid(),
+            "Commit transaction {} does not spend lock transaction {}",
+            commit_tx.txid(),
+            lock_tx.txid()
+        );
+        let lock_amount = lock_tx
+            .output
+            .get(spent_outpoint.vout as usize)
+            .context("Commit transaction spends non-existent lock output")?
+            .value;
+        let commit_amount = self
+            .commit
+            .0
+            .output
+            .iter()
+            .try_fold(0u64, |sum, output| sum.checked_add(output.value))
+            .context("Sum of commit transaction outputs overflows")?;
+
+        let fee = i128::from(lock_amount) - i128::from(commit_amount);
+        let fee = i64::try_from(fee).context("Commit transaction fee out of range")?;
+
+        Ok(SignedAmount::from_sat(fee))
+    }
+
+    /// Verify that the commit transaction pays a sane fee.
+    ///
+    /// A negative fee or one above [`MAX_COMMIT_TX_FEE_SAT`] means that the commit transaction is
+    /// corrupted or that the counterparty tricked us into signing a bogus one during contract
+    /// setup or rollover.
+    pub fn verify_commit_fee(&self) -> Result<()> {
+        let fee = self.commit_fee()?;
+
+        if fee.is_negative() {
+            bail!(
+                "Commit transaction {} has negative fee {fee}",
+                self.commit.0.txid()
+            );
+        }
+
+        let max_fee = Amount::from_sat(MAX_COMMIT_TX_FEE_SAT);
+        if fee > max_fee.to_signed()? {
+            bail!(
+                "Commit transaction {} has fee {fee} above maximum {max_fee}",
+                self.commit.0.txid()
+            );
+        }
+
+        Ok(())
+    }
+
     pub fn signed_commit_tx(&self) -> Result<Transaction> {
         let sig_hash = spending_tx_sighash(
             &self.commit.0,
@@ -2209,8 +2477,62 @@ impl Dlc {
         &self,
         attestation: &olivia::Attestation,
     ) -> Result<Transaction, SignCetError> {
+        let (cet, tx, sig_hash) = self.verified_settlement_cet(attestation)?;
+        let decryption_sk = decryption_sk(attestation, cet.n_bits)?;
+
+        let our_sig = SECP256K1.sign_ecdsa(&sig_hash, &self.identity);
+        let our_pubkey = bitcoin::util::key::PublicKey::new(
+            bdk::bitcoin::secp256k1::PublicKey::from_secret_key(SECP256K1, &self.identity),
+        );
+
+        let counterparty_sig = cet
+            .adaptor_sig
+            .decrypt(&decryption_sk)
+            .context("Failed to decrypt counterparty CET encsig")?;
+        let counterparty_pubkey = self.identity_counterparty;
+
+        let signed_cet = maia::finalize_spend_transaction(
+            tx,
+            &self.commit.2,
+            (our_pubkey, our_sig),
+            (counterparty_pubkey, counterparty_sig),
+        )?;
+
+        Ok(signed_cet)
+    }
+
+    /// Select the CET which settles the DLC according to the oracle's `attestation`.
+    ///
+    /// The attested price must fall within the range of exactly one of the CETs for the attested
+    /// event and the counterparty's adaptor signature on that CET must be valid, encrypted under
+    /// the attestation.
+    pub fn settlement_cet(&self, attestation: &olivia::Attestation) -> Result<Cet, SignCetError> {
+        let (cet, _, _) = self.verified_settlement_cet(attestation)?;
+
+        Ok(cet)
+    }
+
+    /// The amount the party with the given `role` receives when settling with the CET selected
+    /// by the `attestation`.
+    ///
+    /// See [`Dlc::settlement_cet`] for how the CET is selected.
+    pub fn settlement_payout(
+        &self,
+        attestation: &olivia::Attestation,
+        role: Role,
+    ) -> Result<Amount, SignCetError> {
+        let cet = self.settlement_cet(attestation)?;
+
+        Ok(cet.payout(role))
+    }
+
+    /// Select the CET for the `attestation` and verify the counterparty's adaptor signature on
+    /// it, returning the CET alongside its transaction and sighash.
+    fn verified_settlement_cet(
+        &self,
+        attestation: &olivia::Attestation,
+    ) -> Result<(Cet, Transaction, secp256k1_zkp::Message), SignCetError> {
         let event_id = attestation.id;
-        let price = attestation.price;
         let cets = self
             .cets
             .get(&event_id)
@@ -2219,23 +2541,9 @@ impl Dlc {
                 txid: self.lock.0.txid(),
             })?;
 
-        let cet = cets
-            .iter()
-            .find(|Cet { range, .. }| range.contains(&price))
-            .ok_or(SignCetError::PriceOutOfRange {
-                id: event_id,
-                price,
-            })?;
-        let encsig = cet.adaptor_sig;
-
-        let mut decryption_sk = attestation.scalars[0];
-        for oracle_attestation in attestation.scalars[1..cet.n_bits].iter() {
-            decryption_sk
-                .add_assign(oracle_attestation.as_ref())
-                .context("Failed to construct decryption sk")?;
-        }
+        let cet = select_cet_by_price(cets, attestation)?;
 
-        let cet = cet
+        let tx = cet
             .to_tx(
                 (&self.commit.0, &self.commit.2),
                 &self.maker_address,
@@ -2244,29 +2552,24 @@ impl Dlc {
             .context("Failed to reconstruct CET")?;
 
         let sig_hash = spending_tx_sighash(
-            &cet,
+            &tx,
             &self.commit.2,
             Amount::from_sat(self.commit.0.output[0].value),
         )
         .context("could not obtain sighash")?;
-        let our_sig = SECP256K1.sign_ecdsa(&sig_hash, &self.identity);
-        let our_pubkey = bitcoin::util::key::PublicKey::new(
-            bdk::bitcoin::secp256k1::PublicKey::from_secret_key(SECP256K1, &self.identity),
-        );
-
-        let counterparty_sig = encsig
-            .decrypt(&decryption_sk)
-            .context("Failed to decrypt counterparty CET encsig")?;
-        let counterparty_pubkey = self.identity_counterparty;
 
-        let signed_cet = maia::finalize_spend_transaction(
-            cet,
-            &self.commit.2,
-            (our_pubkey, our_sig),
-            (counterparty_pubkey, counterparty_sig),
-        )?;
+        let decryption_sk = decryption_sk(attestation, cet.n_bits)?;
+        let encryption_pk = secp256k1_zkp::PublicKey::from_secret_key(SECP256K1, &decryption_sk);
+        cet.adaptor_sig
+            .verify(
+                SECP256K1,
+                &sig_hash,
+                &self.identity_counterparty.inner,
+                &encryption_pk,
+            )
+            .context("Counterparty CET adaptor signature is not valid under the attestation")?;
 
-        Ok(signed_cet)
+        Ok((cet, tx, sig_hash))
     }
 
     /// All the oracle event IDs associated with the DLC.
@@ -2351,10 +2654,72 @@ pub enum SignCetError {
     IrrelevantAttestation { id: BitMexPriceEventId, txid: Txid },
     #[error("Attested price {price} is not in range of any CETs for event {id}")]
     PriceOutOfRange { id: BitMexPriceEventId, price: u64 },
+    #[error("Attested price {price} is in range of {n_cets} CETs for event {id}")]
+    AmbiguousPrice {
+        id: BitMexPriceEventId,
+        price: u64,
+        n_cets: usize,
+    },
     #[error("{0}")]
     Other(#[from] anyhow::Error),
 }
 
+/// Select the one CET out of `cets` whose range contains the attested
+/// price.
+///
+/// This does not verify the CET's adaptor signature, see
+/// [`Dlc::settlement_cet`] for that.
+fn select_cet_by_price(
+    cets: &[Cet],
+    attestation: &olivia::Attestation,
+) -> Result<Cet, SignCetError> {
+    let id = attestation.id;
+    let price = attestation.price;
+
+    let matching_cets = cets
+        .iter()
+        .filter(|Cet { range, .. }| range.contains(&price))
+        .collect_vec();
+
+    match matching_cets.as_slice() {
+        [cet] => Ok((*cet).clone()),
+        [] => Err(SignCetError::PriceOutOfRange { id, price }),
+        _ => Err(SignCetError::AmbiguousPrice {
+            id,
+            price,
+            n_cets: matching_cets.len(),
+        }),
+    }
+}
+
+/// Combine the first `n_bits` attested scalars into the secret key
+/// which decrypts a CET's adaptor signature.
+fn decryption_sk(
+    attestation: &olivia::Attestation,
+    n_bits: usize,
+) -> Result<secp256k1_zkp::SecretKey> {
+    let (first, rest) = attestation
+        .scalars
+        .get(..n_bits)
+        .and_then(|scalars| scalars.split_first())
+        .with_context(|| {
+            format!(
+                "Attestation for {} has {} scalars, need {n_bits}",
+                attestation.id,
+                attestation.scalars.len()
+            )
+        })?;
+
+    let mut decryption_sk = *first;
+    for oracle_attestation in rest {
+        decryption_sk
+            .add_assign(oracle_attestation.as_ref())
+            .context("Failed to construct decryption sk")?;
+    }
+
+    Ok(decryption_sk)
+}
+
 /// Information which we need to remember in order to construct a
 /// punishment transaction in case the counterparty publishes a
 /// revoked commit transaction.
@@ -2391,6 +2756,86 @@ pub struct RevokedCommit {
     /// Used by the maker to determine the fees when a taker triggers a rollover from a previous
     /// commit-txid.
     pub complete_fee: Option<CompleteFee>,
+
+    /// The revoked commit transaction, which `encsig_ours` was produced over
+    ///
+    /// Used to verify `encsig_ours`. `None` for commit transactions revoked before this was
+    /// recorded.
+    #[serde(default)]
+    pub tx: Option<Transaction>,
+    /// Our publication key for the revoked commit transaction, which `encsig_ours` is encrypted
+    /// under
+    #[serde(default)]
+    pub publication_pk_ours: Option<PublicKey>,
+    /// The revocation key the counterparty committed to before revealing `revocation_sk_theirs`
+    #[serde(default)]
+    pub revocation_pk_theirs: Option<PublicKey>,
+}
+
+impl RevokedCommit {
+    /// Check that the revocation secret and the adaptor signature of the revoked commit
+    /// transaction can be used to punish the counterparty.
+    ///
+    /// The revocation secret has to be the secret of the revocation key the counterparty
+    /// committed to, and `encsig_ours` has to be the counterparty's adaptor signature over the
+    /// commit transaction spending the lock output described by `lock_descriptor` and
+    /// `lock_amount`, encrypted under our publication key. Otherwise we would only find out when
+    /// attempting to punish the counterparty.
+    ///
+    /// Commit transactions revoked before the revocation key, the commit transaction and our
+    /// publication key were recorded only get the checks the remaining data allows.
+    pub fn verify(
+        &self,
+        lock_descriptor: &Descriptor<PublicKey>,
+        lock_amount: Amount,
+        identity_pk_theirs: &PublicKey,
+    ) -> Result<()> {
+        let txid = self.txid;
+        let derived_revocation_pk_theirs = PublicKey::new(
+            secp256k1_zkp::PublicKey::from_secret_key(SECP256K1, &self.revocation_sk_theirs),
+        );
+
+        ensure!(
+            derived_revocation_pk_theirs != self.publication_pk_theirs,
+            "Revocation secret of revoked commit {txid} is the secret of their publication key"
+        );
+
+        if let Some(revocation_pk_theirs) = self.revocation_pk_theirs {
+            ensure!(
+                derived_revocation_pk_theirs == revocation_pk_theirs,
+                "Revocation secret of revoked commit {txid} does not match their revocation key"
+            );
+        }
+
+        if let Some(revocation_sk_ours) = self.revocation_sk_ours {
+            ensure!(
+                revocation_sk_ours != self.revocation_sk_theirs,
+                "Revocation secret of revoked commit {txid} is our own revocation secret"
+            );
+        }
+
+        if let (Some(tx), Some(publication_pk_ours)) = (&self.tx, &self.publication_pk_ours) {
+            ensure!(
+                tx.txid() == txid,
+                "Stored transaction of revoked commit {txid} has TXID {}",
+                tx.txid()
+            );
+
+            verify_adaptor_signature(
+                tx,
+                lock_descriptor,
+                lock_amount,
+                &self.encsig_ours,
+                publication_pk_ours,
+                identity_pk_theirs,
+            )
+            .with_context(|| {
+                format!("Adaptor signature of revoked commit {txid} does not verify")
+            })?;
+        }
+
+        Ok(())
+    }
 }
 
 /// Used when transactions (e.g. collaborative close) are recorded as a part of
@@ -3294,6 +3739,140 @@ mod tests {
         assert_eq!(maker_event.event, EventKind::LockConfirmedAfterFinality);
     }
 
+    #[test]
+    fn given_open_cfd_then_can_propose_collab_settlement() {
+        let cfd = Cfd::dummy_taker_long().dummy_open(dummy_event_id());
+
+        assert_eq!(cfd.can_propose_collab_settlement(), Ok(()));
+    }
+
+    #[test]
+    fn given_lock_not_final_then_cannot_propose_collab_settlement() {
+        let cfd = Cfd::dummy_not_open_yet();
+
+        assert_eq!(
+            cfd.can_propose_collab_settlement(),
+            Err(CannotSettleCollaboratively::NotLocked)
+        );
+    }
+
+    #[test]
+    fn given_ongoing_collab_settlement_then_cannot_propose_collab_settlement() {
+        let cfd = Cfd::dummy_taker_long()
+            .dummy_open(dummy_event_id())
+            .dummy_start_collab_settlement();
+
+        assert_eq!(
+            cfd.can_propose_collab_settlement(),
+            Err(CannotSettleCollaboratively::InCollaborativeSettlement)
+        );
+    }
+
+    #[test]
+    fn given_ongoing_rollover_then_can_propose_collab_settlement() {
+        let cfd = Cfd::dummy_taker_long()
+            .dummy_open(dummy_event_id())
+            .dummy_start_rollover();
+
+        assert_eq!(cfd.can_propose_collab_settlement(), Ok(()));
+    }
+
+    #[test]
+    fn dlc_backup_roundtrip() {
+        let dlc = Cfd::dummy_taker_long()
+            .dummy_open(dummy_event_id())
+            .dlc
+            .unwrap();
+
+        let backup = dlc.to_backup().unwrap();
+        let restored = Dlc::from_backup(&backup).unwrap();
+
+        assert_eq!(restored, dlc);
+    }
+
+    #[test]
+    fn given_unknown_backup_version_then_cannot_restore_dlc() {
+        let dlc = Cfd::dummy_taker_long()
+            .dummy_open(dummy_event_id())
+            .dlc
+            .unwrap();
+
+        let mut backup = dlc.to_backup().unwrap();
+        backup[DLC_BACKUP_MAGIC.len()] = DLC_BACKUP_VERSION + 1;
+
+        assert!(Dlc::from_backup(&backup).is_err());
+    }
+
+    #[test]
+    fn commit_fee_is_lock_output_minus_commit_outputs() {
+        let dlc = Dlc::dummy(None).with_commit_fee(150);
+
+        assert_eq!(dlc.commit_fee().unwrap(), SignedAmount::from_sat(150));
+        assert!(dlc.verify_commit_fee().is_ok());
+    }
+
+    #[test]
+    fn given_change_output_before_lock_output_then_commit_fee_computed_from_spent_output() {
+        let mut dlc = Dlc::dummy(None).with_commit_fee(150);
+        dlc.lock.0.output.insert(
+            0,
+            TxOut {
+                value: 1_000,
+                script_pubkey: Script::new(),
+            },
+        );
+        dlc.commit.0.input[0].previous_output =
+            dlc.lock.0.outpoint(&dlc.lock.1.script_pubkey()).unwrap();
+
+        assert_eq!(dlc.commit_fee().unwrap(), SignedAmount::from_sat(150));
+        assert!(dlc.verify_commit_fee().is_ok());
+    }
+
+    #[test]
+    fn given_commit_tx_not_spending_lock_tx_then_commit_fee_fails() {
+        let mut dlc = Dlc::dummy(None).with_commit_fee(150);
+        dlc.commit.0.input[0].previous_output = bitcoin::OutPoint::default();
+
+        assert!(dlc.commit_fee().is_err());
+    }
+
+    #[test]
+    fn given_descriptors_not_derived_from_keys_then_verification_fails() {
+        let dlc = Dlc::dummy(None);
+
+        let error = dlc.verify_descriptors(Role::Maker).unwrap_err();
+
+        assert!(error.to_string().contains("Lock descriptor"));
+        assert!(dlc.verify(Role::Maker).is_err());
+    }
+
+    #[test]
+    fn given_negative_commit_fee_then_verification_fails() {
+        let dlc = Dlc::dummy(None).with_commit_fee(-1);
+
+        assert_eq!(dlc.commit_fee().unwrap(), SignedAmount::from_sat(-1));
+        assert!(dlc.verify_commit_fee().is_err());
+    }
+
+    #[test]
+    fn given_commit_fee_above_maximum_then_verification_fails() {
+        let dlc = Dlc::dummy(None).with_commit_fee(MAX_COMMIT_TX_FEE_SAT as i64 + 1);
+
+        assert!(dlc.verify_commit_fee().is_err());
+    }
+
+    #[test]
+    fn given_no_magic_bytes_then_cannot_restore_dlc() {
+        let dlc = Cfd::dummy_taker_long()
+            .dummy_open(dummy_event_id())
+            .dlc
+            .unwrap();
+
+        let backup = serde_json::to_vec(&dlc).unwrap();
+
+        assert!(Dlc::from_backup(&backup).is_err());
+    }
+
     #[test]
     fn given_commit_then_cannot_collab_close() {
         let taker_keys = new_keypair();
@@ -3874,6 +4453,305 @@ mod tests {
         assert!(!sane, "an oracle event id that is outdated got accepted")
     }
 
+    #[test]
+    fn given_price_in_range_of_one_cet_then_cet_selected_by_price() {
+        let cets = vec![Cet::dummy(0..=9, 1), Cet::dummy(10..=19, 1)];
+        let attestation = olivia::Attestation::dummy_with_price(15, 1);
+
+        let cet = select_cet_by_price(&cets, &attestation).unwrap();
+
+        assert_eq!(cet, cets[1]);
+    }
+
+    #[test]
+    fn given_price_not_in_range_of_any_cet_then_no_cet_selected_by_price() {
+        let cets = vec![Cet::dummy(0..=9, 1), Cet::dummy(10..=19, 1)];
+        let attestation = olivia::Attestation::dummy_with_price(20, 1);
+
+        let error = select_cet_by_price(&cets, &attestation).unwrap_err();
+
+        assert!(matches!(
+            error,
+            SignCetError::PriceOutOfRange { price: 20, .. }
+        ));
+    }
+
+    #[test]
+    fn given_price_in_range_of_several_cets_then_no_cet_selected_by_price() {
+        let cets = vec![Cet::dummy(0..=10, 1), Cet::dummy(10..=19, 1)];
+        let attestation = olivia::Attestation::dummy_with_price(10, 1);
+
+        let error = select_cet_by_price(&cets, &attestation).unwrap_err();
+
+        assert!(matches!(
+            error,
+            SignCetError::AmbiguousPrice {
+                price: 10,
+                n_cets: 2,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn given_valid_adaptor_signature_then_settlement_cet_selected() {
+        let (counterparty_sk, _) = new_keypair();
+        let attestation = olivia::Attestation::dummy_with_price(15, 1);
+        let dlc = Dlc::dummy(None).with_attested_cets(
+            counterparty_sk,
+            &attestation,
+            vec![
+                (0..=9, Amount::from_sat(900), Amount::from_sat(100)),
+                (10..=19, Amount::from_sat(300), Amount::from_sat(700)),
+            ],
+        );
+
+        let cet = dlc.settlement_cet(&attestation).unwrap();
+
+        assert_eq!(cet, dlc.cets[&attestation.id][1]);
+    }
+
+    #[test]
+    fn given_adaptor_signature_over_different_cet_then_no_settlement_cet() {
+        let (counterparty_sk, _) = new_keypair();
+        let attestation = olivia::Attestation::dummy_with_price(15, 1);
+        let mut dlc = Dlc::dummy(None).with_attested_cets(
+            counterparty_sk,
+            &attestation,
+            vec![
+                (0..=9, Amount::from_sat(900), Amount::from_sat(100)),
+                (10..=19, Amount::from_sat(300), Amount::from_sat(700)),
+            ],
+        );
+        let cets = dlc.cets.get_mut(&attestation.id).unwrap();
+        cets[1].adaptor_sig = cets[0].adaptor_sig;
+
+        let error = dlc.settlement_cet(&attestation).unwrap_err();
+
+        assert!(matches!(error, SignCetError::Other(_)));
+    }
+
+    #[test]
+    fn given_adaptor_signature_under_different_attestation_then_no_settlement_cet() {
+        let (counterparty_sk, _) = new_keypair();
+        let attestation = olivia::Attestation::dummy_with_price(5, 1);
+        let dlc = Dlc::dummy(None).with_attested_cets(
+            counterparty_sk,
+            &attestation,
+            vec![(0..=9, Amount::from_sat(900), Amount::from_sat(100))],
+        );
+
+        let forged = olivia::Attestation {
+            id: attestation.id,
+            ..olivia::Attestation::dummy_with_price(5, 1)
+        };
+        let error = dlc.settlement_cet(&forged).unwrap_err();
+
+        assert!(matches!(error, SignCetError::Other(_)));
+    }
+
+    #[test]
+    fn given_price_in_range_of_one_cet_then_payout_of_that_cet_for_role() {
+        let (counterparty_sk, _) = new_keypair();
+        let attestation = olivia::Attestation::dummy_with_price(15, 1);
+        let dlc = Dlc::dummy(None).with_attested_cets(
+            counterparty_sk,
+            &attestation,
+            vec![
+                (0..=9, Amount::from_sat(900), Amount::from_sat(100)),
+                (10..=19, Amount::from_sat(300), Amount::from_sat(700)),
+            ],
+        );
+
+        let maker_payout = dlc.settlement_payout(&attestation, Role::Maker).unwrap();
+        let taker_payout = dlc.settlement_payout(&attestation, Role::Taker).unwrap();
+
+        assert_eq!(maker_payout, Amount::from_sat(300));
+        assert_eq!(taker_payout, Amount::from_sat(700));
+    }
+
+    #[test]
+    fn given_price_not_in_range_of_any_cet_then_no_settlement_payout() {
+        let (counterparty_sk, _) = new_keypair();
+        let attestation = olivia::Attestation::dummy_with_price(20, 1);
+        let dlc = Dlc::dummy(None).with_attested_cets(
+            counterparty_sk,
+            &attestation,
+            vec![(0..=9, Amount::from_sat(900), Amount::from_sat(100))],
+        );
+
+        let error = dlc
+            .settlement_payout(&attestation, Role::Taker)
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            SignCetError::PriceOutOfRange { price: 20, .. }
+        ));
+    }
+
+    #[test]
+    fn given_attestation_with_too_few_scalars_then_no_settlement_cet() {
+        let (counterparty_sk, _) = new_keypair();
+        let attestation = olivia::Attestation::dummy_with_price(5, 2);
+        let dlc = Dlc::dummy(None).with_attested_cets(
+            counterparty_sk,
+            &attestation,
+            vec![(0..=9, Amount::from_sat(900), Amount::from_sat(100))],
+        );
+
+        let truncated = olivia::Attestation {
+            scalars: attestation.scalars[..1].to_vec(),
+            ..attestation
+        };
+        let error = dlc.settlement_cet(&truncated).unwrap_err();
+
+        assert!(matches!(error, SignCetError::Other(_)));
+    }
+
+    #[test]
+    fn given_distinct_revocation_and_publication_keys_then_revoked_commit_verifies() {
+        let (_, publication_pk_theirs) = new_keypair();
+        let (revocation_sk_theirs, _) = new_keypair();
+        let (revocation_sk_ours, _) = new_keypair();
+
+        let revoked_commit = RevokedCommit::dummy(
+            revocation_sk_theirs,
+            publication_pk_theirs,
+            Some(revocation_sk_ours),
+        );
+
+        let (lock_descriptor, identity_pk_theirs) = dummy_lock();
+        revoked_commit
+            .verify(&lock_descriptor, Amount::ONE_BTC, &identity_pk_theirs)
+            .unwrap();
+    }
+
+    #[test]
+    fn given_revocation_secret_of_publication_key_then_revoked_commit_does_not_verify() {
+        let (sk, pk) = new_keypair();
+
+        let revoked_commit = RevokedCommit::dummy(sk, pk, None);
+
+        let (lock_descriptor, identity_pk_theirs) = dummy_lock();
+        let error = revoked_commit
+            .verify(&lock_descriptor, Amount::ONE_BTC, &identity_pk_theirs)
+            .unwrap_err();
+        assert!(error.to_string().contains(&revoked_commit.txid.to_string()));
+    }
+
+    #[test]
+    fn given_our_own_revocation_secret_then_revoked_commit_does_not_verify() {
+        let (_, publication_pk_theirs) = new_keypair();
+        let (revocation_sk, _) = new_keypair();
+
+        let revoked_commit =
+            RevokedCommit::dummy(revocation_sk, publication_pk_theirs, Some(revocation_sk));
+
+        let (lock_descriptor, identity_pk_theirs) = dummy_lock();
+        assert!(revoked_commit
+            .verify(&lock_descriptor, Amount::ONE_BTC, &identity_pk_theirs)
+            .is_err());
+    }
+
+    #[test]
+    fn given_revocation_secret_of_different_revocation_key_then_revoked_commit_does_not_verify() {
+        let (_, publication_pk_theirs) = new_keypair();
+        let (revocation_sk_theirs, _) = new_keypair();
+        let (_, other_revocation_pk_theirs) = new_keypair();
+
+        let revoked_commit = RevokedCommit {
+            revocation_pk_theirs: Some(other_revocation_pk_theirs),
+            ..RevokedCommit::dummy(revocation_sk_theirs, publication_pk_theirs, None)
+        };
+
+        let (lock_descriptor, identity_pk_theirs) = dummy_lock();
+        assert!(revoked_commit
+            .verify(&lock_descriptor, Amount::ONE_BTC, &identity_pk_theirs)
+            .is_err());
+    }
+
+    #[test]
+    fn given_adaptor_signature_over_commit_tx_then_revoked_commit_verifies() {
+        let (_, publication_pk_theirs) = new_keypair();
+        let (revocation_sk_theirs, revocation_pk_theirs) = new_keypair();
+        let (identity_sk_theirs, identity_pk_theirs) = new_keypair();
+        let (_, publication_pk_ours) = new_keypair();
+        let (lock_descriptor, _) = dummy_lock();
+
+        let revoked_commit = RevokedCommit {
+            revocation_pk_theirs: Some(revocation_pk_theirs),
+            ..RevokedCommit::dummy(revocation_sk_theirs, publication_pk_theirs, None)
+        }
+        .with_commit_tx(
+            dummy_commit_tx(0),
+            &lock_descriptor,
+            identity_sk_theirs,
+            publication_pk_ours,
+        );
+
+        revoked_commit
+            .verify(&lock_descriptor, Amount::ONE_BTC, &identity_pk_theirs)
+            .unwrap();
+    }
+
+    #[test]
+    fn given_adaptor_signature_over_different_commit_tx_then_revoked_commit_does_not_verify() {
+        let (_, publication_pk_theirs) = new_keypair();
+        let (revocation_sk_theirs, _) = new_keypair();
+        let (identity_sk_theirs, identity_pk_theirs) = new_keypair();
+        let (_, publication_pk_ours) = new_keypair();
+        let (lock_descriptor, _) = dummy_lock();
+
+        let other = RevokedCommit::dummy(revocation_sk_theirs, publication_pk_theirs, None)
+            .with_commit_tx(
+                dummy_commit_tx(1),
+                &lock_descriptor,
+                identity_sk_theirs,
+                publication_pk_ours,
+            );
+        let revoked_commit = RevokedCommit {
+            encsig_ours: other.encsig_ours,
+            ..RevokedCommit::dummy(revocation_sk_theirs, publication_pk_theirs, None)
+                .with_commit_tx(
+                    dummy_commit_tx(0),
+                    &lock_descriptor,
+                    identity_sk_theirs,
+                    publication_pk_ours,
+                )
+        };
+
+        let error = revoked_commit
+            .verify(&lock_descriptor, Amount::ONE_BTC, &identity_pk_theirs)
+            .unwrap_err();
+        assert!(error.to_string().contains(&revoked_commit.txid.to_string()));
+    }
+
+    /// A lock descriptor and the counterparty's identity key within it.
+    fn dummy_lock() -> (Descriptor<PublicKey>, PublicKey) {
+        let (_, identity_pk_ours) = new_keypair();
+        let (_, identity_pk_theirs) = new_keypair();
+
+        (
+            lock_descriptor(identity_pk_ours, identity_pk_theirs),
+            identity_pk_theirs,
+        )
+    }
+
+    /// A transaction spending the first output of some lock transaction, distinguished by
+    /// `fee_sat`.
+    fn dummy_commit_tx(fee_sat: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: Amount::ONE_BTC.as_sat() - 1_000 - fee_sat,
+                script_pubkey: Script::new(),
+            }],
+        }
+    }
+
     impl CfdEvent {
         fn dummy_open(event_id: BitMexPriceEventId) -> Vec<Self> {
             vec![
@@ -3989,6 +4867,7 @@ mod tests {
                 dummy_peer_id(),
                 Role::Taker,
                 leverage,
+                dummy_protocol_version(),
             )
         }
 
@@ -4001,6 +4880,7 @@ mod tests {
                 dummy_peer_id(),
                 Role::Maker,
                 leverage,
+                dummy_protocol_version(),
             )
         }
 
@@ -4013,6 +4893,7 @@ mod tests {
                 dummy_peer_id(),
                 Role::Taker,
                 Leverage::TWO,
+                dummy_protocol_version(),
             )
         }
 
@@ -4025,6 +4906,7 @@ mod tests {
                 dummy_peer_id(),
                 Role::Maker,
                 Leverage::TWO,
+                dummy_protocol_version(),
             )
         }
 
@@ -4037,6 +4919,7 @@ mod tests {
                 dummy_peer_id(),
                 Role::Taker,
                 Leverage::TWO,
+                dummy_protocol_version(),
             )
         }
 
@@ -4218,6 +5101,7 @@ mod tests {
                 dummy_peer_id(),
                 Role::Taker,
                 Leverage::TWO,
+                dummy_protocol_version(),
             );
 
             CfdEvent::dummy_attestation_prior_timelock(event_id)
@@ -4235,6 +5119,7 @@ mod tests {
                 dummy_peer_id(),
                 Role::Taker,
                 Leverage::TWO,
+                dummy_protocol_version(),
             );
 
             CfdEvent::dummy_final_cet(event_id)
@@ -4306,6 +5191,82 @@ mod tests {
     }
 
     impl Dlc {
+        /// Make the commit transaction spend a lock output of 1 BTC into a single output, paying
+        /// `fee_sat`.
+        fn with_commit_fee(mut self, fee_sat: i64) -> Self {
+            let lock_amount = Amount::ONE_BTC.as_sat();
+
+            self.lock.0.output = vec![TxOut {
+                value: lock_amount,
+                script_pubkey: self.lock.1.script_pubkey(),
+            }];
+            self.commit.0.input = vec![TxIn {
+                previous_output: self.lock.0.outpoint(&self.lock.1.script_pubkey()).unwrap(),
+                ..Default::default()
+            }];
+            self.commit.0.output = vec![TxOut {
+                value: (lock_amount as i64 - fee_sat) as u64,
+                script_pubkey: self.commit.2.script_pubkey(),
+            }];
+
+            self
+        }
+
+        /// Replace the CETs with ones for the event of the `attestation`, one per `(range,
+        /// maker_amount, taker_amount)`, each adaptor-signed by `counterparty_sk` under the
+        /// attestation.
+        fn with_attested_cets(
+            mut self,
+            counterparty_sk: SecretKey,
+            attestation: &olivia::Attestation,
+            payouts: Vec<(RangeInclusive<u64>, Amount, Amount)>,
+        ) -> Self {
+            let counterparty_pk = bitcoin::PublicKey::new(counterparty_sk.to_public_key());
+            let own_pk = bitcoin::PublicKey::new(self.identity.to_public_key());
+            let commit_amount = Amount::ONE_BTC;
+
+            self.identity_counterparty = counterparty_pk;
+            self.commit.2 = lock_descriptor(own_pk, counterparty_pk);
+            self.commit.0.output = vec![TxOut {
+                value: commit_amount.as_sat(),
+                script_pubkey: self.commit.2.script_pubkey(),
+            }];
+
+            let n_bits = attestation.scalars.len();
+            let decryption_sk = decryption_sk(attestation, n_bits).unwrap();
+            let encryption_pk =
+                secp256k1_zkp::PublicKey::from_secret_key(SECP256K1, &decryption_sk);
+
+            let cets = payouts
+                .into_iter()
+                .map(|(range, maker_amount, taker_amount)| {
+                    let cet = Cet::dummy(range, n_bits).with_payouts(maker_amount, taker_amount);
+                    let tx = cet
+                        .build_tx(
+                            (&self.commit.0, &self.commit.2),
+                            &self.maker_address,
+                            &self.taker_address,
+                        )
+                        .unwrap();
+                    let sig_hash = spending_tx_sighash(&tx, &self.commit.2, commit_amount).unwrap();
+
+                    Cet {
+                        adaptor_sig: EcdsaAdaptorSignature::encrypt(
+                            SECP256K1,
+                            &sig_hash,
+                            &counterparty_sk,
+                            &encryption_pk,
+                        ),
+                        txid: tx.txid(),
+                        ..cet
+                    }
+                })
+                .collect();
+            self.cets = HashMap::from([(attestation.id, cets)]);
+
+            self
+        }
+
         fn with_lock_maker(
             self,
             amount_taker: Amount,
@@ -4387,9 +5348,7 @@ mod tests {
             let dummy_addr = Address::from_str("132F25rTsvBdp9JzLLBHP5mvGY66i1xdiM").unwrap();
 
             let dummy_tx = dummy_partially_signed_transaction().extract_tx();
-            let dummy_adapter_sig = "03424d14a5471c048ab87b3b83f6085d125d5864249ae4297a57c84e74710bb6730223f325042fce535d040fee52ec13231bf709ccd84233c6944b90317e62528b2527dff9d659a96db4c99f9750168308633c1867b70f3a18fb0f4539a1aecedcd1fc0148fc22f36b6303083ece3f872b18e35d368b3958efe5fb081f7716736ccb598d269aa3084d57e1855e1ea9a45efc10463bbf32ae378029f5763ceb40173f"
-                .parse()
-                .unwrap();
+            let dummy_adapter_sig = DUMMY_ADAPTOR_SIG.parse().unwrap();
 
             let dummy_sig = Signature::from_str("3046022100839c1fbc5304de944f697c9f4b1d01d1faeba32d751c0f7acb21ac8a0f436a72022100e89bd46bb3a5a62adc679f659b7ce876d83ee297c7a5587b2011c4fcc72eab45").unwrap();
 
@@ -4472,6 +5431,10 @@ mod tests {
         Some(PeerId::random())
     }
 
+    pub fn dummy_protocol_version() -> ProtocolVersion {
+        ProtocolVersion::new(2, 0, 0)
+    }
+
     pub fn dummy_event_id() -> BitMexPriceEventId {
         BitMexPriceEventId::with_20_digits(OffsetDateTime::now_utc(), ContractSymbol::BtcUsd)
     }
@@ -4492,6 +5455,83 @@ mod tests {
                 scalars: vec![],
             }
         }
+
+        fn dummy_with_price(price: u64, n_scalars: usize) -> Self {
+            Self {
+                id: dummy_event_id(),
+                price,
+                scalars: (0..n_scalars).map(|_| new_keypair().0).collect(),
+            }
+        }
+    }
+
+    impl RevokedCommit {
+        fn dummy(
+            revocation_sk_theirs: SecretKey,
+            publication_pk_theirs: PublicKey,
+            revocation_sk_ours: Option<SecretKey>,
+        ) -> Self {
+            Self {
+                encsig_ours: DUMMY_ADAPTOR_SIG.parse().unwrap(),
+                revocation_sk_ours,
+                revocation_sk_theirs,
+                publication_pk_theirs,
+                txid: dummy_transaction().txid(),
+                script_pubkey: Script::new(),
+                settlement_event_id: None,
+                complete_fee: None,
+                tx: None,
+                publication_pk_ours: None,
+                revocation_pk_theirs: None,
+            }
+        }
+
+        /// Record the counterparty's adaptor signature over `tx`, spending the output of
+        /// `lock_descriptor`, together with the keys it can be verified with.
+        fn with_commit_tx(
+            self,
+            tx: Transaction,
+            lock_descriptor: &Descriptor<PublicKey>,
+            identity_sk_theirs: SecretKey,
+            publication_pk_ours: PublicKey,
+        ) -> Self {
+            let sighash = spending_tx_sighash(&tx, lock_descriptor, Amount::ONE_BTC).unwrap();
+            let encsig_ours = EcdsaAdaptorSignature::encrypt(
+                SECP256K1,
+                &sighash,
+                &identity_sk_theirs,
+                &publication_pk_ours.inner,
+            );
+
+            Self {
+                encsig_ours,
+                txid: tx.txid(),
+                tx: Some(tx),
+                publication_pk_ours: Some(publication_pk_ours),
+                ..self
+            }
+        }
+    }
+
+    impl Cet {
+        fn dummy(range: RangeInclusive<u64>, n_bits: usize) -> Self {
+            Self {
+                maker_amount: Amount::ZERO,
+                taker_amount: Amount::ZERO,
+                adaptor_sig: DUMMY_ADAPTOR_SIG.parse().unwrap(),
+                range,
+                n_bits,
+                txid: dummy_transaction().txid(),
+            }
+        }
+
+        fn with_payouts(self, maker_amount: Amount, taker_amount: Amount) -> Self {
+            Self {
+                maker_amount,
+                taker_amount,
+                ..self
+            }
+        }
     }
 
     impl FundingFee {
@@ -4518,6 +5558,8 @@ mod tests {
 
     const N_PAYOUTS: usize = 200;
 
+    const DUMMY_ADAPTOR_SIG: &str = "03424d14a5471c048ab87b3b83f6085d125d5864249ae4297a57c84e74710bb6730223f325042fce535d040fee52ec13231bf709ccd84233c6944b90317e62528b2527dff9d659a96db4c99f9750168308633c1867b70f3a18fb0f4539a1aecedcd1fc0148fc22f36b6303083ece3f872b18e35d368b3958efe5fb081f7716736ccb598d269aa3084d57e1855e1ea9a45efc10463bbf32ae378029f5763ceb40173f";
+
     fn new_keypair() -> (SecretKey, PublicKey) {
         let (sk, pk) = keypair::new(&mut thread_rng());
         (sk, pk)
@@ -638,6 +638,26 @@ impl FundingFee {
         })
     }
 
+    /// Who pays the funding fee to whom.
+    ///
+    /// Derived from the sign of the `rate`, which is persisted
+    /// alongside the `fee`. A zero `fee` is not paid by anyone.
+    pub fn direction(&self) -> FundingFeeDirection {
+        if self.fee == Amount::ZERO {
+            FundingFeeDirection::None
+        } else if self.rate.short_pays_long() {
+            FundingFeeDirection::ShortPaysLong
+        } else {
+            FundingFeeDirection::LongPaysShort
+        }
+    }
+
+    /// Whether the party in the `position` passed as an argument pays
+    /// the funding fee.
+    pub fn is_paid_by(&self, position: Position) -> bool {
+        self.direction().payer() == Some(position)
+    }
+
     /// Calculate the fee paid or earned for a party in a particular
     /// position.
     ///
@@ -670,6 +690,30 @@ impl FundingFee {
     }
 }
 
+/// Direction of payment of a [`FundingFee`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FundingFeeDirection {
+    LongPaysShort,
+    ShortPaysLong,
+    None,
+}
+
+impl FundingFeeDirection {
+    /// The position paying the funding fee, if any.
+    pub fn payer(&self) -> Option<Position> {
+        match self {
+            FundingFeeDirection::LongPaysShort => Some(Position::Long),
+            FundingFeeDirection::ShortPaysLong => Some(Position::Short),
+            FundingFeeDirection::None => None,
+        }
+    }
+
+    /// The position receiving the funding fee, if any.
+    pub fn receiver(&self) -> Option<Position> {
+        self.payer().map(|payer| payer.counter_position())
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CompleteFee {
     #[serde(with = "::bdk::bitcoin::util::amount::serde::as_sat")]
@@ -885,6 +929,90 @@ impl From<LotSize> for Contracts {
     }
 }
 
+/// The version of the protocol with which a CFD was set up.
+///
+/// The version determines the on-wire format of the data that was exchanged, which we may have to
+/// take into account when interpreting the CFD later on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProtocolVersion {
+    major: u16,
+    minor: u16,
+    patch: u16,
+}
+
+impl ProtocolVersion {
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Extract the version from a protocol name such as `/itchysats/order/2.0.0`.
+    pub fn from_protocol(protocol: &str) -> Result<Self> {
+        let version = protocol
+            .rsplit('/')
+            .next()
+            .with_context(|| format!("Protocol {protocol} has no version"))?;
+
+        version
+            .parse()
+            .with_context(|| format!("Failed to parse version of protocol {protocol}"))
+    }
+
+    pub fn major(&self) -> u16 {
+        self.major
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            major,
+            minor,
+            patch,
+        } = self;
+
+        write!(f, "{major}.{minor}.{patch}")
+    }
+}
+
+impl str::FromStr for ProtocolVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('.').map(str::parse::<u16>);
+
+        let (major, minor, patch) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(major), Some(minor), Some(patch), None) => (major?, minor?, patch?),
+            _ => anyhow::bail!("Expected version of the form major.minor.patch, got {s}"),
+        };
+
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+impl Serialize for ProtocolVersion {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProtocolVersion {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let version = String::deserialize(deserializer)?;
+
+        version.parse().map_err(D::Error::custom)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Vout(u32);
 
@@ -1469,6 +1597,39 @@ mod tests {
         assert!(relative.is_positive())
     }
 
+    #[test]
+    fn given_positive_funding_rate_then_long_pays_short() {
+        let positive_funding_rate = FundingRate::new(dec!(0.001)).unwrap();
+
+        let funding_fee = FundingFee::new(dummy_amount(), positive_funding_rate);
+
+        assert_eq!(funding_fee.direction(), FundingFeeDirection::LongPaysShort);
+        assert!(funding_fee.is_paid_by(Position::Long));
+        assert!(!funding_fee.is_paid_by(Position::Short));
+    }
+
+    #[test]
+    fn given_negative_funding_rate_then_short_pays_long() {
+        let negative_funding_rate = FundingRate::new(dec!(-0.001)).unwrap();
+
+        let funding_fee = FundingFee::new(dummy_amount(), negative_funding_rate);
+
+        assert_eq!(funding_fee.direction(), FundingFeeDirection::ShortPaysLong);
+        assert_eq!(funding_fee.direction().payer(), Some(Position::Short));
+        assert_eq!(funding_fee.direction().receiver(), Some(Position::Long));
+    }
+
+    #[test]
+    fn given_zero_funding_fee_then_nobody_pays() {
+        let positive_funding_rate = FundingRate::new(dec!(0.001)).unwrap();
+
+        let funding_fee = FundingFee::new(Amount::ZERO, positive_funding_rate);
+
+        assert_eq!(funding_fee.direction(), FundingFeeDirection::None);
+        assert!(!funding_fee.is_paid_by(Position::Long));
+        assert!(!funding_fee.is_paid_by(Position::Short));
+    }
+
     #[test]
     fn given_long_fee_account_when_long_pays_short_from_complete_fee_then_same_after_settle() {
         let fee_account = FeeAccount::new(Position::Long, Role::Taker);
@@ -1530,6 +1691,28 @@ mod tests {
         assert_eq!(complete_fee, expected_complete_fee)
     }
 
+    #[test]
+    fn given_protocol_name_then_extract_protocol_version() {
+        let version = ProtocolVersion::from_protocol("/itchysats/order/2.0.0").unwrap();
+
+        assert_eq!(version, ProtocolVersion::new(2, 0, 0));
+        assert_eq!(version.to_string(), "2.0.0");
+    }
+
+    #[test]
+    fn given_protocol_name_without_version_then_error() {
+        assert!(ProtocolVersion::from_protocol("/itchysats/order").is_err());
+        assert!(ProtocolVersion::from_protocol("/itchysats/order/2.0").is_err());
+        assert!(ProtocolVersion::from_protocol("/itchysats/order/2.0.0.1").is_err());
+    }
+
+    #[test]
+    fn roundtrip_protocol_version_serde() {
+        let version = ProtocolVersion::new(2, 1, 0);
+
+        serde_test::assert_tokens(&version, &[serde_test::Token::String("2.1.0")]);
+    }
+
     fn dummy_amount() -> Amount {
         Amount::from_sat(500)
     }
@@ -15,6 +15,7 @@ use anyhow::Result;
 use bdk::bitcoin::secp256k1::SecretKey;
 use bdk::bitcoin::PublicKey;
 use bdk::bitcoin::Script;
+use bdk::bitcoin::Transaction;
 use bdk::miniscript::DescriptorTrait;
 use bdk_ext::SecretKeyExt;
 use maia_core::secp256k1_zkp;
@@ -94,6 +95,10 @@ pub struct BaseCommitParams {
     pub commit_txid: Txid,
     pub commit_script_pubkey: Script,
 
+    // To verify the adaptor signature.
+    pub commit_tx: Option<Transaction>,
+    pub publish_pk_ours: Option<PublicKey>,
+
     // To allow rolling over from arbitrary base.
     pub settlement_event_id: BitMexPriceEventId,
     pub revocation_sk_ours: SecretKey,
@@ -128,6 +133,8 @@ impl Dlc {
                 publish_pk_theirs: self.publish_pk_counterparty,
                 commit_txid: self.commit.0.txid(),
                 commit_script_pubkey: self.commit.2.script_pubkey(),
+                commit_tx: Some(self.commit.0.clone()),
+                publish_pk_ours: Some(PublicKey::new(self.publish.to_public_key())),
                 settlement_event_id: self.settlement_event_id,
                 revocation_sk_ours: self.revocation,
                 complete_fee,
@@ -154,6 +161,9 @@ impl Dlc {
             script_pubkey: commit_script_pubkey,
             settlement_event_id,
             complete_fee,
+            tx: commit_tx,
+            publication_pk_ours: publish_pk_ours,
+            revocation_pk_theirs: _,
         } = self
             .revoked_commit
             .iter()
@@ -180,6 +190,8 @@ impl Dlc {
                 publish_pk_theirs,
                 commit_txid,
                 commit_script_pubkey,
+                commit_tx,
+                publish_pk_ours,
                 settlement_event_id,
                 complete_fee,
             },
@@ -214,6 +226,9 @@ impl BaseDlcParams {
             script_pubkey: self.base_commit_params.commit_script_pubkey,
             settlement_event_id: Some(self.base_commit_params.settlement_event_id),
             complete_fee: Some(self.base_commit_params.complete_fee),
+            tx: self.base_commit_params.commit_tx,
+            publication_pk_ours: self.base_commit_params.publish_pk_ours,
+            revocation_pk_theirs: Some(self.base_commit_params.revocation_pk_theirs),
         };
 
         let mut revoked_commits = self.revoked_commits;
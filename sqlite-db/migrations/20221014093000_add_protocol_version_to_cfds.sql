@@ -0,0 +1,6 @@
+-- Record the version of the protocol with which a CFD was set up.
+-- CFDs set up before this migration have an unknown protocol version.
+ALTER TABLE
+    cfds
+ADD
+    COLUMN protocol_version text;
@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS open_cets_txid ON open_cets (txid);
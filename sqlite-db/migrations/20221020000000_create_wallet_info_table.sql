@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS wallet_info (
+    id integer PRIMARY KEY CHECK (id = 1),
+    network text NOT NULL,
+    balance integer NOT NULL,
+    address text NOT NULL,
+    last_updated_at integer NOT NULL,
+    transactions text NOT NULL
+);
@@ -0,0 +1,6 @@
+ALTER TABLE
+    rollover_completed_event_data
+ADD
+    -- The number of CETs of the rollover, so a rollover whose CETs were only partially inserted can be detected.
+    -- NULL for rollovers persisted before this column was added.
+    COLUMN expected_cets INTEGER NULL;
@@ -0,0 +1,16 @@
+ALTER TABLE
+    revoked_commit_transactions
+ADD
+    -- The revoked commit transaction, to verify encsig_ours against.
+    -- This and the following columns are NULL for commit transactions revoked before they were added.
+    COLUMN commit_tx text NULL;
+ALTER TABLE
+    revoked_commit_transactions
+ADD
+    -- The key encsig_ours is encrypted under.
+    COLUMN publication_pk_ours text NULL;
+ALTER TABLE
+    revoked_commit_transactions
+ADD
+    -- The revocation key the counterparty committed to, to verify revocation_sk_theirs against.
+    COLUMN revocation_pk_theirs text NULL;
@@ -205,6 +205,36 @@ impl Connection {
 
         Ok(ids)
     }
+
+    /// Load the IDs of all closed CFDs with an entry in the `event_log` at or after `since`.
+    pub(crate) async fn load_closed_cfd_ids_changed_since(
+        &self,
+        since: Timestamp,
+    ) -> Result<Vec<OrderId>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let since = models::Timestamp::from(since);
+        let ids = sqlx::query!(
+            r#"
+            SELECT
+                order_id as "order_id: models::OrderId"
+            FROM
+                closed_cfds
+            WHERE EXISTS (
+                SELECT id FROM event_log
+                WHERE event_log.cfd_id = closed_cfds.id AND event_log.created_at >= $1
+            )
+            "#,
+            since
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|r| r.order_id.into())
+        .collect();
+
+        Ok(ids)
+    }
 }
 
 /// Auxiliary type used to gradually combine a `Cfd` with its list of
@@ -1310,6 +1340,7 @@ mod tests {
             FundingRate::default(),
             TxFeeRate::default(),
             ContractSymbol::BtcUsd,
+            None,
         );
 
         let contract_setup_completed =
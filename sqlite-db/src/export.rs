@@ -0,0 +1,313 @@
+//! Export open CFDs to rebuild the database on another node, f.e. when migrating a maker to new
+//! hardware.
+
+use crate::insert_cfd;
+use crate::insert_event;
+use crate::load_cfd_events;
+use crate::load_cfd_row;
+use crate::models;
+use crate::rollover;
+use crate::Cfd;
+use crate::Connection;
+use anyhow::Context;
+use anyhow::Result;
+use futures::Stream;
+use model::libp2p::PeerId;
+use model::CfdEvent;
+use model::ContractSymbol;
+use model::Contracts;
+use model::Dlc;
+use model::EventKind;
+use model::FundingRate;
+use model::Identity;
+use model::Leverage;
+use model::OfferId;
+use model::OpeningFee;
+use model::OrderId;
+use model::Position;
+use model::Price;
+use model::ProtocolVersion;
+use model::Role;
+use model::Timestamp;
+use model::TxFeeRate;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::Acquire;
+use time::Duration;
+
+/// A self-contained record of an open CFD, see [`Connection::stream_export`].
+///
+/// Holds everything needed to rebuild the CFD via [`Connection::import_cfd`], including the DLC
+/// of the latest rollover with its CETs and revoked commits. **The DLC contains our secret keys**,
+/// thus exports must be stored with the same care as the database itself.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CfdExport {
+    pub id: OrderId,
+    pub offer_id: OfferId,
+    pub position: Position,
+    pub initial_price: Price,
+    pub taker_leverage: Leverage,
+    pub settlement_interval_hours: i64,
+    pub quantity: Contracts,
+    pub counterparty_network_identity: Identity,
+    pub counterparty_peer_id: Option<PeerId>,
+    pub role: Role,
+    pub opening_fee: OpeningFee,
+    pub initial_funding_rate: FundingRate,
+    pub initial_tx_fee_rate: TxFeeRate,
+    pub contract_symbol: ContractSymbol,
+    pub protocol_version: Option<ProtocolVersion>,
+    pub events: Vec<ExportedEvent>,
+}
+
+/// An event of a [`CfdExport`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedEvent {
+    pub timestamp: Timestamp,
+    pub event: EventKind,
+    /// The DLC of an [`EventKind::RolloverCompleted`] event.
+    ///
+    /// Carried separately because the DLC is not serialized as part of the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollover_dlc: Option<Dlc>,
+}
+
+impl From<CfdEvent> for ExportedEvent {
+    fn from(event: CfdEvent) -> Self {
+        let rollover_dlc = match &event.event {
+            EventKind::RolloverCompleted { dlc, .. } => dlc.clone(),
+            _ => None,
+        };
+
+        Self {
+            timestamp: event.timestamp,
+            event: event.event,
+            rollover_dlc,
+        }
+    }
+}
+
+impl CfdExport {
+    fn new(cfd: Cfd, events: Vec<CfdEvent>) -> Self {
+        let events = events.into_iter().map(ExportedEvent::from).collect();
+
+        Self {
+            id: cfd.id,
+            offer_id: cfd.offer_id,
+            position: cfd.position,
+            initial_price: cfd.initial_price,
+            taker_leverage: cfd.taker_leverage,
+            settlement_interval_hours: cfd.settlement_interval.whole_hours(),
+            quantity: cfd.quantity,
+            counterparty_network_identity: cfd.counterparty_network_identity,
+            counterparty_peer_id: cfd.counterparty_peer_id,
+            role: cfd.role,
+            opening_fee: cfd.opening_fee,
+            initial_funding_rate: cfd.initial_funding_rate,
+            initial_tx_fee_rate: cfd.initial_tx_fee_rate,
+            contract_symbol: cfd.contract_symbol,
+            protocol_version: cfd.protocol_version,
+            events,
+        }
+    }
+}
+
+impl Connection {
+    /// Export the open CFD with the given ID, see [`CfdExport`].
+    pub async fn export_cfd(&self, id: OrderId) -> Result<CfdExport> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        let cfd = load_cfd_row(&mut db_tx, id).await?;
+        let events = load_cfd_events(&mut db_tx, id, 0).await?;
+
+        db_tx.commit().await?;
+
+        Ok(CfdExport::new(cfd, events))
+    }
+
+    /// Export all open CFDs, one by one.
+    ///
+    /// Closed and failed CFDs are not exported: they are archived without a DLC and are not
+    /// needed to continue operating on another node.
+    pub fn stream_export(&self) -> impl Stream<Item = Result<CfdExport>> + Unpin + '_ {
+        let stream = async_stream::stream! {
+            let ids = self.load_open_cfd_ids().await?;
+            for id in ids {
+                yield self.export_cfd(id).await
+                    .with_context(|| format!("Failed to export CFD {id}"));
+            }
+        };
+
+        Box::pin(stream)
+    }
+
+    /// Rebuild a CFD from an export produced by [`Connection::stream_export`].
+    ///
+    /// The CFD must not exist yet. The CFD and its events are inserted in a single transaction,
+    /// thus a failed import leaves nothing behind. Only the latest rollover carries a DLC, which is
+    /// written like that of any other rollover; earlier rollovers are imported as plain events.
+    pub async fn import_cfd(&self, export: CfdExport) -> Result<()> {
+        let id = export.id;
+        let cfd = model::Cfd::new(
+            id,
+            export.offer_id,
+            export.position,
+            export.initial_price,
+            export.taker_leverage,
+            Duration::hours(export.settlement_interval_hours),
+            export.role,
+            export.quantity,
+            export.counterparty_network_identity,
+            export.counterparty_peer_id,
+            export.opening_fee,
+            export.initial_funding_rate,
+            export.initial_tx_fee_rate,
+            export.contract_symbol,
+            export.protocol_version,
+        );
+
+        let order_id = models::OrderId::from(id);
+        let _rollover_guard = self.rollover_locks.lock(order_id).await;
+
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        insert_cfd(&mut db_tx, &cfd)
+            .await
+            .with_context(|| format!("Failed to import CFD {id}"))?;
+
+        for ExportedEvent {
+            timestamp,
+            event,
+            rollover_dlc,
+        } in export.events
+        {
+            let event = CfdEvent {
+                timestamp,
+                id,
+                event,
+            };
+            let event_row_id = insert_event(&mut db_tx, &event)
+                .await
+                .with_context(|| format!("Failed to import event of CFD {id}"))?;
+
+            if let (
+                EventKind::RolloverCompleted {
+                    funding_fee,
+                    complete_fee,
+                    ..
+                },
+                Some(dlc),
+            ) = (event.event, rollover_dlc)
+            {
+                rollover::overwrite(
+                    &mut db_tx,
+                    event_row_id,
+                    order_id,
+                    dlc,
+                    funding_fee,
+                    complete_fee,
+                )
+                .await
+                .with_context(|| format!("Failed to import rollover of CFD {id}"))?;
+            }
+        }
+
+        db_tx.commit().await?;
+
+        tracing::info!(order_id = %id, "Imported CFD");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use crate::tests::dummy_cfd;
+    use futures::TryStreamExt;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn given_exported_cfds_when_importing_into_new_db_then_roundtrips() {
+        let source = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        source.insert_cfd(&cfd).await.unwrap();
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json").unwrap();
+        let event = serde_json::from_str::<EventKind>(&event).unwrap();
+        for event in [
+            EventKind::ContractSetupStarted,
+            EventKind::RolloverStarted,
+            event,
+        ] {
+            source
+                .append_event(CfdEvent::new(cfd.id(), event))
+                .await
+                .unwrap();
+        }
+
+        let exports = source
+            .stream_export()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let serialized = serde_json::to_string(&exports).unwrap();
+
+        let target = memory().await.unwrap();
+        for export in serde_json::from_str::<Vec<CfdExport>>(&serialized).unwrap() {
+            target.import_cfd(export).await.unwrap();
+        }
+
+        let reimported = target.export_cfd(cfd.id()).await.unwrap();
+
+        assert_eq!(exports, vec![reimported]);
+        assert!(matches!(
+            exports[0].events.last(),
+            Some(ExportedEvent {
+                rollover_dlc: Some(_),
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn given_cfd_rolled_over_twice_when_importing_then_only_latest_rollover_has_dlc() {
+        let source = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        source.insert_cfd(&cfd).await.unwrap();
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json").unwrap();
+        let event = serde_json::from_str::<EventKind>(&event).unwrap();
+        for _ in 0..2 {
+            source
+                .append_event(CfdEvent::new(cfd.id(), event.clone()))
+                .await
+                .unwrap();
+        }
+
+        let export = source.export_cfd(cfd.id()).await.unwrap();
+        assert!(matches!(
+            export.events.as_slice(),
+            [
+                ExportedEvent {
+                    rollover_dlc: None,
+                    ..
+                },
+                ExportedEvent {
+                    rollover_dlc: Some(_),
+                    ..
+                }
+            ]
+        ));
+
+        let target = memory().await.unwrap();
+        target.import_cfd(export.clone()).await.unwrap();
+
+        assert_eq!(target.export_cfd(cfd.id()).await.unwrap(), export);
+    }
+}
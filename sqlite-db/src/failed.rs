@@ -154,6 +154,37 @@ impl Connection {
 
         Ok(ids)
     }
+
+    /// Load the IDs of all failed CFDs with an entry in the `event_log_failed` at or after
+    /// `since`.
+    pub(crate) async fn load_failed_cfd_ids_changed_since(
+        &self,
+        since: Timestamp,
+    ) -> Result<Vec<OrderId>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let since = models::Timestamp::from(since);
+        let ids = sqlx::query!(
+            r#"
+            SELECT
+                order_id as "order_id: models::OrderId"
+            FROM
+                failed_cfds
+            WHERE EXISTS (
+                SELECT id FROM event_log_failed
+                WHERE event_log_failed.cfd_id = failed_cfds.id AND event_log_failed.created_at >= $1
+            )
+            "#,
+            since
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|r| r.order_id.into())
+        .collect();
+
+        Ok(ids)
+    }
 }
 
 async fn insert_failed_cfd(
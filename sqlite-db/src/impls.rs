@@ -20,6 +20,7 @@ impl crate::CfdAggregate for model::Cfd {
             initial_funding_rate,
             initial_tx_fee_rate,
             contract_symbol,
+            protocol_version,
         }: crate::Cfd,
     ) -> Self {
         model::Cfd::new(
@@ -37,6 +38,7 @@ impl crate::CfdAggregate for model::Cfd {
             initial_funding_rate,
             initial_tx_fee_rate,
             contract_symbol,
+            protocol_version,
         )
     }
 
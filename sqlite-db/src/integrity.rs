@@ -0,0 +1,154 @@
+//! A one-shot health check of the persisted CFD data.
+//!
+//! Combines the individual diagnostics of the database into a single [`IntegrityReport`], which
+//! can be produced at startup or on demand.
+
+use crate::rollover;
+use crate::Connection;
+use anyhow::Result;
+use model::OrderId;
+
+/// The problems found by [`Connection::run_integrity_checks`], with the CFDs affected by each.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// CFDs which completed a rollover but have none or not all of their CETs, see
+    /// [`Connection::find_cfds_without_cets`].
+    pub cfds_without_cets: Vec<OrderId>,
+    /// CFDs with CETs but without the rollover data they belong to.
+    pub cfds_with_orphaned_cets: Vec<OrderId>,
+    /// CFDs whose lock amounts disagree with their margins, see
+    /// [`Connection::check_lock_amount_consistency`].
+    pub inconsistent_lock_amounts: Vec<OrderId>,
+    /// CFDs whose rollover data fails to load, f.e. because the counterparty's refund signature
+    /// does not verify.
+    pub invalid_rollovers: Vec<OrderId>,
+}
+
+impl IntegrityReport {
+    pub fn is_healthy(&self) -> bool {
+        self == &IntegrityReport::default()
+    }
+}
+
+impl Connection {
+    /// Check the persisted CFD data for inconsistencies.
+    ///
+    /// This loads and verifies the rollover data of every rolled over CFD, which is expensive with
+    /// many CFDs. It is therefore meant to be run at startup or on demand, not periodically.
+    pub async fn run_integrity_checks(&self) -> Result<IntegrityReport> {
+        let cfds_without_cets = self.find_cfds_without_cets().await?;
+        let inconsistent_lock_amounts = self.check_lock_amount_consistency().await?;
+
+        let mut conn = self.inner.acquire().await?;
+
+        let cfds_with_orphaned_cets = rollover::find_cfds_with_orphaned_cets(&mut *conn).await?;
+
+        let mut invalid_rollovers = Vec::new();
+        for (order_id, cfd_row_id, event_row_id) in
+            rollover::load_rolled_over_cfds(&mut *conn).await?
+        {
+            if let Err(e) = rollover::load(&mut *conn, cfd_row_id, event_row_id).await {
+                tracing::warn!(%order_id, "Rollover data is invalid: {e:#}");
+                invalid_rollovers.push(order_id);
+            }
+        }
+
+        Ok(IntegrityReport {
+            cfds_without_cets,
+            cfds_with_orphaned_cets,
+            inconsistent_lock_amounts,
+            invalid_rollovers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use crate::tests::dummy_cfd;
+    use anyhow::bail;
+    use model::CfdEvent;
+    use model::EventKind;
+    use model::Leverage;
+    use model::Timestamp;
+
+    #[tokio::test]
+    async fn given_rolled_over_cfd_then_report_is_healthy() -> Result<()> {
+        let db = memory().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+        append_rollover(&db, &cfd).await?;
+
+        let report = db.run_integrity_checks().await?;
+
+        assert!(report.is_healthy(), "{report:?}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_cets_without_rollover_data_then_reported_as_orphaned() -> Result<()> {
+        let db = memory().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+        append_rollover(&db, &cfd).await?;
+
+        let mut conn = db.inner.acquire().await?;
+        sqlx::query("DELETE FROM rollover_completed_event_data")
+            .execute(&mut *conn)
+            .await?;
+
+        let report = db.run_integrity_checks().await?;
+
+        assert_eq!(
+            report,
+            IntegrityReport {
+                cfds_with_orphaned_cets: vec![cfd.id()],
+                ..IntegrityReport::default()
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Append the rollover of the test events, with lock amounts matching the CFD's margins.
+    async fn append_rollover(db: &Connection, cfd: &model::Cfd) -> Result<()> {
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let (mut dlc, funding_fee, complete_fee) = match serde_json::from_str::<EventKind>(&event)?
+        {
+            EventKind::RolloverCompleted {
+                dlc: Some(dlc),
+                funding_fee,
+                complete_fee,
+            } => (dlc, funding_fee, complete_fee),
+            _ => bail!("Expected RolloverCompleted event with DLC"),
+        };
+
+        dlc.maker_lock_amount = model::calculate_margin(
+            cfd.contract_symbol(),
+            cfd.initial_price(),
+            cfd.quantity(),
+            Leverage::ONE,
+        );
+        dlc.taker_lock_amount = model::calculate_margin(
+            cfd.contract_symbol(),
+            cfd.initial_price(),
+            cfd.quantity(),
+            cfd.taker_leverage(),
+        );
+
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event: EventKind::RolloverCompleted {
+                dlc: Some(dlc),
+                funding_fee,
+                complete_fee,
+            },
+        })
+        .await
+    }
+}
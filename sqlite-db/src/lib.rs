@@ -3,15 +3,20 @@ mod sqlx_ext; // Must come first because it is a macro.
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
+use bdk::bitcoin::Amount;
+use bdk::bitcoin::Txid;
 use dashmap::DashMap;
 use futures::future::BoxFuture;
 use futures::FutureExt;
 use futures::Stream;
 use model::libp2p::PeerId;
+use model::olivia::BitMexPriceEventId;
+use model::Cet;
 use model::CfdEvent;
 use model::ContractSymbol;
 use model::Contracts;
 use model::EventKind;
+use model::FundingFee;
 use model::FundingRate;
 use model::Identity;
 use model::Leverage;
@@ -20,18 +25,24 @@ use model::OpeningFee;
 use model::OrderId;
 use model::Position;
 use model::Price;
+use model::ProtocolVersion;
 use model::Role;
+use model::Timestamp;
 use model::TxFeeRate;
 use sqlx::migrate::MigrateError;
 use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::sqlite::SqliteSynchronous;
 use sqlx::Acquire;
 use sqlx::SqliteConnection;
 use sqlx::SqlitePool;
 use std::any::Any;
 use std::any::TypeId;
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Instant;
 use time::Duration;
 
 pub use closed::*;
@@ -40,27 +51,81 @@ use model::EventKind::RolloverCompleted;
 
 pub mod closed;
 pub mod event_log;
+pub mod export;
 pub mod failed;
 mod impls;
+pub mod integrity;
 mod models;
 mod rollover;
 pub mod time_to_first_position;
 pub mod user;
+pub mod wallet_info;
 
 #[derive(Clone)]
 pub struct Connection {
     inner: SqlitePool,
     aggregate_cache: Arc<DashMap<(TypeId, OrderId), Box<dyn Any + Send + Sync + 'static>>>,
+    /// If set, the CETs of a rollover are inserted in chunks of this size, each in its own
+    /// transaction. See [`Connection::with_cets_per_transaction`].
+    cets_per_transaction: Option<NonZeroUsize>,
+    rollover_locks: rollover::RolloverLocks,
+    /// The durability the pool was opened with. Rollovers are always written with
+    /// [`Synchronous::Full`].
+    synchronous: Synchronous,
+}
+
+/// How durably SQLite writes committed transactions to disk.
+///
+/// The database is opened in WAL mode. In WAL mode, [`Synchronous::Normal`] cannot corrupt the
+/// database, but a power loss or OS crash may roll back the most recently committed
+/// transactions. [`Synchronous::Full`] syncs the WAL on every commit, making committed
+/// transactions durable at the cost of slower writes.
+///
+/// See <https://www.sqlite.org/pragma.html#pragma_synchronous>.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Synchronous {
+    #[default]
+    Full,
+    Normal,
+}
+
+impl From<Synchronous> for SqliteSynchronous {
+    fn from(synchronous: Synchronous) -> Self {
+        match synchronous {
+            Synchronous::Full => SqliteSynchronous::Full,
+            Synchronous::Normal => SqliteSynchronous::Normal,
+        }
+    }
 }
 
 impl Connection {
-    fn new(pool: SqlitePool) -> Self {
+    fn new(pool: SqlitePool, synchronous: Synchronous) -> Self {
         Self {
             inner: pool,
             aggregate_cache: Arc::new(DashMap::new()),
+            cets_per_transaction: None,
+            rollover_locks: rollover::RolloverLocks::default(),
+            synchronous,
         }
     }
 
+    /// Insert the CETs of a rollover in several transactions of at most `cets_per_transaction`
+    /// CETs each.
+    ///
+    /// By default, a rollover is persisted in a single transaction. For contracts with many CETs
+    /// this holds the write lock for a long time, blocking all other writers. Chunking the CET
+    /// inserts releases the lock in between.
+    ///
+    /// The trade-off is that a crash in between two chunks leaves a rollover with only some of
+    /// its CETs in the database. Loading the CFD then fails instead of returning a partial DLC,
+    /// thus the CFD becomes unusable: it can neither be rolled over nor settled, because both
+    /// require the loaded CFD. The missing CETs cannot be recovered and the previous rollover's
+    /// DLC has already been overwritten, so this requires fixing the database by hand.
+    pub fn with_cets_per_transaction(mut self, cets_per_transaction: NonZeroUsize) -> Self {
+        self.cets_per_transaction = Some(cets_per_transaction);
+        self
+    }
+
     pub async fn close(self) {
         self.inner.close().await;
     }
@@ -71,15 +136,20 @@ impl Connection {
 /// If the database does not exist, it will be created. If it does exist, we load it and apply all
 /// pending migrations. If applying migrations fails, the old database is backed up next to it and a
 /// new one is created.
+///
+/// All writes are made with the given [`Synchronous`] durability, except for rollovers which are
+/// always written with [`Synchronous::Full`].
 pub fn connect(
     path: PathBuf,
     ignore_migration_errors: bool,
+    synchronous: Synchronous,
 ) -> BoxFuture<'static, Result<Connection>> {
     async move {
         let pool = SqlitePool::connect_with(
             SqliteConnectOptions::new()
                 .create_if_missing(true)
-                .filename(&path),
+                .filename(&path)
+                .synchronous(synchronous.into()),
         )
         .await?;
 
@@ -90,7 +160,7 @@ pub fn connect(
             Ok(()) => {
                 tracing::info!("Opened database at {path_display}");
 
-                return Ok(Connection::new(pool));
+                return Ok(Connection::new(pool, synchronous));
             }
             Err(e) => e,
         };
@@ -120,7 +190,7 @@ pub fn connect(
             tracing::info!("Starting with a new database!");
 
             // recurse to reconnect (async recursion requires a `BoxFuture`)
-            return connect(path, ignore_migration_errors).await;
+            return connect(path, ignore_migration_errors, synchronous).await;
         }
 
         Err(error)
@@ -136,7 +206,7 @@ pub async fn memory() -> Result<Connection> {
 
     run_migrations(&pool).await?;
 
-    Ok(Connection::new(pool))
+    Ok(Connection::new(pool, Synchronous::Full))
 }
 
 async fn run_migrations(pool: &SqlitePool) -> Result<()> {
@@ -152,70 +222,94 @@ impl Connection {
     pub async fn insert_cfd(&self, cfd: &model::Cfd) -> Result<()> {
         let mut conn = self.inner.acquire().await?;
 
-        let order_id = models::OrderId::from(cfd.id());
-        let offer_id = models::OfferId::from(cfd.offer_id());
+        insert_cfd(&mut conn, cfd).await
+    }
+
+    /// Import the DLC of an existing CFD, e.g. one restored via [`model::Dlc::from_backup`].
+    ///
+    /// The DLC replaces the one recorded with the CFD's latest `ContractSetupCompleted` or
+    /// `RolloverCompleted` event in place. No event is appended, thus the CFD's event log and fee
+    /// account are left as they are.
+    pub async fn import_dlc(&self, order_id: OrderId, dlc: model::Dlc) -> Result<()> {
+        let id = models::OrderId::from(order_id);
 
-        let role = models::Role::from(cfd.role());
-        let contracts = models::Contracts::from(cfd.quantity());
-        let initial_price = models::Price::from(cfd.initial_price());
-        let leverage = models::Leverage::from(cfd.taker_leverage());
+        let _rollover_guard = self.rollover_locks.lock(id).await;
 
-        let position = models::Position::from(cfd.position());
-        let counterparty_network_identity =
-            models::Identity::from(cfd.counterparty_network_identity());
-        let initial_funding_rate = models::FundingRate::from(cfd.initial_funding_rate());
-        let opening_fee = models::OpeningFee::from(cfd.opening_fee());
-        let tx_fee_rate = models::TxFeeRate::from(cfd.initial_tx_fee_rate());
-        let counterparty_peer_id = cfd.counterparty_peer_id().map(models::PeerId::from);
-        let contract_symbol = models::ContractSymbol::from(cfd.contract_symbol());
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
 
-        let query_result = sqlx::query(
+        let row = sqlx::query!(
             r#"
-        insert into cfds (
-            order_id,
-            offer_id,
-            position,
-            initial_price,
-            leverage,
-            settlement_time_interval_hours,
-            contracts,
-            counterparty_network_identity,
-            counterparty_peer_id,
-            role,
-            opening_fee,
-            initial_funding_rate,
-            initial_tx_fee_rate,
-            contract_symbol
-        ) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)"#,
+            select
+                events.id as event_row_id,
+                events.cfd_id as cfd_row_id,
+                events.name
+            from
+                events
+            join
+                cfds c on c.id = events.cfd_id
+            where
+                c.order_id = $1 and
+                (
+                    events.name = $2 or
+                    events.name = $3
+                )
+            order by
+                events.id desc
+            limit 1
+            "#,
+            id,
+            EventKind::CONTRACT_SETUP_COMPLETED_EVENT,
+            EventKind::ROLLOVER_COMPLETED_EVENT,
         )
-        .bind(&order_id)
-        .bind(&offer_id)
-        .bind(&position)
-        .bind(&initial_price)
-        .bind(&leverage)
-        .bind(&cfd.settlement_time_interval_hours().whole_hours())
-        .bind(&contracts)
-        .bind(&counterparty_network_identity)
-        .bind(&counterparty_peer_id.unwrap_or_else(|| {
-            tracing::debug!(
-                order_id=%cfd.id(),
-                counterparty_identity=%cfd.counterparty_network_identity(),
-                "Inserting deprecated CFD with placeholder peer-id"
-            );
-            models::PeerId::from(model::libp2p::PeerId::placeholder())
-        }))
-        .bind(&role)
-        .bind(&opening_fee)
-        .bind(&initial_funding_rate)
-        .bind(&tx_fee_rate)
-        .bind(&contract_symbol)
-        .execute(&mut conn)
-        .await?;
-
-        if query_result.rows_affected() != 1 {
-            bail!("failed to insert cfd");
+        .fetch_optional(&mut db_tx)
+        .await?
+        .with_context(|| format!("CFD {order_id} has no DLC to replace"))?;
+
+        if row.name == EventKind::ROLLOVER_COMPLETED_EVENT {
+            let (_, funding_fee, complete_fee) =
+                rollover::load(&mut db_tx, row.cfd_row_id, row.event_row_id)
+                    .await?
+                    .with_context(|| {
+                        format!("No rollover data for latest rollover of {order_id}")
+                    })?;
+
+            rollover::overwrite(
+                &mut db_tx,
+                row.event_row_id,
+                id,
+                dlc,
+                funding_fee,
+                complete_fee,
+            )
+            .await?;
+        } else {
+            let (_, data) = EventKind::ContractSetupCompleted { dlc: Some(dlc) }.to_json();
+
+            sqlx::query!(
+                r#"
+                update
+                    events
+                set
+                    data = $1
+                where
+                    id = $2
+                "#,
+                data,
+                row.event_row_id
+            )
+            .execute(&mut db_tx)
+            .await?;
         }
 
+        db_tx.commit().await?;
+
+        // The cached aggregate was built from the DLC we just replaced
+        self.aggregate_cache
+            .retain(|(_, cached_id), _| *cached_id != order_id);
+
+        tracing::info!(%order_id, "Imported DLC");
+
         Ok(())
     }
 
@@ -224,57 +318,71 @@ impl Connection {
     /// To make handling of `None` events more ergonomic, you can pass anything in here that
     /// implements `Into<Option>` event.
     pub async fn append_event(&self, event: impl Into<Option<CfdEvent>>) -> Result<()> {
-        let mut conn = self.inner.acquire().await?;
-        let mut db_tx = conn.begin().await?;
-
         let event = match event.into() {
             Some(event) => event,
             None => return Ok(()),
         };
 
-        let (event_name, event_data) = event.event.to_json();
-
         let order_id = models::OrderId::from(event.id);
-        let timestamp = models::Timestamp::from(event.timestamp);
-        let query_result = sqlx::query(
-            r##"
-        insert into events (
-            cfd_id,
-            name,
-            data,
-            created_at
-        ) values (
-            (select id from cfds where cfds.order_id = $1),
-            $2, $3, $4
-        )"##,
-        )
-        .bind(&order_id)
-        .bind(&event_name)
-        .bind(&event_data)
-        .bind(&timestamp)
-        .execute(&mut db_tx)
-        .await?;
 
-        if query_result.rows_affected() != 1 {
-            bail!("failed to insert event");
+        // Held until all rollover data, including deferred CETs, has been written
+        let rollover_guard = match event.event {
+            RolloverCompleted { .. } => Some(self.rollover_locks.lock(order_id).await),
+            _ => None,
+        };
+
+        let mut conn = self.inner.acquire().await?;
+
+        // Losing a committed rollover would leave us without the latest DLC, thus we do not trade
+        // its durability for speed. Should we fail before resetting the connection, it stays at
+        // `FULL`, which is only slower.
+        let durable_rollover = rollover_guard.is_some() && self.synchronous != Synchronous::Full;
+        if durable_rollover {
+            sqlx::query("PRAGMA synchronous = FULL")
+                .execute(&mut *conn)
+                .await?;
         }
 
+        let mut db_tx = conn.begin().await?;
+
+        let event_name = event.event.to_string();
+        let event_row_id = insert_event(&mut db_tx, &event).await?;
+
+        let mut deferred_cets = Vec::new();
+        let mut rollover_insert = None;
+
         match event.event {
             // if we have a rollover completed event we store it additionally in its own table
             RolloverCompleted {
-                dlc: Some(dlc),
+                dlc: Some(mut dlc),
                 funding_fee,
                 complete_fee,
             } => {
+                let num_cets = dlc.cets.values().map(Vec::len).sum::<usize>();
+                rollover_insert = Some((num_cets, Instant::now()));
+
+                if self.cets_per_transaction.is_some() {
+                    deferred_cets = std::mem::take(&mut dlc.cets)
+                        .into_iter()
+                        .flat_map(|(event_id, cets)| {
+                            cets.into_iter().map(move |cet| (event_id, cet))
+                        })
+                        .collect::<Vec<_>>();
+                }
+
                 rollover::overwrite(
                     &mut db_tx,
-                    query_result.last_insert_rowid(),
+                    event_row_id,
                     order_id,
                     dlc,
                     funding_fee,
                     complete_fee,
                 )
                 .await?;
+
+                if !deferred_cets.is_empty() {
+                    rollover::set_expected_cets(&mut db_tx, order_id, num_cets).await?;
+                }
             }
             RolloverCompleted { dlc: None, .. } => {
                 tracing::error!(
@@ -286,6 +394,24 @@ impl Connection {
 
         db_tx.commit().await?;
 
+        if let Some(cets_per_transaction) = self.cets_per_transaction {
+            for chunk in deferred_cets.chunks(cets_per_transaction.get()) {
+                let mut db_tx = conn.begin().await?;
+                rollover::insert_cets(&mut db_tx, order_id, chunk.to_vec()).await?;
+                db_tx.commit().await?;
+            }
+        }
+
+        if durable_rollover {
+            sqlx::query("PRAGMA synchronous = NORMAL")
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        if let Some((num_cets, started_at)) = rollover_insert {
+            rollover::observe_insert_duration(num_cets, started_at.elapsed());
+        }
+
         tracing::info!(event = %event_name, %order_id, "Appended event to database");
 
         Ok(())
@@ -296,6 +422,11 @@ impl Connection {
     where
         C: CfdAggregate,
     {
+        // The CETs of a rollover may be inserted after its event, see
+        // `Connection::with_cets_per_transaction`. Waiting for the rollover to be persisted
+        // completely ensures we never fold a partial DLC into the aggregate.
+        let _rollover_guard = self.rollover_locks.lock(id.into()).await;
+
         let mut conn = self.inner.acquire().await?;
         let mut db_tx = conn.begin().await?;
 
@@ -336,6 +467,37 @@ impl Connection {
         Ok(cfd)
     }
 
+    /// Load the events of an open CFD in chronological order, alongside when they were recorded.
+    ///
+    /// Meant for diagnosing a single CFD without rehydrating it. The events of closed and failed
+    /// CFDs are not retained, thus only open CFDs have a timeline.
+    pub async fn load_cfd_event_timeline(
+        &self,
+        id: OrderId,
+    ) -> Result<Vec<(Timestamp, EventKind)>, Error> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        // Fails with `OpenCfdNotFound` instead of yielding an empty timeline for an unknown CFD
+        load_cfd_row(&mut db_tx, id).await?;
+
+        let events = load_cfd_events(&mut db_tx, id, 0)
+            .await
+            .with_context(|| format!("Could not load events for CFD {id}"))?;
+
+        db_tx.commit().await?;
+
+        Ok(events
+            .into_iter()
+            .map(|event| (event.timestamp, event.event))
+            .collect())
+    }
+
+    /// Loads all CFDs, open, closed and failed.
+    ///
+    /// CFDs are yielded one by one as they are rehydrated from the database, so callers that
+    /// don't need the entire set at once can process them without holding all of them in memory.
+    /// Use `StreamExt::collect` (or `try_collect`) if the whole set is needed.
     pub fn load_all_cfds<'a, C>(
         &'a self,
         args: C::CtorArgs,
@@ -375,72 +537,555 @@ impl Connection {
             }
         };
 
-        Box::pin(stream)
+        Box::pin(stream)
+    }
+
+    /// Loads all CFDs which have recorded an event at or after the given timestamp.
+    ///
+    /// This allows consumers that poll the database (e.g. a UI or an external indexer) to only
+    /// fetch the CFDs that changed since their last poll. The timestamp is inclusive, thus CFDs
+    /// that changed within the same second as the last poll will be returned again.
+    pub fn load_cfds_changed_since<'a, C>(
+        &'a self,
+        since: Timestamp,
+        args: C::CtorArgs,
+    ) -> impl Stream<Item = Result<C>> + Unpin + '_
+    where
+        C: CfdAggregate + ClosedCfdAggregate + FailedCfdAggregate,
+        C::CtorArgs: Clone + Send + Sync,
+    {
+        let stream = async_stream::stream! {
+            let ids = self.load_open_cfd_ids_changed_since(since).await?;
+            for id in ids {
+                let res = match self.load_open_cfd(id, args.clone()).await {
+                    Err(Error::OpenCfdNotFound) => {
+                        tracing::trace!(
+                            order_id=%id,
+                            target="db",
+                            "Ignoring OpenCfdNotFound"
+                        );
+                        continue;
+                    }
+                    res => res.with_context(|| format!("Could not load open CFD {id}")),
+                };
+
+                yield res;
+            }
+
+            let ids = self.load_closed_cfd_ids_changed_since(since).await?;
+            for id in ids {
+                yield self.load_closed_cfd(id, args.clone()).await
+                    .with_context(|| format!("Failed to load closed CFD {id}"));
+            }
+
+            let ids = self.load_failed_cfd_ids_changed_since(since).await?;
+            for id in ids {
+                yield self.load_failed_cfd(id, args.clone()).await
+                    .with_context(|| format!("Failed to load failed CFD {id}"));
+            }
+        };
+
+        Box::pin(stream)
+    }
+
+    /// Loads all CFDs where we are still able to append events
+    ///
+    /// This function is to be called when we only want to process CFDs where events can still be
+    /// appended, but ignore all other CFDs.
+    /// Open in this context means that the CFD is not final yet, i.e. we can still append events.
+    /// In this context a CFD is not open anymore if one of the following happened:
+    /// 1. Event of the confirmation of a payout (spend) transaction on the blockchain was recorded
+    ///     Cases: Collaborative settlement, CET, Refund
+    /// 2. Event that fails the CFD early was recorded, meaning it becomes irrelevant for processing
+    ///     Cases: Setup failed, Taker's take order rejected
+    pub fn load_all_open_cfds<'a, C>(
+        &'a self,
+        args: C::CtorArgs,
+    ) -> impl Stream<Item = Result<C>> + Unpin + '_
+    where
+        C: CfdAggregate + Unpin,
+        C::CtorArgs: Clone + Send + Sync,
+    {
+        let stream = async_stream::stream! {
+            let ids = self.load_open_cfd_ids().await?;
+
+            for id in ids {
+                let res = match self.load_open_cfd(id, args.clone()).await {
+                    Err(Error::OpenCfdNotFound) => {
+                        tracing::trace!(
+                            order_id=%id,
+                            target="db",
+                            "Ignoring OpenCfdNotFound"
+                        );
+                        continue;
+                    }
+                    res => res.with_context(|| format!("Could not load open CFD {id}")),
+                };
+
+                yield res;
+            }
+        };
+
+        Box::pin(stream)
+    }
+
+    /// Change the order ID of an open CFD from `old_order_id` to `new_order_id`.
+    ///
+    /// All data belonging to the CFD, such as its events and rollover data, references the CFD
+    /// by its row ID and is therefore carried over. Fails if there is no open CFD with
+    /// `old_order_id` or if any CFD, open, closed or failed, already uses `new_order_id`.
+    pub async fn rekey_cfd(&self, old_order_id: OrderId, new_order_id: OrderId) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        let old_id = models::OrderId::from(old_order_id);
+        let new_id = models::OrderId::from(new_order_id);
+
+        let existing = sqlx::query!(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM cfds WHERE order_id = $1) +
+                (SELECT COUNT(*) FROM closed_cfds WHERE order_id = $1) +
+                (SELECT COUNT(*) FROM failed_cfds WHERE order_id = $1) as "count!: i64"
+            "#,
+            new_id
+        )
+        .fetch_one(&mut db_tx)
+        .await?
+        .count;
+
+        if existing > 0 {
+            bail!("Cannot rekey CFD {old_order_id}: a CFD with order ID {new_order_id} already exists");
+        }
+
+        let query_result = sqlx::query!(
+            r#"
+            UPDATE
+                cfds
+            SET
+                order_id = $1
+            WHERE
+                order_id = $2
+            "#,
+            new_id,
+            old_id
+        )
+        .execute(&mut db_tx)
+        .await?;
+
+        if query_result.rows_affected() != 1 {
+            return Err(Error::CfdNotFound(old_order_id).into());
+        }
+
+        db_tx.commit().await?;
+
+        self.aggregate_cache
+            .retain(|(_, order_id), _| *order_id != old_order_id);
+
+        tracing::info!(%old_order_id, %new_order_id, "Changed order ID of CFD");
+
+        Ok(())
+    }
+
+    /// Load the IDs for all the CFDs found in the `cfds` table.
+    ///
+    /// Importantly, callers **cannot** rely on the CFD IDs returned
+    /// corresponding to open CFDs.
+    pub async fn load_open_cfd_ids(&self) -> Result<Vec<OrderId>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let ids = sqlx::query!(
+            r#"
+            SELECT
+                order_id as "order_id: models::OrderId"
+            FROM
+                cfds
+            "#
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|r| r.order_id.into())
+        .collect();
+
+        Ok(ids)
+    }
+
+    /// Load the IDs of all CFDs in the `cfds` table with an event at or after `since`.
+    async fn load_open_cfd_ids_changed_since(&self, since: Timestamp) -> Result<Vec<OrderId>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let since = models::Timestamp::from(since);
+        let ids = sqlx::query!(
+            r#"
+            SELECT
+                order_id as "order_id: models::OrderId"
+            FROM
+                cfds
+            WHERE EXISTS (
+                SELECT id FROM events
+                WHERE events.cfd_id = cfds.id AND events.created_at >= $1
+            )
+            "#,
+            since
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|r| r.order_id.into())
+        .collect();
+
+        Ok(ids)
+    }
+
+    /// Load the IDs of all CFDs in the `cfds` table whose latest event has the given name.
+    ///
+    /// The `state_label` is the name of an event as it is stored in the `events` table, e.g.
+    /// [`EventKind::CONTRACT_SETUP_FAILED`]. This allows to find CFDs that got stuck in a
+    /// particular state (e.g. during contract setup) without having to load the full CFDs.
+    pub async fn load_order_ids_in_state(&self, state_label: &str) -> Result<Vec<OrderId>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let ids = sqlx::query!(
+            r#"
+            SELECT
+                order_id as "order_id: models::OrderId"
+            FROM
+                cfds
+            JOIN
+                events latest_event on latest_event.cfd_id = cfds.id
+            WHERE
+                latest_event.id = (SELECT MAX(events.id) FROM events WHERE events.cfd_id = cfds.id) AND
+                latest_event.name = $1
+            "#,
+            state_label
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|r| r.order_id.into())
+        .collect();
+
+        Ok(ids)
+    }
+
+    /// Count the CFDs in the `cfds` table per counterparty peer.
+    ///
+    /// CFDs which were created before we recorded the counterparty's peer ID are left out, as are
+    /// peers without any CFDs. Like [`Connection::load_open_cfd_ids`], this may include CFDs which
+    /// are closed but not archived yet.
+    pub async fn count_cfds_by_counterparty_peer_id(&self) -> Result<Vec<(PeerId, usize)>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let placeholder = models::PeerId::from(PeerId::placeholder());
+        let counts = sqlx::query!(
+            r#"
+            SELECT
+                counterparty_peer_id as "counterparty_peer_id: models::PeerId",
+                COUNT(*) as "count!: i64"
+            FROM
+                cfds
+            WHERE
+                counterparty_peer_id != $1
+            GROUP BY
+                counterparty_peer_id
+            "#,
+            placeholder
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| (row.counterparty_peer_id.into(), row.count as usize))
+        .collect();
+
+        Ok(counts)
+    }
+
+    /// Count the CFDs in the `cfds` table with the given counterparty peer.
+    ///
+    /// Like [`Connection::count_cfds_by_counterparty_peer_id`], this may include CFDs which are
+    /// closed but not archived yet.
+    pub async fn count_cfds_with_counterparty_peer_id(&self, peer_id: PeerId) -> Result<usize> {
+        let mut conn = self.inner.acquire().await?;
+
+        let peer_id = models::PeerId::from(peer_id);
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) as "count!: i64"
+            FROM
+                cfds
+            WHERE
+                counterparty_peer_id = $1
+            "#,
+            peer_id
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        let count = usize::try_from(row.count).context("CFD count out of range")?;
+
+        Ok(count)
+    }
+
+    /// Load the IDs of all open CFDs whose refund timelock expires at most `margin_blocks` after
+    /// `current_block_height`.
+    ///
+    /// The refund timelock is relative to the confirmation of the commit transaction. Until the
+    /// commit transaction is confirmed, we assume that it could have been confirmed together with
+    /// the lock transaction, which is the earliest the refund transaction could become valid.
+    /// `confirmation_height` returns the height at which a transaction was confirmed, if it was.
+    /// CFDs whose lock transaction is not confirmed yet are never due.
+    ///
+    /// The timelock is taken from the latest DLC of the CFD, i.e. that of the latest rollover or,
+    /// if the CFD was never rolled over, that of the contract setup.
+    pub async fn load_cfds_due_for_rollover(
+        &self,
+        current_block_height: u32,
+        margin_blocks: u32,
+        confirmation_height: impl Fn(Txid) -> Option<u32>,
+    ) -> Result<Vec<OrderId>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let rolled_over = sqlx::query!(
+            r#"
+            SELECT
+                order_id as "order_id: models::OrderId",
+                rollover.refund_timelock as "refund_timelock: i64",
+                rollover.lock_tx as "lock_tx: models::Transaction",
+                rollover.commit_tx as "commit_tx: models::Transaction"
+            FROM
+                cfds
+            JOIN
+                rollover_completed_event_data rollover on rollover.cfd_id = cfds.id
+            "#
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let lock_tx = bdk::bitcoin::Transaction::from(row.lock_tx);
+            let commit_tx = bdk::bitcoin::Transaction::from(row.commit_tx);
+            let refund_timelock =
+                u32::try_from(row.refund_timelock).context("Refund timelock out of range")?;
+
+            anyhow::Ok((
+                row.order_id.into(),
+                refund_timelock,
+                lock_tx.txid(),
+                commit_tx.txid(),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+        let never_rolled_over = sqlx::query!(
+            r#"
+            SELECT
+                order_id as "order_id: models::OrderId",
+                events.name,
+                events.data
+            FROM
+                cfds
+            JOIN
+                events on events.cfd_id = cfds.id
+            WHERE
+                events.name = $1 AND
+                NOT EXISTS (
+                    SELECT id FROM rollover_completed_event_data rollover
+                    WHERE rollover.cfd_id = cfds.id
+                )
+            "#,
+            EventKind::CONTRACT_SETUP_COMPLETED_EVENT
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .filter_map(|row| {
+            let order_id = OrderId::from(row.order_id);
+            match EventKind::from_json(row.name, row.data) {
+                Ok(EventKind::ContractSetupCompleted { dlc: Some(dlc) }) => Some(Ok((
+                    order_id,
+                    dlc.refund_timelock,
+                    dlc.lock.0.txid(),
+                    dlc.commit.0.txid(),
+                ))),
+                Ok(_) => None,
+                Err(e) => Some(Err(e.context(format!(
+                    "Failed to deserialize contract setup of {order_id}"
+                )))),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+        let ids = rolled_over
+            .into_iter()
+            .chain(never_rolled_over)
+            .filter_map(|(order_id, refund_timelock, lock_txid, commit_txid)| {
+                let confirmed_at =
+                    confirmation_height(commit_txid).or_else(|| confirmation_height(lock_txid))?;
+                let refund_height = u64::from(confirmed_at) + u64::from(refund_timelock);
+                let due_height = refund_height.saturating_sub(u64::from(margin_blocks));
+
+                (u64::from(current_block_height) >= due_height).then(|| order_id)
+            })
+            .collect();
+
+        Ok(ids)
+    }
+
+    /// Load the IDs of all CFDs whose lock amounts stored with the latest rollover disagree with
+    /// the margins derived from the `cfds` table.
+    ///
+    /// The lock amounts are redundant: they are fully determined by the price, quantity and
+    /// leverage of the CFD. This check detects drift between the two sources, e.g. before
+    /// deduplicating the columns.
+    pub async fn check_lock_amount_consistency(&self) -> Result<Vec<OrderId>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                order_id as "order_id: models::OrderId",
+                initial_price as "initial_price: models::Price",
+                leverage as "leverage: models::Leverage",
+                contracts as "contracts: models::Contracts",
+                contract_symbol as "contract_symbol: models::ContractSymbol",
+                rollover.maker_lock_amount as "maker_lock_amount: i64",
+                rollover.taker_lock_amount as "taker_lock_amount: i64"
+            FROM
+                cfds
+            JOIN
+                rollover_completed_event_data rollover on rollover.cfd_id = cfds.id
+            "#
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut inconsistent = Vec::new();
+        for row in rows {
+            let order_id = OrderId::from(row.order_id);
+            let contract_symbol = ContractSymbol::from(row.contract_symbol);
+            let price = Price::from(row.initial_price);
+            let quantity = Contracts::try_from(row.contracts)?;
+            let taker_leverage = Leverage::from(row.leverage);
+
+            // The maker never takes on leverage
+            let maker_margin =
+                model::calculate_margin(contract_symbol, price, quantity, Leverage::ONE);
+            let taker_margin =
+                model::calculate_margin(contract_symbol, price, quantity, taker_leverage);
+
+            let matches =
+                |margin: Amount, stored: i64| i64::try_from(margin.as_sat()).ok() == Some(stored);
+
+            if !matches(maker_margin, row.maker_lock_amount)
+                || !matches(taker_margin, row.taker_lock_amount)
+            {
+                tracing::warn!(
+                    %order_id,
+                    maker_lock_amount = row.maker_lock_amount,
+                    taker_lock_amount = row.taker_lock_amount,
+                    %maker_margin,
+                    %taker_margin,
+                    "Lock amounts of latest rollover disagree with CFD"
+                );
+                inconsistent.push(order_id);
+            }
+        }
+
+        Ok(inconsistent)
+    }
+
+    /// Sum the lock amounts of all open CFDs, returning the maker's and the taker's total.
+    ///
+    /// This aggregates in the database instead of loading every `Dlc`.
+    pub async fn total_open_exposure(&self) -> Result<(Amount, Amount)> {
+        let mut conn = self.inner.acquire().await?;
+
+        rollover::total_open_exposure(&mut *conn).await
+    }
+
+    /// Load the settlement events of all open CFDs that have been rolled over.
+    ///
+    /// This is what needs to be subscribed to at the oracle to be able to settle these CFDs.
+    pub async fn load_active_settlement_events(&self) -> Result<HashSet<BitMexPriceEventId>> {
+        let mut conn = self.inner.acquire().await?;
+
+        rollover::load_active_settlement_events(&mut *conn).await
+    }
+
+    /// Load the time and funding fee of every rollover of a CFD, ordered by rollover.
+    pub async fn load_funding_rate_history(
+        &self,
+        id: OrderId,
+    ) -> Result<Vec<(Timestamp, FundingFee)>> {
+        let mut conn = self.inner.acquire().await?;
+
+        rollover::load_funding_rate_history(&mut *conn, id).await
+    }
+
+    /// Load a CET of an open CFD by its transaction ID.
+    ///
+    /// Returns the ID of the CFD the CET belongs to alongside the CET itself, or `None` if no
+    /// stored CET matches the `txid`.
+    pub async fn load_cet_by_txid(&self, txid: Txid) -> Result<Option<(OrderId, Cet)>> {
+        let mut conn = self.inner.acquire().await?;
+
+        rollover::load_cet_by_txid(&mut *conn, txid).await
     }
 
-    /// Loads all CFDs where we are still able to append events
+    /// Count the CETs of an open CFD without loading them.
     ///
-    /// This function is to be called when we only want to process CFDs where events can still be
-    /// appended, but ignore all other CFDs.
-    /// Open in this context means that the CFD is not final yet, i.e. we can still append events.
-    /// In this context a CFD is not open anymore if one of the following happened:
-    /// 1. Event of the confirmation of a payout (spend) transaction on the blockchain was recorded
-    ///     Cases: Collaborative settlement, CET, Refund
-    /// 2. Event that fails the CFD early was recorded, meaning it becomes irrelevant for processing
-    ///     Cases: Setup failed, Taker's take order rejected
-    pub fn load_all_open_cfds<'a, C>(
-        &'a self,
-        args: C::CtorArgs,
-    ) -> impl Stream<Item = Result<C>> + Unpin + '_
-    where
-        C: CfdAggregate + Unpin,
-        C::CtorArgs: Clone + Send + Sync,
-    {
-        let stream = async_stream::stream! {
-            let ids = self.load_open_cfd_ids().await?;
+    /// Cheaper than loading the CFD's `Dlc` when only the number of CETs is of interest, e.g. to
+    /// show the progress of a rollover.
+    pub async fn count_cets(&self, id: OrderId) -> Result<u64> {
+        let mut conn = self.inner.acquire().await?;
 
-            for id in ids {
-                let res = match self.load_open_cfd(id, args.clone()).await {
-                    Err(Error::OpenCfdNotFound) => {
-                        tracing::trace!(
-                            order_id=%id,
-                            target="db",
-                            "Ignoring OpenCfdNotFound"
-                        );
-                        continue;
-                    }
-                    res => res.with_context(|| format!("Could not load open CFD {id}")),
-                };
+        let order_id = models::OrderId::from(id);
+        let cfd_row_id = sqlx::query!(r#"select id from cfds where order_id = $1"#, order_id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .and_then(|row| row.id)
+            .with_context(|| format!("No open CFD with order ID {id}"))?;
 
-                yield res;
-            }
-        };
+        rollover::count_cets(&mut *conn, cfd_row_id).await
+    }
 
-        Box::pin(stream)
+    /// Find all CFDs which completed a rollover but have none or not all of its CETs stored.
+    ///
+    /// Used to detect rollovers whose CETs failed to be inserted.
+    pub async fn find_cfds_without_cets(&self) -> Result<Vec<OrderId>> {
+        let mut conn = self.inner.acquire().await?;
+
+        rollover::find_cfds_without_cets(&mut *conn).await
     }
 
-    /// Load the IDs for all the CFDs found in the `cfds` table.
+    /// Load the IDs of all CFDs in the `cfds` table with the given counterparty network identity.
     ///
-    /// Importantly, callers **cannot** rely on the CFD IDs returned
-    /// corresponding to open CFDs.
-    pub async fn load_open_cfd_ids(&self) -> Result<Vec<OrderId>> {
+    /// Unlike the identity of the DLC, the network identity is fixed when the CFD is created, so
+    /// CFDs are found regardless of whether and how often they were rolled over.
+    pub async fn load_cfds_by_counterparty(&self, identity: Identity) -> Result<Vec<OrderId>> {
         let mut conn = self.inner.acquire().await?;
 
-        let ids = sqlx::query!(
+        let identity = models::Identity::from(identity);
+        let order_ids = sqlx::query!(
             r#"
             SELECT
                 order_id as "order_id: models::OrderId"
             FROM
                 cfds
-            "#
+            WHERE
+                counterparty_network_identity = $1
+            "#,
+            identity
         )
         .fetch_all(&mut *conn)
         .await?
         .into_iter()
-        .map(|r| r.order_id.into())
+        .map(|row| row.order_id.into())
         .collect();
 
-        Ok(ids)
+        Ok(order_ids)
     }
 
     async fn closed_cfd_ids_according_to_the_blockchain(&self) -> Result<Vec<OrderId>> {
@@ -526,12 +1171,16 @@ pub struct Cfd {
     pub initial_funding_rate: FundingRate,
     pub initial_tx_fee_rate: TxFeeRate,
     pub contract_symbol: ContractSymbol,
+    /// The version of the protocol with which the CFD was set up, if known.
+    pub protocol_version: Option<ProtocolVersion>,
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("The CFD requested was not found in the open CFDs")]
     OpenCfdNotFound,
+    #[error("No CFD found for order id {0}")]
+    CfdNotFound(OrderId),
     #[error("{0:#}")]
     Sqlx(#[source] sqlx::Error),
     #[error("{0:#}")]
@@ -563,6 +1212,110 @@ pub trait CfdAggregate: Clone + Send + Sync + 'static {
     fn version(&self) -> u32;
 }
 
+async fn insert_cfd(conn: &mut SqliteConnection, cfd: &model::Cfd) -> Result<()> {
+    let order_id = models::OrderId::from(cfd.id());
+    let offer_id = models::OfferId::from(cfd.offer_id());
+
+    let role = models::Role::from(cfd.role());
+    let contracts = models::Contracts::from(cfd.quantity());
+    let initial_price = models::Price::from(cfd.initial_price());
+    let leverage = models::Leverage::from(cfd.taker_leverage());
+
+    let position = models::Position::from(cfd.position());
+    let counterparty_network_identity = models::Identity::from(cfd.counterparty_network_identity());
+    let initial_funding_rate = models::FundingRate::from(cfd.initial_funding_rate());
+    let opening_fee = models::OpeningFee::from(cfd.opening_fee());
+    let tx_fee_rate = models::TxFeeRate::from(cfd.initial_tx_fee_rate());
+    let counterparty_peer_id = cfd.counterparty_peer_id().map(models::PeerId::from);
+    let contract_symbol = models::ContractSymbol::from(cfd.contract_symbol());
+    let protocol_version = cfd.protocol_version().map(models::ProtocolVersion::from);
+
+    let query_result = sqlx::query(
+        r#"
+        insert into cfds (
+            order_id,
+            offer_id,
+            position,
+            initial_price,
+            leverage,
+            settlement_time_interval_hours,
+            contracts,
+            counterparty_network_identity,
+            counterparty_peer_id,
+            role,
+            opening_fee,
+            initial_funding_rate,
+            initial_tx_fee_rate,
+            contract_symbol,
+            protocol_version
+        ) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)"#,
+    )
+    .bind(&order_id)
+    .bind(&offer_id)
+    .bind(&position)
+    .bind(&initial_price)
+    .bind(&leverage)
+    .bind(&cfd.settlement_time_interval_hours().whole_hours())
+    .bind(&contracts)
+    .bind(&counterparty_network_identity)
+    .bind(&counterparty_peer_id.unwrap_or_else(|| {
+        tracing::debug!(
+            order_id=%cfd.id(),
+            counterparty_identity=%cfd.counterparty_network_identity(),
+            "Inserting deprecated CFD with placeholder peer-id"
+        );
+        models::PeerId::from(model::libp2p::PeerId::placeholder())
+    }))
+    .bind(&role)
+    .bind(&opening_fee)
+    .bind(&initial_funding_rate)
+    .bind(&tx_fee_rate)
+    .bind(&contract_symbol)
+    .bind(&protocol_version)
+    .execute(&mut *conn)
+    .await?;
+
+    if query_result.rows_affected() != 1 {
+        bail!("failed to insert cfd");
+    }
+
+    Ok(())
+}
+
+/// Insert an event of an existing CFD, returning the row ID of the event.
+///
+/// Only the event itself is stored; any rollover data is left to the caller.
+async fn insert_event(conn: &mut SqliteConnection, event: &CfdEvent) -> Result<i64> {
+    let order_id = models::OrderId::from(event.id);
+    let (event_name, event_data) = event.event.to_json();
+    let timestamp = models::Timestamp::from(event.timestamp);
+
+    let query_result = sqlx::query(
+        r##"
+        insert into events (
+            cfd_id,
+            name,
+            data,
+            created_at
+        ) values (
+            (select id from cfds where cfds.order_id = $1),
+            $2, $3, $4
+        )"##,
+    )
+    .bind(&order_id)
+    .bind(&event_name)
+    .bind(&event_data)
+    .bind(&timestamp)
+    .execute(&mut *conn)
+    .await?;
+
+    if query_result.rows_affected() != 1 {
+        bail!("failed to insert event");
+    }
+
+    Ok(query_result.last_insert_rowid())
+}
+
 async fn load_cfd_row(conn: &mut SqliteConnection, id: OrderId) -> Result<Cfd, Error> {
     let id = models::OrderId::from(id);
 
@@ -583,7 +1336,8 @@ async fn load_cfd_row(conn: &mut SqliteConnection, id: OrderId) -> Result<Cfd, E
                 opening_fee as "opening_fee: models::OpeningFee",
                 initial_funding_rate as "initial_funding_rate: models::FundingRate",
                 initial_tx_fee_rate as "initial_tx_fee_rate: models::TxFeeRate",
-                contract_symbol as "contract_symbol: models::ContractSymbol"
+                contract_symbol as "contract_symbol: models::ContractSymbol",
+                protocol_version as "protocol_version: models::ProtocolVersion"
             from
                 cfds
             where
@@ -620,6 +1374,7 @@ async fn load_cfd_row(conn: &mut SqliteConnection, id: OrderId) -> Result<Cfd, E
         initial_funding_rate: cfd_row.initial_funding_rate.into(),
         initial_tx_fee_rate: cfd_row.initial_tx_fee_rate.into(),
         contract_symbol: cfd_row.contract_symbol.into(),
+        protocol_version: cfd_row.protocol_version.map(Into::into),
     })
 }
 
@@ -703,9 +1458,13 @@ async fn load_cfd_events(
 
     for (cfd_row_id, event_row_id, event) in events.iter_mut() {
         if let RolloverCompleted { .. } = event.event {
+            let started_at = Instant::now();
             if let Some((dlc, funding_fee, complete_fee)) =
                 rollover::load(&mut *conn, *cfd_row_id, *event_row_id).await?
             {
+                let num_cets = dlc.cets.values().map(Vec::len).sum::<usize>();
+                rollover::observe_load_duration(num_cets, started_at.elapsed());
+
                 event.event = RolloverCompleted {
                     dlc: Some(dlc),
                     funding_fee,
@@ -775,6 +1534,7 @@ mod tests {
     use model::OpeningFee;
     use model::Position;
     use model::Price;
+    use model::ProtocolVersion;
     use model::Role;
     use model::Timestamp;
     use model::TxFeeRate;
@@ -804,6 +1564,7 @@ mod tests {
             initial_funding_rate,
             initial_tx_fee_rate,
             contract_symbol,
+            protocol_version,
         } = load_cfd_row(&mut *conn, cfd.id()).await.unwrap();
 
         assert_eq!(cfd.id(), id);
@@ -823,6 +1584,20 @@ mod tests {
         assert_eq!(cfd.initial_funding_rate(), initial_funding_rate);
         assert_eq!(cfd.initial_tx_fee_rate(), initial_tx_fee_rate);
         assert_eq!(cfd.contract_symbol(), contract_symbol);
+        assert_eq!(cfd.protocol_version(), protocol_version);
+    }
+
+    #[tokio::test]
+    async fn given_cfd_with_protocol_version_when_loading_then_protocol_version_surfaced() {
+        let db = memory().await.unwrap();
+        let mut conn = db.inner.acquire().await.unwrap();
+
+        let cfd = dummy_taker_with_counterparty_peer_id();
+        db.insert_cfd(&cfd).await.unwrap();
+
+        let loaded = load_cfd_row(&mut *conn, cfd.id()).await.unwrap();
+
+        assert_eq!(loaded.protocol_version, Some(ProtocolVersion::new(2, 0, 0)));
     }
 
     #[tokio::test]
@@ -858,6 +1633,175 @@ mod tests {
         assert_eq!(events, vec![event1, event2])
     }
 
+    #[tokio::test]
+    async fn given_dlc_backup_when_importing_then_dlc_restored() {
+        let db = memory().await.unwrap();
+        let mut conn = db.inner.acquire().await.unwrap();
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json").unwrap();
+        let event = serde_json::from_str::<EventKind>(&event).unwrap();
+        let dlc = match &event {
+            EventKind::RolloverCompleted { dlc: Some(dlc), .. } => dlc.clone(),
+            _ => panic!("Expected RolloverCompleted event with DLC"),
+        };
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event,
+        })
+        .await
+        .unwrap();
+
+        let backup = dlc.to_backup().unwrap();
+        let restored = model::Dlc::from_backup(&backup).unwrap();
+
+        db.import_dlc(cfd.id(), restored).await.unwrap();
+
+        let events = load_cfd_events(&mut *conn, cfd.id(), 0).await.unwrap();
+        assert!(matches!(
+            events.as_slice(),
+            [CfdEvent {
+                event: EventKind::RolloverCompleted {
+                    dlc: Some(imported),
+                    ..
+                },
+                ..
+            }] if *imported == dlc
+        ));
+    }
+
+    #[tokio::test]
+    async fn given_rolled_over_cfd_when_importing_dlc_then_fee_account_unchanged() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json").unwrap();
+        let event = serde_json::from_str::<EventKind>(&event).unwrap();
+        let dlc = match &event {
+            EventKind::RolloverCompleted { dlc: Some(dlc), .. } => dlc.clone(),
+            _ => panic!("Expected RolloverCompleted event with DLC"),
+        };
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event,
+        })
+        .await
+        .unwrap();
+
+        let before = db.load_open_cfd::<model::Cfd>(cfd.id(), ()).await.unwrap();
+
+        db.import_dlc(cfd.id(), dlc).await.unwrap();
+
+        // Compares the fee account as well as the version, which would have been bumped by an
+        // additional event
+        let after = db.load_open_cfd::<model::Cfd>(cfd.id(), ()).await.unwrap();
+        assert_eq!(before, after);
+    }
+
+    #[tokio::test]
+    async fn given_cfd_without_dlc_when_importing_dlc_then_error() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json").unwrap();
+        let dlc = match serde_json::from_str::<EventKind>(&event).unwrap() {
+            EventKind::RolloverCompleted { dlc: Some(dlc), .. } => dlc,
+            _ => panic!("Expected RolloverCompleted event with DLC"),
+        };
+
+        assert!(db.import_dlc(cfd.id(), dlc).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn given_open_cfd_then_event_timeline_in_chronological_order() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+
+        let earlier = Timestamp::new(1);
+        let later = Timestamp::new(2);
+        for (timestamp, event) in [
+            (earlier, EventKind::OfferRejected),
+            (later, EventKind::RevokeConfirmed),
+        ] {
+            db.append_event(CfdEvent {
+                timestamp,
+                id: cfd.id(),
+                event,
+            })
+            .await
+            .unwrap();
+        }
+
+        let timeline = db.load_cfd_event_timeline(cfd.id()).await.unwrap();
+
+        assert_eq!(
+            timeline,
+            vec![
+                (earlier, EventKind::OfferRejected),
+                (later, EventKind::RevokeConfirmed)
+            ]
+        );
+        assert!(matches!(
+            db.load_cfd_event_timeline(OrderId::default()).await,
+            Err(Error::OpenCfdNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn given_rekeyed_cfd_then_events_loaded_under_new_order_id() {
+        let db = memory().await.unwrap();
+        let mut conn = db.inner.acquire().await.unwrap();
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+        let event = CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event: EventKind::OfferRejected,
+        };
+        db.append_event(event.clone()).await.unwrap();
+
+        let new_order_id = OrderId::default();
+        db.rekey_cfd(cfd.id(), new_order_id).await.unwrap();
+
+        assert!(matches!(
+            load_cfd_row(&mut *conn, cfd.id()).await,
+            Err(Error::OpenCfdNotFound)
+        ));
+        let events = load_cfd_events(&mut *conn, new_order_id, 0).await.unwrap();
+        assert_eq!(
+            events,
+            vec![CfdEvent {
+                id: new_order_id,
+                ..event
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn given_new_order_id_already_taken_when_rekeying_then_error() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        let other_cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+        db.insert_cfd(&other_cfd).await.unwrap();
+
+        let result = db.rekey_cfd(cfd.id(), other_cfd.id()).await;
+
+        assert!(result.is_err());
+    }
+
     #[tokio::test]
     async fn given_insert_cfd_with_peer_id_then_peer_id_loaded() {
         let db = memory().await.unwrap();
@@ -874,6 +1818,63 @@ mod tests {
         assert_eq!(cfd.counterparty_peer_id(), counterparty_peer_id);
     }
 
+    #[tokio::test]
+    async fn given_cfds_with_and_without_peer_id_then_only_those_with_peer_id_counted() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_taker_with_counterparty_peer_id();
+        db.insert_cfd(&cfd).await.unwrap();
+        db.insert_cfd(&dummy_cfd()).await.unwrap();
+
+        let counts = db.count_cfds_by_counterparty_peer_id().await.unwrap();
+
+        assert_eq!(counts, vec![(cfd.counterparty_peer_id().unwrap(), 1)]);
+    }
+
+    #[tokio::test]
+    async fn given_cfds_with_different_counterparties_then_only_matching_cfd_loaded() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+        let other_identity = "0000000000000000000000000000000000000000000000000000000000000001";
+        db.insert_cfd(&dummy_taker_with_legacy_identity(other_identity))
+            .await
+            .unwrap();
+
+        let order_ids = db
+            .load_cfds_by_counterparty(cfd.counterparty_network_identity())
+            .await
+            .unwrap();
+
+        assert_eq!(order_ids, vec![cfd.id()]);
+    }
+
+    #[tokio::test]
+    async fn given_cfds_with_different_peers_then_only_those_of_peer_counted() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_taker_with_counterparty_peer_id();
+        let peer_id = cfd.counterparty_peer_id().unwrap();
+        db.insert_cfd(&cfd).await.unwrap();
+        db.insert_cfd(&dummy_taker_with_counterparty_peer_id())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            db.count_cfds_with_counterparty_peer_id(peer_id)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            db.count_cfds_with_counterparty_peer_id(PeerId::random())
+                .await
+                .unwrap(),
+            0
+        );
+    }
+
     #[tokio::test]
     async fn given_insert_cfd_without_peer_id_when_known_mainnet_maker_then_peer_id_loaded() {
         let db = memory().await.unwrap();
@@ -932,6 +1933,56 @@ mod tests {
         assert_eq!(None, counterparty_peer_id);
     }
 
+    #[tokio::test]
+    async fn given_cfd_when_latest_event_matches_then_order_id_in_state() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+        db.append_event(order_rejected(&cfd)).await.unwrap();
+
+        let rejected = db
+            .load_order_ids_in_state(EventKind::OFFER_REJECTED)
+            .await
+            .unwrap();
+        assert_eq!(rejected, vec![cfd.id()]);
+
+        db.append_event(lock_confirmed(&cfd)).await.unwrap();
+
+        let rejected = db
+            .load_order_ids_in_state(EventKind::OFFER_REJECTED)
+            .await
+            .unwrap();
+        assert!(rejected.is_empty());
+    }
+
+    #[tokio::test]
+    async fn given_event_after_timestamp_then_cfd_changed_since() {
+        let db = memory().await.unwrap();
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::new(1_000),
+            id: cfd.id(),
+            event: EventKind::LockConfirmed,
+        })
+        .await
+        .unwrap();
+
+        let changed = db
+            .load_open_cfd_ids_changed_since(Timestamp::new(1_000))
+            .await
+            .unwrap();
+        assert_eq!(changed, vec![cfd.id()]);
+
+        let changed = db
+            .load_open_cfd_ids_changed_since(Timestamp::new(1_001))
+            .await
+            .unwrap();
+        assert!(changed.is_empty());
+    }
+
     pub fn dummy_cfd() -> Cfd {
         dummy_taker_with_legacy_identity(
             "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
@@ -956,6 +2007,7 @@ mod tests {
             FundingRate::default(),
             TxFeeRate::default(),
             ContractSymbol::BtcUsd,
+            Some(ProtocolVersion::new(2, 0, 0)),
         )
     }
 
@@ -975,6 +2027,7 @@ mod tests {
             FundingRate::default(),
             TxFeeRate::default(),
             ContractSymbol::BtcUsd,
+            None,
         )
     }
 
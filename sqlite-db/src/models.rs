@@ -8,6 +8,7 @@ use bdk::bitcoin::hashes::hex::FromHex;
 use bdk::bitcoin::hashes::hex::ToHex;
 use bdk::bitcoin::Amount;
 use bdk::bitcoin::SignedAmount;
+use bdk::descriptor;
 use maia_core::secp256k1_zkp;
 use model::olivia::EVENT_TIME_FORMAT;
 use rust_decimal::prelude::ToPrimitive;
@@ -230,6 +231,38 @@ impl From<bitcoin::Transaction> for Transaction {
 
 impl_sqlx_type_display_from_str!(Transaction);
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Descriptor(descriptor::Descriptor<bitcoin::PublicKey>);
+
+impl fmt::Display for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for Descriptor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let descriptor = descriptor::Descriptor::from_str(s)?;
+        Ok(Self(descriptor))
+    }
+}
+
+impl From<Descriptor> for descriptor::Descriptor<bitcoin::PublicKey> {
+    fn from(descriptor: Descriptor) -> Self {
+        descriptor.0
+    }
+}
+
+impl From<descriptor::Descriptor<bitcoin::PublicKey>> for Descriptor {
+    fn from(descriptor: descriptor::Descriptor<bitcoin::PublicKey>) -> Self {
+        Self(descriptor)
+    }
+}
+
+impl_sqlx_type_display_from_str!(Descriptor);
+
 /// Represents "quantity" or "contract size" in Cfd terms
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd)]
 pub struct Contracts(Decimal);
@@ -968,19 +1001,18 @@ pub fn into_complete_fee(
 
 pub fn into_complete_fee_and_flow(
     complete_fee: Option<model::CompleteFee>,
-) -> (Option<i64>, Option<FeeFlow>) {
-    match complete_fee {
-        None => (None, None),
-        Some(complete_fee) => match complete_fee {
-            model::CompleteFee::LongPaysShort(amount) => {
-                (Some(amount.as_sat() as i64), Some(FeeFlow::LongPaysShort))
-            }
-            model::CompleteFee::ShortPaysLong(amount) => {
-                (Some(amount.as_sat() as i64), Some(FeeFlow::ShortPaysLong))
-            }
-            model::CompleteFee::None => (Some(0), Some(FeeFlow::None)),
-        },
-    }
+) -> Result<(Option<i64>, Option<FeeFlow>)> {
+    let (amount, flow) = match complete_fee {
+        None => return Ok((None, None)),
+        Some(model::CompleteFee::LongPaysShort(amount)) => (amount, FeeFlow::LongPaysShort),
+        Some(model::CompleteFee::ShortPaysLong(amount)) => (amount, FeeFlow::ShortPaysLong),
+        Some(model::CompleteFee::None) => (Amount::ZERO, FeeFlow::None),
+    };
+
+    let amount = i64::try_from(amount.as_sat())
+        .with_context(|| format!("Complete fee {amount} does not fit into i64"))?;
+
+    Ok((Some(amount), Some(flow)))
 }
 
 /// Trading pair of the Cfd
@@ -1008,6 +1040,38 @@ impl From<ContractSymbol> for model::ContractSymbol {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ProtocolVersion(model::ProtocolVersion);
+
+impl From<model::ProtocolVersion> for ProtocolVersion {
+    fn from(version: model::ProtocolVersion) -> Self {
+        Self(version)
+    }
+}
+
+impl From<ProtocolVersion> for model::ProtocolVersion {
+    fn from(version: ProtocolVersion) -> Self {
+        version.0
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for ProtocolVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let version = model::ProtocolVersion::from_str(s)?;
+        Ok(Self(version))
+    }
+}
+
+impl_sqlx_type_display_from_str!(ProtocolVersion);
+
 #[derive(Debug)]
 pub struct User {
     pub id: u32,
@@ -1019,11 +1083,18 @@ pub struct User {
 mod tests {
     use super::*;
 
+    #[test]
+    fn given_invalid_descriptor_string_then_parsing_fails() {
+        let result = Descriptor::from_str("not-a-descriptor");
+
+        assert!(result.is_err());
+    }
+
     #[test]
     fn into_complete_fee_and_flow_long_pays_short() {
         let model_complete_fee = model::CompleteFee::LongPaysShort(Amount::from_sat(1000));
         let (complete_fee, complete_fee_flow) =
-            into_complete_fee_and_flow(Some(model_complete_fee));
+            into_complete_fee_and_flow(Some(model_complete_fee)).unwrap();
 
         assert_eq!(complete_fee_flow, Some(FeeFlow::LongPaysShort));
         assert_eq!(complete_fee, Some(1000));
@@ -1033,7 +1104,7 @@ mod tests {
     fn into_complete_fee_and_flow_short_pays_long() {
         let model_complete_fee = model::CompleteFee::ShortPaysLong(Amount::from_sat(1000));
         let (complete_fee, complete_fee_flow) =
-            into_complete_fee_and_flow(Some(model_complete_fee));
+            into_complete_fee_and_flow(Some(model_complete_fee)).unwrap();
 
         assert_eq!(complete_fee_flow, Some(FeeFlow::ShortPaysLong));
         assert_eq!(complete_fee, Some(1000));
@@ -1043,7 +1114,7 @@ mod tests {
     fn into_complete_fee_and_flow_none_fee_flow() {
         let model_complete_fee = model::CompleteFee::None;
         let (complete_fee, complete_fee_flow) =
-            into_complete_fee_and_flow(Some(model_complete_fee));
+            into_complete_fee_and_flow(Some(model_complete_fee)).unwrap();
 
         assert_eq!(complete_fee_flow, Some(FeeFlow::None));
         assert_eq!(complete_fee, Some(0));
@@ -1051,12 +1122,19 @@ mod tests {
 
     #[test]
     fn into_complete_fee_and_flow_none_fee_none() {
-        let (complete_fee, complete_fee_flow) = into_complete_fee_and_flow(None);
+        let (complete_fee, complete_fee_flow) = into_complete_fee_and_flow(None).unwrap();
 
         assert_eq!(complete_fee_flow, None);
         assert_eq!(complete_fee, None);
     }
 
+    #[test]
+    fn into_complete_fee_and_flow_amount_exceeding_i64_fails() {
+        let model_complete_fee = model::CompleteFee::LongPaysShort(Amount::from_sat(u64::MAX));
+
+        assert!(into_complete_fee_and_flow(Some(model_complete_fee)).is_err());
+    }
+
     #[test]
     fn into_complete_fee_long_pays_short() {
         let complete_fee = into_complete_fee(Some(FeeFlow::LongPaysShort), Some(1000));
@@ -1,8 +1,135 @@
+use crate::models;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::Amount;
+use conquer_once::Lazy;
+use dashmap::DashMap;
+use prometheus::HistogramVec;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::sync::OwnedMutexGuard;
+
 mod load;
 mod overwrite;
 
+pub use load::count_cets;
+pub use load::find_cfds_with_orphaned_cets;
+pub use load::find_cfds_without_cets;
 pub use load::load;
+pub use load::load_active_settlement_events;
+pub use load::load_cet_by_txid;
+pub use load::load_funding_rate_history;
+pub use load::load_rolled_over_cfds;
+pub use load::total_open_exposure;
+pub use overwrite::insert_cets;
 pub use overwrite::overwrite;
+pub use overwrite::set_expected_cets;
+
+/// Convert an amount to the signed integer SQLite stores it as.
+///
+/// Fails instead of wrapping around if the amount exceeds `i64::MAX` sats.
+pub(crate) fn amount_to_sql(amount: Amount) -> Result<i64> {
+    i64::try_from(amount.as_sat()).with_context(|| format!("Amount {amount} does not fit into i64"))
+}
+
+/// Convert an integer loaded from SQLite back into an amount.
+///
+/// Fails if the stored value is negative, which can only happen if the row was corrupted.
+pub(crate) fn amount_from_sql(sats: i64) -> Result<Amount> {
+    let sats =
+        u64::try_from(sats).with_context(|| format!("Invalid negative amount {sats} sats"))?;
+
+    Ok(Amount::from_sat(sats))
+}
+
+/// Per-CFD locks ensuring that at most one rollover is persisted per CFD at a time.
+///
+/// Persisting a rollover deletes the previous rollover data of the CFD before inserting the new
+/// one, possibly across several transactions. Two of these running concurrently for the same CFD
+/// could interleave and leave a mix of both DLCs behind.
+#[derive(Clone, Default)]
+pub(crate) struct RolloverLocks(Arc<DashMap<models::OrderId, Arc<Mutex<()>>>>);
+
+impl RolloverLocks {
+    /// Wait until no other rollover of the CFD is being persisted.
+    pub(crate) async fn lock(&self, order_id: models::OrderId) -> RolloverGuard {
+        let mutex = self.0.entry(order_id).or_default().clone();
+        let guard = mutex.lock_owned().await;
+
+        RolloverGuard {
+            locks: self.clone(),
+            order_id,
+            guard: Some(guard),
+        }
+    }
+}
+
+pub(crate) struct RolloverGuard {
+    locks: RolloverLocks,
+    order_id: models::OrderId,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for RolloverGuard {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+
+        // Nobody else is waiting for the lock if the map holds the only reference to it
+        self.locks
+            .0
+            .remove_if(&self.order_id, |_, mutex| Arc::strong_count(mutex) == 1);
+    }
+}
+
+/// Record how long persisting a rollover with `num_cets` CETs took, including deferred CETs.
+pub(crate) fn observe_insert_duration(num_cets: usize, duration: std::time::Duration) {
+    ROLLOVER_INSERT_DURATION_HISTOGRAM
+        .with_label_values(&[cets_bucket(num_cets)])
+        .observe(duration.as_secs_f64());
+}
+
+/// Record how long loading a rollover with `num_cets` CETs took.
+pub(crate) fn observe_load_duration(num_cets: usize, duration: std::time::Duration) {
+    ROLLOVER_LOAD_DURATION_HISTOGRAM
+        .with_label_values(&[cets_bucket(num_cets)])
+        .observe(duration.as_secs_f64());
+}
+
+/// Group CET counts into a few buckets to keep the cardinality of the label low.
+fn cets_bucket(num_cets: usize) -> &'static str {
+    match num_cets {
+        0 => "0",
+        1..=99 => "1-99",
+        100..=999 => "100-999",
+        _ => "1000+",
+    }
+}
+
+const CETS_LABEL: &str = "cets";
+
+const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+static ROLLOVER_INSERT_DURATION_HISTOGRAM: Lazy<HistogramVec> = Lazy::new(|| {
+    prometheus::register_histogram_vec!(
+        "sqlite_rollover_insert_duration_seconds",
+        "The duration of persisting a rollover in seconds, segregated by number of CETs.",
+        &[CETS_LABEL],
+        DURATION_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+static ROLLOVER_LOAD_DURATION_HISTOGRAM: Lazy<HistogramVec> = Lazy::new(|| {
+    prometheus::register_histogram_vec!(
+        "sqlite_rollover_load_duration_seconds",
+        "The duration of loading a rollover in seconds, segregated by number of CETs.",
+        &[CETS_LABEL],
+        DURATION_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
 
 #[cfg(test)]
 mod tests {
@@ -12,7 +139,10 @@ mod tests {
     use anyhow::bail;
     use anyhow::Context;
     use anyhow::Result;
+    use bdk::bitcoin::secp256k1::Message;
+    use bdk::bitcoin::secp256k1::SECP256K1;
     use bdk::bitcoin::Amount;
+    use bdk::bitcoin::Txid;
     use model::olivia::BitMexPriceEventId;
     use model::Cfd;
     use model::CfdEvent;
@@ -34,6 +164,8 @@ mod tests {
     use model::TxFeeRate;
     use rust_decimal_macros::dec;
     use sqlx::SqliteConnection;
+    use std::collections::HashSet;
+    use std::num::NonZeroUsize;
     use time::macros::datetime;
     use time::Duration;
     use time::OffsetDateTime;
@@ -56,6 +188,7 @@ mod tests {
             FundingRate::default(),
             TxFeeRate::default(),
             ContractSymbol::BtcUsd,
+            None,
         )
     }
 
@@ -95,6 +228,335 @@ mod tests {
         assert_eq!(cets, 2);
     }
 
+    #[tokio::test]
+    async fn given_unknown_order_id_when_inserting_rollover_data_then_cfd_not_found() -> Result<()>
+    {
+        let db = memory().await?;
+        let mut conn = db.inner.acquire().await?;
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+
+        let order_id = OrderId::default();
+        let (dlc, funding_fee, complete_fee) = extract_rollover_completed_data(event);
+        let error = overwrite(
+            &mut *conn,
+            1,
+            order_id.into(),
+            dlc,
+            funding_fee,
+            complete_fee,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<crate::Error>(),
+            Some(crate::Error::CfdNotFound(id)) if *id == order_id
+        ));
+
+        let (rollovers, revokes, cets) = count_table_entries(&mut *conn).await;
+        assert_eq!(rollovers, 0);
+        assert_eq!(revokes, 0);
+        assert_eq!(cets, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_cets_per_transaction_when_appending_rollover_then_all_cets_inserted(
+    ) -> Result<()> {
+        let db = memory()
+            .await?
+            .with_cets_per_transaction(NonZeroUsize::new(1).context("non-zero")?);
+        let mut conn = db.inner.acquire().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event,
+        })
+        .await?;
+
+        let (rollovers, revokes, cets) = count_table_entries(&mut *conn).await;
+        assert_eq!(rollovers, 1);
+        assert_eq!(revokes, 2);
+        assert_eq!(cets, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_concurrent_rollovers_of_same_cfd_then_only_one_rollover_is_stored() -> Result<()>
+    {
+        let db = memory()
+            .await?
+            .with_cets_per_transaction(NonZeroUsize::new(1).context("non-zero")?);
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+        let first_rollover = CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event: event.clone(),
+        };
+        let second_rollover = update_event_id(
+            Timestamp::now(),
+            event,
+            datetime!(2021-06-01 10:00:00).assume_utc(),
+            cfd.id(),
+            cfd.contract_symbol(),
+        )?;
+
+        let (first, second) = tokio::join!(
+            db.append_event(first_rollover),
+            db.append_event(second_rollover)
+        );
+        first?;
+        second?;
+
+        let mut conn = db.inner.acquire().await?;
+        let (rollovers, revokes, cets) = count_table_entries(&mut *conn).await;
+        assert_eq!(rollovers, 1);
+        assert_eq!(revokes, 2);
+        assert_eq!(cets, 2);
+
+        assert!(
+            db.rollover_locks.0.is_empty(),
+            "Locks of finished rollovers are cleaned up"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_lock_amounts_disagree_with_cfd_then_flagged_as_inconsistent() -> Result<()> {
+        let db = memory().await?;
+        let mut conn = db.inner.acquire().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event: event.clone(),
+        })
+        .await?;
+
+        let (mut dlc, funding_fee, complete_fee) = extract_rollover_completed_data(event);
+        dlc.maker_lock_amount = model::calculate_margin(
+            cfd.contract_symbol(),
+            cfd.initial_price(),
+            cfd.quantity(),
+            Leverage::ONE,
+        );
+        dlc.taker_lock_amount = model::calculate_margin(
+            cfd.contract_symbol(),
+            cfd.initial_price(),
+            cfd.quantity(),
+            cfd.taker_leverage(),
+        );
+        overwrite(
+            &mut *conn,
+            1,
+            cfd.id().into(),
+            dlc.clone(),
+            funding_fee,
+            complete_fee,
+        )
+        .await?;
+
+        assert!(db.check_lock_amount_consistency().await?.is_empty());
+
+        dlc.maker_lock_amount += Amount::from_sat(1);
+        overwrite(
+            &mut *conn,
+            1,
+            cfd.id().into(),
+            dlc,
+            funding_fee,
+            complete_fee,
+        )
+        .await?;
+
+        assert_eq!(db.check_lock_amount_consistency().await?, vec![cfd.id()]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_rolled_over_cfds_then_total_open_exposure_sums_lock_amounts() -> Result<()> {
+        let db = memory().await?;
+
+        let (maker, taker) = db.total_open_exposure().await?;
+        assert_eq!(maker, Amount::ZERO);
+        assert_eq!(taker, Amount::ZERO);
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+        let (dlc, _, _) = extract_rollover_completed_data(event.clone());
+
+        for _ in 0..2 {
+            let cfd = dummy_cfd();
+            db.insert_cfd(&cfd).await?;
+            db.append_event(CfdEvent {
+                timestamp: Timestamp::now(),
+                id: cfd.id(),
+                event: event.clone(),
+            })
+            .await?;
+        }
+
+        let (maker, taker) = db.total_open_exposure().await?;
+        assert_eq!(maker, dlc.maker_lock_amount * 2);
+        assert_eq!(taker, dlc.taker_lock_amount * 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_rolled_over_cfds_then_settlement_events_of_latest_rollovers_are_active(
+    ) -> Result<()> {
+        let db = memory().await?;
+
+        assert!(db.load_active_settlement_events().await?.is_empty());
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+        let (dlc, _, _) = extract_rollover_completed_data(event.clone());
+
+        // Two CFDs rolled over to the same settlement event
+        for _ in 0..2 {
+            let cfd = dummy_cfd();
+            db.insert_cfd(&cfd).await?;
+            db.append_event(CfdEvent {
+                timestamp: Timestamp::now(),
+                id: cfd.id(),
+                event: event.clone(),
+            })
+            .await?;
+        }
+
+        // A CFD rolled over twice, only its latest settlement event is active
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event: event.clone(),
+        })
+        .await?;
+        let latest_rollover = update_event_id(
+            Timestamp::now(),
+            event,
+            datetime!(2021-06-01 10:00:00).assume_utc(),
+            cfd.id(),
+            cfd.contract_symbol(),
+        )?;
+        let latest_settlement_event_id = match &latest_rollover.event {
+            EventKind::RolloverCompleted { dlc: Some(dlc), .. } => dlc.settlement_event_id,
+            _ => bail!("Expected rollover completed event"),
+        };
+        db.append_event(latest_rollover).await?;
+
+        let active_settlement_events = db.load_active_settlement_events().await?;
+
+        assert_eq!(
+            active_settlement_events,
+            HashSet::from([dlc.settlement_event_id, latest_settlement_event_id])
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_rolled_over_cfd_twice_then_funding_rate_history_contains_both_rollovers(
+    ) -> Result<()> {
+        let db = memory().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        assert!(db.load_funding_rate_history(cfd.id()).await?.is_empty());
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+        let (_, first_funding_fee, _) = extract_rollover_completed_data(event.clone());
+        let first_timestamp = Timestamp::new(1_000);
+
+        db.append_event(CfdEvent {
+            timestamp: first_timestamp,
+            id: cfd.id(),
+            event: event.clone(),
+        })
+        .await?;
+
+        // Another CFD's rollover does not show up in the history
+        let other_cfd = dummy_cfd();
+        db.insert_cfd(&other_cfd).await?;
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::new(1_500),
+            id: other_cfd.id(),
+            event: event.clone(),
+        })
+        .await?;
+
+        let second_funding_fee = FundingFee {
+            fee: first_funding_fee.fee + Amount::from_sat(1_000),
+            rate: FundingRate::new(dec!(-0.0005))?,
+        };
+        let second_timestamp = Timestamp::new(2_000);
+        let second_rollover = match update_event_id(
+            second_timestamp,
+            event,
+            datetime!(2021-06-01 10:00:00).assume_utc(),
+            cfd.id(),
+            cfd.contract_symbol(),
+        )? {
+            CfdEvent {
+                event:
+                    EventKind::RolloverCompleted {
+                        dlc, complete_fee, ..
+                    },
+                timestamp,
+                id,
+            } => CfdEvent {
+                timestamp,
+                id,
+                event: EventKind::RolloverCompleted {
+                    dlc,
+                    funding_fee: second_funding_fee,
+                    complete_fee,
+                },
+            },
+            _ => bail!("Expected rollover completed event"),
+        };
+        db.append_event(second_rollover).await?;
+
+        assert_eq!(
+            db.load_funding_rate_history(cfd.id()).await?,
+            vec![
+                (first_timestamp, first_funding_fee),
+                (second_timestamp, second_funding_fee)
+            ]
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn repeatedly_insert_rollover_completed_event_data_should_not_error() -> Result<()> {
         let db = memory().await?;
@@ -157,6 +619,47 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn given_more_revoked_commits_than_fit_in_one_statement_then_all_are_inserted(
+    ) -> Result<()> {
+        let db = memory().await?;
+        let mut conn = db.inner.acquire().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+
+        let rollover_completed = CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event: event.clone(),
+        };
+        db.append_event(rollover_completed).await?;
+
+        let (mut dlc, funding_fee, complete_fee) = extract_rollover_completed_data(event);
+        let revoked_commit = dlc.revoked_commit[0].clone();
+        dlc.revoked_commit = vec![revoked_commit; 250];
+
+        overwrite(
+            &mut *conn,
+            1,
+            cfd.id().into(),
+            dlc,
+            funding_fee,
+            complete_fee,
+        )
+        .await?;
+
+        let (rollovers, revokes, cets) = count_table_entries(&mut *conn).await;
+        assert_eq!(rollovers, 1);
+        assert_eq!(revokes, 250);
+        assert_eq!(cets, 2);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn given_one_rollover_event_load_should_not_error() -> Result<()> {
         let db = memory().await?;
@@ -211,25 +714,477 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn when_having_two_rollovers_should_load_last() -> Result<()> {
+    async fn given_rollover_event_when_loading_cet_by_txid_then_cet_and_order_id_found(
+    ) -> Result<()> {
         let db = memory().await?;
         let mut conn = db.inner.acquire().await?;
 
         let cfd = dummy_cfd();
         db.insert_cfd(&cfd).await?;
-        let timestamp = Timestamp::now();
+
         let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
         let event = serde_json::from_str::<EventKind>(&event)?;
-        let rollover_completed = CfdEvent {
-            timestamp,
+
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
             id: cfd.id(),
             event: event.clone(),
-        };
-
-        // insert first RolloverCompleted event data
-        db.append_event(rollover_completed.clone()).await?;
+        })
+        .await?;
 
-        let (dlc, funding_fee, complete_fee) = extract_rollover_completed_data(event.clone());
+        let (dlc, funding_fee, complete_fee) = extract_rollover_completed_data(event);
+        overwrite(
+            &mut *conn,
+            1,
+            cfd.id().into(),
+            dlc.clone(),
+            funding_fee,
+            complete_fee,
+        )
+        .await?;
+
+        let cet = dlc
+            .cets
+            .values()
+            .flatten()
+            .next()
+            .context("Expect at least one CET")?;
+
+        let (order_id, loaded_cet) = load_cet_by_txid(&mut *conn, cet.txid)
+            .await?
+            .context("Expect to find CET")?;
+
+        assert_eq!(order_id, cfd.id());
+        assert_eq!(loaded_cet.txid, cet.txid);
+        assert_eq!(loaded_cet.range, cet.range);
+        assert_eq!(loaded_cet.maker_amount, cet.maker_amount);
+        assert_eq!(loaded_cet.taker_amount, cet.taker_amount);
+
+        let unknown_txid = dlc.commit.0.txid();
+        assert!(load_cet_by_txid(&mut *conn, unknown_txid).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_rollover_event_when_counting_cets_then_matches_dlc() -> Result<()> {
+        let db = memory().await?;
+        let mut conn = db.inner.acquire().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        let order_id = models::OrderId::from(cfd.id());
+        let cfd_row_id = sqlx::query!(r#"select id from cfds where order_id = $1"#, order_id)
+            .fetch_one(&mut *conn)
+            .await?
+            .id
+            .unwrap();
+
+        assert_eq!(count_cets(&mut *conn, cfd_row_id).await?, 0);
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event: event.clone(),
+        })
+        .await?;
+
+        let (dlc, funding_fee, complete_fee) = extract_rollover_completed_data(event);
+        overwrite(
+            &mut *conn,
+            1,
+            cfd.id().into(),
+            dlc.clone(),
+            funding_fee,
+            complete_fee,
+        )
+        .await?;
+
+        let expected = dlc.cets.values().map(|cets| cets.len() as u64).sum::<u64>();
+        assert_eq!(count_cets(&mut *conn, cfd_row_id).await?, expected);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_rollover_without_cets_then_cfd_found_without_cets() -> Result<()> {
+        let db = memory().await?;
+        let mut conn = db.inner.acquire().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event: event.clone(),
+        })
+        .await?;
+
+        assert!(find_cfds_without_cets(&mut *conn).await?.is_empty());
+
+        let (mut dlc, funding_fee, complete_fee) = extract_rollover_completed_data(event);
+        dlc.cets.clear();
+        overwrite(
+            &mut *conn,
+            1,
+            cfd.id().into(),
+            dlc,
+            funding_fee,
+            complete_fee,
+        )
+        .await?;
+
+        assert_eq!(find_cfds_without_cets(&mut *conn).await?, vec![cfd.id()]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_rollover_with_partially_inserted_cets_then_load_fails_and_cfd_found(
+    ) -> Result<()> {
+        let db = memory().await?;
+        let mut conn = db.inner.acquire().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event,
+        })
+        .await?;
+
+        let order_id = models::OrderId::from(cfd.id());
+        let cfd_row_id = sqlx::query!(r#"select id from cfds where order_id = $1"#, order_id)
+            .fetch_one(&mut *conn)
+            .await?
+            .id
+            .unwrap();
+
+        assert!(load(&mut *conn, cfd_row_id, 1).await?.is_some());
+
+        // As if we crashed before inserting the last chunk of CETs
+        sqlx::query("DELETE FROM open_cets WHERE id = (SELECT MAX(id) FROM open_cets)")
+            .execute(&mut *conn)
+            .await?;
+
+        assert!(load(&mut *conn, cfd_row_id, 1).await.is_err());
+        assert_eq!(find_cfds_without_cets(&mut *conn).await?, vec![cfd.id()]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_refund_timelock_within_margin_then_cfd_due_for_rollover() -> Result<()> {
+        let db = memory().await?;
+        let mut conn = db.inner.acquire().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event: event.clone(),
+        })
+        .await?;
+
+        let (dlc, funding_fee, complete_fee) = extract_rollover_completed_data(event);
+        let refund_timelock = dlc.refund_timelock;
+        let lock_txid = dlc.lock.0.txid();
+        overwrite(
+            &mut *conn,
+            1,
+            cfd.id().into(),
+            dlc,
+            funding_fee,
+            complete_fee,
+        )
+        .await?;
+
+        let lock_confirmed_at = 700_000;
+        let confirmation_height = |txid: Txid| (txid == lock_txid).then(|| lock_confirmed_at);
+
+        let due = db
+            .load_cfds_due_for_rollover(
+                lock_confirmed_at + refund_timelock - 10,
+                10,
+                confirmation_height,
+            )
+            .await?;
+        assert_eq!(due, vec![cfd.id()]);
+
+        let not_yet_due = db
+            .load_cfds_due_for_rollover(
+                lock_confirmed_at + refund_timelock - 11,
+                10,
+                confirmation_height,
+            )
+            .await?;
+        assert!(not_yet_due.is_empty());
+
+        // The refund timelock is relative, thus it must not be compared to the height directly
+        let not_due_at_relative_timelock = db
+            .load_cfds_due_for_rollover(refund_timelock, 10, confirmation_height)
+            .await?;
+        assert!(not_due_at_relative_timelock.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_commit_tx_confirmed_then_refund_timelock_relative_to_commit_tx() -> Result<()> {
+        let db = memory().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+        let (dlc, _, _) = extract_rollover_completed_data(event.clone());
+
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event,
+        })
+        .await?;
+
+        let lock_txid = dlc.lock.0.txid();
+        let commit_txid = dlc.commit.0.txid();
+        let confirmation_height = |txid: Txid| match txid {
+            txid if txid == lock_txid => Some(700_000),
+            txid if txid == commit_txid => Some(700_100),
+            _ => None,
+        };
+
+        let due = db
+            .load_cfds_due_for_rollover(700_000 + dlc.refund_timelock, 10, confirmation_height)
+            .await?;
+        assert!(due.is_empty());
+
+        let due = db
+            .load_cfds_due_for_rollover(700_100 + dlc.refund_timelock, 10, confirmation_height)
+            .await?;
+        assert_eq!(due, vec![cfd.id()]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_cfd_never_rolled_over_then_due_according_to_contract_setup() -> Result<()> {
+        let db = memory().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let (dlc, _, _) = extract_rollover_completed_data(serde_json::from_str(&event)?);
+
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event: EventKind::ContractSetupCompleted {
+                dlc: Some(dlc.clone()),
+            },
+        })
+        .await?;
+
+        let lock_txid = dlc.lock.0.txid();
+        let confirmation_height = |txid: Txid| (txid == lock_txid).then(|| 700_000);
+
+        let due = db
+            .load_cfds_due_for_rollover(700_000 + dlc.refund_timelock, 10, confirmation_height)
+            .await?;
+        assert_eq!(due, vec![cfd.id()]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_commit_descriptor_does_not_match_keys_then_still_loaded() -> Result<()> {
+        let db = memory().await?;
+        let mut conn = db.inner.acquire().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+
+        let rollover_completed = CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event: event.clone(),
+        };
+        db.append_event(rollover_completed).await?;
+
+        let (mut dlc, funding_fee, complete_fee) = extract_rollover_completed_data(event);
+        dlc.commit.2 = dlc.lock.1.clone();
+
+        overwrite(
+            &mut *conn,
+            1,
+            cfd.id().into(),
+            dlc,
+            funding_fee,
+            complete_fee,
+        )
+        .await?;
+
+        let order_id = models::OrderId::from(cfd.id());
+
+        let cfd_row_id = sqlx::query!(r#"select id from cfds where order_id = $1"#, order_id)
+            .fetch_one(&mut *conn)
+            .await?
+            .id
+            .unwrap();
+
+        let (dlc, _, _) = load(&mut *conn, cfd_row_id, 1).await?.unwrap();
+        let error = dlc.verify(cfd.role()).unwrap_err();
+
+        assert!(error.to_string().contains("Commit descriptor"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn given_refund_signature_does_not_verify_then_still_loaded() -> Result<()> {
+        let db = memory().await?;
+        let mut conn = db.inner.acquire().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event: event.clone(),
+        })
+        .await?;
+
+        let (mut dlc, funding_fee, complete_fee) = extract_rollover_completed_data(event);
+        let unrelated_message = Message::from_slice(&[1; 32])?;
+        dlc.refund.1 = SECP256K1.sign_ecdsa(&unrelated_message, &dlc.identity);
+
+        overwrite(
+            &mut *conn,
+            1,
+            cfd.id().into(),
+            dlc,
+            funding_fee,
+            complete_fee,
+        )
+        .await?;
+
+        let order_id = models::OrderId::from(cfd.id());
+
+        let cfd_row_id = sqlx::query!(r#"select id from cfds where order_id = $1"#, order_id)
+            .fetch_one(&mut *conn)
+            .await?
+            .id
+            .unwrap();
+
+        let (dlc, _, _) = load(&mut *conn, cfd_row_id, 1).await?.unwrap();
+        let error = dlc.verify(cfd.role()).unwrap_err();
+
+        assert!(error.to_string().contains("refund signature"));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "verify-cets")]
+    #[tokio::test]
+    async fn given_cet_does_not_match_dlc_when_loading_then_error_names_cet() -> Result<()> {
+        let db = memory().await?;
+        let mut conn = db.inner.acquire().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+
+        db.append_event(CfdEvent {
+            timestamp: Timestamp::now(),
+            id: cfd.id(),
+            event: event.clone(),
+        })
+        .await?;
+
+        let (mut dlc, funding_fee, complete_fee) = extract_rollover_completed_data(event);
+        let cet = dlc
+            .cets
+            .values_mut()
+            .flatten()
+            .next()
+            .context("Expect at least one CET")?;
+        cet.maker_amount += Amount::from_sat(1);
+        let tampered_txid = cet.txid;
+
+        overwrite(
+            &mut *conn,
+            1,
+            cfd.id().into(),
+            dlc,
+            funding_fee,
+            complete_fee,
+        )
+        .await?;
+
+        let order_id = models::OrderId::from(cfd.id());
+
+        let cfd_row_id = sqlx::query!(r#"select id from cfds where order_id = $1"#, order_id)
+            .fetch_one(&mut *conn)
+            .await?
+            .id
+            .unwrap();
+
+        let error = load(&mut *conn, cfd_row_id, 1).await.unwrap_err();
+
+        assert!(error.to_string().contains(&tampered_txid.to_string()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn when_having_two_rollovers_should_load_last() -> Result<()> {
+        let db = memory().await?;
+        let mut conn = db.inner.acquire().await?;
+
+        let cfd = dummy_cfd();
+        db.insert_cfd(&cfd).await?;
+        let timestamp = Timestamp::now();
+        let event = std::fs::read_to_string("./src/test_events/rollover_completed.json")?;
+        let event = serde_json::from_str::<EventKind>(&event)?;
+        let rollover_completed = CfdEvent {
+            timestamp,
+            id: cfd.id(),
+            event: event.clone(),
+        };
+
+        // insert first RolloverCompleted event data
+        db.append_event(rollover_completed.clone()).await?;
+
+        let (dlc, funding_fee, complete_fee) = extract_rollover_completed_data(event.clone());
         overwrite(
             &mut *conn,
             1,
@@ -285,6 +1240,26 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn given_amount_above_i64_max_then_conversion_to_sql_fails() {
+        assert!(amount_to_sql(Amount::from_sat(i64::MAX as u64)).is_ok());
+        assert!(amount_to_sql(Amount::from_sat(i64::MAX as u64 + 1)).is_err());
+    }
+
+    #[test]
+    fn cet_counts_are_grouped_into_buckets() {
+        assert_eq!(cets_bucket(0), "0");
+        assert_eq!(cets_bucket(1), "1-99");
+        assert_eq!(cets_bucket(100), "100-999");
+        assert_eq!(cets_bucket(1000), "1000+");
+    }
+
+    #[test]
+    fn given_negative_stored_amount_then_conversion_from_sql_fails() {
+        assert_eq!(amount_from_sql(1000).unwrap(), Amount::from_sat(1000));
+        assert!(amount_from_sql(-1).is_err());
+    }
+
     async fn count_table_entries(conn: &mut SqliteConnection) -> (i32, i32, i32) {
         let row = sqlx::query!(
             r#"
@@ -1,20 +1,29 @@
 use crate::models;
 use crate::models::into_complete_fee;
+use crate::rollover::amount_from_sql;
+use anyhow::bail;
+#[cfg(feature = "verify-cets")]
+use anyhow::Context;
 use anyhow::Result;
+use bdk::bitcoin;
 use bdk::bitcoin::hashes::hex::FromHex;
 use bdk::bitcoin::secp256k1;
 use bdk::bitcoin::Address;
 use bdk::bitcoin::Amount;
 use bdk::bitcoin::Script;
-use bdk::descriptor::Descriptor;
 use model::olivia::BitMexPriceEventId;
 use model::Cet;
 use model::CompleteFee;
 use model::Dlc;
+use model::EventKind;
 use model::FundingFee;
+use model::OrderId;
 use model::RevokedCommit;
+use model::Role;
+use model::Timestamp;
 use sqlx::SqliteConnection;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::ops::RangeInclusive;
 use std::str::FromStr;
 
@@ -29,6 +38,7 @@ pub async fn load(
 ) -> Result<Option<(Dlc, FundingFee, Option<CompleteFee>)>> {
     let revoked_commit = load_revoked_commit_transactions(&mut *conn, cfd_row_id).await?;
     let cets = load_cets(&mut *conn, cfd_row_id).await?;
+    ensure_all_cets_loaded(&mut *conn, cfd_row_id, &cets).await?;
 
     let row = sqlx::query!(
         r#"
@@ -48,10 +58,10 @@ pub async fn load(
                 revocation_secret as "revocation_secret: models::SecretKey",
                 revocation_pk_counterparty as "revocation_pk_counterparty: models::PublicKey",
                 lock_tx as "lock_tx: models::Transaction",
-                lock_tx_descriptor,
+                lock_tx_descriptor as "lock_tx_descriptor: models::Descriptor",
                 commit_tx as "commit_tx: models::Transaction",
                 commit_adaptor_signature as "commit_adaptor_signature: models::AdaptorSignature",
-                commit_descriptor,
+                commit_descriptor as "commit_descriptor: models::Descriptor",
                 refund_tx as "refund_tx: models::Transaction",
                 refund_signature,
                 complete_fee as "complete_fee: i64",
@@ -82,36 +92,128 @@ pub async fn load(
         publish_pk_counterparty: row.publish_pk_counterparty.into(),
         maker_address: Address::from_str(row.maker_address.as_str())?,
         taker_address: Address::from_str(row.taker_address.as_str())?,
-        lock: (
-            row.lock_tx.into(),
-            Descriptor::from_str(row.lock_tx_descriptor.as_str())?,
-        ),
+        lock: (row.lock_tx.into(), row.lock_tx_descriptor.into()),
         commit: (
             row.commit_tx.into(),
             row.commit_adaptor_signature.into(),
-            Descriptor::from_str(row.commit_descriptor.as_str())?,
+            row.commit_descriptor.into(),
         ),
         refund: (
             row.refund_tx.into(),
             secp256k1::ecdsa::Signature::from_str(row.refund_signature.as_str())?,
         ),
         cets,
-        maker_lock_amount: Amount::from_sat(row.maker_lock_amount as u64),
-        taker_lock_amount: Amount::from_sat(row.taker_lock_amount as u64),
+        maker_lock_amount: amount_from_sql(row.maker_lock_amount)?,
+        taker_lock_amount: amount_from_sql(row.taker_lock_amount)?,
         revoked_commit,
         settlement_event_id: row.settlement_event_id.into(),
         refund_timelock: row.refund_timelock as u32,
     };
     let funding_fee = FundingFee {
-        fee: Amount::from_sat(row.funding_fee as u64),
+        fee: amount_from_sql(row.funding_fee)?,
         rate: row.rate.into(),
     };
 
     let complete_fee = into_complete_fee(row.complete_fee_flow, row.complete_fee);
 
+    // DLCs are verified when they are created during contract setup or rollover. Failing here
+    // would make CFDs unusable whose DLC was persisted before a check was introduced or tightened.
+    let role = load_role(&mut *conn, cfd_row_id).await?;
+    if let Err(e) = dlc.verify(role) {
+        tracing::warn!(cfd_row_id, "Loaded DLC failed verification: {e:#}");
+    }
+
+    #[cfg(feature = "verify-cets")]
+    verify_cets(&dlc)?;
+
     Ok(Some((dlc, funding_fee, complete_fee)))
 }
 
+/// Fail if fewer CETs were loaded than the latest rollover of the CFD has.
+///
+/// This happens if the CETs of the rollover were inserted in several transactions and not all of
+/// them have been inserted (yet). Rollovers persisted before the number of CETs was recorded are
+/// not checked.
+async fn ensure_all_cets_loaded(
+    conn: &mut SqliteConnection,
+    cfd_row_id: i64,
+    cets: &HashMap<BitMexPriceEventId, Vec<Cet>>,
+) -> Result<()> {
+    let row = sqlx::query!(
+        r#"
+            SELECT
+                expected_cets
+            FROM
+                rollover_completed_event_data
+            WHERE
+                cfd_id = $1
+            "#,
+        cfd_row_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let expected_cets = match row.and_then(|row| row.expected_cets) {
+        Some(expected_cets) => expected_cets,
+        None => return Ok(()),
+    };
+    let num_cets = cets.values().map(Vec::len).sum::<usize>();
+
+    if i64::try_from(num_cets)? != expected_cets {
+        bail!("Rollover is incomplete: loaded {num_cets} of {expected_cets} CETs");
+    }
+
+    Ok(())
+}
+
+async fn load_role(conn: &mut SqliteConnection, cfd_row_id: i64) -> Result<Role> {
+    let row = sqlx::query!(
+        r#"
+            SELECT
+                role as "role: models::Role"
+            FROM
+                cfds
+            WHERE
+                id = $1
+            "#,
+        cfd_row_id,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(row.role.into())
+}
+
+/// Verify that every stored CET can be reconstructed from the stored commit transaction and
+/// payout addresses.
+///
+/// Each CET's adaptor signature was produced over the reconstructed transaction, so a mismatch
+/// means that the signature cannot be used at settlement. The adaptor signatures themselves cannot
+/// be verified here because that requires the oracle's announced nonces, which are not persisted.
+///
+/// This is expensive for DLCs with many CETs and therefore only enabled with the `verify-cets`
+/// feature.
+#[cfg(feature = "verify-cets")]
+fn verify_cets(dlc: &Dlc) -> Result<()> {
+    for (event_id, cets) in dlc.cets.iter() {
+        for cet in cets {
+            cet.to_tx(
+                (&dlc.commit.0, &dlc.commit.2),
+                &dlc.maker_address,
+                &dlc.taker_address,
+            )
+            .with_context(|| {
+                format!(
+                    "Stored CET {} for range {:?} of event {event_id} is invalid",
+                    cet.txid, cet.range
+                )
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
 async fn load_revoked_commit_transactions(
     conn: &mut SqliteConnection,
     cfd_row_id: i64,
@@ -127,7 +229,10 @@ async fn load_revoked_commit_transactions(
                 settlement_event_id as "settlement_event_id: models::BitMexPriceEventId",
                 txid as "txid: models::Txid",
                 complete_fee as "complete_fee: i64",
-                complete_fee_flow as "complete_fee_flow: models::FeeFlow"
+                complete_fee_flow as "complete_fee_flow: models::FeeFlow",
+                commit_tx as "commit_tx: models::Transaction",
+                publication_pk_ours as "publication_pk_ours: models::PublicKey",
+                revocation_pk_theirs as "revocation_pk_theirs: models::PublicKey"
             FROM
                 revoked_commit_transactions
             WHERE
@@ -153,6 +258,13 @@ async fn load_revoked_commit_transactions(
                 .settlement_event_id
                 .map(|settlement_event_id| settlement_event_id.into()),
             complete_fee: into_complete_fee(row.complete_fee_flow, row.complete_fee),
+            tx: row.commit_tx.map(|commit_tx| commit_tx.into()),
+            publication_pk_ours: row
+                .publication_pk_ours
+                .map(|publication_pk_ours| publication_pk_ours.into()),
+            revocation_pk_theirs: row
+                .revocation_pk_theirs
+                .map(|revocation_pk_theirs| revocation_pk_theirs.into()),
         })
     })
     .collect::<Result<Vec<_>>>()?;
@@ -185,19 +297,19 @@ async fn load_cets(
     .await?
     .into_iter()
     .map(|row| {
-        (
+        Ok((
             row.oracle_event_id.into(),
             Cet {
-                maker_amount: Amount::from_sat(row.maker_amount as u64),
-                taker_amount: Amount::from_sat(row.taker_amount as u64),
+                maker_amount: amount_from_sql(row.maker_amount)?,
+                taker_amount: amount_from_sql(row.taker_amount)?,
                 adaptor_sig: row.adaptor_sig.into(),
                 range: RangeInclusive::new(row.range_start as u64, row.range_end as u64),
                 n_bits: row.n_bits as usize,
                 txid: row.txid.into(),
             },
-        )
+        ))
     })
-    .collect::<Vec<(_, _)>>();
+    .collect::<Result<Vec<(_, _)>>>()?;
 
     let mut revoked_commit: HashMap<BitMexPriceEventId, Vec<Cet>> = HashMap::new();
     for (event, cet) in revoked_commit_per_event {
@@ -213,3 +325,252 @@ async fn load_cets(
 
     Ok(revoked_commit)
 }
+
+/// Count the CETs stored for the CFD, without loading them.
+pub async fn count_cets(conn: &mut SqliteConnection, cfd_row_id: i64) -> Result<u64> {
+    let row = sqlx::query!(
+        r#"
+            SELECT
+                COUNT(*) as "count!: i64"
+            FROM
+                open_cets
+            WHERE
+                cfd_id = $1
+            "#,
+        cfd_row_id,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(row.count as u64)
+}
+
+/// Find all CFDs which completed a rollover but have none or not all of its CETs stored in
+/// `open_cets`.
+///
+/// Every rollover produces CETs, so a CFD with rollover data but without CETs points to a
+/// rollover which was only partially persisted. Where the number of CETs of the rollover was
+/// recorded, a CFD with fewer CETs than that is found as well.
+pub async fn find_cfds_without_cets(conn: &mut SqliteConnection) -> Result<Vec<OrderId>> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT
+                cfds.order_id as "order_id: models::OrderId"
+            FROM
+                cfds
+            JOIN
+                rollover_completed_event_data rollover on rollover.cfd_id = cfds.id
+            WHERE
+                (SELECT COUNT(*) FROM open_cets WHERE open_cets.cfd_id = cfds.id) = 0 OR
+                (SELECT COUNT(*) FROM open_cets WHERE open_cets.cfd_id = cfds.id) != rollover.expected_cets
+            "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let order_ids = rows.into_iter().map(|row| row.order_id.into()).collect();
+
+    Ok(order_ids)
+}
+
+/// Find all CFDs which have CETs stored in `open_cets` but no rollover data.
+///
+/// CETs are only ever stored and deleted together with the rollover data they belong to, thus
+/// such CETs are orphaned.
+pub async fn find_cfds_with_orphaned_cets(conn: &mut SqliteConnection) -> Result<Vec<OrderId>> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT DISTINCT
+                cfds.order_id as "order_id: models::OrderId"
+            FROM
+                open_cets
+            JOIN
+                cfds on cfds.id = open_cets.cfd_id
+            LEFT JOIN
+                rollover_completed_event_data rollover on rollover.cfd_id = open_cets.cfd_id
+            WHERE
+                rollover.id IS NULL
+            "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let order_ids = rows.into_iter().map(|row| row.order_id.into()).collect();
+
+    Ok(order_ids)
+}
+
+/// Load the order ID of every rolled over CFD, together with the CFD's and the event's row ID
+/// needed to [`load`] the rollover data.
+pub async fn load_rolled_over_cfds(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<(OrderId, i64, i64)>> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT
+                cfds.order_id as "order_id: models::OrderId",
+                rollover.cfd_id as "cfd_id: i64",
+                rollover.event_id as "event_id: i64"
+            FROM
+                rollover_completed_event_data rollover
+            JOIN
+                cfds on cfds.id = rollover.cfd_id
+            "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let cfds = rows
+        .into_iter()
+        .map(|row| (row.order_id.into(), row.cfd_id, row.event_id))
+        .collect();
+
+    Ok(cfds)
+}
+
+/// Load a single CET by its transaction ID, together with the ID of the CFD it belongs to.
+///
+/// Returns `Ok(None)` if no CET with the given `txid` is stored in `open_cets`.
+pub async fn load_cet_by_txid(
+    conn: &mut SqliteConnection,
+    txid: bitcoin::Txid,
+) -> Result<Option<(OrderId, Cet)>> {
+    let txid = models::Txid::from(txid);
+
+    let row = sqlx::query!(
+        r#"
+            SELECT
+                cfds.order_id as "order_id: models::OrderId",
+                open_cets.adaptor_sig as "adaptor_sig: models::AdaptorSignature",
+                open_cets.maker_amount as "maker_amount: i64",
+                open_cets.taker_amount as "taker_amount: i64",
+                open_cets.n_bits as "n_bits: i64",
+                open_cets.range_end as "range_end: i64",
+                open_cets.range_start as "range_start: i64",
+                open_cets.txid as "txid: models::Txid"
+            FROM
+                open_cets
+            JOIN
+                cfds on cfds.id = open_cets.cfd_id
+            WHERE
+                open_cets.txid = $1
+            "#,
+        txid,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let cet = row
+        .map(|row| -> Result<_> {
+            Ok((
+                row.order_id.into(),
+                Cet {
+                    maker_amount: amount_from_sql(row.maker_amount)?,
+                    taker_amount: amount_from_sql(row.taker_amount)?,
+                    adaptor_sig: row.adaptor_sig.into(),
+                    range: RangeInclusive::new(row.range_start as u64, row.range_end as u64),
+                    n_bits: row.n_bits as usize,
+                    txid: row.txid.into(),
+                },
+            ))
+        })
+        .transpose()?;
+
+    Ok(cet)
+}
+
+/// Load the distinct settlement events of the latest rollover of all open CFDs.
+///
+/// These are the oracle events whose attestations are needed to settle the open CFDs. CFDs which
+/// have not been rolled over yet are not included.
+pub async fn load_active_settlement_events(
+    conn: &mut SqliteConnection,
+) -> Result<HashSet<BitMexPriceEventId>> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT DISTINCT
+                rollover.settlement_event_id as "settlement_event_id: models::BitMexPriceEventId"
+            FROM
+                rollover_completed_event_data rollover
+            JOIN
+                cfds on cfds.id = rollover.cfd_id
+            "#
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let settlement_events = rows
+        .into_iter()
+        .map(|row| row.settlement_event_id.into())
+        .collect();
+
+    Ok(settlement_events)
+}
+
+/// Load the funding fees the CFD was charged in each of its rollovers, ordered by rollover.
+///
+/// Each entry pairs the time of the rollover with the funding fee, including the funding rate,
+/// that was charged for it. Unlike the data in `rollover_completed_event_data`, which only holds
+/// the latest rollover, the `RolloverCompleted` events of all rollovers are kept.
+pub async fn load_funding_rate_history(
+    conn: &mut SqliteConnection,
+    order_id: OrderId,
+) -> Result<Vec<(Timestamp, FundingFee)>> {
+    let order_id = models::OrderId::from(order_id);
+    let event_name = EventKind::ROLLOVER_COMPLETED_EVENT;
+
+    let rows = sqlx::query!(
+        r#"
+            SELECT
+                events.name,
+                events.data,
+                events.created_at as "created_at: models::Timestamp"
+            FROM
+                events
+            JOIN
+                cfds on cfds.id = events.cfd_id
+            WHERE
+                cfds.order_id = $1 AND events.name = $2
+            ORDER BY
+                events.id
+            "#,
+        order_id,
+        event_name,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| match EventKind::from_json(row.name, row.data)? {
+            EventKind::RolloverCompleted { funding_fee, .. } => {
+                Ok((row.created_at.into(), funding_fee))
+            }
+            event => bail!("Expected RolloverCompleted event, got {event}"),
+        })
+        .collect()
+}
+
+/// Sum the lock amounts of all open CFDs, separately for the maker and the taker.
+///
+/// The lock amounts are taken from the DLC of the latest rollover, thus CFDs which have not been
+/// rolled over yet are not included.
+pub async fn total_open_exposure(conn: &mut SqliteConnection) -> Result<(Amount, Amount)> {
+    let row = sqlx::query!(
+        r#"
+            SELECT
+                COALESCE(SUM(rollover.maker_lock_amount), 0) as "maker_lock_amount!: i64",
+                COALESCE(SUM(rollover.taker_lock_amount), 0) as "taker_lock_amount!: i64"
+            FROM
+                rollover_completed_event_data rollover
+            JOIN
+                cfds on cfds.id = rollover.cfd_id
+            "#
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let maker = amount_from_sql(row.maker_lock_amount)?;
+    let taker = amount_from_sql(row.taker_lock_amount)?;
+
+    Ok((maker, taker))
+}
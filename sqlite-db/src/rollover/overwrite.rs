@@ -1,15 +1,20 @@
 use crate::models;
 use crate::models::into_complete_fee_and_flow;
+use crate::rollover::amount_to_sql;
+use crate::Error;
 use anyhow::bail;
 use anyhow::Result;
 use bdk::bitcoin::hashes::hex::ToHex;
 use delete::delete;
+use model::olivia;
 use model::Cet;
 use model::CompleteFee;
 use model::Dlc;
 use model::FundingFee;
 use model::RevokedCommit;
 use models::BitMexPriceEventId;
+use sqlx::QueryBuilder;
+use sqlx::Sqlite;
 use sqlx::SqliteConnection;
 use sqlx::SqliteExecutor;
 
@@ -27,6 +32,8 @@ pub async fn overwrite(
     funding_fee: FundingFee,
     complete_fee: Option<CompleteFee>,
 ) -> Result<()> {
+    ensure_cfd_exists(&mut *conn, order_id).await?;
+
     delete(&mut *conn, order_id).await?;
 
     insert_rollover_completed_event_data(
@@ -39,9 +46,10 @@ pub async fn overwrite(
     )
     .await?;
 
-    for revoked in dlc.revoked_commit {
-        insert_revoked_commit_transaction(&mut *conn, order_id, revoked).await?;
-    }
+    insert_revoked_commit_transactions(&mut *conn, order_id, &dlc.revoked_commit).await?;
+
+    let num_cets = dlc.cets.values().map(Vec::len).sum();
+    set_expected_cets(&mut *conn, order_id, num_cets).await?;
 
     for (event_id, cets) in dlc.cets {
         for cet in cets {
@@ -52,6 +60,72 @@ pub async fn overwrite(
     Ok(())
 }
 
+/// Insert CETs of a CFD's latest rollover.
+///
+/// Allows inserting the CETs separately from the rest of the rollover data, after calling
+/// [`overwrite`] with a `Dlc` without CETs.
+pub async fn insert_cets(
+    conn: &mut SqliteConnection,
+    order_id: models::OrderId,
+    cets: Vec<(olivia::BitMexPriceEventId, Cet)>,
+) -> Result<()> {
+    for (event_id, cet) in cets {
+        insert_cet(&mut *conn, event_id.into(), order_id, cet).await?;
+    }
+
+    Ok(())
+}
+
+/// Record how many CETs the latest rollover of a CFD has.
+///
+/// [`overwrite`] records the CETs of the `Dlc` it was given. If the CETs are inserted separately
+/// via [`insert_cets`], the total has to be recorded with this afterwards, so that loading the
+/// rollover can tell whether all of them have been inserted.
+pub async fn set_expected_cets(
+    conn: &mut SqliteConnection,
+    order_id: models::OrderId,
+    expected_cets: usize,
+) -> Result<()> {
+    let expected_cets = i64::try_from(expected_cets)?;
+
+    let query_result = sqlx::query!(
+        r#"
+            update
+                rollover_completed_event_data
+            set
+                expected_cets = $1
+            where
+                cfd_id = (select id from cfds where cfds.order_id = $2)
+        "#,
+        expected_cets,
+        order_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if query_result.rows_affected() != 1 {
+        bail!("failed to set expected number of CETs");
+    }
+
+    Ok(())
+}
+
+/// Fails with [`Error::CfdNotFound`] if there is no CFD for the given order id.
+///
+/// Without this check, inserting rollover data for an unknown order id would only fail on the
+/// `NOT NULL` constraint of `cfd_id`, which does not tell the caller what actually went wrong.
+async fn ensure_cfd_exists(conn: &mut SqliteConnection, order_id: models::OrderId) -> Result<()> {
+    let row = sqlx::query!(r#"select id from cfds where order_id = $1"#, order_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    if row.is_none() {
+        return Err(Error::CfdNotFound(order_id.into()).into());
+    }
+
+    Ok(())
+}
+
 /// Inserts RolloverCompleted data and returns the resulting rowid
 async fn insert_rollover_completed_event_data(
     conn: impl SqliteExecutor<'_>,
@@ -69,19 +143,20 @@ async fn insert_rollover_completed_event_data(
     let commit_tx = models::Transaction::from(commit_tx);
     let refund_tx = models::Transaction::from(refund_tx);
 
+    let lock_tx_descriptor = models::Descriptor::from(lock_tx_descriptor);
+    let commit_descriptor = models::Descriptor::from(commit_descriptor);
+
     let commit_adaptor_signature = models::AdaptorSignature::from(commit_adaptor_signature);
 
     // casting because u64 is not implemented for sqlx: https://github.com/launchbadge/sqlx/pull/919#discussion_r557256333
-    let funding_fee_as_sat = funding_fee.fee.as_sat() as i64;
+    let funding_fee_as_sat = amount_to_sql(funding_fee.fee)?;
     // TODO: these seem to be redundant and should be in `cfds` table only
-    let maker_lock_amount = dlc.maker_lock_amount.as_sat() as i64;
-    let taker_lock_amount = dlc.taker_lock_amount.as_sat() as i64;
+    let maker_lock_amount = amount_to_sql(dlc.maker_lock_amount)?;
+    let taker_lock_amount = amount_to_sql(dlc.taker_lock_amount)?;
 
     let maker_address = dlc.maker_address.to_string();
     let taker_address = dlc.taker_address.to_string();
 
-    let lock_tx_descriptor = lock_tx_descriptor.to_string();
-    let commit_tx_descriptor = commit_descriptor.to_string();
     let refund_signature = refund_signature.to_string();
 
     let identity = models::SecretKey::from(dlc.identity);
@@ -93,7 +168,7 @@ async fn insert_rollover_completed_event_data(
     let rate = models::FundingRate::from(funding_fee.rate);
     let settlement_event_id = models::BitMexPriceEventId::from(dlc.settlement_event_id);
 
-    let (complete_fee, complete_fee_flow) = into_complete_fee_and_flow(complete_fee);
+    let (complete_fee, complete_fee_flow) = into_complete_fee_and_flow(complete_fee)?;
 
     let query_result = sqlx::query!(
         r#"
@@ -148,7 +223,7 @@ async fn insert_rollover_completed_event_data(
         lock_tx_descriptor,
         commit_tx,
         commit_adaptor_signature,
-        commit_tx_descriptor,
+        commit_descriptor,
         refund_tx,
         refund_signature,
         complete_fee,
@@ -163,25 +238,30 @@ async fn insert_rollover_completed_event_data(
     Ok(())
 }
 
-async fn insert_revoked_commit_transaction(
+/// SQLite's default upper bound on the number of host parameters in a single statement.
+const MAX_SQL_PARAMETERS: usize = 999;
+
+/// The number of parameters bound per row when inserting into `revoked_commit_transactions`.
+const REVOKED_COMMIT_TRANSACTION_PARAMETERS: usize = 13;
+
+/// Inserts all revoked commit transactions using multi-row inserts.
+///
+/// The rows are chunked so that a single statement never exceeds [`MAX_SQL_PARAMETERS`].
+async fn insert_revoked_commit_transactions(
     conn: &mut SqliteConnection,
     order_id: models::OrderId,
-    revoked: RevokedCommit,
+    revoked_commits: &[RevokedCommit],
 ) -> Result<()> {
-    let revoked_tx_script_pubkey = revoked.script_pubkey.to_hex();
-    let revocation_sk_theirs = models::SecretKey::from(revoked.revocation_sk_theirs);
-    let revocation_sk_ours = revoked.revocation_sk_ours.map(models::SecretKey::from);
-    let publication_pk_theirs = models::PublicKey::from(revoked.publication_pk_theirs);
-    let encsig_ours = models::AdaptorSignature::from(revoked.encsig_ours);
-    let txid = models::Txid::from(revoked.txid);
-    let settlement_event_id = revoked
-        .settlement_event_id
-        .map(models::BitMexPriceEventId::from);
-
-    let (complete_fee, complete_fee_flow) = into_complete_fee_and_flow(revoked.complete_fee);
+    let rows_per_statement = MAX_SQL_PARAMETERS / REVOKED_COMMIT_TRANSACTION_PARAMETERS;
 
-    let query_result = sqlx::query!(
-        r#"
+    for chunk in revoked_commits.chunks(rows_per_statement) {
+        let complete_fees = chunk
+            .iter()
+            .map(|revoked| into_complete_fee_and_flow(revoked.complete_fee))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut query_builder = QueryBuilder::<Sqlite>::new(
+            r#"
                 insert into revoked_commit_transactions (
                     cfd_id,
                     encsig_ours,
@@ -192,26 +272,45 @@ async fn insert_revoked_commit_transaction(
                     settlement_event_id,
                     complete_fee,
                     complete_fee_flow,
-                    revocation_sk_ours
-                ) values ( (select id from cfds where cfds.order_id = $1), $2, $3, $4, $5, $6, $7, $8, $9, $10 )
-            "#,
-        order_id,
-        encsig_ours,
-        publication_pk_theirs,
-        revocation_sk_theirs,
-        revoked_tx_script_pubkey,
-        txid,
-        settlement_event_id,
-        complete_fee,
-        complete_fee_flow,
-        revocation_sk_ours
-    )
-    .execute(&mut *conn)
-    .await?;
+                    revocation_sk_ours,
+                    commit_tx,
+                    publication_pk_ours,
+                    revocation_pk_theirs
+                ) "#,
+        );
 
-    if query_result.rows_affected() != 1 {
-        bail!("failed to insert revoked transaction data");
+        query_builder.push_values(
+            chunk.iter().zip(complete_fees),
+            |mut row, (revoked, (complete_fee, complete_fee_flow))| {
+                row.push("(select id from cfds where cfds.order_id = ")
+                    .push_bind_unseparated(order_id)
+                    .push_unseparated(")")
+                    .push_bind(models::AdaptorSignature::from(revoked.encsig_ours))
+                    .push_bind(models::PublicKey::from(revoked.publication_pk_theirs))
+                    .push_bind(models::SecretKey::from(revoked.revocation_sk_theirs))
+                    .push_bind(revoked.script_pubkey.to_hex())
+                    .push_bind(models::Txid::from(revoked.txid))
+                    .push_bind(
+                        revoked
+                            .settlement_event_id
+                            .map(models::BitMexPriceEventId::from),
+                    )
+                    .push_bind(complete_fee)
+                    .push_bind(complete_fee_flow)
+                    .push_bind(revoked.revocation_sk_ours.map(models::SecretKey::from))
+                    .push_bind(revoked.tx.clone().map(models::Transaction::from))
+                    .push_bind(revoked.publication_pk_ours.map(models::PublicKey::from))
+                    .push_bind(revoked.revocation_pk_theirs.map(models::PublicKey::from));
+            },
+        );
+
+        let query_result = query_builder.build().execute(&mut *conn).await?;
+
+        if query_result.rows_affected() != chunk.len() as u64 {
+            bail!("failed to insert revoked transaction data");
+        }
     }
+
     Ok(())
 }
 
@@ -221,8 +320,8 @@ async fn insert_cet(
     order_id: models::OrderId,
     cet: Cet,
 ) -> Result<()> {
-    let maker_amount = cet.maker_amount.as_sat() as i64;
-    let taker_amount = cet.taker_amount.as_sat() as i64;
+    let maker_amount = amount_to_sql(cet.maker_amount)?;
+    let taker_amount = amount_to_sql(cet.taker_amount)?;
     let n_bits = cet.n_bits as i64;
     let range_start = *cet.range.start() as i64;
     let range_end = *cet.range.end() as i64;
@@ -0,0 +1,163 @@
+use crate::rollover::amount_from_sql;
+use crate::rollover::amount_to_sql;
+use crate::Connection;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::Address;
+use bdk::bitcoin::Network;
+use model::Timestamp;
+use model::WalletInfo;
+use std::str::FromStr;
+
+impl Connection {
+    /// Persist `wallet_info` as the latest snapshot of our wallet, replacing the previous one.
+    pub async fn insert_wallet_info(&self, wallet_info: &WalletInfo) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        let network = wallet_info.network.to_string();
+        let balance = amount_to_sql(wallet_info.balance)?;
+        let address = wallet_info.address.to_string();
+        let last_updated_at = wallet_info.last_updated_at.seconds();
+        let transactions = serde_json::to_string(&wallet_info.transactions)?;
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO wallet_info
+            (
+                id,
+                network,
+                balance,
+                address,
+                last_updated_at,
+                transactions
+            )
+            VALUES (1, $1, $2, $3, $4, $5)
+            "#,
+            network,
+            balance,
+            address,
+            last_updated_at,
+            transactions,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the latest snapshot of our wallet, if any was persisted via
+    /// [`Connection::insert_wallet_info`].
+    ///
+    /// This allows showing the last known balance before the wallet is synced for the first time.
+    pub async fn load_last_wallet_info(&self) -> Result<Option<WalletInfo>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                network,
+                balance,
+                address,
+                last_updated_at,
+                transactions
+            FROM
+                wallet_info
+            "#
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let wallet_info = WalletInfo {
+            network: Network::from_str(&row.network).context("Invalid network")?,
+            balance: amount_from_sql(row.balance).context("Invalid balance")?,
+            address: Address::from_str(&row.address).context("Invalid address")?,
+            last_updated_at: Timestamp::new(row.last_updated_at),
+            transactions: serde_json::from_str(&row.transactions)
+                .context("Invalid transactions")?,
+        };
+
+        Ok(Some(wallet_info))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+    use bdk::bitcoin::Amount;
+    use bdk::bitcoin::Txid;
+    use bdk::TransactionDetails;
+
+    #[tokio::test]
+    async fn given_no_wallet_info_then_none_loaded() {
+        let db = memory().await.unwrap();
+
+        let wallet_info = db.load_last_wallet_info().await.unwrap();
+
+        assert!(wallet_info.is_none());
+    }
+
+    #[tokio::test]
+    async fn given_several_wallet_infos_then_latest_loaded() {
+        let db = memory().await.unwrap();
+
+        db.insert_wallet_info(&dummy_wallet_info(Amount::from_sat(1_000), 1))
+            .await
+            .unwrap();
+        db.insert_wallet_info(&dummy_wallet_info(Amount::from_sat(2_000), 2))
+            .await
+            .unwrap();
+
+        let wallet_info = db.load_last_wallet_info().await.unwrap().unwrap();
+
+        assert_eq!(wallet_info.network, Network::Testnet);
+        assert_eq!(wallet_info.balance, Amount::from_sat(2_000));
+        assert_eq!(wallet_info.last_updated_at, Timestamp::new(2));
+        assert_eq!(
+            wallet_info.address,
+            Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").unwrap()
+        );
+        assert_eq!(
+            wallet_info.transactions,
+            vec![TransactionDetails {
+                txid: Txid::default(),
+                received: 2_000,
+                ..TransactionDetails::default()
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn given_negative_balance_stored_then_loading_fails() {
+        let db = memory().await.unwrap();
+        db.insert_wallet_info(&dummy_wallet_info(Amount::from_sat(1_000), 1))
+            .await
+            .unwrap();
+
+        sqlx::query("UPDATE wallet_info SET balance = -1")
+            .execute(&db.inner)
+            .await
+            .unwrap();
+
+        assert!(db.load_last_wallet_info().await.is_err());
+    }
+
+    fn dummy_wallet_info(balance: Amount, last_updated_at: i64) -> WalletInfo {
+        WalletInfo {
+            network: Network::Testnet,
+            balance,
+            address: Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").unwrap(),
+            last_updated_at: Timestamp::new(last_updated_at),
+            transactions: vec![TransactionDetails {
+                txid: Txid::default(),
+                received: balance.as_sat(),
+                ..TransactionDetails::default()
+            }],
+        }
+    }
+}
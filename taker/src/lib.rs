@@ -362,10 +362,30 @@ pub async fn run(opts: Opts) -> Result<()> {
 
     let mut tasks = Tasks::default();
 
+    let db = sqlite_db::connect(
+        data_dir.join("taker.sqlite"),
+        true,
+        sqlite_db::Synchronous::Full,
+    )
+    .await?;
+
+    let last_wallet_info = db.load_last_wallet_info().await.unwrap_or_else(|e| {
+        tracing::warn!("Failed to load last wallet info: {e:#}");
+        None
+    });
+
     let mut wallet_dir = data_dir.clone();
     wallet_dir.push(TAKER_WALLET_ID);
-    let (wallet, wallet_feed_receiver) =
-        wallet::Actor::spawn(network.electrum(), ext_priv_key, wallet_dir)?;
+    let (wallet, wallet_feed_receiver) = wallet::Actor::spawn(
+        network.electrum(),
+        ext_priv_key,
+        wallet_dir,
+        last_wallet_info,
+    )?;
+    tasks.add(wallet::persist_wallet_info(
+        db.clone(),
+        wallet_feed_receiver.clone(),
+    ));
 
     if let Some(Withdraw::Withdraw {
         amount,
@@ -390,8 +410,6 @@ pub async fn run(opts: Opts) -> Result<()> {
         .merge(("cli_colors", false))
         .merge(("secret_key", RandomSeed::default().seed()));
 
-    let db = sqlite_db::connect(data_dir.join("taker.sqlite"), true).await?;
-
     // Create actors
 
     let possible_addresses = resolve_maker_addresses(maker_url.as_str()).await?;
@@ -450,6 +468,7 @@ pub async fn run(opts: Opts) -> Result<()> {
     let taker = TakerActorSystem::new(
         db.clone(),
         wallet.clone(),
+        wallet_feed_receiver.clone(),
         *olivia::PUBLIC_KEY,
         identities,
         |executor| oracle::Actor::new(db.clone(), executor),
@@ -495,6 +514,7 @@ pub async fn run(opts: Opts) -> Result<()> {
                 routes::post_withdraw_request,
                 routes::get_metrics,
                 routes::put_sync_wallet,
+                routes::put_resend_feed,
                 routes::get_version,
                 routes::change_password,
                 routes::post_login,
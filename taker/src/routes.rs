@@ -10,6 +10,7 @@ use daemon::oracle;
 use daemon::projection;
 use daemon::projection::CfdAction;
 use daemon::projection::FeedReceivers;
+use daemon::taker_cfd;
 use daemon::wallet;
 use daemon::TakerActorSystem;
 use http_api_problem::HttpApiProblem;
@@ -177,7 +178,15 @@ pub async fn post_order_request(
         )
         .await
         .map_err(|e| {
-            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            let is_invalid_request = e.downcast_ref::<taker_cfd::InsufficientFunds>().is_some()
+                || e.downcast_ref::<taker_cfd::InvalidQuantity>().is_some();
+            let status = if is_invalid_request {
+                StatusCode::BAD_REQUEST
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            };
+
+            HttpApiProblem::new(status)
                 .title("Order request failed")
                 .detail(format!("{e:#}"))
         })?;
@@ -325,6 +334,18 @@ pub async fn put_sync_wallet(taker: &State<Taker>, _user: User) -> Result<(), Ht
     Ok(())
 }
 
+#[rocket::put("/feed/resend")]
+#[instrument(name = "PUT /feed/resend", skip_all, err)]
+pub async fn put_resend_feed(taker: &State<Taker>, _user: User) -> Result<(), HttpApiProblem> {
+    taker.resend_feeds().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Could not resend feed")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(())
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct HealthCheck {
     daemon_version: String,
@@ -3,3 +3,6 @@ mod protocol;
 pub mod taker;
 
 pub const PROTOCOL: &str = "/itchysats/offer/2.0.0";
+
+/// Protocol used by the maker to tell takers that one of its offers is no longer available.
+pub const WITHDRAW_PROTOCOL: &str = "/itchysats/offer/withdraw/1.0.0";
@@ -1,7 +1,9 @@
 use crate::current::protocol;
 use crate::current::PROTOCOL;
+use crate::current::WITHDRAW_PROTOCOL;
 use async_trait::async_trait;
 use model::ContractSymbol;
+use model::OfferId;
 use model::Position;
 use std::collections::HashMap;
 use std::collections::HashSet;
@@ -69,6 +71,47 @@ impl Actor {
             err_handler,
         );
     }
+
+    #[tracing::instrument(name = "Notify taker of withdrawn offer", skip(self, ctx))]
+    async fn send_withdrawal(
+        &self,
+        peer_id: PeerId,
+        offer_id: OfferId,
+        ctx: &mut xtra::Context<Self>,
+    ) {
+        let endpoint = self.endpoint.clone();
+
+        let task = async move {
+            let stream = endpoint
+                .send(OpenSubstream::single_protocol(peer_id, WITHDRAW_PROTOCOL))
+                .await??
+                .await?;
+
+            protocol::send_withdrawal(stream, protocol::Withdrawal { id: offer_id }).await?;
+
+            anyhow::Ok(())
+        };
+
+        let err_handler = move |e: anyhow::Error| async move {
+            match e.downcast_ref::<xtra_libp2p::Error>() {
+                Some(xtra_libp2p::Error::ProtocolNotSupportedByPeer) => {
+                    // Takers on older versions only learn about the withdrawal when the
+                    // offer is replaced
+                }
+                Some(xtra_libp2p::Error::NegotiationFailed(_)) => {
+                    tracing::debug!(%peer_id, "Failed to send offer withdrawal: {e:#}")
+                }
+                _ => tracing::warn!(%peer_id, "Failed to send offer withdrawal: {e:#}"),
+            }
+        };
+
+        let this = ctx.address().expect("self to be alive");
+        spawn_fallible(
+            &this,
+            task.instrument(tracing::Span::current()),
+            err_handler,
+        );
+    }
 }
 
 #[xtra_productivity]
@@ -87,6 +130,24 @@ impl Actor {
     async fn handle(&mut self, _: GetLatestOffers) -> Vec<model::Offer> {
         self.current_offers.to_vec()
     }
+
+    /// Returns `false` if the offer was not among the current offers, in which case no peer is
+    /// notified.
+    async fn handle(&mut self, msg: WithdrawOffer, ctx: &mut xtra::Context<Self>) -> bool {
+        let WithdrawOffer(offer_id) = msg;
+
+        if !self.current_offers.remove(offer_id) {
+            return false;
+        }
+
+        // Orders for this offer that reach the maker from now on are rejected because the
+        // offer can no longer be picked from the current offers
+        for peer_id in self.connected_peers.iter().copied() {
+            self.send_withdrawal(peer_id, offer_id, ctx).await
+        }
+
+        true
+    }
 }
 
 #[xtra_productivity]
@@ -121,6 +182,11 @@ impl NewOffers {
 #[derive(Clone, Copy)]
 pub struct GetLatestOffers;
 
+/// Instruct the `offer::maker::Actor` to stop offering the offer with
+/// the given ID and to notify all connected peers about it.
+#[derive(Clone, Copy)]
+pub struct WithdrawOffer(pub OfferId);
+
 #[derive(Clone, Default)]
 struct Offers(HashMap<(ContractSymbol, Position), model::Offer>);
 
@@ -137,6 +203,14 @@ impl Offers {
         }
     }
 
+    /// Remove the offer with the given ID, returning whether it was present.
+    fn remove(&mut self, offer_id: OfferId) -> bool {
+        let len = self.0.len();
+        self.0.retain(|_, offer| offer.id != offer_id);
+
+        self.0.len() != len
+    }
+
     fn to_vec(&self) -> Vec<model::Offer> {
         self.0.iter().map(|(_, offer)| offer).cloned().collect()
     }
@@ -47,9 +47,39 @@ where
     Ok(offers)
 }
 
+pub(crate) async fn send_withdrawal<S>(
+    sink: S,
+    withdrawal: Withdrawal,
+) -> Result<(), JsonCodecError>
+where
+    S: AsyncWriteExt + Unpin,
+{
+    let mut framed = FramedWrite::new(sink, JsonCodec::<Withdrawal, ()>::new());
+    framed.send(withdrawal).await?;
+
+    Ok(())
+}
+
+pub(crate) async fn recv_withdrawal<S>(stream: S) -> Result<Withdrawal, ReceiveError>
+where
+    S: AsyncReadExt + Unpin,
+{
+    let mut framed = FramedRead::new(stream, JsonCodec::<(), Withdrawal>::new());
+
+    let withdrawal = framed.next().await.ok_or(ReceiveError::Terminated)??;
+
+    Ok(withdrawal)
+}
+
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
 pub(crate) struct Offers(Vec<Offer>);
 
+/// Notification that the offer with the given ID can no longer be taken.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+pub(crate) struct Withdrawal {
+    pub(crate) id: OfferId,
+}
+
 #[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub(crate) struct Offer {
     id: OfferId,
@@ -175,4 +205,19 @@ mod tests {
         assert!(send_res.is_ok());
         assert_eq!(maker_offers, Vec::<model::Offer>::from(recv_res.unwrap()))
     }
+
+    #[tokio::test]
+    async fn sent_withdrawal_matches_received_withdrawal() {
+        let (stream, sink) = pipe();
+
+        let withdrawal = Withdrawal {
+            id: OfferId::default(),
+        };
+
+        let (send_res, recv_res) =
+            tokio::join!(send_withdrawal(sink, withdrawal), recv_withdrawal(stream));
+
+        assert!(send_res.is_ok());
+        assert_eq!(withdrawal, recv_res.unwrap())
+    }
 }
@@ -1,5 +1,6 @@
 use crate::current::protocol;
 use async_trait::async_trait;
+use model::OfferId;
 use tracing::Instrument;
 use xtra::prelude::MessageChannel;
 use xtra_libp2p::NewInboundSubstream;
@@ -49,6 +50,54 @@ impl Actor {
 /// offers.
 pub struct LatestOffers(pub Vec<model::Offer>);
 
+/// Handler for the maker's notifications about withdrawn offers.
+pub struct WithdrawalActor {
+    withdrawn_offers: MessageChannel<WithdrawnOffer, ()>,
+}
+
+impl WithdrawalActor {
+    pub fn new(withdrawn_offers: MessageChannel<WithdrawnOffer, ()>) -> Self {
+        Self { withdrawn_offers }
+    }
+}
+
+#[xtra_productivity]
+impl WithdrawalActor {
+    async fn handle(&mut self, msg: NewInboundSubstream, ctx: &mut xtra::Context<Self>) {
+        let NewInboundSubstream { peer_id, stream } = msg;
+        let withdrawn_offers = self.withdrawn_offers.clone();
+
+        let this = ctx.address().expect("self to be alive");
+
+        let task = async move {
+            let withdrawal = protocol::recv_withdrawal(stream).await?;
+
+            tracing::debug!(offer_id = %withdrawal.id, "Received offer withdrawal");
+
+            withdrawn_offers.send(WithdrawnOffer(withdrawal.id)).await?;
+
+            anyhow::Ok(())
+        };
+
+        let err_handler = move |e| async move {
+            tracing::warn!(%peer_id, "Failed to process offer withdrawal: {e:#}")
+        };
+
+        tokio_extras::spawn_fallible(&this, task, err_handler);
+    }
+}
+
+/// Message used to inform other actors that the maker withdrew the
+/// offer with the given ID.
+pub struct WithdrawnOffer(pub OfferId);
+
+#[async_trait]
+impl xtra::Actor for WithdrawalActor {
+    type Stop = ();
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
 #[async_trait]
 impl xtra::Actor for Actor {
     type Stop = ();
@@ -7,6 +7,7 @@ pub use current::*;
 mod tests {
     use super::*;
     use crate::taker::LatestOffers;
+    use crate::taker::WithdrawnOffer;
     use async_trait::async_trait;
     use futures::Future;
     use model::olivia::BitMexPriceEventId;
@@ -151,6 +152,100 @@ mod tests {
         assert!(received_offers.contains(&offer_eth_usd_short));
     }
 
+    #[tokio::test]
+    async fn given_withdrawn_offer_then_taker_no_longer_has_it() {
+        let _g = tracing_subscriber::fmt()
+            .with_env_filter("xtra_libp2p_offer=trace")
+            .with_test_writer()
+            .set_default();
+
+        let (maker_peer_id, maker_offer_addr, maker_endpoint_addr) =
+            create_endpoint_with_offer_maker();
+        let (offer_receiver_addr, taker_endpoint_addr) = create_endpoint_with_offer_taker();
+
+        maker_endpoint_addr
+            .send(ListenOn(Multiaddr::empty().with(Protocol::Memory(1001))))
+            .await
+            .unwrap();
+
+        let offer_btc_usd_long = dummy_offer(ContractSymbol::BtcUsd, Position::Long);
+        let offer_btc_usd_short = dummy_offer(ContractSymbol::BtcUsd, Position::Short);
+        maker_offer_addr
+            .send(crate::maker::NewOffers::new(vec![
+                offer_btc_usd_long.clone(),
+                offer_btc_usd_short.clone(),
+            ]))
+            .await
+            .unwrap();
+
+        taker_endpoint_addr
+            .send(Connect(
+                Multiaddr::empty()
+                    .with(Protocol::Memory(1001))
+                    .with(Protocol::P2p(maker_peer_id.into())),
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let received_offers = retry_until_some(|| {
+            let offer_receiver_addr = offer_receiver_addr.clone();
+            async move { offer_receiver_addr.send(GetLatestOffers).await.unwrap() }
+        })
+        .await;
+        assert_eq!(received_offers.len(), 2);
+
+        let withdrawn = maker_offer_addr
+            .send(crate::maker::WithdrawOffer(offer_btc_usd_long.id))
+            .await
+            .unwrap();
+        assert!(withdrawn);
+
+        let maker_offers = maker_offer_addr
+            .send(crate::maker::GetLatestOffers)
+            .await
+            .unwrap();
+        assert_eq!(maker_offers, vec![offer_btc_usd_short.clone()]);
+
+        let received_offers = retry_until_some(|| {
+            let offer_receiver_addr = offer_receiver_addr.clone();
+            async move {
+                let offers = offer_receiver_addr.send(GetLatestOffers).await.unwrap();
+
+                // Keep waiting until the withdrawal has been processed
+                if offers.len() == 2 {
+                    Vec::new()
+                } else {
+                    offers
+                }
+            }
+        })
+        .await;
+        assert_eq!(received_offers, vec![offer_btc_usd_short]);
+    }
+
+    #[tokio::test]
+    async fn given_unknown_offer_when_withdrawing_then_nothing_is_withdrawn() {
+        let (_, maker_offer_addr, _maker_endpoint_addr) = create_endpoint_with_offer_maker();
+
+        maker_offer_addr
+            .send(crate::maker::NewOffers::new(dummy_offers()))
+            .await
+            .unwrap();
+
+        let withdrawn = maker_offer_addr
+            .send(crate::maker::WithdrawOffer(Default::default()))
+            .await
+            .unwrap();
+
+        assert!(!withdrawn);
+        let maker_offers = maker_offer_addr
+            .send(crate::maker::GetLatestOffers)
+            .await
+            .unwrap();
+        assert_eq!(maker_offers.len(), 2);
+    }
+
     fn create_endpoint_with_offer_maker(
     ) -> (PeerId, Address<crate::maker::Actor>, Address<Endpoint>) {
         let (endpoint_addr, endpoint_context) = Context::new(None);
@@ -187,11 +282,19 @@ mod tests {
             .create(None)
             .spawn_global();
 
+        let offer_withdrawal_addr =
+            crate::taker::WithdrawalActor::new(offers_receiver_addr.clone().into())
+                .create(None)
+                .spawn_global();
+
         let endpoint_addr = Endpoint::new(
             Box::new(MemoryTransport::default),
             Keypair::generate_ed25519(),
             Duration::from_secs(10),
-            [(PROTOCOL, offer_taker_addr.into())],
+            [
+                (PROTOCOL, offer_taker_addr.into()),
+                (WITHDRAW_PROTOCOL, offer_withdrawal_addr.into()),
+            ],
             Subscribers::default(),
             Arc::new(HashSet::default()),
         )
@@ -223,6 +326,10 @@ mod tests {
         async fn handle(&mut self, msg: LatestOffers) {
             self.offers = msg.0;
         }
+
+        async fn handle(&mut self, msg: WithdrawnOffer) {
+            self.offers.retain(|offer| offer.id != msg.0);
+        }
     }
 
     struct GetLatestOffers;
@@ -29,6 +29,7 @@ mod tests {
     use xtra_libp2p::Connect;
     use xtra_libp2p::Endpoint;
     use xtra_libp2p::ListenOn;
+    use xtra_libp2p::NewInboundSubstream;
 
     #[tokio::test]
     async fn latency_to_peer_is_recorded() {
@@ -82,6 +83,24 @@ mod tests {
         assert!(!bob_to_alice_latency.is_zero());
     }
 
+    #[tokio::test]
+    async fn pong_answers_ping_on_inbound_substream() {
+        let pong_address = pong::Actor.create(None).spawn_global();
+        let pair = xtra_libp2p::test_utils::substream_pair(PROTOCOL)
+            .await
+            .unwrap();
+
+        pong_address
+            .send(NewInboundSubstream {
+                peer_id: pair.peer_id,
+                stream: pair.listener,
+            })
+            .await
+            .unwrap();
+
+        protocol::send(pair.dialer).await.unwrap();
+    }
+
     #[allow(clippy::type_complexity)]
     fn create_endpoint_with_ping() -> (PeerId, Address<ping::Actor>, Address<Endpoint>) {
         let (endpoint_address, endpoint_context) = Context::new(None);
@@ -16,6 +16,7 @@ use xtra_libp2p::libp2p::PeerId;
 use xtra_libp2p::Endpoint;
 use xtra_libp2p::GetConnectionStats;
 use xtra_libp2p::OpenSubstream;
+use xtra_libp2p::RecordRtt;
 use xtra_productivity::xtra_productivity;
 use xtras::SendAsyncNext;
 use xtras::SendInterval;
@@ -29,7 +30,8 @@ use xtras::SendInterval;
 /// devices along the connection pathway.
 ///
 /// When constructed with a `ping_interval`, the actor will request all connected peers from the
-/// provided [`Endpoint`] and ping all peers.
+/// provided [`Endpoint`] and ping all peers. The measured latencies are recorded at the
+/// [`Endpoint`] as well, see [`RecordRtt`].
 ///
 /// This actor also implements the listening end of the ping protocol and will correctly handle
 /// incoming pings even without a `ping_interval` set. This is useful if an application wants to
@@ -144,6 +146,12 @@ impl Actor {
         let RecordLatency { peer_id, latency } = msg;
 
         self.latencies.insert(peer_id, latency);
+        self.endpoint
+            .send_async_next(RecordRtt {
+                peer_id,
+                rtt: latency,
+            })
+            .await;
 
         let latency_milliseconds = latency.as_millis();
 
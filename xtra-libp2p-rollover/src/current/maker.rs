@@ -13,8 +13,9 @@ use model::ExecuteOnCfd;
 use model::Position;
 use model::Role;
 use tokio_extras::FutureExt;
+use xtra_libp2p::framing;
+use xtra_libp2p::framing::BufferedSubstream;
 use xtra_libp2p::NewInboundSubstream;
-use xtra_libp2p::Substream;
 use xtra_productivity::xtra_productivity;
 
 /// Permanent actor to handle incoming substreams for the `/itchysats/rollover/2.0.0`
@@ -88,8 +89,11 @@ where
         tokio_extras::spawn_fallible(
             &address.clone(),
             async move {
-                let mut framed =
-                    Framed::new(stream, JsonCodec::<ListenerMessage, DialerMessage>::new());
+                let mut framed = framing::framed_with_read_buffer(
+                    stream,
+                    JsonCodec::<ListenerMessage, DialerMessage>::new(),
+                    READ_BUFFER_CAPACITY,
+                );
 
                 let propose = framed
                     .next()
@@ -373,6 +377,8 @@ where
                     refund_timelock: rollover_params.refund_timelock,
                 };
 
+                dlc.verify(our_role).context("Rollover resulted in invalid DLC")?;
+
                 emit_completed(order_id, dlc, funding_fee, complete_fee, &executor).await;
 
                 Ok(())
@@ -405,6 +411,6 @@ impl UpdateConfiguration {
 
 struct ProposeReceived {
     propose: Propose,
-    framed: Framed<Substream, JsonCodec<ListenerMessage, DialerMessage>>,
+    framed: Framed<BufferedSubstream, JsonCodec<ListenerMessage, DialerMessage>>,
     peer_id: PeerId,
 }
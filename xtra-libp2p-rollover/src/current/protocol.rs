@@ -52,6 +52,12 @@ use std::time::Duration;
 /// rollovers are not a big deal.
 pub(crate) const ROLLOVER_MSG_TIMEOUT: Duration = Duration::from_secs(60);
 
+/// The capacity of the buffer through which rollover messages are read from the substream.
+///
+/// Rollover messages carry all the CETs and their adaptor signatures, so reading them through a
+/// larger buffer than the default saves a lot of small reads.
+pub(crate) const READ_BUFFER_CAPACITY: usize = 64 * 1024;
+
 pub struct RolloverCompletedParams {
     pub dlc: Dlc,
     pub funding_fee: FundingFee,
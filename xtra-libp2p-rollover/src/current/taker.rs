@@ -18,6 +18,7 @@ use model::Timestamp;
 use std::time::Duration;
 use tokio_extras::FutureExt;
 use xtra::Address;
+use xtra_libp2p::framing;
 use xtra_libp2p::Endpoint;
 use xtra_libp2p::OpenSubstream;
 use xtra_libp2p::Substream;
@@ -127,9 +128,10 @@ where
                 let oracle_pk = self.oracle_pk;
                 let n_payouts = self.n_payouts;
                 async move {
-                    let mut framed = asynchronous_codec::Framed::new(
+                    let mut framed = framing::framed_with_read_buffer(
                         substream,
                         asynchronous_codec::JsonCodec::<DialerMessage, ListenerMessage>::new(),
+                        READ_BUFFER_CAPACITY,
                     );
 
                     let contract_symbol = executor
@@ -341,6 +343,8 @@ where
                                 refund_timelock: rollover_params.refund_timelock,
                             };
 
+                            dlc.verify(our_role).context("Rollover resulted in invalid DLC")?;
+
                             emit_completed(
                                 order_id,
                                 dlc,
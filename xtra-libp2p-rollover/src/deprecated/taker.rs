@@ -341,6 +341,8 @@ where
                                 refund_timelock: rollover_params.refund_timelock,
                             };
 
+                            dlc.verify(our_role).context("Rollover resulted in invalid DLC")?;
+
                             emit_completed(
                                 order_id,
                                 dlc,
@@ -13,12 +13,11 @@ use std::sync::Arc;
 use std::time::Duration;
 use xtra::prelude::*;
 use xtra::spawn::TokioGlobalSpawnExt;
-use xtra_libp2p::dialer;
+use xtra_libp2p::endpoint;
 use xtra_libp2p::endpoint::Subscribers;
+use xtra_libp2p::ConnectAndWait;
 use xtra_libp2p::Endpoint;
 use xtra_libp2p::OpenSubstream;
-use xtras::supervisor::always_restart;
-use xtras::supervisor::Supervisor;
 
 #[derive(Parser)]
 struct Opts {
@@ -45,22 +44,19 @@ async fn main() -> Result<()> {
         Subscribers::default(),
         Arc::new(HashSet::default()),
     )
-    .create(None)
+    .create(Some(endpoint::DEFAULT_MAILBOX_CAPACITY))
     .spawn_global();
 
-    let dialer_constructor = {
-        let connect_addr = opts.multiaddr.clone();
-        let endpoint_addr = endpoint_addr.clone();
-        move || dialer::Actor::new(endpoint_addr.clone(), connect_addr.clone())
-    };
-
-    let (supervisor, _dialer_actor) =
-        Supervisor::with_policy(dialer_constructor, always_restart::<dialer::Error>());
-
-    #[allow(clippy::disallowed_methods)]
-    tokio::spawn(supervisor.run());
-
-    tokio_extras::time::sleep(Duration::from_secs(1)).await;
+    // `Connect` returns as soon as dialing starts, thus opening a substream right away would fail
+    // with `Error::StillConnecting`. Wait for the connection to be established instead. To stay
+    // connected to the peer over time, use a `dialer::Actor` instead.
+    endpoint_addr
+        .send(ConnectAndWait(opts.multiaddr.clone()))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
 
     let stream = endpoint_addr
         .send(OpenSubstream::single_protocol(
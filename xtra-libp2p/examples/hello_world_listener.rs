@@ -13,6 +13,7 @@ use std::time::Duration;
 use tracing::Level;
 use xtra::prelude::*;
 use xtra::spawn::TokioGlobalSpawnExt;
+use xtra_libp2p::endpoint;
 use xtra_libp2p::endpoint::Subscribers;
 use xtra_libp2p::listener;
 use xtra_libp2p::Endpoint;
@@ -60,7 +61,7 @@ async fn main() -> Result<()> {
         Subscribers::default(),
         Arc::new(HashSet::default()),
     )
-    .create(None)
+    .create(Some(endpoint::DEFAULT_MAILBOX_CAPACITY))
     .spawn_global();
 
     let endpoint_listen = multiaddr_str.parse::<Multiaddr>().unwrap();
@@ -0,0 +1,29 @@
+#![no_main]
+
+use asynchronous_codec::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use xtra_libp2p::framing::decode_frame;
+
+const MAX_FRAME_LEN: usize = 1024;
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte determines how many bytes arrive per read, so that frames are also split
+    // across several reads.
+    let (chunk_len, data) = match data.split_first() {
+        Some((chunk_len, data)) => (usize::from(*chunk_len).max(1), data),
+        None => return,
+    };
+
+    let mut buffer = BytesMut::new();
+    for chunk in data.chunks(chunk_len) {
+        buffer.extend_from_slice(chunk);
+
+        loop {
+            match decode_frame(&mut buffer, MAX_FRAME_LEN) {
+                Ok(Some(frame)) => assert!(frame.len() <= MAX_FRAME_LEN),
+                Ok(None) => break,
+                Err(_) => return,
+            }
+        }
+    }
+});
@@ -1,18 +1,32 @@
+use crate::multiaddress_ext::expand_wildcard;
+use crate::multiaddress_ext::has_unspecified_port;
+use crate::multiaddress_ext::is_wildcard;
 use crate::multiaddress_ext::MultiaddrExt as _;
+use crate::protocol_set::ProtocolSet;
+use crate::substream::OpenSubstreamsRegistry;
+use crate::substream::SubstreamStats;
+use crate::substream::SubstreamTimings;
+use crate::substream::SubstreamTimingsRegistry;
 use crate::upgrade;
 use crate::Connection;
 use crate::Substream;
-use anyhow::bail;
 use anyhow::Context as _;
 use anyhow::Result;
 use async_trait::async_trait;
+use conquer_once::Lazy;
+use futures::channel::oneshot;
+use futures::future;
 use futures::future::BoxFuture;
+use futures::future::Either;
 use futures::stream::BoxStream;
 use futures::AsyncRead;
 use futures::AsyncWrite;
+use futures::FutureExt;
+use futures::Stream;
 use futures::StreamExt;
 use futures::TryStreamExt;
 use libp2p_core::identity::Keypair;
+use libp2p_core::multiaddr::Protocol;
 use libp2p_core::transport::Boxed;
 use libp2p_core::transport::ListenerEvent;
 use libp2p_core::Multiaddr;
@@ -21,19 +35,36 @@ use libp2p_core::PeerId;
 use libp2p_core::Transport;
 use multistream_select::NegotiationError;
 use multistream_select::Version;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fmt;
 use std::marker::PhantomData;
+use std::num::NonZeroUsize;
 use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
 use thiserror::Error;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
+use tokio_extras::TaskMap;
 use tokio_extras::Tasks;
 use tracing::instrument;
 use tracing::Instrument;
+use tracing::Level;
 use xtra::message_channel::MessageChannel;
 use xtra::Address;
 use xtra::Context;
+use xtra::Handler;
 use xtra_productivity::xtra_productivity;
 use xtras::SendAsyncNext;
 use xtras::SendAsyncSafe;
@@ -44,7 +75,11 @@ use xtras::SendAsyncSafe;
 /// The actor does not impose any policy on connection and/or protocol management.
 /// New connections can be established by sending a [`Connect`] messages. Existing connections can
 /// be disconnected by sending [`Disconnect`]. Listening for incoming connections is done by sending
-/// a [`ListenOn`] message. To list the current state, send the [`GetConnectionStats`] message.
+/// a [`ListenOn`] message, which returns a [`ListenerId`] that can later be passed to
+/// [`StopListening`]. New inbound connections can be rejected temporarily by sending
+/// [`PauseInbound`] and accepted again with [`ResumeInbound`]. To list the current state, send the
+/// [`GetConnectionStats`] message.
+/// The endpoint's identity can be changed at runtime by sending [`RotateIdentity`].
 ///
 /// The combination of the above should make it possible to implement a fairly large number of
 /// policies. For example, to maintain a connection to an another endpoint, you can regularly check
@@ -55,26 +90,272 @@ use xtras::SendAsyncSafe;
 /// connection. Any incoming substream will - assuming the protocol is supported by the endpoint -
 /// trigger a [`NewInboundSubstream`] message to the actor provided in the constructor.
 /// Opening a new substream can be achieved by sending the [`OpenSubstream`] message.
+///
+/// # Overload
+///
+/// The endpoint's own connection tasks report back to it by means of messages (e.g. when a new
+/// connection was established or an existing connection failed). Under a connection storm, these
+/// can arrive faster than the actor processes them. To bound the memory used by the mailbox,
+/// create the actor with a capacity, f.e. [`DEFAULT_MAILBOX_CAPACITY`]. Once the mailbox is full,
+/// senders wait until there is space again. Dropping messages is not an option because each of
+/// them keeps the endpoint's view of the connections consistent. Instead, waiting applies
+/// backpressure to the dialing and upgrading of further connections.
 pub struct Endpoint {
-    transport_fn: Box<dyn Fn() -> Boxed<Connection> + Send + 'static>,
-    controls: HashMap<PeerId, (yamux::Control, Tasks)>,
-    inbound_substream_channels: HashMap<&'static str, MessageChannel<NewInboundSubstream, ()>>,
-    listen_addresses: HashSet<Multiaddr>,
+    transport_fn: Box<dyn Fn(&Keypair, usize) -> Boxed<Connection> + Send + 'static>,
+    identity: Keypair,
+    controls: HashMap<PeerId, EstablishedConnection>,
+    next_connection_id: ConnectionId,
+    inbound_substream_handlers: ProtocolSet,
+    /// The address each listener listens on, as given to [`ListenOn`].
+    listen_addresses: HashMap<ListenerId, Multiaddr>,
+    /// The addresses we report for each listener, see [`WildcardListenPolicy`].
+    advertised_listen_addresses: HashMap<ListenerId, HashSet<Multiaddr>>,
+    wildcard_listen_policy: WildcardListenPolicy,
+    listeners: TaskMap<ListenerId>,
+    next_listener_id: ListenerId,
+    /// Listeners queued until [`StartListening`], if enabled via
+    /// [`Endpoint::with_deferred_listening`].
+    pending_listeners: Option<Vec<(ListenerId, Multiaddr)>>,
     inflight_connections: HashSet<PeerId>,
+    /// Notified once the dial to an in-flight peer completes, see [`ConnectAndWait`].
+    connection_waiters: HashMap<PeerId, oneshot::Sender<Result<(), Error>>>,
+    /// Bounds the number of concurrent dials, if enabled via
+    /// [`Endpoint::with_max_concurrent_dials`].
+    dial_limit: Option<DialLimit>,
+    /// The address we dialed each peer at, see [`ResetConnection`]. Forgotten once the
+    /// connection is dropped or the dial fails.
+    last_dialed_addresses: HashMap<PeerId, Multiaddr>,
     blocked_peers: Arc<HashSet<PeerId>>,
+    /// Peers we refuse to be connected to until the given instant, see [`Quarantine`].
+    quarantined_peers: HashMap<PeerId, tokio::time::Instant>,
+    /// Whether listeners currently reject new inbound connections, see [`PauseInbound`].
+    inbound_paused: Arc<AtomicBool>,
     connection_timeout: Duration,
+    /// Scales the negotiation timeout of outbound substreams with the peer's RTT, if enabled via
+    /// [`Endpoint::with_adaptive_negotiation_timeout`].
+    adaptive_negotiation_timeout: Option<AdaptiveNegotiationTimeout>,
+    /// The most recent round-trip time to each connected peer, see [`RecordRtt`].
+    peer_rtts: HashMap<PeerId, Duration>,
+    /// When we were last connected to each peer, see [`GetLastSeen`].
+    last_seen: HashMap<PeerId, SystemTime>,
+    /// How long we wait for a handler to accept an inbound substream, see
+    /// [`Endpoint::with_inbound_substream_timeout`].
+    inbound_substream_timeout: Duration,
+    /// How many inbound substreams per connection are buffered until dispatched, see
+    /// [`Endpoint::with_max_buffered_inbound_substreams`].
+    max_buffered_inbound_substreams: usize,
+    /// The priority of dispatching inbound substreams per protocol, see
+    /// [`Endpoint::with_inbound_substream_priority`].
+    inbound_substream_priorities: HashMap<&'static str, u8>,
+    /// The levels at which failures to negotiate inbound substreams are logged, see
+    /// [`Endpoint::with_negotiation_log_levels`].
+    negotiation_log_levels: NegotiationLogLevels,
+    /// How long a connection's inbound substream worker may go without activity before the
+    /// connection is closed, if enabled via [`Endpoint::with_inbound_worker_watchdog`].
+    inbound_worker_watchdog: Option<Duration>,
+    /// How long a connection is kept until it is recycled, if enabled via
+    /// [`Endpoint::with_max_connection_lifetime`].
+    max_connection_lifetime: Option<Duration>,
+    /// Decides whether new connections are kept, if set via [`Endpoint::with_connection_gate`].
+    connection_gate: Option<ConnectionGate>,
+    /// Where substream timings are recorded, if enabled via
+    /// [`Endpoint::with_substream_timings`].
+    substream_timings: Option<SubstreamTimingsRegistry>,
+    /// The substreams which are currently open, see [`GetSubstreamStats`].
+    open_substreams: OpenSubstreamsRegistry,
     subscribers: Subscribers,
     peer_listen_protocols: HashMap<PeerId, HashSet<String>>,
 }
 
+/// Bounds the number of concurrent dials, see [`Endpoint::with_max_concurrent_dials`].
+#[derive(Clone)]
+struct DialLimit {
+    permits: Arc<Semaphore>,
+    /// How many dials are currently waiting for a permit.
+    queued: Arc<AtomicUsize>,
+}
+
+impl DialLimit {
+    fn new(max: NonZeroUsize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max.get())),
+            queued: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Wait until fewer than the maximum number of dials are ongoing.
+    ///
+    /// The dial counts as queued until this returns or is dropped.
+    async fn acquire(&self) -> Result<OwnedSemaphorePermit> {
+        let _queued = QueuedDial::new(&self.queued);
+
+        self.permits
+            .clone()
+            .acquire_owned()
+            .await
+            .context("Dial limit semaphore closed")
+    }
+
+    fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+}
+
+/// Counts a dial as queued for as long as it is alive, see [`DialLimit::acquire`].
+struct QueuedDial<'a>(&'a AtomicUsize);
+
+impl<'a> QueuedDial<'a> {
+    fn new(queued: &'a AtomicUsize) -> Self {
+        queued.fetch_add(1, Ordering::SeqCst);
+
+        Self(queued)
+    }
+}
+
+impl Drop for QueuedDial<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A sensible mailbox capacity for the [`Endpoint`] actor.
+///
+/// See [`Endpoint`] for how the endpoint behaves once its mailbox is full.
+pub const DEFAULT_MAILBOX_CAPACITY: usize = 1024;
+
+/// The default time we wait for a handler to accept an inbound substream.
+///
+/// See [`Endpoint::with_inbound_substream_timeout`].
+pub const DEFAULT_INBOUND_SUBSTREAM_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a connection which exceeded its maximum lifetime waits for all its substreams to be
+/// closed before it is recycled regardless.
+///
+/// See [`Endpoint::with_max_connection_lifetime`].
+pub const MAX_RECYCLE_DELAY: Duration = Duration::from_secs(60);
+
+/// The default number of inbound substreams buffered per connection until they are dispatched.
+///
+/// See [`Endpoint::with_max_buffered_inbound_substreams`].
+pub const DEFAULT_MAX_BUFFERED_INBOUND_SUBSTREAMS: usize = 5;
+
+/// The maximum number of peers the [`Endpoint`] remembers when it was last connected to.
+///
+/// See [`GetLastSeen`].
+pub const MAX_LAST_SEEN_PEERS: usize = 1000;
+
+/// Decides whether a new connection to a peer is kept, see [`Endpoint::with_connection_gate`].
+///
+/// Resolves to `Err` with the reason for rejecting the peer.
+pub type ConnectionGate =
+    Box<dyn Fn(PeerId) -> BoxFuture<'static, Result<(), String>> + Send + 'static>;
+
+/// Scale the negotiation timeout of outbound substreams with the round-trip time to the peer.
+///
+/// The timeout is a multiple of the most recent round-trip time recorded for the peer via
+/// [`RecordRtt`], bounded by `min` and `max`. Without a recorded round-trip time, the endpoint's
+/// `connection_timeout` applies.
+///
+/// Set via [`Endpoint::with_adaptive_negotiation_timeout`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdaptiveNegotiationTimeout {
+    rtt_multiplier: u32,
+    min: Duration,
+    max: Duration,
+}
+
+impl AdaptiveNegotiationTimeout {
+    /// # Panics
+    ///
+    /// If `min` is greater than `max`.
+    pub fn new(rtt_multiplier: u32, min: Duration, max: Duration) -> Self {
+        assert!(min <= max, "min timeout must not exceed max timeout");
+
+        Self {
+            rtt_multiplier,
+            min,
+            max,
+        }
+    }
+
+    fn timeout(&self, rtt: Duration) -> Duration {
+        rtt.saturating_mul(self.rtt_multiplier)
+            .clamp(self.min, self.max)
+    }
+}
+
+/// The levels at which failures to negotiate a peer's inbound substreams are logged, incl.
+/// hitting the negotiation timeout.
+///
+/// Failures of peers we have never been connected to are mostly noise, f.e. from port scanners or
+/// misconfigured clients. Failures of peers we have been connected to before rather point at an
+/// actual problem. Both default to [`Level::DEBUG`].
+///
+/// Set via [`Endpoint::with_negotiation_log_levels`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NegotiationLogLevels {
+    /// For peers we have not been connected to before the current connection.
+    pub new_peer: Level,
+    /// For peers we have been connected to before.
+    pub known_peer: Level,
+}
+
+impl NegotiationLogLevels {
+    fn level(&self, known_peer: bool) -> Level {
+        if known_peer {
+            self.known_peer
+        } else {
+            self.new_peer
+        }
+    }
+}
+
+impl Default for NegotiationLogLevels {
+    fn default() -> Self {
+        Self {
+            new_peer: Level::DEBUG,
+            known_peer: Level::DEBUG,
+        }
+    }
+}
+
+/// How listening on a wildcard address like `/ip4/0.0.0.0/tcp/9999` is reflected in the listen
+/// addresses of the [`Endpoint`].
+///
+/// The listen addresses are reported by [`GetConnectionStats`] and to the subscribers of listen
+/// address changes. Nobody can dial a wildcard address, thus reporting it verbatim advertises an
+/// address that is of no use to peers. Other addresses are always reported as given to
+/// [`ListenOn`], unless they leave the port to the transport, see [`ListenOn`].
+///
+/// Set via [`Endpoint::with_wildcard_listen_policy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WildcardListenPolicy {
+    /// Report one address per local interface of the wildcard's IP version.
+    ///
+    /// Falls back to reporting the wildcard as is if the interfaces cannot be enumerated.
+    #[default]
+    Expand,
+    /// Report the wildcard as is.
+    Keep,
+    /// Don't report the wildcard, only the concrete addresses the transport announces for the
+    /// listener, if any.
+    Suppress,
+}
+
 /// Open a substream to the provided peer.
 ///
 /// Fails if we are not connected to the peer or the peer does not support any of the requested
-/// protocols.
+/// protocols. If we are still dialing the peer, this fails with [`Error::StillConnecting`]; use
+/// [`ConnectAndWait`] to open a substream right after connecting.
+///
+/// The protocol negotiation does not run inside the [`Endpoint`] actor, but in the future returned
+/// by the handler. To cancel an in-flight negotiation, drop that future, f.e. by racing it against
+/// a cancellation signal. This resets the substream.
 #[derive(Debug)]
 pub struct OpenSubstream<P> {
     peer_id: PeerId,
     protocols: Vec<&'static str>,
+    version: Version,
     marker_num_protocols: PhantomData<P>,
 }
 
@@ -97,6 +378,7 @@ impl OpenSubstream<Single> {
         Self {
             peer_id,
             protocols: vec![protocol],
+            version: Version::V1,
             marker_num_protocols: PhantomData,
         }
     }
@@ -116,11 +398,29 @@ impl OpenSubstream<Multiple> {
         Self {
             peer_id,
             protocols,
+            version: Version::V1,
             marker_num_protocols: PhantomData,
         }
     }
 }
 
+impl<P> OpenSubstream<P> {
+    /// Use the given multistream-select [`Version`] for negotiating the protocol.
+    ///
+    /// Defaults to [`Version::V1`].
+    ///
+    /// [`Version::V1Lazy`] saves a round-trip by optimistically settling on the (last) protocol
+    /// and sending data before the listener has confirmed it. This is only safe if we know that
+    /// the peer supports the protocol: if it doesn't, negotiation failures surface as I/O errors
+    /// on the first read or write of the substream instead of as [`Error::NegotiationFailed`].
+    /// Older peers that only understand strict [`Version::V1`] are still compatible on the wire
+    /// but lose this more precise error reporting.
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+}
+
 /// Connect to the given [`Multiaddr`].
 ///
 /// The address must contain a `/p2p` suffix.
@@ -128,25 +428,199 @@ impl OpenSubstream<Multiple> {
 #[derive(Debug)]
 pub struct Connect(pub Multiaddr);
 
+/// Connect to the given [`Multiaddr`] and wait until the connection is established.
+///
+/// Like [`Connect`], the address must contain a `/p2p` suffix and this fails right away if we are
+/// already connected or connecting to the peer. Otherwise, the returned future resolves once the
+/// connection is established or fails with [`Error::DialFailed`] if the dial does not succeed.
+#[derive(Debug)]
+pub struct ConnectAndWait(pub Multiaddr);
+
+/// Connect to the given peer at the given [`Multiaddr`].
+///
+/// Unlike [`Connect`], the address does not need to contain a `/p2p` suffix because the peer is
+/// already known. If the address does contain a peer ID, it must match `peer_id`.
+/// Will fail if we are already connected to the peer.
+#[derive(Debug)]
+pub struct ConnectWithPeerId {
+    pub peer_id: PeerId,
+    pub address: Multiaddr,
+}
+
 /// Disconnect from the given peer.
 #[derive(Clone, Copy, Debug)]
 pub struct Disconnect(pub PeerId);
 
+/// Disconnect from all of the given peers at once.
+///
+/// Returns the peers we were actually connected to, i.e. the ones we disconnected from.
+#[derive(Clone, Debug)]
+pub struct DisconnectMany(pub Vec<PeerId>);
+
+/// Close the connection to the given peer and immediately dial it again.
+///
+/// Useful if a connection is in a bad state even though the peer is reachable. The peer is
+/// redialed at the address we dialed it at via [`Connect`]. Fails with
+/// [`Error::NoKnownAddress`] if we did not dial the peer, e.g. because it dialed us; the
+/// connection is closed regardless.
+#[derive(Clone, Copy, Debug)]
+pub struct ResetConnection(pub PeerId);
+
 /// Listen on the provided [`Multiaddr`].
 ///
 /// For this to work, the [`Endpoint`] needs to be constructed with a compatible transport.
 /// In other words, you cannot listen on a `/memory` address if you haven't configured a `/memory`
 /// transport.
+///
+/// Returns a [`ListenerId`] identifying the listener, even if the same address is listened on more
+/// than once.
+///
+/// If the address leaves the port to the transport, like `/ip4/127.0.0.1/tcp/0`, the addresses
+/// the transport announces are reported as the listen addresses, see [`ListenAddressAdded`].
 pub struct ListenOn(pub Multiaddr);
 
+/// Bind all listeners queued via [`ListenOn`] on an [`Endpoint`] constructed with
+/// [`Endpoint::with_deferred_listening`].
+///
+/// Later [`ListenOn`] messages bind right away. Does nothing if listening has already started or
+/// was never deferred.
+#[derive(Clone, Copy, Debug)]
+pub struct StartListening;
+
+/// Stop the listener identified by the given [`ListenerId`].
+///
+/// Does nothing if the listener has already stopped.
+#[derive(Clone, Copy, Debug)]
+pub struct StopListening(pub ListenerId);
+
+/// Disconnect from the given peer and refuse connections to and from it for `duration`.
+///
+/// Unlike the blocked peers the [`Endpoint`] is constructed with, the peer is allowed again once
+/// the quarantine ends. Quarantining a peer again replaces the remaining duration. Connecting to a
+/// quarantined peer fails with [`Error::Quarantined`].
+#[derive(Clone, Copy, Debug)]
+pub struct Quarantine {
+    pub peer: PeerId,
+    pub duration: Duration,
+}
+
+/// Stop accepting new inbound connections until [`ResumeInbound`] is sent.
+///
+/// Listeners keep running and established connections are unaffected, for example to let
+/// in-flight protocols complete during a maintenance window. Peers trying to connect in the
+/// meantime have their connection closed right away.
+#[derive(Clone, Copy, Debug)]
+pub struct PauseInbound;
+
+/// Accept new inbound connections again after [`PauseInbound`].
+#[derive(Clone, Copy, Debug)]
+pub struct ResumeInbound;
+
+/// Identifies a listener started via [`ListenOn`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ListenerId(u64);
+
+/// Replace the [`Endpoint`]'s identity with the given [`Keypair`].
+///
+/// Changing the identity changes the endpoint's [`PeerId`]. All existing connections are closed
+/// because they were authenticated with the old identity and all listeners are restarted so that
+/// incoming connections are authenticated with the new one.
+///
+/// Peers must learn about the new [`PeerId`] out of band. Peers that keep dialing the old
+/// [`PeerId`] will fail to connect.
+pub struct RotateIdentity(pub Keypair);
+
+/// Check whether the [`Endpoint`] has a handler registered for the given protocol.
+///
+/// Only protocols with a handler are negotiated for inbound substreams. Startup code can use this
+/// to assert that all the protocols it relies on are wired up.
+#[derive(Clone, Copy, Debug)]
+pub struct HasHandler(pub &'static str);
+
+/// Retrieve the [`SubstreamTimings`] of the most recent substream per peer and protocol.
+///
+/// Empty unless enabled via [`Endpoint::with_substream_timings`]. The timings of a peer are
+/// forgotten once the connection to it is dropped.
+#[derive(Clone, Copy, Debug)]
+pub struct GetSubstreamTimings;
+
+/// Record the round-trip time to a connected peer, see [`AdaptiveNegotiationTimeout`].
+///
+/// Samples for peers we are not connected to are ignored.
+#[derive(Clone, Copy, Debug)]
+pub struct RecordRtt {
+    pub peer_id: PeerId,
+    pub rtt: Duration,
+}
+
+/// Retrieve when the [`Endpoint`] was last connected to each peer.
+///
+/// A peer's entry is updated whenever a connection to it is established and once that connection
+/// is dropped. Peers we are currently connected to are thus reported as of the time the
+/// connection was established. Entries are kept in memory only, i.e. they are lost on restart.
+/// Beyond [`MAX_LAST_SEEN_PEERS`], the disconnected peer seen the longest ago is forgotten.
+#[derive(Clone, Copy, Debug)]
+pub struct GetLastSeen;
+
+/// Retrieve the [`SubstreamStats`] of the currently open substreams.
+#[derive(Clone, Copy, Debug)]
+pub struct GetSubstreamStats;
+
 /// Retrieve [`ConnectionStats`] from the [`Endpoint`].
 #[derive(Clone, Copy, Debug)]
 pub struct GetConnectionStats;
 
+/// Retrieve an [`EndpointDebug`] snapshot of the [`Endpoint`]'s internal state.
+///
+/// Meant for diagnostics, f.e. to attach to a support request. Prefer [`GetConnectionStats`]
+/// anywhere else.
+#[derive(Clone, Copy, Debug)]
+pub struct GetDebugSnapshot;
+
+/// Serializable snapshot of the [`Endpoint`]'s internal state.
+///
+/// Peers and addresses are rendered as strings so that the snapshot can be serialized as is.
+#[derive(Clone, Debug, Serialize)]
+pub struct EndpointDebug {
+    pub peer_id: String,
+    pub connections: Vec<ConnectionDebug>,
+    pub inflight_connections: BTreeSet<String>,
+    /// In-flight dials waiting for a slot, see [`Endpoint::with_max_concurrent_dials`].
+    pub queued_dials: usize,
+    pub listen_addresses: BTreeMap<u64, String>,
+    pub advertised_listen_addresses: BTreeMap<u64, BTreeSet<String>>,
+    /// Listeners waiting for [`StartListening`], see [`Endpoint::with_deferred_listening`].
+    pub pending_listen_addresses: BTreeMap<u64, String>,
+    pub last_dialed_addresses: BTreeMap<String, String>,
+    pub peer_listen_protocols: BTreeMap<String, BTreeSet<String>>,
+    pub inbound_protocols: BTreeSet<&'static str>,
+    pub blocked_peers: BTreeSet<String>,
+    /// The remaining time of each peer's [`Quarantine`].
+    pub quarantined_peers: BTreeMap<String, Duration>,
+    pub inbound_paused: bool,
+    pub connection_timeout: Duration,
+    pub inbound_substream_timeout: Duration,
+    pub substream_timings_enabled: bool,
+}
+
+/// An established connection as part of an [`EndpointDebug`] snapshot.
+#[derive(Clone, Debug, Serialize)]
+pub struct ConnectionDebug {
+    pub peer_id: String,
+    pub connection_id: u64,
+    /// Whether we dialed the peer, as opposed to the peer dialing us.
+    pub dialer: bool,
+}
+
 #[derive(Debug, Default)]
 pub struct ConnectionStats {
     pub connected_peers: HashSet<PeerId>,
+    /// The addresses we can be reached at, see [`WildcardListenPolicy`].
     pub listen_addresses: HashSet<Multiaddr>,
+    /// The addresses we listen on, as given to [`ListenOn`].
+    pub listening_on: HashSet<Multiaddr>,
+    /// Whether new inbound connections are currently rejected, see [`PauseInbound`].
+    pub inbound_paused: bool,
 }
 
 /// Notifies an actor of a new, inbound substream from the given peer.
@@ -156,6 +630,45 @@ pub struct NewInboundSubstream {
     pub stream: Substream,
 }
 
+/// Builder for the inbound substream handlers an [`Endpoint`] is constructed with.
+///
+/// Pairs each protocol with the [`Address`] of the actor which should be given the substreams
+/// negotiated for it, sparing callers the conversion of every address into a [`MessageChannel`].
+#[derive(Default)]
+pub struct InboundSubstreamHandlers(Vec<(&'static str, MessageChannel<NewInboundSubstream, ()>)>);
+
+impl InboundSubstreamHandlers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Route inbound substreams for `protocol` to the actor behind `handler`.
+    pub fn with<A>(mut self, protocol: &'static str, handler: Address<A>) -> Self
+    where
+        A: Handler<NewInboundSubstream, Return = ()>,
+    {
+        self.0.push((protocol, handler.into()));
+        self
+    }
+
+    /// Produce the handlers in the form expected by [`Endpoint::new`].
+    ///
+    /// Fails with [`Error::InboundSubstreamHandlerCount`] if the number of registered handlers
+    /// does not match `N`.
+    pub fn build<const N: usize>(
+        self,
+    ) -> Result<[(&'static str, MessageChannel<NewInboundSubstream, ()>); N], Error> {
+        let registered = self.0.len();
+
+        self.0
+            .try_into()
+            .map_err(|_| Error::InboundSubstreamHandlerCount {
+                expected: N,
+                registered,
+            })
+    }
+}
+
 /// Message used to tell the [`Endpoint`] about the listen protocols that a peer supports.
 pub struct RegisterListenProtocols {
     pub peer_id: PeerId,
@@ -172,12 +685,79 @@ pub enum Error {
     NegotiationFailed(#[from] NegotiationError), // TODO(public-api): Consider breaking this up.
     #[error("Bad connection")]
     BadConnection(#[from] yamux::ConnectionError), // TODO(public-api): Consider removing this.
+    #[error("Still connecting to {0}")]
+    StillConnecting(PeerId),
     #[error("Address {0} does not end with a peer ID")]
     NoPeerIdInAddress(Multiaddr),
+    #[error("Address {address} does not belong to peer {expected}")]
+    PeerIdMismatch {
+        expected: PeerId,
+        address: Multiaddr,
+    },
+    #[error("Address {0} belongs to ourselves")]
+    SelfDial(Multiaddr),
     #[error("Already trying to connect to peer {0}")]
     AlreadyTryingToConnected(PeerId),
+    #[error("Failed to dial peer {peer_id}: {error:#}")]
+    DialFailed {
+        peer_id: PeerId,
+        error: Arc<anyhow::Error>,
+    },
+    #[error("No known address to dial peer {0}")]
+    NoKnownAddress(PeerId),
     #[error("Peer does not listen for given protocol(s)")]
     ProtocolNotSupportedByPeer,
+    #[error("Substream was not negotiated within {0:?}")]
+    Deadline(Duration),
+    #[error("Endpoint actor is disconnected")]
+    EndpointDisconnected,
+    #[error("Expected {expected} inbound substream handlers but {registered} were registered")]
+    InboundSubstreamHandlerCount { expected: usize, registered: usize },
+    #[error("Connection to {peer_id} was rejected: {reason}")]
+    ConnectionRejected { peer_id: PeerId, reason: String },
+    #[error("Peer {peer_id} is quarantined for another {remaining:?}")]
+    Quarantined {
+        peer_id: PeerId,
+        remaining: Duration,
+    },
+}
+
+/// Extension trait for opening substreams through an [`Endpoint`]'s [`Address`].
+#[async_trait]
+pub trait OpenSubstreamExt {
+    /// Open a substream, failing with [`Error::Deadline`] if it is not fully negotiated within
+    /// the given `deadline`.
+    ///
+    /// The deadline covers both sending the message to the [`Endpoint`] and the protocol
+    /// negotiation. This allows callers to bound the time they are willing to wait independently
+    /// of the endpoint's `connection_timeout`.
+    async fn open_substream_with_deadline(
+        &self,
+        msg: OpenSubstream<Single>,
+        deadline: Duration,
+    ) -> Result<Substream, Error>;
+}
+
+#[async_trait]
+impl OpenSubstreamExt for Address<Endpoint> {
+    async fn open_substream_with_deadline(
+        &self,
+        msg: OpenSubstream<Single>,
+        deadline: Duration,
+    ) -> Result<Substream, Error> {
+        tokio_extras::time::timeout(
+            deadline,
+            async {
+                self.send(msg)
+                    .await
+                    .map_err(|_| Error::EndpointDisconnected)??
+                    .await
+            },
+            || tracing::debug_span!("open substream with deadline"),
+        )
+        .await
+        .map_err(|_timeout| Error::Deadline(deadline))?
+    }
 }
 
 /// Subscribers that get notified on connection changes
@@ -190,6 +770,7 @@ pub struct Subscribers {
     connection_dropped: Vec<MessageChannel<ConnectionDropped, ()>>,
     listen_address_added: Vec<MessageChannel<ListenAddressAdded, ()>>,
     listen_address_removed: Vec<MessageChannel<ListenAddressRemoved, ()>>,
+    dial_failure: Vec<MessageChannel<DialFailure, ()>>,
 }
 
 impl Subscribers {
@@ -204,8 +785,18 @@ impl Subscribers {
             connection_dropped,
             listen_address_added,
             listen_address_removed,
+            dial_failure: Vec::new(),
         }
     }
+
+    /// Additionally notify the given subscribers whenever dialing a peer fails.
+    ///
+    /// This is opt-in because it is mostly useful for debugging why connections cannot be
+    /// established.
+    pub fn with_dial_failure(mut self, dial_failure: Vec<MessageChannel<DialFailure, ()>>) -> Self {
+        self.dial_failure = dial_failure;
+        self
+    }
 }
 
 impl Endpoint {
@@ -213,13 +804,26 @@ impl Endpoint {
     ///
     /// An [`Endpoint`]s identity ([`PeerId`]) will be computed from the given [`Keypair`].
     ///
+    /// The transport is expected to be a plain one (e.g. TCP). The endpoint always upgrades it
+    /// itself, authenticating and encrypting every connection with noise before multiplexing it
+    /// with yamux. There is no way to construct an [`Endpoint`] that skips this upgrade, thus
+    /// connections cannot end up in plaintext by misconfiguration.
+    ///
     /// The `connection_timeout` is applied to:
     /// 1. Dialing
     /// 2. Connection upgrades (i.e. noise handshake, yamux upgrade, etc)
     /// 3. Protocol negotiations
     ///
+    /// All timeouts are driven by tokio's timer. Tests can therefore use
+    /// [`tokio::time::pause`] to advance time deterministically instead of waiting for the real
+    /// duration.
+    ///
     /// The provided substream handlers are actors that will be given the fully-negotiated
     /// substreams whenever a peer opens a new substream for the provided protocol.
+    ///
+    /// To listen on and dial over several transports (e.g. TCP and memory) with the same endpoint,
+    /// combine them via [`Transport::or_transport`]. For each address, [`ListenOn`] and
+    /// [`Connect`] use the first transport that supports the address's protocol stack.
     pub fn new<T, const N: usize>(
         transport: Box<dyn Fn() -> T + Send + 'static>,
         identity: Keypair,
@@ -236,281 +840,230 @@ impl Endpoint {
         T::Dial: Send + 'static,
         T::ListenerUpgrade: Send + 'static,
     {
+        let inbound_substream_handlers = ProtocolSet::new(inbound_substream_handlers);
+
         let transport_fn = Box::new({
             let transport = Box::new(transport);
-            let identity = identity;
-            let handlers: Vec<&'static str> = inbound_substream_handlers
-                .iter()
-                .map(|(proto, _)| *proto)
-                .collect();
+            let inbound_substream_handlers = inbound_substream_handlers.clone();
 
-            move || {
+            move |identity: &Keypair, max_buffered_inbound_substreams: usize| {
                 upgrade::transport(
                     (transport)(),
-                    &identity,
-                    handlers.clone(),
+                    identity,
+                    inbound_substream_handlers.clone(),
                     connection_timeout,
+                    max_buffered_inbound_substreams,
                 )
             }
         });
 
         Self {
             transport_fn,
-            inbound_substream_channels: verify_unique_handlers(inbound_substream_handlers),
+            identity,
+            inbound_substream_handlers,
             controls: HashMap::default(),
-            listen_addresses: HashSet::default(),
+            next_connection_id: ConnectionId(0),
+            listen_addresses: HashMap::default(),
+            advertised_listen_addresses: HashMap::default(),
+            wildcard_listen_policy: WildcardListenPolicy::default(),
+            listeners: TaskMap::default(),
+            next_listener_id: ListenerId(0),
+            pending_listeners: None,
             inflight_connections: HashSet::default(),
+            connection_waiters: HashMap::default(),
+            dial_limit: None,
+            last_dialed_addresses: HashMap::default(),
             blocked_peers,
+            quarantined_peers: HashMap::default(),
+            inbound_paused: Arc::new(AtomicBool::new(false)),
             connection_timeout,
+            adaptive_negotiation_timeout: None,
+            peer_rtts: HashMap::default(),
+            last_seen: HashMap::default(),
+            inbound_substream_timeout: DEFAULT_INBOUND_SUBSTREAM_TIMEOUT,
+            max_buffered_inbound_substreams: DEFAULT_MAX_BUFFERED_INBOUND_SUBSTREAMS,
+            inbound_substream_priorities: HashMap::default(),
+            negotiation_log_levels: NegotiationLogLevels::default(),
+            inbound_worker_watchdog: None,
+            max_connection_lifetime: None,
+            connection_gate: None,
+            substream_timings: None,
+            open_substreams: OpenSubstreamsRegistry::default(),
             subscribers,
             peer_listen_protocols: HashMap::default(),
         }
     }
 
-    fn does_peer_listen_for(&self, peer_id: PeerId, protocols: &[&str]) -> Result<(), Error> {
-        let listen_protocols = match self.peer_listen_protocols.get(&peer_id) {
-            Some(listen_protocols) => listen_protocols,
-            None => {
-                // Without the information about the peer we cannot decide either way. We default to
-                // answering yes to our caller, just in case
-                return Ok(());
-            }
-        };
-        let listen_protocols = listen_protocols
-            .iter()
-            .map(|p| p.as_ref())
-            .collect::<Vec<_>>();
+    /// Bound the time we wait for a handler to accept an inbound substream.
+    ///
+    /// Inbound substreams of a connection are handed to their handlers one after the other. A
+    /// handler that doesn't accept a substream in time (f.e. because its mailbox is full) would
+    /// otherwise stall all other protocols on the same connection. If the timeout is hit, the
+    /// substream is dropped, which closes it, and we move on to the next one.
+    ///
+    /// Defaults to [`DEFAULT_INBOUND_SUBSTREAM_TIMEOUT`].
+    pub fn with_inbound_substream_timeout(mut self, timeout: Duration) -> Self {
+        self.inbound_substream_timeout = timeout;
+        self
+    }
 
-        protocols
-            .iter()
-            .any(|protocol| listen_protocols.contains(protocol))
-            .then(|| ())
-            .ok_or(Error::ProtocolNotSupportedByPeer)?;
+    /// Limit how many inbound substreams of a connection are buffered until they are dispatched to
+    /// their handlers.
+    ///
+    /// Inbound substreams are dispatched one after the other. If dispatching falls behind, f.e.
+    /// because handlers are slow to accept substreams, the substreams opened by the peer in the
+    /// meantime are buffered. Once `max` substreams are buffered, we stop reading from the
+    /// connection, which applies back-pressure to the peer instead of buffering without bound.
+    ///
+    /// Only affects connections established after this is set. Defaults to
+    /// [`DEFAULT_MAX_BUFFERED_INBOUND_SUBSTREAMS`].
+    pub fn with_max_buffered_inbound_substreams(mut self, max: usize) -> Self {
+        self.max_buffered_inbound_substreams = max;
+        self
+    }
 
-        Ok(())
+    /// Dispatch inbound substreams of `protocol` with the given priority.
+    ///
+    /// If several inbound substreams of a connection are negotiated and waiting to be dispatched,
+    /// f.e. because the peer floods us with substreams of one protocol, those with the highest
+    /// priority are dispatched first. Protocols without a priority have priority 0.
+    ///
+    /// Only affects connections established after this is set.
+    pub fn with_inbound_substream_priority(mut self, protocol: &'static str, priority: u8) -> Self {
+        self.inbound_substream_priorities.insert(protocol, priority);
+        self
     }
 
-    async fn drop_connection(&mut self, this: &Address<Self>, peer_id: &PeerId) {
-        self.peer_listen_protocols.remove(peer_id);
+    /// Log failures to negotiate inbound substreams at the given levels, see
+    /// [`NegotiationLogLevels`].
+    ///
+    /// A peer counts as known if we have been connected to it since the endpoint was started. Only
+    /// affects connections established after this is set.
+    pub fn with_negotiation_log_levels(mut self, levels: NegotiationLogLevels) -> Self {
+        self.negotiation_log_levels = levels;
+        self
+    }
 
-        let (mut control, tasks) = match self.controls.remove(peer_id) {
-            None => return,
-            Some(control) => control,
-        };
+    /// Close connections whose inbound substream worker sees no activity for longer than
+    /// `window`.
+    ///
+    /// Inbound substreams of a connection are accepted by a worker task. If that worker stops
+    /// making progress, the connection still looks established but cannot serve the peer anymore.
+    /// Whilst waiting for substreams, the worker records a heartbeat twice per `window`, thus idle
+    /// connections are kept. Connections closed this way are reported as
+    /// [`DisconnectReason::InboundWorkerStalled`] and counted in the
+    /// `inbound_substream_worker_stalls_total` metric.
+    pub fn with_inbound_worker_watchdog(mut self, window: Duration) -> Self {
+        self.inbound_worker_watchdog = Some(window);
+        self
+    }
 
-        // TODO: Evaluate whether dropping and closing has to be in a particular order.
-        tokio_extras::spawn(this, async move {
-            let _ = control.close().await;
-            drop(tasks);
-        });
-        self.notify_connection_dropped(*peer_id).await;
+    /// Gracefully close connections once they are older than `lifetime`.
+    ///
+    /// Some NATs and middleboxes drop long-lived connections without notice. Recycling them
+    /// beforehand lets us choose the moment: once a connection exceeds its lifetime, we wait until
+    /// no substreams are open on it, but at most [`MAX_RECYCLE_DELAY`]. Connections closed this way
+    /// are reported as [`DisconnectReason::Recycled`] and counted in the
+    /// `connections_recycled_total` metric. Peers we keep connected to via a
+    /// [`dialer`](crate::dialer) reconnect as for any other dropped connection.
+    ///
+    /// Only affects connections established after this is set.
+    pub fn with_max_connection_lifetime(mut self, lifetime: Duration) -> Self {
+        self.max_connection_lifetime = Some(lifetime);
+        self
     }
 
-    #[instrument(skip(control, connection_timeout))]
-    async fn open_substream(
-        mut control: yamux::Control,
-        peer_id: PeerId,
-        protocols: Vec<&'static str>,
-        connection_timeout: Duration,
-    ) -> Result<(&'static str, Substream), Error> {
-        let stream = control
-            .open_stream()
-            .instrument(tracing::debug_span!("open yamux stream"))
-            .await?;
+    /// Consult `gate` before keeping a new connection, inbound or outbound.
+    ///
+    /// This allows rejecting peers based on business rules, f.e. because they already hold the
+    /// maximum number of positions. Rejected connections are closed gracefully and the reason is
+    /// logged. Dialing a rejected peer fails with [`Error::ConnectionRejected`].
+    ///
+    /// The gate is awaited by the endpoint actor itself and thus should resolve quickly. If it
+    /// does not resolve within the `connection_timeout`, the connection is rejected.
+    pub fn with_connection_gate(mut self, gate: ConnectionGate) -> Self {
+        self.connection_gate = Some(gate);
+        self
+    }
 
-        let (protocol, stream) = tokio_extras::time::timeout(
-            connection_timeout,
-            multistream_select::dialer_select_proto(stream, protocols, Version::V1),
-            || tracing::debug_span!("dialer_select_proto", version = ?Version::V1),
-        )
-        .await
-        .map_err(|_timeout| Error::NegotiationTimeoutReached)?
-        .map_err(Error::NegotiationFailed)?;
+    /// Record [`SubstreamTimings`] per peer and protocol, retrievable via
+    /// [`GetSubstreamTimings`].
+    ///
+    /// This is meant for debugging protocols that stall with specific peers. Only the timings of
+    /// the most recent substream per peer and protocol are kept, and only while connected to the
+    /// peer.
+    pub fn with_substream_timings(mut self) -> Self {
+        self.substream_timings = Some(SubstreamTimingsRegistry::default());
+        self
+    }
 
-        Ok((
-            protocol,
-            Substream::new(stream, protocol, libp2p_core::Endpoint::Dialer),
-        ))
+    /// Scale the negotiation timeout of outbound substreams with the round-trip time to the peer,
+    /// see [`AdaptiveNegotiationTimeout`].
+    ///
+    /// Round-trip times are not measured by the endpoint itself but have to be recorded via
+    /// [`RecordRtt`], f.e. by a ping protocol. Inbound substreams are negotiated with the
+    /// `connection_timeout` regardless.
+    pub fn with_adaptive_negotiation_timeout(
+        mut self,
+        timeout: AdaptiveNegotiationTimeout,
+    ) -> Self {
+        self.adaptive_negotiation_timeout = Some(timeout);
+        self
     }
-}
 
-#[xtra_productivity]
-impl Endpoint {
-    async fn handle(&mut self, msg: NewConnection, ctx: &mut Context<Self>) {
-        self.inflight_connections.remove(&msg.peer_id);
-        let this = ctx.address().expect("we are alive");
+    /// Dial at most `max` peers at the same time.
+    ///
+    /// Further [`Connect`] requests are queued and dialed once an ongoing dial completes. Queued
+    /// peers count as in-flight, thus connecting to them again fails right away with
+    /// [`Error::AlreadyTryingToConnected`]. The `connection_timeout` only starts once a queued
+    /// dial actually starts.
+    ///
+    /// By default, the number of concurrent dials is not bounded.
+    pub fn with_max_concurrent_dials(mut self, max: NonZeroUsize) -> Self {
+        self.dial_limit = Some(DialLimit::new(max));
+        self
+    }
 
-        let NewConnection {
-            peer_id,
-            control,
-            mut incoming_substreams,
-            worker,
-        } = msg;
+    /// Configure how listening on a wildcard address is reported, see [`WildcardListenPolicy`].
+    pub fn with_wildcard_listen_policy(mut self, policy: WildcardListenPolicy) -> Self {
+        self.wildcard_listen_policy = policy;
+        self
+    }
 
-        let mut tasks = Tasks::default();
-        tasks.add(worker);
-        tasks.add_fallible(
+    /// Queue [`ListenOn`] requests instead of binding right away, until [`StartListening`] is
+    /// sent.
+    ///
+    /// This allows constructing the endpoint before all inbound substream handlers are ready to
+    /// receive substreams, without accepting connections in the meantime. Dialing is unaffected.
+    pub fn with_deferred_listening(mut self) -> Self {
+        self.pending_listeners = Some(Vec::new());
+        self
+    }
+
+    /// Spawn a listener task for `listen_address`, tracked under `listener_id`.
+    fn listen(&mut self, this: &Address<Self>, listener_id: ListenerId, listen_address: Multiaddr) {
+        let this = this.clone();
+        let mut transport =
+            (self.transport_fn)(&self.identity, self.max_buffered_inbound_substreams);
+
+        self.listeners.add_fallible(
+            listener_id,
             {
-                let inbound_substream_channels = self
-                    .inbound_substream_channels
-                    .iter()
-                    .map(|(proto, channel)| (proto.to_owned(), channel.clone()))
-                    .collect::<HashMap<_, _>>();
-
-                async move {
-                    loop {
-                        let (stream, protocol) = match incoming_substreams.try_next().await {
-                            Ok(Some(Ok((stream, protocol)))) => (stream, protocol),
-                            Ok(Some(Err(upgrade::Error::NegotiationTimeoutReached))) => {
-                                tracing::debug!("Hit timeout while negotiating substream");
-                                continue;
-                            }
-                            Ok(Some(Err(upgrade::Error::NegotiationFailed(e)))) => {
-                                tracing::debug!("Failed to negotiate substream: {}", e);
-                                continue;
-                            }
-                            Ok(None) => bail!("Substream listener closed"),
-                            Err(e) => bail!(e),
-                        };
-
-                        let channel = inbound_substream_channels
-                            .get(&protocol)
-                            .expect("Cannot negotiate a protocol that we don't support");
-
-                        let stream =
-                            Substream::new(stream, protocol, libp2p_core::Endpoint::Listener);
-
-                        let substream = NewInboundSubstream { peer_id, stream };
-                        let span =
-                            tracing::debug_span!("Register new inbound substream", ?substream);
-                        let _ = channel.send_async_safe(substream).instrument(span).await;
-                    }
-                }
-            },
-            move |error| async move {
-                this.send_async_next(ExistingConnectionFailed { peer_id, error })
-                    .await;
-            },
-        );
-
-        if self.controls.insert(peer_id, (control, tasks)).is_some() {
-            tracing::warn!(%peer_id, "Missed drop event, replacing old connection")
-        }
-
-        self.notify_connection_established(peer_id).await;
-    }
-
-    async fn handle(&mut self, msg: ListenerFailed) {
-        tracing::debug!("Listener failed: {:#}", msg.error);
-
-        self.listen_addresses.remove(&msg.address);
-        self.notify_listen_address_removed(msg.address).await;
-    }
-
-    async fn handle(&mut self, msg: FailedToConnect, ctx: &mut Context<Self>) {
-        tracing::debug!("Failed to connect: {:#}", msg.error);
-        let peer = msg.peer_id;
-
-        self.inflight_connections.remove(&peer);
-        self.drop_connection(&ctx.address().expect("self to be alive"), &peer)
-            .await;
-    }
-
-    async fn handle(&mut self, msg: ExistingConnectionFailed, ctx: &mut Context<Self>) {
-        tracing::debug!("Connection failed: {:#}", msg.error);
-        let peer = msg.peer_id;
-
-        self.drop_connection(&ctx.address().expect("self to be alive"), &peer)
-            .await;
-    }
-
-    async fn handle(&mut self, _: GetConnectionStats) -> ConnectionStats {
-        ConnectionStats {
-            connected_peers: self.controls.keys().copied().collect(),
-            listen_addresses: self.listen_addresses.clone(),
-        }
-    }
-
-    async fn handle(&mut self, msg: Connect, ctx: &mut Context<Self>) -> Result<(), Error> {
-        let this = ctx.address().expect("we are alive");
-
-        let peer_id = msg
-            .0
-            .clone()
-            .extract_peer_id()
-            .ok_or_else(|| Error::NoPeerIdInAddress(msg.0.clone()))?;
-
-        if self.inflight_connections.contains(&peer_id) || self.controls.contains_key(&peer_id) {
-            return Err(Error::AlreadyTryingToConnected(peer_id));
-        }
-
-        let mut transport = (self.transport_fn)();
-
-        self.inflight_connections.insert(peer_id);
-        tokio_extras::spawn_fallible(
-            &this.clone(),
-            {
-                let this = this.clone();
-                let connection_timeout = self.connection_timeout;
-
-                let fut = async move {
-                    let (peer_id, control, incoming_substreams, worker) =
-                        tokio_extras::time::timeout(
-                            connection_timeout,
-                            transport.dial(msg.0)?,
-                            || tracing::debug_span!("transport dial"),
-                        )
-                        .await
-                        .context("Dialing timed out")??;
-
-                    this.send_async_next(NewConnection {
-                        peer_id,
-                        control,
-                        incoming_substreams,
-                        worker,
-                    })
-                    .await;
-
-                    anyhow::Ok(())
-                };
-
-                fut.instrument(tracing::debug_span!("Dial new connection").or_current())
-            },
-            move |error| async move {
-                this.send_async_next(FailedToConnect { peer_id, error })
-                    .await;
-            },
-        );
-
-        Ok(())
-    }
-
-    async fn handle(&mut self, msg: Disconnect, ctx: &mut Context<Self>) {
-        self.drop_connection(&ctx.address().expect("self to be alive"), &msg.0)
-            .await;
-    }
-
-    async fn handle(&mut self, msg: ListenOn, ctx: &mut Context<Self>) {
-        let this = ctx.address().expect("we are alive");
-        let listen_address = msg.0.clone();
-
-        let mut transport = (self.transport_fn)();
-
-        tokio_extras::spawn_fallible::<_, _, _, (), _, _, _>(
-            &this.clone(),
-            {
-                let blocked_peers = self.blocked_peers.clone();
-                let this = this.clone();
-                let listen_address = listen_address.clone();
+                let blocked_peers = self.blocked_peers.clone();
+                let inbound_paused = self.inbound_paused.clone();
+                let connection_timeout = self.connection_timeout;
+                let this = this.clone();
+                let listen_address = listen_address.clone();
 
                 async move {
                     let mut stream = transport
-                        .listen_on(msg.0)
+                        .listen_on(listen_address.clone())
                         .context("cannot establish transport stream")?;
 
                     let mut tasks = Tasks::default();
 
                     this.send(NewListenAddress {
+                        listener_id,
                         listen_address: listen_address.clone(),
                     })
                     .await?;
@@ -523,6 +1076,14 @@ impl Endpoint {
                                 remote_addr,
                                 ..
                             }) => {
+                                if inbound_paused.load(Ordering::SeqCst) {
+                                    tracing::debug!(
+                                        %remote_addr,
+                                        "Rejecting inbound connection whilst paused"
+                                    );
+                                    continue; // Dropping the upgrade closes the connection
+                                }
+
                                 match PeerId::try_from_multiaddr(&remote_addr) {
                                     Some(peer_id) if blocked_peers.contains(&peer_id) => {
                                         tracing::info!(
@@ -540,7 +1101,14 @@ impl Endpoint {
                                 tasks.add_fallible(
                                     async move {
                                         let (peer_id, control, incoming_substreams, worker) =
-                                            upgrade.await.with_context(|| {
+                                            tokio_extras::time::timeout(
+                                                connection_timeout,
+                                                upgrade,
+                                                || tracing::debug_span!("upgrade inbound connection"),
+                                            )
+                                            .await
+                                            .context("Upgrading inbound connection timed out")?
+                                            .with_context(|| {
                                                 match PeerId::try_from_multiaddr(&remote_addr) {
                                                     Some(peer_id) => format!(
                                                         "Failed to connect with peer: {peer_id}"
@@ -563,6 +1131,8 @@ impl Endpoint {
                                             control,
                                             incoming_substreams,
                                             worker,
+                                            endpoint: libp2p_core::Endpoint::Listener,
+                                            dial_started_at: None,
                                         })
                                         .await;
                                         Ok(())
@@ -572,6 +1142,20 @@ impl Endpoint {
                                     },
                                 );
                             }
+                            Ok(ListenerEvent::NewAddress(address)) => {
+                                this.send_async_next(ListenerAddressAdded {
+                                    listener_id,
+                                    address,
+                                })
+                                .await;
+                            }
+                            Ok(ListenerEvent::AddressExpired(address)) => {
+                                this.send_async_next(ListenerAddressExpired {
+                                    listener_id,
+                                    address,
+                                })
+                                .await;
+                            }
                             Err(e) => {
                                 tracing::error!("Listener emitted error: {e:#}");
                                 continue;
@@ -584,6 +1168,7 @@ impl Endpoint {
             |error| async move {
                 let _ = this
                     .send(ListenerFailed {
+                        listener_id,
                         address: listen_address,
                         error,
                     })
@@ -592,171 +1177,1283 @@ impl Endpoint {
         );
     }
 
-    #[must_use]
-    async fn handle(
-        &mut self,
-        msg: OpenSubstream<Single>,
-        ctx: &mut Context<Self>,
-    ) -> Result<Pin<Box<dyn futures::Future<Output = Result<Substream, Error>> + Send>>, Error>
-    {
-        let peer_id = msg.peer_id;
-        let protocols = msg.protocols;
-
-        debug_assert!(
-            protocols.len() == 1,
-            "Type-system enforces that we only try to negotiate one protocol"
-        );
-
-        let (control, _) = self
-            .controls
-            .get(&peer_id)
-            .ok_or(Error::NoConnection(peer_id))?;
-
-        self.does_peer_listen_for(peer_id, &protocols)?;
-
-        let this = ctx.address().expect("self to be alive");
-        let fut = {
-            let connection_timeout = self.connection_timeout;
-            let control = control.clone();
-            async move {
-                let res =
-                    Self::open_substream(control, peer_id, protocols.clone(), connection_timeout)
-                        .await;
-
-                if let Err(Error::BadConnection(e)) = &res {
-                    tracing::debug!(
-                        %peer_id,
-                        "Disconnecting peer due to yamux connection error when opening substream: {e}"
-                    );
-                    let _ = this.send_async_next(Disconnect(peer_id)).await;
-                }
-
-                let (protocol, stream) = res?;
-
-                debug_assert!(
-                    protocol == protocols[0],
-                    "If negotiation is successful, must have selected the only protocol we sent."
-                );
-
-                Ok(stream)
+    fn does_peer_listen_for(&self, peer_id: PeerId, protocols: &[&str]) -> Result<(), Error> {
+        let listen_protocols = match self.peer_listen_protocols.get(&peer_id) {
+            Some(listen_protocols) => listen_protocols,
+            None => {
+                // Without the information about the peer we cannot decide either way. We default to
+                // answering yes to our caller, just in case
+                return Ok(());
             }
         };
+        let listen_protocols = listen_protocols
+            .iter()
+            .map(|p| p.as_ref())
+            .collect::<Vec<_>>();
 
-        Ok(Box::pin(fut))
+        protocols
+            .iter()
+            .any(|protocol| listen_protocols.contains(protocol))
+            .then(|| ())
+            .ok_or(Error::ProtocolNotSupportedByPeer)?;
+
+        Ok(())
     }
 
-    #[must_use]
-    async fn handle(
+    async fn drop_connection(
         &mut self,
-        msg: OpenSubstream<Multiple>,
-    ) -> Result<
-        Pin<Box<dyn futures::Future<Output = Result<(&'static str, Substream), Error>> + Send>>,
-        Error,
-    > {
-        let peer = msg.peer_id;
-        let protocols = msg.protocols;
+        this: &Address<Self>,
+        peer_id: &PeerId,
+        reason: DisconnectReason,
+    ) {
+        self.peer_listen_protocols.remove(peer_id);
+        self.peer_rtts.remove(peer_id);
 
-        let (control, _) = self.controls.get(&peer).ok_or(Error::NoConnection(peer))?;
+        let connection = match self.controls.remove(peer_id) {
+            None => return,
+            Some(connection) => connection,
+        };
 
-        let fut = {
-            let connection_timeout = self.connection_timeout;
-            let control = control.clone();
-            async move {
-                let (protocol, stream) =
-                    Self::open_substream(control, peer, protocols, connection_timeout).await?;
+        connection.close(this);
+        self.record_last_seen(*peer_id);
+        if let Some(registry) = &self.substream_timings {
+            registry.remove_peer(peer_id);
+        }
+        // A dial to the peer may still be in flight, f.e. if the dropped connection was inbound
+        if !self.inflight_connections.contains(peer_id) {
+            self.last_dialed_addresses.remove(peer_id);
+        }
+        self.notify_connection_dropped(*peer_id, reason).await;
+    }
 
-                Ok((protocol, stream))
+    /// Remember that we were connected to `peer_id` just now, returning whether we were connected
+    /// to it before.
+    ///
+    /// Forgets the disconnected peer seen the longest ago once we remember more than
+    /// [`MAX_LAST_SEEN_PEERS`].
+    fn record_last_seen(&mut self, peer_id: PeerId) -> bool {
+        let known_peer = self.last_seen.insert(peer_id, SystemTime::now()).is_some();
+
+        if self.last_seen.len() > MAX_LAST_SEEN_PEERS {
+            let longest_ago = self
+                .last_seen
+                .iter()
+                .filter(|(peer_id, _)| !self.controls.contains_key(peer_id))
+                .min_by_key(|(_, seen)| **seen)
+                .map(|(peer_id, _)| *peer_id);
+
+            if let Some(peer_id) = longest_ago {
+                self.last_seen.remove(&peer_id);
             }
-        };
+        }
 
-        Ok(Box::pin(fut))
+        known_peer
     }
 
-    async fn handle(&mut self, msg: NewListenAddress) {
-        // FIXME: This address could be a "catch-all" like "0.0.0.0" which actually results in
-        // listening on multiple interfaces.
-        self.listen_addresses.insert(msg.listen_address.clone());
-        self.notify_listen_address_added(msg.listen_address).await;
+    /// The control of the established connection to `peer_id`, used to open substreams, together
+    /// with the registry of the connection's open substreams.
+    fn control(&self, peer_id: PeerId) -> Result<(yamux::Control, OpenSubstreamsRegistry), Error> {
+        match self.controls.get(&peer_id) {
+            Some(connection) => Ok((
+                connection.control.clone(),
+                connection.open_substreams.clone(),
+            )),
+            None if self.inflight_connections.contains(&peer_id) => {
+                Err(Error::StillConnecting(peer_id))
+            }
+            None => Err(Error::NoConnection(peer_id)),
+        }
     }
 
-    async fn handle(&mut self, msg: RegisterListenProtocols) {
-        self.peer_listen_protocols
-            .insert(msg.peer_id, msg.listen_protocols);
+    /// Tell whoever waits for the dial to `peer_id` via [`ConnectAndWait`] how it went.
+    fn notify_connection_waiter(&mut self, peer_id: PeerId, result: Result<(), Error>) {
+        if let Some(waiter) = self.connection_waiters.remove(&peer_id) {
+            // The caller may have stopped waiting
+            let _ = waiter.send(result);
+        }
     }
-}
 
-impl Endpoint {
-    async fn notify_connection_established(&mut self, peer_id: PeerId) {
-        tracing::info!(%peer_id, "Connection established");
-
-        for subscriber in &self.subscribers.connection_established {
-            subscriber
-                .send_async_next(ConnectionEstablished { peer_id })
-                .await;
+    /// Ask the [`ConnectionGate`], if any, whether to keep a new connection to `peer_id`.
+    ///
+    /// The returned future does not borrow the endpoint, so it can be awaited within handlers.
+    fn check_connection_gate(
+        &self,
+        peer_id: PeerId,
+    ) -> impl futures::Future<Output = Result<(), String>> + 'static {
+        let verdict = self.connection_gate.as_ref().map(|gate| gate(peer_id));
+        let connection_timeout = self.connection_timeout;
+
+        async move {
+            let verdict = match verdict {
+                None => return Ok(()),
+                Some(verdict) => verdict,
+            };
+
+            tokio_extras::time::timeout(connection_timeout, verdict, || {
+                tracing::debug_span!("check connection gate")
+            })
+            .await
+            .unwrap_or_else(|_| Err("Connection gate did not decide in time".to_owned()))
         }
     }
 
-    async fn notify_connection_dropped(&mut self, peer_id: PeerId) {
-        tracing::info!(%peer_id, "Connection dropped");
+    /// How much longer `peer_id` is quarantined, if at all, see [`Quarantine`].
+    ///
+    /// Forgets about the quarantine once it has ended.
+    fn remaining_quarantine(&mut self, peer_id: &PeerId) -> Option<Duration> {
+        let until = *self.quarantined_peers.get(peer_id)?;
 
-        for subscriber in &self.subscribers.connection_dropped {
-            subscriber
-                .send_async_next(ConnectionDropped { peer_id })
-                .await
+        match until.checked_duration_since(tokio::time::Instant::now()) {
+            Some(remaining) if !remaining.is_zero() => Some(remaining),
+            _ => {
+                self.quarantined_peers.remove(peer_id);
+                tracing::info!(%peer_id, "Quarantine of peer ended");
+
+                None
+            }
         }
     }
 
-    async fn notify_listen_address_added(&mut self, added: Multiaddr) {
-        tracing::info!(address=%added, "Listen address added");
-
-        for subscriber in &self.subscribers.listen_address_added {
-            subscriber
-                .send_async_next(ListenAddressAdded {
-                    address: added.clone(),
-                })
-                .await;
+    /// The timeout for negotiating the protocol of an outbound substream to the peer.
+    fn negotiation_timeout(&self, peer_id: &PeerId) -> Duration {
+        match (
+            self.adaptive_negotiation_timeout,
+            self.peer_rtts.get(peer_id),
+        ) {
+            (Some(adaptive), Some(rtt)) => adaptive.timeout(*rtt),
+            _ => self.connection_timeout,
         }
     }
 
-    async fn notify_listen_address_removed(&mut self, removed: Multiaddr) {
-        tracing::info!(address=%removed, "Listen address removed");
+    /// Connect to the peer at `address`, which must end with the peer's ID.
+    fn connect(
+        &mut self,
+        this: &Address<Self>,
+        peer_id: PeerId,
+        address: Multiaddr,
+    ) -> Result<(), Error> {
+        if peer_id == self.identity.public().to_peer_id() {
+            return Err(Error::SelfDial(address));
+        }
 
-        for subscriber in &self.subscribers.listen_address_removed {
-            subscriber
-                .send_async_next(ListenAddressRemoved {
-                    address: removed.clone(),
-                })
-                .await
+        if self.inflight_connections.contains(&peer_id) || self.controls.contains_key(&peer_id) {
+            return Err(Error::AlreadyTryingToConnected(peer_id));
         }
-    }
-}
 
-fn verify_unique_handlers<const N: usize>(
-    inbound_substream_handlers: [(&str, MessageChannel<NewInboundSubstream, ()>); N],
-) -> HashMap<&str, MessageChannel<NewInboundSubstream, ()>> {
-    let mut map = HashMap::with_capacity(inbound_substream_handlers.len());
+        if let Some(remaining) = self.remaining_quarantine(&peer_id) {
+            return Err(Error::Quarantined { peer_id, remaining });
+        }
 
-    for (protocol, handler) in inbound_substream_handlers {
-        let previous_handler = map.insert(protocol, handler);
+        self.dial(this, peer_id, address);
 
-        debug_assert!(
-            previous_handler.is_none(),
-            "Duplicate handler declared for protocol {protocol}"
-        );
+        Ok(())
     }
 
-    map
-}
+    /// Dial the peer at `address` in the background.
+    ///
+    /// The outcome is reported back to us via [`NewConnection`] or [`FailedToConnect`].
+    fn dial(&mut self, this: &Address<Self>, peer_id: PeerId, address: Multiaddr) {
+        let mut transport =
+            (self.transport_fn)(&self.identity, self.max_buffered_inbound_substreams);
 
-#[async_trait]
-impl xtra::Actor for Endpoint {
-    type Stop = ();
+        self.inflight_connections.insert(peer_id);
+        self.last_dialed_addresses.insert(peer_id, address.clone());
+        tokio_extras::spawn_fallible(
+            this,
+            {
+                let this = this.clone();
+                let connection_timeout = self.connection_timeout;
+                let address = address.clone();
+                let dial_limit = self.dial_limit.clone();
 
-    async fn stopped(self) -> Self::Stop {}
-}
+                let fut = async move {
+                    // Held until the dial completes, successfully or not
+                    let _permit = match &dial_limit {
+                        Some(dial_limit) => Some(dial_limit.acquire().await?),
+                        None => None,
+                    };
+
+                    let dial_started_at = Instant::now();
+
+                    let (peer_id, control, incoming_substreams, worker) =
+                        tokio_extras::time::timeout(
+                            connection_timeout,
+                            transport.dial(address)?,
+                            || tracing::debug_span!("transport dial"),
+                        )
+                        .await
+                        .context("Dialing timed out")??;
+
+                    this.send_async_next(NewConnection {
+                        peer_id,
+                        control,
+                        incoming_substreams,
+                        worker,
+                        endpoint: libp2p_core::Endpoint::Dialer,
+                        dial_started_at: Some(dial_started_at),
+                    })
+                    .await;
+
+                    anyhow::Ok(())
+                };
+
+                fut.instrument(tracing::debug_span!("Dial new connection").or_current())
+            },
+            {
+                let this = this.clone();
+                move |error| async move {
+                    this.send_async_next(FailedToConnect {
+                        peer_id,
+                        address,
+                        error,
+                    })
+                    .await;
+                }
+            },
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(skip(
+        control,
+        connection_timeout,
+        substream_timings,
+        open_substreams,
+        connection_substreams
+    ))]
+    async fn open_substream(
+        mut control: yamux::Control,
+        peer_id: PeerId,
+        protocols: Vec<&'static str>,
+        version: Version,
+        connection_timeout: Duration,
+        substream_timings: Option<SubstreamTimingsRegistry>,
+        open_substreams: OpenSubstreamsRegistry,
+        connection_substreams: OpenSubstreamsRegistry,
+    ) -> Result<(&'static str, Substream), Error> {
+        let opened_at = Instant::now();
+
+        let stream = control
+            .open_stream()
+            .instrument(tracing::debug_span!("open yamux stream"))
+            .await?;
+
+        let negotiation = tokio_extras::time::timeout(
+            connection_timeout,
+            multistream_select::dialer_select_proto(stream, protocols, version),
+            || tracing::debug_span!("dialer_select_proto"),
+        )
+        .await;
+
+        // If the negotiation times out or fails, the yamux stream is dropped together with it
+        // without having been closed. yamux resets such streams, thus the remote does not keep a
+        // half-open substream around until its own negotiation times out.
+        let (protocol, stream) = negotiation
+            .map_err(|_timeout| Error::NegotiationTimeoutReached)?
+            .map_err(Error::NegotiationFailed)?;
+
+        let mut stream = Substream::new(stream, protocol, libp2p_core::Endpoint::Dialer)
+            .with_open_counter(&open_substreams, protocol, libp2p_core::Endpoint::Dialer)
+            .with_open_counter(
+                &connection_substreams,
+                protocol,
+                libp2p_core::Endpoint::Dialer,
+            );
+        if let Some(registry) = &substream_timings {
+            stream = stream.with_timings(registry, peer_id, protocol, opened_at);
+        }
+
+        Ok((protocol, stream))
+    }
+}
+
+#[xtra_productivity]
+impl Endpoint {
+    async fn handle(&mut self, msg: NewConnection, ctx: &mut Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+
+        let NewConnection {
+            peer_id,
+            control,
+            mut incoming_substreams,
+            worker,
+            endpoint,
+            dial_started_at,
+        } = msg;
+
+        let verdict = match self.remaining_quarantine(&peer_id) {
+            Some(remaining) => Err(Error::Quarantined { peer_id, remaining }),
+            None => self
+                .check_connection_gate(peer_id)
+                .await
+                .map_err(|reason| Error::ConnectionRejected { peer_id, reason }),
+        };
+
+        if let Err(error) = verdict {
+            tracing::info!(%peer_id, %error, "Rejecting connection");
+
+            if endpoint == libp2p_core::Endpoint::Dialer {
+                self.inflight_connections.remove(&peer_id);
+                if !self.controls.contains_key(&peer_id) {
+                    self.last_dialed_addresses.remove(&peer_id);
+                }
+                self.notify_connection_waiter(peer_id, Err(error));
+            }
+
+            let mut tasks = Tasks::default();
+            tasks.add(worker);
+
+            let rejected_connection = EstablishedConnection {
+                id: self.next_connection_id,
+                control,
+                tasks,
+                endpoint,
+                open_substreams: OpenSubstreamsRegistry::default(),
+            };
+            rejected_connection.close(&this);
+
+            return;
+        }
+
+        match endpoint {
+            libp2p_core::Endpoint::Dialer => {
+                self.inflight_connections.remove(&peer_id);
+                self.notify_connection_waiter(peer_id, Ok(()));
+            }
+            // The peer dialed us whilst we are still dialing them. We keep the inbound connection
+            // for now and decide which one to keep once our dial completes, because our dial may
+            // yet fail. See `keep_new_connection`.
+            libp2p_core::Endpoint::Listener if self.inflight_connections.contains(&peer_id) => {
+                tracing::debug!(%peer_id, "Peer dialed us whilst we are dialing them");
+            }
+            libp2p_core::Endpoint::Listener => {}
+        }
+
+        let known_peer = self.record_last_seen(peer_id);
+
+        let connection_id = self.next_connection_id;
+        self.next_connection_id = ConnectionId(connection_id.0 + 1);
+
+        if let Some(dial_started_at) = dial_started_at {
+            CONNECTION_ESTABLISHMENT_HISTOGRAM.observe(dial_started_at.elapsed().as_secs_f64());
+        }
+
+        let mut tasks = Tasks::default();
+        tasks.add(worker);
+
+        let connection_substreams = OpenSubstreamsRegistry::default();
+        if let Some(lifetime) = self.max_connection_lifetime {
+            tasks.add({
+                let this = this.clone();
+                let connection_substreams = connection_substreams.clone();
+                async move {
+                    let quiet = wait_until_recyclable(lifetime, connection_substreams).await;
+                    this.send_async_next(RecycleConnection {
+                        peer_id,
+                        connection_id,
+                        quiet,
+                    })
+                    .await;
+                }
+            });
+        }
+
+        let last_activity = Arc::new(Mutex::new(tokio::time::Instant::now()));
+        if let Some(window) = self.inbound_worker_watchdog {
+            tasks.add({
+                let this = this.clone();
+                let last_activity = last_activity.clone();
+                async move {
+                    let idle = inbound_worker_watchdog(last_activity, window).await;
+                    this.send_async_next(InboundWorkerStalled {
+                        peer_id,
+                        connection_id,
+                        idle,
+                    })
+                    .await;
+                }
+            });
+        }
+        let heartbeat_interval = self.inbound_worker_watchdog.map(|window| window / 2);
+
+        tasks.add_fallible(
+            {
+                let inbound_substream_handlers = self.inbound_substream_handlers.clone();
+                let inbound_substream_timeout = self.inbound_substream_timeout;
+                let substream_timings = self.substream_timings.clone();
+                let open_substreams = self.open_substreams.clone();
+                let connection_substreams = connection_substreams.clone();
+                let mut queue = InboundSubstreamQueue::new(
+                    peer_id,
+                    self.max_buffered_inbound_substreams,
+                    self.inbound_substream_priorities.clone(),
+                    self.negotiation_log_levels.level(known_peer),
+                );
+
+                async move {
+                    loop {
+                        if queue.is_empty() {
+                            let next = incoming_substreams.try_next();
+                            let next = match heartbeat_interval {
+                                Some(interval) => {
+                                    with_heartbeat(next, &last_activity, interval).await
+                                }
+                                None => next.await,
+                            };
+                            queue.push(next)?;
+                        }
+
+                        // Also queue other substreams that are negotiated already, so that the
+                        // one with the highest priority is dispatched first
+                        queue.push_ready(&mut incoming_substreams)?;
+
+                        *last_activity.lock().expect("lock not poisoned") =
+                            tokio::time::Instant::now();
+
+                        let (stream, protocol) = match queue.pop() {
+                            Some(next) => next,
+                            None => continue,
+                        };
+
+                        let channel = inbound_substream_handlers.handler(protocol);
+
+                        let mut stream =
+                            Substream::new(stream, protocol, libp2p_core::Endpoint::Listener)
+                                .with_open_counter(
+                                    &open_substreams,
+                                    protocol,
+                                    libp2p_core::Endpoint::Listener,
+                                )
+                                .with_open_counter(
+                                    &connection_substreams,
+                                    protocol,
+                                    libp2p_core::Endpoint::Listener,
+                                );
+                        if let Some(registry) = &substream_timings {
+                            stream =
+                                stream.with_timings(registry, peer_id, protocol, Instant::now());
+                        }
+
+                        let substream = NewInboundSubstream { peer_id, stream };
+                        let span =
+                            tracing::debug_span!("Register new inbound substream", ?substream);
+                        let delivery = tokio_extras::time::timeout(
+                            inbound_substream_timeout,
+                            channel.send_async_safe(substream).instrument(span),
+                            || tracing::debug_span!("deliver inbound substream"),
+                        )
+                        .await;
+
+                        if delivery.is_err() {
+                            tracing::warn!(
+                                %peer_id,
+                                %protocol,
+                                timeout_secs = inbound_substream_timeout.as_secs(),
+                                "Handler did not accept inbound substream in time, closing it"
+                            );
+                        }
+                    }
+                }
+            },
+            {
+                let this = this.clone();
+                move |error| async move {
+                    this.send_async_next(ExistingConnectionFailed {
+                        peer_id,
+                        connection_id,
+                        error,
+                    })
+                    .await;
+                }
+            },
+        );
+
+        let new_connection = EstablishedConnection {
+            id: connection_id,
+            control,
+            tasks,
+            endpoint,
+            open_substreams: connection_substreams,
+        };
+
+        let existing_connection = match self.controls.remove(&peer_id) {
+            None => {
+                self.controls.insert(peer_id, new_connection);
+                self.notify_connection_established(peer_id).await;
+
+                return;
+            }
+            Some(existing_connection) => existing_connection,
+        };
+
+        let local_peer_id = self.identity.public().to_peer_id();
+        let (kept, redundant) = if keep_new_connection(
+            local_peer_id,
+            peer_id,
+            existing_connection.endpoint,
+            new_connection.endpoint,
+        ) {
+            (new_connection, existing_connection)
+        } else {
+            (existing_connection, new_connection)
+        };
+
+        tracing::debug!(
+            %peer_id,
+            kept = ?kept.endpoint,
+            closed = ?redundant.endpoint,
+            "Closing redundant connection"
+        );
+
+        self.controls.insert(peer_id, kept);
+        redundant.close(&this);
+    }
+
+    async fn handle(&mut self, msg: ListenerFailed) {
+        tracing::debug!(address = %msg.address, "Listener failed: {:#}", msg.error);
+
+        self.listeners.remove(&msg.listener_id);
+        self.remove_listen_addresses(msg.listener_id).await;
+    }
+
+    async fn handle(&mut self, msg: FailedToConnect, ctx: &mut Context<Self>) {
+        tracing::debug!("Failed to connect: {:#}", msg.error);
+        let peer = msg.peer_id;
+
+        self.inflight_connections.remove(&peer);
+        let error = Arc::new(msg.error);
+        self.notify_dial_failure(peer, msg.address, error.clone())
+            .await;
+
+        // The peer may have dialed us whilst we were dialing them, in which case we are still
+        // connected.
+        if self.controls.contains_key(&peer) {
+            tracing::debug!(peer_id = %peer, "Keeping inbound connection after failed dial");
+            self.notify_connection_waiter(peer, Ok(()));
+            return;
+        }
+
+        self.last_dialed_addresses.remove(&peer);
+        self.notify_connection_waiter(
+            peer,
+            Err(Error::DialFailed {
+                peer_id: peer,
+                error,
+            }),
+        );
+
+        self.drop_connection(
+            &ctx.address().expect("self to be alive"),
+            &peer,
+            DisconnectReason::ConnectionFailed,
+        )
+        .await;
+    }
+
+    async fn handle(&mut self, msg: ExistingConnectionFailed, ctx: &mut Context<Self>) {
+        let peer = msg.peer_id;
+
+        match self.controls.get(&peer) {
+            Some(connection) if connection.id == msg.connection_id => {}
+            _ => {
+                tracing::trace!(peer_id = %peer, "Ignoring failure of already closed connection");
+                return;
+            }
+        }
+
+        tracing::debug!("Connection failed: {:#}", msg.error);
+
+        self.drop_connection(
+            &ctx.address().expect("self to be alive"),
+            &peer,
+            DisconnectReason::ConnectionFailed,
+        )
+        .await;
+    }
+
+    async fn handle(&mut self, msg: RecycleConnection, ctx: &mut Context<Self>) {
+        let peer = msg.peer_id;
+
+        match self.controls.get(&peer) {
+            Some(connection) if connection.id == msg.connection_id => {}
+            _ => {
+                tracing::trace!(peer_id = %peer, "Ignoring recycling of already closed connection");
+                return;
+            }
+        }
+
+        tracing::debug!(
+            peer_id = %peer,
+            quiet = msg.quiet,
+            "Recycling connection which exceeded its maximum lifetime"
+        );
+        CONNECTIONS_RECYCLED_COUNTER.inc();
+
+        self.drop_connection(
+            &ctx.address().expect("self to be alive"),
+            &peer,
+            DisconnectReason::Recycled,
+        )
+        .await;
+    }
+
+    async fn handle(&mut self, msg: InboundWorkerStalled, ctx: &mut Context<Self>) {
+        let peer = msg.peer_id;
+
+        match self.controls.get(&peer) {
+            Some(connection) if connection.id == msg.connection_id => {}
+            _ => {
+                tracing::trace!(peer_id = %peer, "Ignoring stall of already closed connection");
+                return;
+            }
+        }
+
+        tracing::warn!(
+            peer_id = %peer,
+            idle_secs = msg.idle.as_secs(),
+            "Closing connection whose inbound substream worker stalled"
+        );
+        INBOUND_WORKER_STALLS_COUNTER.inc();
+
+        self.drop_connection(
+            &ctx.address().expect("self to be alive"),
+            &peer,
+            DisconnectReason::InboundWorkerStalled,
+        )
+        .await;
+    }
+
+    async fn handle(&mut self, _: GetConnectionStats) -> ConnectionStats {
+        ConnectionStats {
+            connected_peers: self.controls.keys().copied().collect(),
+            listen_addresses: self
+                .advertised_listen_addresses
+                .values()
+                .flatten()
+                .cloned()
+                .collect(),
+            listening_on: self.listen_addresses.values().cloned().collect(),
+            inbound_paused: self.inbound_paused.load(Ordering::SeqCst),
+        }
+    }
+
+    async fn handle(&mut self, _: GetDebugSnapshot) -> EndpointDebug {
+        let now = tokio::time::Instant::now();
+        let connections = self
+            .controls
+            .iter()
+            .map(|(peer_id, connection)| ConnectionDebug {
+                peer_id: peer_id.to_string(),
+                connection_id: connection.id.0,
+                dialer: matches!(connection.endpoint, libp2p_core::Endpoint::Dialer),
+            })
+            .collect();
+
+        EndpointDebug {
+            peer_id: self.identity.public().to_peer_id().to_string(),
+            connections,
+            inflight_connections: self
+                .inflight_connections
+                .iter()
+                .map(PeerId::to_string)
+                .collect(),
+            queued_dials: self.dial_limit.as_ref().map_or(0, DialLimit::queued),
+            listen_addresses: self
+                .listen_addresses
+                .iter()
+                .map(|(listener_id, address)| (listener_id.0, address.to_string()))
+                .collect(),
+            advertised_listen_addresses: self
+                .advertised_listen_addresses
+                .iter()
+                .map(|(listener_id, addresses)| {
+                    (
+                        listener_id.0,
+                        addresses.iter().map(Multiaddr::to_string).collect(),
+                    )
+                })
+                .collect(),
+            pending_listen_addresses: self
+                .pending_listeners
+                .iter()
+                .flatten()
+                .map(|(listener_id, address)| (listener_id.0, address.to_string()))
+                .collect(),
+            last_dialed_addresses: self
+                .last_dialed_addresses
+                .iter()
+                .map(|(peer_id, address)| (peer_id.to_string(), address.to_string()))
+                .collect(),
+            peer_listen_protocols: self
+                .peer_listen_protocols
+                .iter()
+                .map(|(peer_id, protocols)| {
+                    (peer_id.to_string(), protocols.iter().cloned().collect())
+                })
+                .collect(),
+            inbound_protocols: self
+                .inbound_substream_handlers
+                .protocols()
+                .into_iter()
+                .collect(),
+            blocked_peers: self.blocked_peers.iter().map(PeerId::to_string).collect(),
+            quarantined_peers: self
+                .quarantined_peers
+                .iter()
+                .filter_map(|(peer_id, until)| {
+                    let remaining = until.checked_duration_since(now)?;
+
+                    Some((peer_id.to_string(), remaining))
+                })
+                .collect(),
+            inbound_paused: self.inbound_paused.load(Ordering::SeqCst),
+            connection_timeout: self.connection_timeout,
+            inbound_substream_timeout: self.inbound_substream_timeout,
+            substream_timings_enabled: self.substream_timings.is_some(),
+        }
+    }
+
+    async fn handle(
+        &mut self,
+        _: GetSubstreamTimings,
+    ) -> HashMap<(PeerId, &'static str), SubstreamTimings> {
+        self.substream_timings
+            .as_ref()
+            .map(SubstreamTimingsRegistry::snapshot)
+            .unwrap_or_default()
+    }
+
+    async fn handle(&mut self, msg: RecordRtt) {
+        if self.controls.contains_key(&msg.peer_id) {
+            self.peer_rtts.insert(msg.peer_id, msg.rtt);
+        }
+    }
+
+    async fn handle(&mut self, _: GetLastSeen) -> HashMap<PeerId, SystemTime> {
+        self.last_seen.clone()
+    }
+
+    async fn handle(&mut self, _: GetSubstreamStats) -> SubstreamStats {
+        self.open_substreams.snapshot()
+    }
+
+    async fn handle(&mut self, msg: HasHandler) -> bool {
+        self.inbound_substream_handlers.contains(msg.0)
+    }
+
+    async fn handle(&mut self, msg: Connect, ctx: &mut Context<Self>) -> Result<(), Error> {
+        let this = ctx.address().expect("we are alive");
+
+        let peer_id = msg
+            .0
+            .clone()
+            .extract_peer_id()
+            .ok_or_else(|| Error::NoPeerIdInAddress(msg.0.clone()))?;
+
+        self.connect(&this, peer_id, msg.0)
+    }
+
+    async fn handle(
+        &mut self,
+        msg: ConnectAndWait,
+        ctx: &mut Context<Self>,
+    ) -> Result<BoxFuture<'static, Result<(), Error>>, Error> {
+        let this = ctx.address().expect("we are alive");
+
+        let peer_id = msg
+            .0
+            .clone()
+            .extract_peer_id()
+            .ok_or_else(|| Error::NoPeerIdInAddress(msg.0.clone()))?;
+
+        self.connect(&this, peer_id, msg.0)?;
+
+        let (sender, receiver) = oneshot::channel();
+        self.connection_waiters.insert(peer_id, sender);
+
+        Ok(async move {
+            receiver
+                .await
+                .map_err(|_canceled| Error::EndpointDisconnected)?
+        }
+        .boxed())
+    }
+
+    async fn handle(
+        &mut self,
+        msg: ConnectWithPeerId,
+        ctx: &mut Context<Self>,
+    ) -> Result<(), Error> {
+        let this = ctx.address().expect("we are alive");
+
+        let ConnectWithPeerId { peer_id, address } = msg;
+
+        let address = match address.clone().extract_peer_id() {
+            Some(address_peer_id) if address_peer_id == peer_id => address,
+            Some(_) => {
+                return Err(Error::PeerIdMismatch {
+                    expected: peer_id,
+                    address,
+                })
+            }
+            None => address.with(Protocol::P2p(peer_id.into())),
+        };
+
+        self.connect(&this, peer_id, address)
+    }
+
+    async fn handle(&mut self, msg: ResetConnection, ctx: &mut Context<Self>) -> Result<(), Error> {
+        let this = ctx.address().expect("we are alive");
+        let peer_id = msg.0;
+
+        // Dropping the connection forgets the address
+        let address = self.last_dialed_addresses.get(&peer_id).cloned();
+
+        self.drop_connection(&this, &peer_id, DisconnectReason::Requested)
+            .await;
+
+        let address = address.ok_or(Error::NoKnownAddress(peer_id))?;
+
+        if self.inflight_connections.contains(&peer_id) {
+            return Err(Error::AlreadyTryingToConnected(peer_id));
+        }
+
+        tracing::info!(%peer_id, %address, "Redialing peer after resetting connection");
+        self.dial(&this, peer_id, address);
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, msg: Disconnect, ctx: &mut Context<Self>) {
+        self.drop_connection(
+            &ctx.address().expect("self to be alive"),
+            &msg.0,
+            DisconnectReason::Requested,
+        )
+        .await;
+    }
+
+    async fn handle(&mut self, msg: DisconnectMany, ctx: &mut Context<Self>) -> HashSet<PeerId> {
+        let this = ctx.address().expect("self to be alive");
+
+        let mut disconnected = HashSet::new();
+        for peer_id in msg.0 {
+            if self.controls.contains_key(&peer_id) {
+                self.drop_connection(&this, &peer_id, DisconnectReason::Requested)
+                    .await;
+                disconnected.insert(peer_id);
+            }
+        }
+
+        disconnected
+    }
+
+    async fn handle(&mut self, msg: ListenOn, ctx: &mut Context<Self>) -> ListenerId {
+        let this = ctx.address().expect("we are alive");
+
+        let listener_id = self.next_listener_id;
+        self.next_listener_id = ListenerId(listener_id.0 + 1);
+
+        match self.pending_listeners.as_mut() {
+            Some(pending_listeners) => {
+                tracing::debug!(address = %msg.0, "Deferring listener until start");
+                pending_listeners.push((listener_id, msg.0));
+            }
+            None => self.listen(&this, listener_id, msg.0),
+        }
+
+        listener_id
+    }
+
+    async fn handle(&mut self, _: StartListening, ctx: &mut Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+
+        let pending_listeners = match self.pending_listeners.take() {
+            Some(pending_listeners) => pending_listeners,
+            None => return,
+        };
+
+        for (listener_id, address) in pending_listeners {
+            self.listen(&this, listener_id, address);
+        }
+    }
+
+    async fn handle(&mut self, msg: StopListening) {
+        let StopListening(listener_id) = msg;
+
+        if let Some(pending_listeners) = self.pending_listeners.as_mut() {
+            pending_listeners.retain(|(id, _)| *id != listener_id);
+        }
+        self.listeners.remove(&listener_id);
+        self.remove_listen_addresses(listener_id).await;
+    }
+
+    async fn handle(&mut self, msg: Quarantine, ctx: &mut Context<Self>) {
+        let Quarantine { peer, duration } = msg;
+        tracing::info!(peer_id = %peer, ?duration, "Quarantining peer");
+
+        // Quarantines which ended without the peer trying to connect again would linger otherwise
+        let now = tokio::time::Instant::now();
+        self.quarantined_peers.retain(|_, until| *until > now);
+        self.quarantined_peers.insert(peer, now + duration);
+        self.drop_connection(
+            &ctx.address().expect("self to be alive"),
+            &peer,
+            DisconnectReason::Quarantined,
+        )
+        .await;
+    }
+
+    async fn handle(&mut self, _: PauseInbound) {
+        tracing::info!("Pausing inbound connections");
+
+        self.inbound_paused.store(true, Ordering::SeqCst);
+    }
+
+    async fn handle(&mut self, _: ResumeInbound) {
+        tracing::info!("Resuming inbound connections");
+
+        self.inbound_paused.store(false, Ordering::SeqCst);
+    }
+
+    async fn handle(&mut self, msg: Relisten, ctx: &mut Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+
+        self.listen(&this, msg.listener_id, msg.address);
+    }
+
+    #[must_use]
+    async fn handle(
+        &mut self,
+        msg: OpenSubstream<Single>,
+        ctx: &mut Context<Self>,
+    ) -> Result<Pin<Box<dyn futures::Future<Output = Result<Substream, Error>> + Send>>, Error>
+    {
+        let peer_id = msg.peer_id;
+        let protocols = msg.protocols;
+        let version = msg.version;
+
+        debug_assert!(
+            protocols.len() == 1,
+            "Type-system enforces that we only try to negotiate one protocol"
+        );
+
+        let (control, connection_substreams) = self.control(peer_id)?;
+
+        self.does_peer_listen_for(peer_id, &protocols)?;
+
+        let this = ctx.address().expect("self to be alive");
+        let fut = {
+            let connection_timeout = self.negotiation_timeout(&peer_id);
+            let substream_timings = self.substream_timings.clone();
+            let open_substreams = self.open_substreams.clone();
+            async move {
+                let res = Self::open_substream(
+                    control,
+                    peer_id,
+                    protocols.clone(),
+                    version,
+                    connection_timeout,
+                    substream_timings,
+                    open_substreams,
+                    connection_substreams,
+                )
+                .await;
+
+                if let Err(Error::BadConnection(e)) = &res {
+                    tracing::debug!(
+                        %peer_id,
+                        "Disconnecting peer due to yamux connection error when opening substream: {e}"
+                    );
+                    let _ = this.send_async_next(Disconnect(peer_id)).await;
+                }
+
+                let (protocol, stream) = res?;
+
+                debug_assert!(
+                    protocol == protocols[0],
+                    "If negotiation is successful, must have selected the only protocol we sent."
+                );
+
+                Ok(stream)
+            }
+        };
+
+        Ok(Box::pin(fut))
+    }
+
+    #[must_use]
+    async fn handle(
+        &mut self,
+        msg: OpenSubstream<Multiple>,
+    ) -> Result<
+        Pin<Box<dyn futures::Future<Output = Result<(&'static str, Substream), Error>> + Send>>,
+        Error,
+    > {
+        let peer = msg.peer_id;
+        let protocols = msg.protocols;
+        let version = msg.version;
+
+        let (control, connection_substreams) = self.control(peer)?;
+
+        self.does_peer_listen_for(peer, &protocols)?;
+
+        let fut = {
+            let connection_timeout = self.negotiation_timeout(&peer);
+            let substream_timings = self.substream_timings.clone();
+            let open_substreams = self.open_substreams.clone();
+            async move {
+                let (protocol, stream) = Self::open_substream(
+                    control,
+                    peer,
+                    protocols,
+                    version,
+                    connection_timeout,
+                    substream_timings,
+                    open_substreams,
+                    connection_substreams,
+                )
+                .await?;
+
+                Ok((protocol, stream))
+            }
+        };
+
+        Ok(Box::pin(fut))
+    }
+
+    async fn handle(&mut self, msg: NewListenAddress) {
+        let NewListenAddress {
+            listener_id,
+            listen_address,
+        } = msg;
+
+        let advertised = match self.wildcard_listen_policy {
+            // Only the transport knows which port it picked
+            _ if has_unspecified_port(&listen_address) => HashSet::new(),
+            _ if !is_wildcard(&listen_address) => HashSet::from([listen_address.clone()]),
+            WildcardListenPolicy::Expand => expand_to_local_interfaces(&listen_address),
+            WildcardListenPolicy::Keep => HashSet::from([listen_address.clone()]),
+            WildcardListenPolicy::Suppress => HashSet::new(),
+        };
+
+        // Keying by listener keeps the addresses apart if the same address is listened on more
+        // than once.
+        self.listen_addresses.insert(listener_id, listen_address);
+        for address in advertised {
+            self.add_advertised_listen_address(listener_id, address)
+                .await;
+        }
+    }
+
+    async fn handle(&mut self, msg: ListenerAddressAdded) {
+        if self.reports_transport_addresses(msg.listener_id) {
+            self.add_advertised_listen_address(msg.listener_id, msg.address)
+                .await;
+        }
+    }
+
+    async fn handle(&mut self, msg: ListenerAddressExpired) {
+        if self.reports_transport_addresses(msg.listener_id) {
+            self.remove_advertised_listen_address(msg.listener_id, msg.address)
+                .await;
+        }
+    }
+
+    async fn handle(&mut self, msg: RegisterListenProtocols) {
+        self.peer_listen_protocols
+            .insert(msg.peer_id, msg.listen_protocols);
+    }
+
+    async fn handle(&mut self, msg: RotateIdentity, ctx: &mut Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        let RotateIdentity(identity) = msg;
+
+        tracing::info!(
+            old_peer_id = %self.identity.public().to_peer_id(),
+            new_peer_id = %identity.public().to_peer_id(),
+            "Rotating identity"
+        );
+        self.identity = identity;
+
+        let connected_peers = self.controls.keys().copied().collect::<Vec<_>>();
+        for peer_id in connected_peers {
+            self.drop_connection(&this, &peer_id, DisconnectReason::IdentityRotated)
+                .await;
+        }
+
+        for (listener_id, address) in std::mem::take(&mut self.listen_addresses) {
+            for advertised in self
+                .advertised_listen_addresses
+                .remove(&listener_id)
+                .unwrap_or_default()
+            {
+                self.notify_listen_address_removed(advertised).await;
+            }
+
+            // Dropping the listener task only signals it to stop; give it some time to release the
+            // address before listening on it again with the new identity. Replacing the listener
+            // task keeps the `ListenerId` valid, so the listener can still be stopped meanwhile.
+            self.listeners.add(listener_id, {
+                let this = this.clone();
+                async move {
+                    tokio_extras::time::sleep(RELISTEN_DELAY).await;
+                    this.send_async_next(Relisten {
+                        listener_id,
+                        address,
+                    })
+                    .await;
+                }
+            });
+        }
+    }
+}
+
+/// How long to wait after stopping a listener before listening on its address again.
+const RELISTEN_DELAY: Duration = Duration::from_millis(100);
+
+impl Endpoint {
+    async fn notify_connection_established(&mut self, peer_id: PeerId) {
+        tracing::info!(%peer_id, "Connection established");
+
+        for subscriber in &self.subscribers.connection_established {
+            subscriber
+                .send_async_next(ConnectionEstablished { peer_id })
+                .await;
+        }
+    }
+
+    async fn notify_connection_dropped(&mut self, peer_id: PeerId, reason: DisconnectReason) {
+        tracing::info!(%peer_id, ?reason, "Connection dropped");
+
+        for subscriber in &self.subscribers.connection_dropped {
+            subscriber
+                .send_async_next(ConnectionDropped { peer_id, reason })
+                .await
+        }
+    }
+
+    async fn notify_listen_address_added(&mut self, added: Multiaddr) {
+        tracing::info!(address=%added, "Listen address added");
+
+        for subscriber in &self.subscribers.listen_address_added {
+            subscriber
+                .send_async_next(ListenAddressAdded {
+                    address: added.clone(),
+                })
+                .await;
+        }
+    }
+
+    async fn notify_dial_failure(
+        &mut self,
+        peer_id: PeerId,
+        address: Multiaddr,
+        error: Arc<anyhow::Error>,
+    ) {
+        for subscriber in &self.subscribers.dial_failure {
+            subscriber
+                .send_async_next(DialFailure {
+                    peer_id,
+                    address: address.clone(),
+                    error: error.clone(),
+                })
+                .await;
+        }
+    }
+
+    /// Whether we report the addresses the transport announces for the listener.
+    ///
+    /// This is only the case for addresses which leave the port to the transport and for
+    /// wildcard addresses under [`WildcardListenPolicy::Suppress`].
+    fn reports_transport_addresses(&self, listener_id: ListenerId) -> bool {
+        let listen_address = match self.listen_addresses.get(&listener_id) {
+            Some(listen_address) => listen_address,
+            None => return false,
+        };
+
+        has_unspecified_port(listen_address)
+            || (self.wildcard_listen_policy == WildcardListenPolicy::Suppress
+                && is_wildcard(listen_address))
+    }
+
+    async fn add_advertised_listen_address(&mut self, listener_id: ListenerId, address: Multiaddr) {
+        let is_new = self
+            .advertised_listen_addresses
+            .entry(listener_id)
+            .or_default()
+            .insert(address.clone());
+
+        if is_new {
+            self.notify_listen_address_added(address).await;
+        }
+    }
+
+    async fn remove_advertised_listen_address(
+        &mut self,
+        listener_id: ListenerId,
+        address: Multiaddr,
+    ) {
+        let was_advertised = self
+            .advertised_listen_addresses
+            .get_mut(&listener_id)
+            .map_or(false, |addresses| addresses.remove(&address));
+
+        if was_advertised {
+            self.notify_listen_address_removed(address).await;
+        }
+    }
+
+    /// Forget all addresses of the listener, notifying subscribers about the ones we advertised.
+    async fn remove_listen_addresses(&mut self, listener_id: ListenerId) {
+        self.listen_addresses.remove(&listener_id);
+
+        for address in self
+            .advertised_listen_addresses
+            .remove(&listener_id)
+            .unwrap_or_default()
+        {
+            self.notify_listen_address_removed(address).await;
+        }
+    }
+
+    async fn notify_listen_address_removed(&mut self, removed: Multiaddr) {
+        tracing::info!(address=%removed, "Listen address removed");
+
+        for subscriber in &self.subscribers.listen_address_removed {
+            subscriber
+                .send_async_next(ListenAddressRemoved {
+                    address: removed.clone(),
+                })
+                .await
+        }
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Endpoint {
+    type Stop = ();
+
+    async fn stopped(self) -> Self::Stop {
+        // Close all connections instead of just dropping them, so that our peers receive a yamux
+        // GoAway frame and stop opening new substreams while we are shutting down.
+        let closing = self
+            .controls
+            .into_values()
+            .map(EstablishedConnection::close_gracefully);
+
+        let _ = tokio_extras::time::timeout(
+            self.connection_timeout,
+            futures::future::join_all(closing),
+            || tracing::debug_span!("close connections"),
+        )
+        .await;
+    }
+}
 
 #[derive(Debug)]
 struct ListenerFailed {
+    listener_id: ListenerId,
     address: Multiaddr,
     error: anyhow::Error,
 }
@@ -764,19 +2461,252 @@ struct ListenerFailed {
 #[derive(Debug)]
 struct FailedToConnect {
     peer_id: PeerId,
+    address: Multiaddr,
     error: anyhow::Error,
 }
 
 #[derive(Debug)]
 struct ExistingConnectionFailed {
     peer_id: PeerId,
+    connection_id: ConnectionId,
     error: anyhow::Error,
 }
 
+/// An existing connection exceeded its maximum lifetime, see
+/// [`Endpoint::with_max_connection_lifetime`].
+struct RecycleConnection {
+    peer_id: PeerId,
+    connection_id: ConnectionId,
+    /// Whether no substreams were open on the connection.
+    quiet: bool,
+}
+
+/// The inbound substream worker of an existing connection stalled, see
+/// [`Endpoint::with_inbound_worker_watchdog`].
+struct InboundWorkerStalled {
+    peer_id: PeerId,
+    connection_id: ConnectionId,
+    /// How long the worker made no progress for.
+    idle: Duration,
+}
+
+/// Identifies a connection, allowing to tell apart multiple connections to the same peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ConnectionId(u64);
+
+struct EstablishedConnection {
+    id: ConnectionId,
+    control: yamux::Control,
+    tasks: Tasks,
+    /// Whether we dialed the peer or the peer dialed us.
+    endpoint: libp2p_core::Endpoint,
+    /// The substreams which are currently open on this connection.
+    open_substreams: OpenSubstreamsRegistry,
+}
+
+impl EstablishedConnection {
+    /// Gracefully close the connection in the background.
+    fn close(self, this: &Address<Endpoint>) {
+        tokio_extras::spawn(this, self.close_gracefully());
+    }
+
+    /// Close the connection, which sends a yamux GoAway frame to the peer.
+    ///
+    /// The tasks driving the connection are only dropped afterwards, because they are needed to
+    /// send the frame.
+    async fn close_gracefully(self) {
+        let EstablishedConnection {
+            mut control, tasks, ..
+        } = self;
+
+        let _ = control.close().await;
+        drop(tasks);
+    }
+}
+
+/// Decide whether a new connection should replace an existing connection to the same peer.
+///
+/// Both peers have to come to the same conclusion, otherwise each of them might close a different
+/// connection. We therefore keep the connection that was dialed by the peer with the lower
+/// [`PeerId`]. If both connections were dialed by the same peer, the old connection is assumed to
+/// be stale and replaced.
+fn keep_new_connection(
+    local_peer_id: PeerId,
+    remote_peer_id: PeerId,
+    existing: libp2p_core::Endpoint,
+    new: libp2p_core::Endpoint,
+) -> bool {
+    if existing == new {
+        return true;
+    }
+
+    let dialer = |endpoint| match endpoint {
+        libp2p_core::Endpoint::Dialer => local_peer_id,
+        libp2p_core::Endpoint::Listener => remote_peer_id,
+    };
+
+    dialer(new) < dialer(existing)
+}
+
 struct NewListenAddress {
+    listener_id: ListenerId,
     listen_address: Multiaddr,
 }
 
+/// The addresses of the local interfaces the wildcard `address` listens on.
+fn expand_to_local_interfaces(address: &Multiaddr) -> HashSet<Multiaddr> {
+    let ips = match if_addrs::get_if_addrs() {
+        Ok(interfaces) => interfaces
+            .iter()
+            .map(|interface| interface.ip())
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            tracing::warn!(%address, "Failed to enumerate local interfaces, reporting wildcard listen address as is: {e:#}");
+            return HashSet::from([address.clone()]);
+        }
+    };
+
+    expand_wildcard(address, &ips)
+        .unwrap_or_default()
+        .into_iter()
+        .collect()
+}
+
+/// The transport announced a new address for a listener.
+struct ListenerAddressAdded {
+    listener_id: ListenerId,
+    address: Multiaddr,
+}
+
+/// An address the transport announced for a listener is no longer valid.
+struct ListenerAddressExpired {
+    listener_id: ListenerId,
+    address: Multiaddr,
+}
+
+/// Listen again on an address after the listener was stopped by [`RotateIdentity`].
+struct Relisten {
+    listener_id: ListenerId,
+    address: Multiaddr,
+}
+
+type NegotiatedInboundSubstream<S> =
+    Result<Option<Result<(S, &'static str), upgrade::Error>>, yamux::ConnectionError>;
+
+/// Inbound substreams of a connection whose protocol is negotiated, waiting to be dispatched to
+/// their handlers.
+///
+/// Substreams are popped highest priority first, see
+/// [`Endpoint::with_inbound_substream_priority`]. Substreams of equal priority are popped in the
+/// order they were pushed.
+///
+/// The queue holds at most `capacity` substreams, see
+/// [`Endpoint::with_max_buffered_inbound_substreams`].
+struct InboundSubstreamQueue<S> {
+    peer_id: PeerId,
+    capacity: usize,
+    priorities: HashMap<&'static str, u8>,
+    /// The level at which failed negotiations are logged, see [`NegotiationLogLevels`].
+    negotiation_log_level: Level,
+    queued: BTreeMap<(Reverse<u8>, u64), (S, &'static str)>,
+    next_sequence_number: u64,
+}
+
+impl<S> InboundSubstreamQueue<S> {
+    fn new(
+        peer_id: PeerId,
+        capacity: usize,
+        priorities: HashMap<&'static str, u8>,
+        negotiation_log_level: Level,
+    ) -> Self {
+        Self {
+            peer_id,
+            capacity: capacity.max(1),
+            priorities,
+            negotiation_log_level,
+            queued: BTreeMap::new(),
+            next_sequence_number: 0,
+        }
+    }
+
+    /// Queue the next item of a connection's incoming substreams.
+    ///
+    /// Substreams whose negotiation failed are skipped. Fails if the connection can no longer
+    /// accept substreams.
+    fn push(&mut self, next: NegotiatedInboundSubstream<S>) -> Result<()> {
+        let (stream, protocol) = match next {
+            Ok(Some(Ok((stream, protocol)))) => (stream, protocol),
+            Ok(Some(Err(upgrade::Error::NegotiationTimeoutReached))) => {
+                self.log_negotiation_failure(format_args!(
+                    "Hit timeout while negotiating substream"
+                ));
+                return Ok(());
+            }
+            Ok(Some(Err(upgrade::Error::NegotiationFailed(e)))) => {
+                self.log_negotiation_failure(format_args!("Failed to negotiate substream: {}", e));
+                return Ok(());
+            }
+            Ok(None) => bail!("Substream listener closed"),
+            Err(e) => bail!(e),
+        };
+
+        let priority = self.priorities.get(protocol).copied().unwrap_or_default();
+        self.queued.insert(
+            (Reverse(priority), self.next_sequence_number),
+            (stream, protocol),
+        );
+        self.next_sequence_number += 1;
+
+        Ok(())
+    }
+
+    fn log_negotiation_failure(&self, message: fmt::Arguments) {
+        let peer_id = self.peer_id;
+
+        // `tracing`'s macros require the level to be known at compile time
+        match self.negotiation_log_level {
+            Level::ERROR => tracing::error!(%peer_id, "{message}"),
+            Level::WARN => tracing::warn!(%peer_id, "{message}"),
+            Level::INFO => tracing::info!(%peer_id, "{message}"),
+            Level::DEBUG => tracing::debug!(%peer_id, "{message}"),
+            _ => tracing::trace!(%peer_id, "{message}"),
+        }
+    }
+
+    fn pop(&mut self) -> Option<(S, &'static str)> {
+        let key = *self.queued.keys().next()?;
+
+        self.queued.remove(&key)
+    }
+
+    /// Queue the substreams of `incoming` which are negotiated already, until the queue is full.
+    ///
+    /// Substreams beyond the capacity stay in `incoming`, thus the bounded buffer of the
+    /// transport keeps applying back-pressure to the peer.
+    fn push_ready<I>(&mut self, incoming: &mut I) -> Result<()>
+    where
+        I: Stream<Item = Result<Result<(S, &'static str), upgrade::Error>, yamux::ConnectionError>>
+            + Unpin,
+    {
+        while !self.is_full() {
+            match incoming.try_next().now_or_never() {
+                Some(next) => self.push(next)?,
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queued.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.queued.len() >= self.capacity
+    }
+}
+
 struct NewConnection {
     peer_id: PeerId,
     control: yamux::Control,
@@ -789,6 +2719,10 @@ struct NewConnection {
         >,
     >,
     worker: BoxFuture<'static, ()>,
+    /// Whether we dialed the peer or the peer dialed us.
+    endpoint: libp2p_core::Endpoint,
+    /// When we started dialing the peer, if the connection was established by us.
+    dial_started_at: Option<Instant>,
 }
 
 #[derive(Clone, Copy)]
@@ -799,12 +2733,542 @@ pub struct ConnectionEstablished {
 #[derive(Clone, Copy)]
 pub struct ConnectionDropped {
     pub peer_id: PeerId,
+    pub reason: DisconnectReason,
+}
+
+/// Why a connection was dropped.
+///
+/// This allows subscribers to tell apart intentional disconnects from network errors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The connection was closed upon a [`Disconnect`] request.
+    Requested,
+    /// The connection failed, e.g. because of a network error or the peer closing it.
+    ConnectionFailed,
+    /// The connection was closed because we rotated our identity.
+    IdentityRotated,
+    /// The connection was closed upon a [`Quarantine`] request.
+    Quarantined,
+    /// The connection was closed because it exceeded its maximum lifetime, see
+    /// [`Endpoint::with_max_connection_lifetime`].
+    Recycled,
+    /// The connection was closed because its inbound substream worker stalled, see
+    /// [`Endpoint::with_inbound_worker_watchdog`].
+    InboundWorkerStalled,
 }
 
 pub struct ListenAddressAdded {
     pub address: Multiaddr,
 }
 
+/// Dialing a peer failed.
+#[derive(Clone, Debug)]
+pub struct DialFailure {
+    pub peer_id: PeerId,
+    /// The address we attempted to dial.
+    pub address: Multiaddr,
+    /// Why dialing failed, including the full error chain.
+    pub error: Arc<anyhow::Error>,
+}
+
 pub struct ListenAddressRemoved {
     pub address: Multiaddr,
 }
+
+/// Return once the inbound substream worker saw no activity for longer than `window`.
+///
+/// The worker records the time of its latest activity in `last_activity`. Returns for how long the
+/// worker was idle.
+async fn inbound_worker_watchdog(
+    last_activity: Arc<Mutex<tokio::time::Instant>>,
+    window: Duration,
+) -> Duration {
+    loop {
+        let idle = last_activity.lock().expect("lock not poisoned").elapsed();
+
+        if idle >= window {
+            return idle;
+        }
+
+        tokio_extras::time::sleep_silent(window - idle).await;
+    }
+}
+
+/// Await `future`, recording a heartbeat in `last_activity` every `interval` until it completes.
+///
+/// The heartbeat is recorded from within the task awaiting `future`, thus it stops if that task
+/// stops being polled.
+async fn with_heartbeat<F>(
+    future: F,
+    last_activity: &Mutex<tokio::time::Instant>,
+    interval: Duration,
+) -> F::Output
+where
+    F: futures::Future,
+{
+    futures::pin_mut!(future);
+
+    loop {
+        *last_activity.lock().expect("lock not poisoned") = tokio::time::Instant::now();
+
+        let heartbeat = tokio_extras::time::sleep_silent(interval);
+        futures::pin_mut!(heartbeat);
+
+        match future::select(future.as_mut(), heartbeat).await {
+            Either::Left((output, _)) => return output,
+            Either::Right(((), _)) => continue,
+        }
+    }
+}
+
+/// Wait until a connection older than `lifetime` can be recycled.
+///
+/// Once the lifetime is exceeded, we wait for a moment without open substreams, but at most
+/// [`MAX_RECYCLE_DELAY`]. Returns whether such a moment was found.
+async fn wait_until_recyclable(
+    lifetime: Duration,
+    open_substreams: OpenSubstreamsRegistry,
+) -> bool {
+    tokio_extras::time::sleep_silent(lifetime).await;
+
+    let give_up_at = tokio::time::Instant::now() + MAX_RECYCLE_DELAY;
+    loop {
+        let stats = open_substreams.snapshot();
+        if stats.inbound_open + stats.outbound_open == 0 {
+            return true;
+        }
+
+        if tokio::time::Instant::now() >= give_up_at {
+            return false;
+        }
+
+        tokio_extras::time::sleep_silent(RECYCLE_POLL_INTERVAL).await;
+    }
+}
+
+/// How often a connection waiting to be recycled checks for open substreams.
+const RECYCLE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Counts connections closed because they exceeded their maximum lifetime, see
+/// [`Endpoint::with_max_connection_lifetime`].
+static CONNECTIONS_RECYCLED_COUNTER: Lazy<prometheus::IntCounter> = Lazy::new(|| {
+    prometheus::register_int_counter!(
+        "connections_recycled_total",
+        "The number of connections closed because they exceeded their maximum lifetime."
+    )
+    .unwrap()
+});
+
+/// Counts connections closed because their inbound substream worker stalled, see
+/// [`Endpoint::with_inbound_worker_watchdog`].
+static INBOUND_WORKER_STALLS_COUNTER: Lazy<prometheus::IntCounter> = Lazy::new(|| {
+    prometheus::register_int_counter!(
+        "inbound_substream_worker_stalls_total",
+        "The number of connections closed because their inbound substream worker stalled."
+    )
+    .unwrap()
+});
+
+/// Measures the time from starting the dial until the resulting connection is registered with the
+/// [`Endpoint`].
+///
+/// Time spent waiting for a permit of [`Endpoint::with_max_concurrent_dials`] is not included, so
+/// that the metric reflects the network rather than our own queue. Only successful dials are
+/// recorded. Slow dials are often an indication of NAT or relay
+/// problems.
+static CONNECTION_ESTABLISHMENT_HISTOGRAM: Lazy<prometheus::Histogram> = Lazy::new(|| {
+    prometheus::register_histogram!(
+        "connection_establishment_seconds",
+        "The time it took to dial and upgrade a new connection in seconds.",
+        vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 7.5, 10.0, 15.0, 20.0, 30.0]
+    )
+    .unwrap()
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::transport::MemoryTransport;
+    use xtra::spawn::TokioGlobalSpawnExt;
+    use xtra::Actor as _;
+
+    #[test]
+    fn both_peers_agree_on_which_connection_to_keep() {
+        let alice = PeerId::random();
+        let bob = PeerId::random();
+
+        let (dialer, listener) = (
+            libp2p_core::Endpoint::Dialer,
+            libp2p_core::Endpoint::Listener,
+        );
+
+        // Alice registers the connection she dialed first whereas Bob registers the connection he
+        // dialed first.
+        let alice_keeps_connection_dialed_by_bob =
+            keep_new_connection(alice, bob, dialer, listener);
+        let bob_keeps_connection_dialed_by_bob = !keep_new_connection(bob, alice, dialer, listener);
+
+        assert_eq!(
+            alice_keeps_connection_dialed_by_bob,
+            bob_keeps_connection_dialed_by_bob
+        );
+        assert_eq!(alice_keeps_connection_dialed_by_bob, bob < alice);
+    }
+
+    #[test]
+    fn inbound_substreams_are_popped_by_priority_then_in_order() {
+        let mut queue = InboundSubstreamQueue::new(
+            PeerId::random(),
+            5,
+            HashMap::from([("/settlement", 1)]),
+            Level::DEBUG,
+        );
+
+        queue.push(Ok(Some(Ok((1, "/order"))))).unwrap();
+        queue.push(Ok(Some(Ok((2, "/order"))))).unwrap();
+        queue.push(Ok(Some(Ok((3, "/settlement"))))).unwrap();
+
+        assert_eq!(queue.pop(), Some((3, "/settlement")));
+        assert_eq!(queue.pop(), Some((1, "/order")));
+        assert_eq!(queue.pop(), Some((2, "/order")));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn failed_negotiations_are_skipped_and_closed_listener_fails() {
+        let mut queue =
+            InboundSubstreamQueue::<()>::new(PeerId::random(), 5, HashMap::default(), Level::DEBUG);
+
+        queue
+            .push(Ok(Some(Err(upgrade::Error::NegotiationTimeoutReached))))
+            .unwrap();
+        assert!(queue.is_empty());
+
+        assert!(queue.push(Ok(None)).is_err());
+    }
+
+    #[test]
+    fn given_flood_of_negotiated_substreams_then_only_capacity_is_buffered() {
+        let mut queue =
+            InboundSubstreamQueue::new(PeerId::random(), 3, HashMap::default(), Level::DEBUG);
+        let mut incoming = futures::stream::iter((0..100).map(|i| Ok(Ok((i, "/order")))));
+
+        queue.push_ready(&mut incoming).unwrap();
+
+        assert!(queue.is_full());
+        assert_eq!(queue.queued.len(), 3);
+        assert!(matches!(
+            incoming.next().now_or_never(),
+            Some(Some(Ok(Ok((3, "/order")))))
+        ));
+    }
+
+    #[test]
+    fn negotiation_log_level_depends_on_whether_peer_is_known() {
+        let levels = NegotiationLogLevels {
+            new_peer: Level::TRACE,
+            known_peer: Level::WARN,
+        };
+
+        assert_eq!(levels.level(false), Level::TRACE);
+        assert_eq!(levels.level(true), Level::WARN);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn given_no_inbound_activity_within_window_then_watchdog_fires() {
+        let last_activity = Arc::new(Mutex::new(tokio::time::Instant::now()));
+
+        let started_at = tokio::time::Instant::now();
+        let idle = inbound_worker_watchdog(last_activity, Duration::from_secs(30)).await;
+
+        assert_eq!(idle, Duration::from_secs(30));
+        assert_eq!(started_at.elapsed(), Duration::from_secs(30));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn given_worker_waiting_with_heartbeat_then_watchdog_does_not_fire() {
+        let window = Duration::from_secs(30);
+        let last_activity = Arc::new(Mutex::new(tokio::time::Instant::now()));
+
+        let idle_worker = with_heartbeat(
+            tokio_extras::time::sleep_silent(window * 10),
+            &last_activity,
+            window / 2,
+        );
+        let watchdog = inbound_worker_watchdog(last_activity.clone(), window);
+        futures::pin_mut!(idle_worker, watchdog);
+
+        assert!(matches!(
+            future::select(idle_worker, watchdog).await,
+            Either::Left(((), _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn given_dial_limit_reached_then_further_dials_queue_until_permit_released() {
+        let dial_limit = DialLimit::new(NonZeroUsize::new(1).unwrap());
+
+        let first = dial_limit.acquire().await.unwrap();
+        let mut second = Box::pin(dial_limit.acquire());
+
+        assert!(futures::poll!(&mut second).is_pending());
+        assert_eq!(dial_limit.queued(), 1);
+
+        drop(first);
+        second.await.unwrap();
+
+        assert_eq!(dial_limit.queued(), 0);
+    }
+
+    #[tokio::test]
+    async fn given_queued_dial_cancelled_then_no_longer_counted_as_queued() {
+        let dial_limit = DialLimit::new(NonZeroUsize::new(1).unwrap());
+
+        let _first = dial_limit.acquire().await.unwrap();
+        let mut second = Box::pin(dial_limit.acquire());
+
+        assert!(futures::poll!(&mut second).is_pending());
+        assert_eq!(dial_limit.queued(), 1);
+
+        drop(second);
+
+        assert_eq!(dial_limit.queued(), 0);
+    }
+
+    #[test]
+    fn given_fewer_handlers_than_expected_when_building_then_error() {
+        let result = InboundSubstreamHandlers::new().build::<1>();
+
+        assert!(matches!(
+            result,
+            Err(Error::InboundSubstreamHandlerCount {
+                expected: 1,
+                registered: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn adaptive_negotiation_timeout_scales_with_rtt_within_bounds() {
+        let adaptive =
+            AdaptiveNegotiationTimeout::new(10, Duration::from_secs(1), Duration::from_secs(30));
+
+        assert_eq!(
+            adaptive.timeout(Duration::from_millis(500)),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            adaptive.timeout(Duration::from_millis(1)),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            adaptive.timeout(Duration::from_secs(10)),
+            Duration::from_secs(30)
+        );
+    }
+
+    #[test]
+    fn given_no_rtt_sample_then_negotiation_timeout_falls_back_to_connection_timeout() {
+        let connection_timeout = Duration::from_secs(20);
+        let mut endpoint = Endpoint::new(
+            Box::new(MemoryTransport::default),
+            Keypair::generate_ed25519(),
+            connection_timeout,
+            [],
+            Subscribers::default(),
+            Arc::new(HashSet::new()),
+        )
+        .with_adaptive_negotiation_timeout(AdaptiveNegotiationTimeout::new(
+            10,
+            Duration::from_secs(1),
+            Duration::from_secs(30),
+        ));
+        let peer_id = PeerId::random();
+
+        assert_eq!(endpoint.negotiation_timeout(&peer_id), connection_timeout);
+
+        endpoint
+            .peer_rtts
+            .insert(peer_id, Duration::from_millis(200));
+
+        assert_eq!(
+            endpoint.negotiation_timeout(&peer_id),
+            Duration::from_secs(2)
+        );
+    }
+
+    #[test]
+    fn given_same_direction_then_new_connection_replaces_existing() {
+        let alice = PeerId::random();
+        let bob = PeerId::random();
+
+        assert!(keep_new_connection(
+            alice,
+            bob,
+            libp2p_core::Endpoint::Dialer,
+            libp2p_core::Endpoint::Dialer
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn given_peer_stalls_negotiation_when_opening_substream_then_timeout_reached() {
+        let connection_timeout = Duration::from_secs(20);
+
+        let alice = Endpoint::new(
+            Box::new(MemoryTransport::default),
+            Keypair::generate_ed25519(),
+            connection_timeout,
+            [],
+            Subscribers::default(),
+            Arc::new(HashSet::new()),
+        )
+        .create(None)
+        .spawn_global();
+
+        // Bob upgrades the connection but never polls it afterwards, thus never responding to
+        // any protocol negotiation.
+        let bob_identity = Keypair::generate_ed25519();
+        let bob_peer_id = bob_identity.public().to_peer_id();
+        let mut bob_transport = upgrade::transport(
+            MemoryTransport::default(),
+            &bob_identity,
+            ProtocolSet::new([]),
+            connection_timeout,
+            DEFAULT_MAX_BUFFERED_INBOUND_SUBSTREAMS,
+        );
+        let mut bob_listener = bob_transport
+            .listen_on("/memory/41000".parse().unwrap())
+            .unwrap();
+
+        let (_bob_connection, connect_result) = tokio::join!(
+            async {
+                loop {
+                    if let ListenerEvent::Upgrade { upgrade, .. } =
+                        bob_listener.next().await.unwrap().unwrap()
+                    {
+                        break upgrade.await.unwrap();
+                    }
+                }
+            },
+            alice.send(Connect(
+                format!("/memory/41000/p2p/{bob_peer_id}").parse().unwrap()
+            ))
+        );
+        connect_result.unwrap().unwrap();
+
+        while !alice
+            .send(GetConnectionStats)
+            .await
+            .unwrap()
+            .connected_peers
+            .contains(&bob_peer_id)
+        {
+            tokio_extras::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        let started_at = tokio::time::Instant::now();
+        let result = alice
+            .send(OpenSubstream::single_protocol(
+                bob_peer_id,
+                "/stalled/1.0.0",
+            ))
+            .await
+            .unwrap()
+            .unwrap()
+            .await;
+
+        assert!(matches!(result, Err(Error::NegotiationTimeoutReached)));
+        assert!(started_at.elapsed() >= connection_timeout);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn given_negotiation_timed_out_then_substream_is_reset_on_remote() {
+        let alice_connection_timeout = Duration::from_secs(20);
+        let bob_connection_timeout = Duration::from_secs(60);
+
+        let alice = Endpoint::new(
+            Box::new(MemoryTransport::default),
+            Keypair::generate_ed25519(),
+            alice_connection_timeout,
+            [],
+            Subscribers::default(),
+            Arc::new(HashSet::new()),
+        )
+        .create(None)
+        .spawn_global();
+
+        // Bob does not poll the connection until Alice's negotiation timed out.
+        let bob_identity = Keypair::generate_ed25519();
+        let bob_peer_id = bob_identity.public().to_peer_id();
+        let mut bob_transport = upgrade::transport(
+            MemoryTransport::default(),
+            &bob_identity,
+            ProtocolSet::new([]),
+            bob_connection_timeout,
+            DEFAULT_MAX_BUFFERED_INBOUND_SUBSTREAMS,
+        );
+        let mut bob_listener = bob_transport
+            .listen_on("/memory/41001".parse().unwrap())
+            .unwrap();
+
+        let (bob_connection, connect_result) = tokio::join!(
+            async {
+                loop {
+                    if let ListenerEvent::Upgrade { upgrade, .. } =
+                        bob_listener.next().await.unwrap().unwrap()
+                    {
+                        break upgrade.await.unwrap();
+                    }
+                }
+            },
+            alice.send(Connect(
+                format!("/memory/41001/p2p/{bob_peer_id}").parse().unwrap()
+            ))
+        );
+        connect_result.unwrap().unwrap();
+
+        while !alice
+            .send(GetConnectionStats)
+            .await
+            .unwrap()
+            .connected_peers
+            .contains(&bob_peer_id)
+        {
+            tokio_extras::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        let result = alice
+            .send(OpenSubstream::single_protocol(
+                bob_peer_id,
+                "/stalled/1.0.0",
+            ))
+            .await
+            .unwrap()
+            .unwrap()
+            .await;
+        assert!(matches!(result, Err(Error::NegotiationTimeoutReached)));
+
+        // Alice reset the substream she gave up on. Thus, once Bob picks it up, his negotiation
+        // fails instead of running into his own timeout.
+        let (_bob_peer_id, _bob_control, mut bob_incoming, bob_worker) = bob_connection;
+        let started_at = tokio::time::Instant::now();
+        let bob_result = tokio::select! {
+            substream = bob_incoming.next() => substream.unwrap().unwrap(),
+            _ = bob_worker => panic!("Bob's connection closed unexpectedly"),
+        };
+
+        assert!(matches!(
+            bob_result,
+            Err(upgrade::Error::NegotiationFailed(_))
+        ));
+        assert!(started_at.elapsed() < bob_connection_timeout);
+
+        // Alice keeps no state of the failed substream and can still talk to Bob.
+        assert!(alice
+            .send(GetConnectionStats)
+            .await
+            .unwrap()
+            .connected_peers
+            .contains(&bob_peer_id));
+    }
+}
@@ -0,0 +1,610 @@
+//! Helpers for framing messages sent over a [`Substream`].
+
+use crate::Substream;
+use asynchronous_codec::Bytes;
+use asynchronous_codec::BytesMut;
+use asynchronous_codec::Decoder;
+use asynchronous_codec::Encoder;
+use asynchronous_codec::Framed;
+use futures::io::BufReader;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+use std::marker::PhantomData;
+
+/// The capacity of the read buffer used by [`framed`].
+///
+/// This matches the amount of bytes [`Framed`] attempts to read from the underlying stream at
+/// once.
+pub const DEFAULT_READ_BUFFER_CAPACITY: usize = 8 * 1024;
+
+/// A [`Substream`] whose reads go through a buffer of configurable capacity.
+pub type BufferedSubstream = BufReader<Substream>;
+
+/// Frame the messages sent over `substream` according to `codec`.
+pub fn framed<C>(substream: Substream, codec: C) -> Framed<BufferedSubstream, C>
+where
+    C: Encoder + Decoder,
+{
+    framed_with_read_buffer(substream, codec, DEFAULT_READ_BUFFER_CAPACITY)
+}
+
+/// Frame the messages sent over `substream` according to `codec`, reading from the substream
+/// through a buffer of `read_buffer_capacity` bytes.
+///
+/// Protocols which receive large messages, such as those carrying CETs, can use a larger capacity
+/// to reduce the number of reads from the underlying connection.
+pub fn framed_with_read_buffer<C>(
+    substream: Substream,
+    codec: C,
+    read_buffer_capacity: usize,
+) -> Framed<BufferedSubstream, C>
+where
+    C: Encoder + Decoder,
+{
+    Framed::new(
+        BufReader::with_capacity(read_buffer_capacity, substream),
+        codec,
+    )
+}
+
+/// The number of bytes used to encode the length of a frame.
+const LENGTH_PREFIX_LEN: usize = 8;
+
+/// A length-delimited codec with an upper bound on the size of a frame.
+///
+/// Each frame is prefixed with its length as a big-endian `u64`, which is the same wire format as
+/// [`asynchronous_codec::LengthCodec`]. Unlike the latter, frames longer than `max_frame_len` are
+/// rejected as soon as their length prefix has been read. Given that the bytes on a substream are
+/// controlled by the peer, this prevents them from making us buffer arbitrarily large frames.
+#[derive(Clone, Copy, Debug)]
+pub struct LengthDelimited {
+    max_frame_len: usize,
+}
+
+impl LengthDelimited {
+    pub fn new(max_frame_len: usize) -> Self {
+        Self { max_frame_len }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FrameError {
+    #[error("Frame of {len} bytes exceeds the maximum of {max} bytes")]
+    TooLarge { len: u64, max: usize },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl Decoder for LengthDelimited {
+    type Item = Bytes;
+    type Error = FrameError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        decode_frame(src, self.max_frame_len)
+    }
+}
+
+impl Encoder for LengthDelimited {
+    type Item = Bytes;
+    type Error = FrameError;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        if item.len() > self.max_frame_len {
+            return Err(FrameError::TooLarge {
+                len: item.len() as u64,
+                max: self.max_frame_len,
+            });
+        }
+
+        dst.reserve(LENGTH_PREFIX_LEN + item.len());
+        dst.extend_from_slice(&(item.len() as u64).to_be_bytes());
+        dst.extend_from_slice(&item);
+
+        Ok(())
+    }
+}
+
+/// Decode a single length-delimited frame from the start of `src`.
+///
+/// Returns `Ok(None)` if `src` does not hold a complete frame yet, in which case `src` is left
+/// untouched. Malformed input never panics: a length prefix beyond `max_frame_len` is reported as
+/// [`FrameError::TooLarge`] before any memory is reserved for the frame.
+///
+/// This is exercised by the `length_delimited` fuzz target: `cargo fuzz run length_delimited`
+/// from within the `xtra-libp2p` directory.
+pub fn decode_frame(src: &mut BytesMut, max_frame_len: usize) -> Result<Option<Bytes>, FrameError> {
+    let prefix = match src.get(..LENGTH_PREFIX_LEN) {
+        Some(prefix) => prefix,
+        None => return Ok(None),
+    };
+
+    let mut len = [0u8; LENGTH_PREFIX_LEN];
+    len.copy_from_slice(prefix);
+    let len = u64::from_be_bytes(len);
+
+    let frame_len = usize::try_from(len)
+        .ok()
+        .filter(|frame_len| *frame_len <= max_frame_len)
+        .ok_or(FrameError::TooLarge {
+            len,
+            max: max_frame_len,
+        })?;
+
+    if src.len() - LENGTH_PREFIX_LEN < frame_len {
+        return Ok(None);
+    }
+
+    let _prefix = src.split_to(LENGTH_PREFIX_LEN);
+
+    Ok(Some(src.split_to(frame_len).freeze()))
+}
+
+/// A protocol message tagged with the version of its wire format.
+///
+/// Peers decoding an envelope can branch on [`Envelope::version`] to handle messages from peers
+/// running a different version. Additionally, unknown fields are ignored when decoding, so adding
+/// a field to a message does not break peers which don't know about it yet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub version: u16,
+    /// The position of the message within the substream, if enabled via
+    /// [`EnvelopeCodec::with_sequence_numbers`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+    pub payload: T,
+}
+
+/// The serialization format of the frames produced by an [`EnvelopeCodec`].
+///
+/// Frames do not identify their format, thus both peers have to agree on it beforehand. Include
+/// [`SerializationCodec::NAME`] in the name of the protocol, f.e. `/itchysats/rollover/1.0.0/cbor`,
+/// so that protocol negotiation fails instead of a peer being fed a format it cannot decode.
+pub trait SerializationCodec {
+    /// Identifies the format, meant to be part of the protocol name.
+    const NAME: &'static str;
+
+    fn to_vec<T>(value: &T) -> Result<Vec<u8>, EnvelopeError>
+    where
+        T: Serialize;
+
+    fn from_slice<T>(bytes: &[u8]) -> Result<T, EnvelopeError>
+    where
+        T: DeserializeOwned;
+}
+
+/// Serializes frames as JSON.
+///
+/// Human-readable, but verbose for binary data such as signatures and transactions.
+#[derive(Clone, Copy, Debug)]
+pub enum JsonCodec {}
+
+impl SerializationCodec for JsonCodec {
+    const NAME: &'static str = "json";
+
+    fn to_vec<T>(value: &T) -> Result<Vec<u8>, EnvelopeError>
+    where
+        T: Serialize,
+    {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn from_slice<T>(bytes: &[u8]) -> Result<T, EnvelopeError>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Serializes frames as CBOR.
+///
+/// More compact and faster to process than [`JsonCodec`] for messages carrying binary data.
+#[derive(Clone, Copy, Debug)]
+pub enum CborCodec {}
+
+impl SerializationCodec for CborCodec {
+    const NAME: &'static str = "cbor";
+
+    fn to_vec<T>(value: &T) -> Result<Vec<u8>, EnvelopeError>
+    where
+        T: Serialize,
+    {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(value, &mut bytes)
+            .map_err(|e| EnvelopeError::Cbor(e.to_string()))?;
+
+        Ok(bytes)
+    }
+
+    fn from_slice<T>(bytes: &[u8]) -> Result<T, EnvelopeError>
+    where
+        T: DeserializeOwned,
+    {
+        ciborium::de::from_reader(bytes).map_err(|e| EnvelopeError::Cbor(e.to_string()))
+    }
+}
+
+/// Frames [`Envelope`]s on top of [`LengthDelimited`], serialized according to `C`.
+///
+/// Messages are encoded with the version given upon construction. Defaults to [`JsonCodec`].
+#[derive(Debug)]
+pub struct EnvelopeCodec<T, C = JsonCodec> {
+    version: u16,
+    inner: LengthDelimited,
+    sequence_numbers: Option<SequenceNumbers>,
+    marker: PhantomData<fn() -> (T, C)>,
+}
+
+/// The sequence numbers of the next message in either direction, see
+/// [`EnvelopeCodec::with_sequence_numbers`].
+#[derive(Clone, Copy, Debug, Default)]
+struct SequenceNumbers {
+    next_outbound: u64,
+    next_inbound: u64,
+}
+
+impl<T, C> EnvelopeCodec<T, C> {
+    pub fn new(version: u16, max_frame_len: usize) -> Self {
+        Self {
+            version,
+            inner: LengthDelimited::new(max_frame_len),
+            sequence_numbers: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Number the messages sent over the substream and only accept messages from the peer which
+    /// are numbered in order.
+    ///
+    /// Both directions start at 0. A message which is replayed, skipped or lacks a sequence number
+    /// fails to decode with [`EnvelopeError::UnexpectedSequence`]. Both peers have to enable this,
+    /// thus protocols opting in should do so as part of a new protocol version.
+    pub fn with_sequence_numbers(mut self) -> Self {
+        self.sequence_numbers = Some(SequenceNumbers::default());
+        self
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EnvelopeError {
+    #[error(transparent)]
+    Frame(#[from] FrameError),
+    #[error("Failed to (de)serialize envelope")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to (de)serialize envelope: {0}")]
+    Cbor(String),
+    #[error("Expected message with sequence number {expected}, got {received:?}")]
+    UnexpectedSequence {
+        expected: u64,
+        received: Option<u64>,
+    },
+}
+
+impl From<std::io::Error> for EnvelopeError {
+    fn from(e: std::io::Error) -> Self {
+        EnvelopeError::Frame(FrameError::Io(e))
+    }
+}
+
+impl<T, C> Decoder for EnvelopeCodec<T, C>
+where
+    T: DeserializeOwned,
+    C: SerializationCodec,
+{
+    type Item = Envelope<T>;
+    type Error = EnvelopeError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let frame = match self.inner.decode(src)? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+
+        let envelope = C::from_slice::<Envelope<T>>(&frame)?;
+
+        if let Some(sequence_numbers) = &mut self.sequence_numbers {
+            let expected = sequence_numbers.next_inbound;
+            if envelope.sequence != Some(expected) {
+                return Err(EnvelopeError::UnexpectedSequence {
+                    expected,
+                    received: envelope.sequence,
+                });
+            }
+
+            sequence_numbers.next_inbound += 1;
+        }
+
+        Ok(Some(envelope))
+    }
+}
+
+impl<T, C> Encoder for EnvelopeCodec<T, C>
+where
+    T: Serialize,
+    C: SerializationCodec,
+{
+    type Item = T;
+    type Error = EnvelopeError;
+
+    fn encode(&mut self, payload: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let sequence = self.sequence_numbers.as_mut().map(|sequence_numbers| {
+            let sequence = sequence_numbers.next_outbound;
+            sequence_numbers.next_outbound += 1;
+
+            sequence
+        });
+        let envelope = Envelope {
+            version: self.version,
+            sequence,
+            payload,
+        };
+        let bytes = C::to_vec(&envelope)?;
+
+        self.inner.encode(Bytes::from(bytes), dst)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_FRAME_LEN: usize = 16;
+
+    #[test]
+    fn given_encoded_frame_when_decoding_then_roundtrips() {
+        let mut codec = LengthDelimited::new(MAX_FRAME_LEN);
+        let mut buffer = BytesMut::new();
+
+        codec
+            .encode(Bytes::from_static(b"hello"), &mut buffer)
+            .unwrap();
+        let frame = codec.decode(&mut buffer).unwrap();
+
+        assert_eq!(frame, Some(Bytes::from_static(b"hello")));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn given_truncated_frame_when_decoding_then_waits_for_more_bytes() {
+        let mut buffer = BytesMut::new();
+        buffer.extend_from_slice(&5u64.to_be_bytes()[..4]);
+
+        assert!(decode_frame(&mut buffer, MAX_FRAME_LEN).unwrap().is_none());
+
+        buffer.extend_from_slice(&5u64.to_be_bytes()[4..]);
+        buffer.extend_from_slice(b"hel");
+
+        assert!(decode_frame(&mut buffer, MAX_FRAME_LEN).unwrap().is_none());
+        assert_eq!(
+            buffer.len(),
+            LENGTH_PREFIX_LEN + 3,
+            "buffer to be untouched"
+        );
+    }
+
+    #[test]
+    fn given_length_prefix_above_max_when_decoding_then_error() {
+        let mut buffer = BytesMut::new();
+        buffer.extend_from_slice(&u64::MAX.to_be_bytes());
+
+        let error = decode_frame(&mut buffer, MAX_FRAME_LEN).unwrap_err();
+
+        assert!(matches!(
+            error,
+            FrameError::TooLarge {
+                len: u64::MAX,
+                max: MAX_FRAME_LEN
+            }
+        ));
+    }
+
+    #[test]
+    fn given_frame_above_max_when_encoding_then_error() {
+        let mut codec = LengthDelimited::new(MAX_FRAME_LEN);
+
+        let error = codec
+            .encode(
+                Bytes::from(vec![0u8; MAX_FRAME_LEN + 1]),
+                &mut BytesMut::new(),
+            )
+            .unwrap_err();
+
+        assert!(matches!(error, FrameError::TooLarge { .. }));
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct MessageV1 {
+        name: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct MessageV2 {
+        name: String,
+        age: u8,
+    }
+
+    #[test]
+    fn given_envelope_when_decoding_then_roundtrips() {
+        let mut codec = EnvelopeCodec::<MessageV1, JsonCodec>::new(1, 1024);
+        let mut buffer = BytesMut::new();
+
+        codec
+            .encode(
+                MessageV1 {
+                    name: "Alice".to_owned(),
+                },
+                &mut buffer,
+            )
+            .unwrap();
+        let envelope = codec.decode(&mut buffer).unwrap();
+
+        assert_eq!(
+            envelope,
+            Some(Envelope {
+                version: 1,
+                sequence: None,
+                payload: MessageV1 {
+                    name: "Alice".to_owned()
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn given_newer_message_with_additional_field_when_decoding_older_then_field_ignored() {
+        let mut buffer = BytesMut::new();
+        EnvelopeCodec::<MessageV2>::new(2, 1024)
+            .encode(
+                MessageV2 {
+                    name: "Alice".to_owned(),
+                    age: 42,
+                },
+                &mut buffer,
+            )
+            .unwrap();
+
+        let envelope = EnvelopeCodec::<MessageV1>::new(1, 1024)
+            .decode(&mut buffer)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(envelope.version, 2);
+        assert_eq!(
+            envelope.payload,
+            MessageV1 {
+                name: "Alice".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn given_cbor_envelope_when_decoding_then_roundtrips() {
+        let mut codec = EnvelopeCodec::<MessageV2, CborCodec>::new(2, 1024);
+        let mut buffer = BytesMut::new();
+
+        codec
+            .encode(
+                MessageV2 {
+                    name: "Alice".to_owned(),
+                    age: 42,
+                },
+                &mut buffer,
+            )
+            .unwrap();
+        let envelope = codec.decode(&mut buffer).unwrap();
+
+        assert_eq!(
+            envelope,
+            Some(Envelope {
+                version: 2,
+                sequence: None,
+                payload: MessageV2 {
+                    name: "Alice".to_owned(),
+                    age: 42,
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn given_cbor_envelope_when_decoding_as_json_then_error() {
+        let mut buffer = BytesMut::new();
+        EnvelopeCodec::<MessageV1, CborCodec>::new(1, 1024)
+            .encode(
+                MessageV1 {
+                    name: "Alice".to_owned(),
+                },
+                &mut buffer,
+            )
+            .unwrap();
+
+        let error = EnvelopeCodec::<MessageV1, JsonCodec>::new(1, 1024)
+            .decode(&mut buffer)
+            .unwrap_err();
+
+        assert!(matches!(error, EnvelopeError::Json(_)));
+    }
+
+    #[test]
+    fn given_sequence_numbers_when_decoding_in_order_then_accepted() {
+        let mut sender = EnvelopeCodec::<MessageV1>::new(1, 1024).with_sequence_numbers();
+        let mut receiver = EnvelopeCodec::<MessageV1>::new(1, 1024).with_sequence_numbers();
+        let mut buffer = BytesMut::new();
+
+        for name in ["Alice", "Bob"] {
+            sender
+                .encode(
+                    MessageV1 {
+                        name: name.to_owned(),
+                    },
+                    &mut buffer,
+                )
+                .unwrap();
+        }
+
+        let first = receiver.decode(&mut buffer).unwrap().unwrap();
+        let second = receiver.decode(&mut buffer).unwrap().unwrap();
+
+        assert_eq!(first.sequence, Some(0));
+        assert_eq!(second.sequence, Some(1));
+    }
+
+    #[test]
+    fn given_sequence_numbers_when_frame_replayed_then_rejected() {
+        let mut sender = EnvelopeCodec::<MessageV1>::new(1, 1024).with_sequence_numbers();
+        let mut receiver = EnvelopeCodec::<MessageV1>::new(1, 1024).with_sequence_numbers();
+        let mut buffer = BytesMut::new();
+
+        sender
+            .encode(
+                MessageV1 {
+                    name: "Alice".to_owned(),
+                },
+                &mut buffer,
+            )
+            .unwrap();
+        let frame = buffer.clone();
+
+        receiver.decode(&mut buffer).unwrap().unwrap();
+
+        buffer.extend_from_slice(&frame);
+        let error = receiver.decode(&mut buffer).unwrap_err();
+
+        assert!(matches!(
+            error,
+            EnvelopeError::UnexpectedSequence {
+                expected: 1,
+                received: Some(0)
+            }
+        ));
+    }
+
+    #[test]
+    fn given_sequence_numbers_when_peer_sends_none_then_rejected() {
+        let mut buffer = BytesMut::new();
+        EnvelopeCodec::<MessageV1>::new(1, 1024)
+            .encode(
+                MessageV1 {
+                    name: "Alice".to_owned(),
+                },
+                &mut buffer,
+            )
+            .unwrap();
+
+        let error = EnvelopeCodec::<MessageV1>::new(1, 1024)
+            .with_sequence_numbers()
+            .decode(&mut buffer)
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            EnvelopeError::UnexpectedSequence {
+                expected: 0,
+                received: None
+            }
+        ));
+    }
+}
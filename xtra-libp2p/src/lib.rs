@@ -1,17 +1,45 @@
+pub use crate::endpoint::AdaptiveNegotiationTimeout;
 pub use crate::endpoint::Connect;
+pub use crate::endpoint::ConnectAndWait;
+pub use crate::endpoint::ConnectWithPeerId;
+pub use crate::endpoint::ConnectionDebug;
+pub use crate::endpoint::ConnectionGate;
 pub use crate::endpoint::ConnectionStats;
 pub use crate::endpoint::Disconnect;
+pub use crate::endpoint::DisconnectMany;
 pub use crate::endpoint::Endpoint;
+pub use crate::endpoint::EndpointDebug;
 pub use crate::endpoint::Error;
 pub use crate::endpoint::GetConnectionStats;
+pub use crate::endpoint::GetDebugSnapshot;
+pub use crate::endpoint::GetLastSeen;
+pub use crate::endpoint::GetSubstreamStats;
+pub use crate::endpoint::GetSubstreamTimings;
+pub use crate::endpoint::HasHandler;
+pub use crate::endpoint::InboundSubstreamHandlers;
 pub use crate::endpoint::ListenOn;
+pub use crate::endpoint::ListenerId;
 pub use crate::endpoint::Multiple;
+pub use crate::endpoint::NegotiationLogLevels;
 pub use crate::endpoint::NewInboundSubstream;
 pub use crate::endpoint::OpenSubstream;
+pub use crate::endpoint::OpenSubstreamExt;
+pub use crate::endpoint::PauseInbound;
+pub use crate::endpoint::Quarantine;
+pub use crate::endpoint::RecordRtt;
+pub use crate::endpoint::ResetConnection;
+pub use crate::endpoint::ResumeInbound;
+pub use crate::endpoint::RotateIdentity;
 pub use crate::endpoint::Single;
+pub use crate::endpoint::StartListening;
+pub use crate::endpoint::StopListening;
+pub use crate::endpoint::WildcardListenPolicy;
 pub use crate::substream::Substream;
+pub use crate::substream::SubstreamStats;
+pub use crate::substream::SubstreamTimings;
 pub use libp2p_core as libp2p;
 pub use multistream_select::NegotiationError;
+pub use multistream_select::Version;
 
 use futures::future::BoxFuture;
 use futures::stream::BoxStream;
@@ -20,9 +48,13 @@ use libp2p_core::PeerId;
 
 pub mod dialer;
 pub mod endpoint;
+pub mod framing;
 pub mod listener;
 pub mod multiaddress_ext;
+mod protocol_set;
 mod substream;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 mod upgrade;
 mod verify_peer_id;
 
@@ -48,7 +48,7 @@ impl Actor {
             .endpoint
             .send(GetConnectionStats)
             .await?
-            .listen_addresses
+            .listening_on
             .contains(&self.listen_address))
     }
 
@@ -97,9 +97,13 @@ impl Actor {
 
 #[xtra_productivity]
 impl Actor {
-    async fn handle(&mut self, msg: endpoint::ListenAddressRemoved, ctx: &mut xtra::Context<Self>) {
-        if msg.address == self.listen_address {
-            self.stop_with_error(Error::ConnectionDropped, ctx);
+    async fn handle(&mut self, _: endpoint::ListenAddressRemoved, ctx: &mut xtra::Context<Self>) {
+        // The removed address may be one of the addresses our wildcard address was expanded to,
+        // so we check whether we are still listening instead of comparing addresses.
+        match self.is_listening().await {
+            Ok(true) => {}
+            Ok(false) => self.stop_with_error(Error::ConnectionDropped, ctx),
+            Err(e) => self.stop_with_error(Error::Failed { source: e }, ctx),
         }
     }
 }
@@ -1,6 +1,7 @@
 use libp2p_core::multiaddr::Protocol;
 use libp2p_core::Multiaddr;
 use libp2p_core::PeerId;
+use std::net::IpAddr;
 
 pub trait MultiaddrExt {
     fn extract_peer_id(self) -> Option<PeerId>;
@@ -16,3 +17,94 @@ impl MultiaddrExt for Multiaddr {
         Some(peer_id)
     }
 }
+
+/// Whether the address listens on all interfaces, like `/ip4/0.0.0.0/tcp/9999`.
+pub(crate) fn is_wildcard(address: &Multiaddr) -> bool {
+    match address.iter().next() {
+        Some(Protocol::Ip4(ip)) => ip.is_unspecified(),
+        Some(Protocol::Ip6(ip)) => ip.is_unspecified(),
+        _ => false,
+    }
+}
+
+/// Whether the address leaves picking the TCP or UDP port to the transport, like
+/// `/ip4/127.0.0.1/tcp/0`.
+pub(crate) fn has_unspecified_port(address: &Multiaddr) -> bool {
+    address
+        .iter()
+        .any(|protocol| matches!(protocol, Protocol::Tcp(0) | Protocol::Udp(0)))
+}
+
+/// Replace the unspecified IP of a wildcard address with each of the given IPs of the same
+/// version.
+///
+/// Returns `None` if the address is not a wildcard address, see [`is_wildcard`].
+pub(crate) fn expand_wildcard(address: &Multiaddr, ips: &[IpAddr]) -> Option<Vec<Multiaddr>> {
+    if !is_wildcard(address) {
+        return None;
+    }
+
+    let mut protocols = address.iter();
+    let is_ipv4 = matches!(protocols.next(), Some(Protocol::Ip4(_)));
+    let rest = protocols.collect::<Vec<_>>();
+
+    let expanded = ips
+        .iter()
+        .filter(|ip| ip.is_ipv4() == is_ipv4)
+        .map(|ip| {
+            let ip = match ip {
+                IpAddr::V4(ip) => Protocol::Ip4(*ip),
+                IpAddr::V6(ip) => Protocol::Ip6(*ip),
+            };
+
+            std::iter::once(ip).chain(rest.iter().cloned()).collect()
+        })
+        .collect();
+
+    Some(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_wildcard_then_expanded_to_ips_of_same_version() {
+        let wildcard = "/ip4/0.0.0.0/tcp/9999".parse::<Multiaddr>().unwrap();
+        let ips = [
+            "127.0.0.1".parse().unwrap(),
+            "192.168.0.2".parse().unwrap(),
+            "::1".parse().unwrap(),
+        ];
+
+        let expanded = expand_wildcard(&wildcard, &ips).unwrap();
+
+        assert_eq!(
+            expanded,
+            vec![
+                "/ip4/127.0.0.1/tcp/9999".parse::<Multiaddr>().unwrap(),
+                "/ip4/192.168.0.2/tcp/9999".parse().unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn given_concrete_address_then_not_expanded() {
+        let address = "/ip4/127.0.0.1/tcp/9999".parse::<Multiaddr>().unwrap();
+
+        assert!(!is_wildcard(&address));
+        assert!(expand_wildcard(&address, &["127.0.0.1".parse().unwrap()]).is_none());
+        assert!(!is_wildcard(&"/memory/0".parse().unwrap()));
+    }
+
+    #[test]
+    fn given_port_zero_then_port_unspecified() {
+        assert!(has_unspecified_port(&"/ip4/0.0.0.0/tcp/0".parse().unwrap()));
+        assert!(has_unspecified_port(
+            &"/ip4/127.0.0.1/tcp/0".parse().unwrap()
+        ));
+        assert!(!has_unspecified_port(
+            &"/ip4/0.0.0.0/tcp/9999".parse().unwrap()
+        ));
+    }
+}
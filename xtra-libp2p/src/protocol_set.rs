@@ -0,0 +1,48 @@
+use crate::NewInboundSubstream;
+use std::collections::HashMap;
+use std::sync::Arc;
+use xtra::message_channel::MessageChannel;
+
+/// The set of protocols an [`Endpoint`](crate::Endpoint) accepts inbound substreams for.
+///
+/// Each protocol is paired with the handler that is given the fully-negotiated substreams. The
+/// connection upgrade only negotiates protocols of this set and the handlers are looked up from
+/// the very same set. Thus, we can never negotiate a protocol for which we don't have a handler.
+#[derive(Clone)]
+pub struct ProtocolSet(Arc<HashMap<&'static str, MessageChannel<NewInboundSubstream, ()>>>);
+
+impl ProtocolSet {
+    pub fn new<const N: usize>(
+        inbound_substream_handlers: [(&'static str, MessageChannel<NewInboundSubstream, ()>); N],
+    ) -> Self {
+        let mut map = HashMap::with_capacity(inbound_substream_handlers.len());
+
+        for (protocol, handler) in inbound_substream_handlers {
+            let previous_handler = map.insert(protocol, handler);
+
+            debug_assert!(
+                previous_handler.is_none(),
+                "Duplicate handler declared for protocol {protocol}"
+            );
+        }
+
+        Self(Arc::new(map))
+    }
+
+    /// The protocols to negotiate for inbound substreams.
+    pub fn protocols(&self) -> Vec<&'static str> {
+        self.0.keys().copied().collect()
+    }
+
+    /// Whether a handler is registered for the given protocol.
+    pub fn contains(&self, protocol: &str) -> bool {
+        self.0.contains_key(protocol)
+    }
+
+    /// The handler for a protocol that was negotiated from this set.
+    pub fn handler(&self, protocol: &str) -> &MessageChannel<NewInboundSubstream, ()> {
+        self.0
+            .get(protocol)
+            .expect("only protocols of this set are negotiated")
+    }
+}
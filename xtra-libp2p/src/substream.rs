@@ -4,6 +4,7 @@ use futures::AsyncRead;
 use futures::AsyncWrite;
 use libp2p_core::Endpoint;
 use libp2p_core::Negotiated;
+use libp2p_core::PeerId;
 use pin_project::pin_project;
 use prometheus::HistogramTimer;
 use prometheus::IntCounter;
@@ -13,8 +14,12 @@ use std::fmt::Debug;
 use std::io::IoSlice;
 use std::io::IoSliceMut;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::task::Context;
 use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
 
 /// A substream is an isolated channel within another connection.
 ///
@@ -28,7 +33,8 @@ use std::task::Poll;
 /// Each substream is dedicated to a specific protocol which must be specified upon construction.
 ///
 /// Substreams are instrumented with prometheus metrics that track the duration they are alive for
-/// and how many bytes are read from and written to the stream.
+/// and how many bytes are read from and written to the stream. Additionally, per-peer timings can
+/// be recorded, see [`SubstreamTimings`].
 #[pin_project]
 pub struct Substream {
     #[pin]
@@ -45,6 +51,12 @@ pub struct Substream {
 
     /// The prometheus counter for the number of bytes written.
     written_counter: IntCounter,
+
+    /// Records per-peer timings of the substream, if enabled on the endpoint.
+    timings: Option<TimingsRecorder>,
+
+    /// Counts the substream as open in each registry for as long as it is alive.
+    _open: Vec<OpenSubstreamGuard>,
 }
 
 impl Debug for Substream {
@@ -72,8 +84,238 @@ impl Substream {
             _timer: SUBSTREAM_DURATION_HISTOGRAM.with(&labels).start_timer(),
             read_counter: SUBSTREAM_BYTES_READ_COUNTER.with(&labels),
             written_counter: SUBSTREAM_BYTES_WRITTEN_COUNTER.with(&labels),
+            timings: None,
+            _open: Vec::new(),
         }
     }
+
+    /// Record the timings of this substream in the given registry.
+    ///
+    /// `opened_at` is when the substream was requested, so that a slow protocol negotiation is
+    /// reflected in the time to first byte.
+    pub(crate) fn with_timings(
+        mut self,
+        registry: &SubstreamTimingsRegistry,
+        peer_id: PeerId,
+        protocol: &'static str,
+        opened_at: Instant,
+    ) -> Self {
+        self.timings = Some(TimingsRecorder::new(
+            registry.clone(),
+            (peer_id, protocol),
+            opened_at,
+        ));
+        self
+    }
+
+    /// Count this substream as open in the given registry until it is dropped.
+    ///
+    /// Can be called several times to count the substream in several registries.
+    pub(crate) fn with_open_counter(
+        mut self,
+        registry: &OpenSubstreamsRegistry,
+        protocol: &'static str,
+        role: Endpoint,
+    ) -> Self {
+        self._open
+            .push(OpenSubstreamGuard::new(registry.clone(), protocol, role));
+        self
+    }
+}
+
+/// The number of currently open substreams of an endpoint.
+///
+/// See [`GetSubstreamStats`](crate::GetSubstreamStats).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SubstreamStats {
+    /// Substreams opened by the peer.
+    pub inbound_open: usize,
+    /// Substreams opened by us.
+    pub outbound_open: usize,
+    /// Open substreams of either direction, per protocol.
+    pub per_protocol: HashMap<&'static str, usize>,
+}
+
+/// Tracks the substreams which are currently open.
+#[derive(Clone, Default)]
+pub(crate) struct OpenSubstreamsRegistry(Arc<Mutex<SubstreamStats>>);
+
+impl OpenSubstreamsRegistry {
+    pub(crate) fn snapshot(&self) -> SubstreamStats {
+        self.0.lock().expect("lock not to be poisoned").clone()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut SubstreamStats)) {
+        f(&mut self.0.lock().expect("lock not to be poisoned"))
+    }
+}
+
+/// Counts a single substream as open in an [`OpenSubstreamsRegistry`] until it is dropped.
+struct OpenSubstreamGuard {
+    registry: OpenSubstreamsRegistry,
+    protocol: &'static str,
+    role: Endpoint,
+}
+
+impl OpenSubstreamGuard {
+    fn new(registry: OpenSubstreamsRegistry, protocol: &'static str, role: Endpoint) -> Self {
+        registry.update(|stats| {
+            match role {
+                Endpoint::Dialer => stats.outbound_open += 1,
+                Endpoint::Listener => stats.inbound_open += 1,
+            }
+            *stats.per_protocol.entry(protocol).or_default() += 1;
+        });
+
+        Self {
+            registry,
+            protocol,
+            role,
+        }
+    }
+}
+
+impl Drop for OpenSubstreamGuard {
+    fn drop(&mut self) {
+        let (protocol, role) = (self.protocol, self.role);
+
+        self.registry.update(|stats| {
+            match role {
+                Endpoint::Dialer => stats.outbound_open -= 1,
+                Endpoint::Listener => stats.inbound_open -= 1,
+            }
+            if let Some(count) = stats.per_protocol.get_mut(protocol) {
+                *count -= 1;
+                if *count == 0 {
+                    stats.per_protocol.remove(protocol);
+                }
+            }
+        });
+    }
+}
+
+/// Timings of a substream with a peer for a specific protocol.
+///
+/// See [`Endpoint::with_substream_timings`](crate::Endpoint::with_substream_timings).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SubstreamTimings {
+    /// The time from opening the substream until the first byte was read, if any was read yet.
+    pub time_to_first_byte: Option<Duration>,
+    /// The time the substream was alive for, if it was closed already.
+    pub duration: Option<Duration>,
+}
+
+/// The timings of the most recent substream, keyed by peer and protocol.
+#[derive(Clone, Default)]
+pub(crate) struct SubstreamTimingsRegistry(Arc<Mutex<TimingsByPeerAndProtocol>>);
+
+#[derive(Default)]
+struct TimingsByPeerAndProtocol {
+    next_substream_id: u64,
+    timings: HashMap<(PeerId, &'static str), (u64, SubstreamTimings)>,
+}
+
+impl SubstreamTimingsRegistry {
+    pub(crate) fn snapshot(&self) -> HashMap<(PeerId, &'static str), SubstreamTimings> {
+        self.0
+            .lock()
+            .expect("lock not to be poisoned")
+            .timings
+            .iter()
+            .map(|(key, (_, timings))| (*key, *timings))
+            .collect()
+    }
+
+    /// Forget the timings of all substreams with `peer_id`.
+    pub(crate) fn remove_peer(&self, peer_id: &PeerId) {
+        self.0
+            .lock()
+            .expect("lock not to be poisoned")
+            .timings
+            .retain(|(peer, _), _| peer != peer_id);
+    }
+
+    /// Start tracking a new substream, replacing the previous one with the same key.
+    fn register(&self, key: (PeerId, &'static str)) -> u64 {
+        let mut inner = self.0.lock().expect("lock not to be poisoned");
+
+        let substream_id = inner.next_substream_id;
+        inner.next_substream_id += 1;
+        inner
+            .timings
+            .insert(key, (substream_id, SubstreamTimings::default()));
+
+        substream_id
+    }
+
+    /// Update the timings of a substream unless it has been replaced by a newer one.
+    fn update(
+        &self,
+        key: (PeerId, &'static str),
+        substream_id: u64,
+        f: impl FnOnce(&mut SubstreamTimings),
+    ) {
+        let mut inner = self.0.lock().expect("lock not to be poisoned");
+
+        if let Some((id, timings)) = inner.timings.get_mut(&key) {
+            if *id == substream_id {
+                f(timings)
+            }
+        }
+    }
+}
+
+/// Records the timings of a single substream into a [`SubstreamTimingsRegistry`].
+///
+/// The duration is recorded once the recorder, i.e. the substream, is dropped.
+struct TimingsRecorder {
+    registry: SubstreamTimingsRegistry,
+    key: (PeerId, &'static str),
+    substream_id: u64,
+    opened_at: Instant,
+    received_first_byte: bool,
+}
+
+impl TimingsRecorder {
+    fn new(
+        registry: SubstreamTimingsRegistry,
+        key: (PeerId, &'static str),
+        opened_at: Instant,
+    ) -> Self {
+        let substream_id = registry.register(key);
+
+        Self {
+            registry,
+            key,
+            substream_id,
+            opened_at,
+            received_first_byte: false,
+        }
+    }
+
+    fn record_read(&mut self, bytes_read: usize) {
+        if bytes_read == 0 || self.received_first_byte {
+            return;
+        }
+
+        self.received_first_byte = true;
+
+        let time_to_first_byte = self.opened_at.elapsed();
+        self.registry
+            .update(self.key, self.substream_id, |timings| {
+                timings.time_to_first_byte = Some(time_to_first_byte)
+            });
+    }
+}
+
+impl Drop for TimingsRecorder {
+    fn drop(&mut self) {
+        let duration = self.opened_at.elapsed();
+        self.registry
+            .update(self.key, self.substream_id, |timings| {
+                timings.duration = Some(duration)
+            });
+    }
 }
 
 impl AsyncRead for Substream {
@@ -86,6 +328,9 @@ impl AsyncRead for Substream {
 
         let bytes_read = ready!(this.inner.poll_read(cx, buf)?);
         this.read_counter.inc_by(bytes_read as u64);
+        if let Some(timings) = this.timings {
+            timings.record_read(bytes_read);
+        }
 
         Poll::Ready(Ok(bytes_read))
     }
@@ -99,6 +344,9 @@ impl AsyncRead for Substream {
 
         let bytes_read = ready!(this.inner.poll_read_vectored(cx, bufs)?);
         this.read_counter.inc_by(bytes_read as u64);
+        if let Some(timings) = this.timings {
+            timings.record_read(bytes_read);
+        }
 
         Poll::Ready(Ok(bytes_read))
     }
@@ -0,0 +1,139 @@
+//! Utilities for testing protocol handlers in isolation, without standing up an
+//! [`Endpoint`](crate::Endpoint).
+//!
+//! Only available with the `test-utils` feature, meant to be enabled for dev-dependencies.
+
+use crate::Substream;
+use anyhow::bail;
+use anyhow::Context as _;
+use anyhow::Result;
+use futures::channel::oneshot;
+use futures::StreamExt;
+use libp2p_core::transport::ListenerEvent;
+use libp2p_core::transport::MemoryTransport;
+use libp2p_core::Endpoint;
+use libp2p_core::PeerId;
+use libp2p_core::Transport;
+use multistream_select::Version;
+use tokio_extras::Tasks;
+
+/// Two ends of a substream, negotiated for a single protocol over an in-memory connection.
+///
+/// Hand [`SubstreamPair::listener`] to the handler under test, f.e. as part of a
+/// [`NewInboundSubstream`](crate::NewInboundSubstream) from [`SubstreamPair::peer_id`], and drive
+/// the protocol from the test through [`SubstreamPair::dialer`].
+///
+/// The connection is driven in the background for as long as the pair is alive. It is fine to
+/// move the substreams out of the pair, but the pair itself has to be kept around until the test
+/// is done with them.
+pub struct SubstreamPair {
+    /// The peer which opened the substream, i.e. the test.
+    pub peer_id: PeerId,
+    /// The end of the substream opened by the peer.
+    pub dialer: Substream,
+    /// The end of the substream accepted by us, i.e. the handler under test.
+    pub listener: Substream,
+    _connection: Tasks,
+}
+
+/// Open a substream for `protocol` over a new in-memory connection.
+///
+/// Unlike a connection between two endpoints, the connection is neither authenticated nor
+/// encrypted, but multiplexed via yamux and negotiated via multistream-select like any other.
+pub async fn substream_pair(protocol: &'static str) -> Result<SubstreamPair> {
+    let mut listener = MemoryTransport::default().listen_on("/memory/0".parse()?)?;
+    let address = match listener.next().await {
+        Some(Ok(ListenerEvent::NewAddress(address))) => address,
+        _ => bail!("Memory transport did not report listen address"),
+    };
+
+    let dial = MemoryTransport::default().dial(address)?;
+    let accept = async {
+        loop {
+            match listener.next().await {
+                Some(Ok(ListenerEvent::Upgrade { upgrade, .. })) => break upgrade.await,
+                Some(_) => continue,
+                None => bail!("Memory transport listener closed"),
+            }
+        }
+        .context("Failed to accept in-memory connection")
+    };
+    let (dialer_io, listener_io) = futures::try_join!(
+        async { dial.await.context("Failed to dial in-memory connection") },
+        accept
+    )?;
+
+    let mut dialer_connection =
+        yamux::Connection::new(dialer_io, yamux::Config::default(), yamux::Mode::Client);
+    let mut listener_connection =
+        yamux::Connection::new(listener_io, yamux::Config::default(), yamux::Mode::Server);
+    let mut control = dialer_connection.control();
+
+    // Both connections have to be polled continuously for any data to flow
+    let mut tasks = Tasks::default();
+    tasks.add(async move { while let Ok(Some(_)) = dialer_connection.next_stream().await {} });
+    let (sender, receiver) = oneshot::channel();
+    tasks.add(async move {
+        let mut sender = Some(sender);
+        while let Ok(Some(stream)) = listener_connection.next_stream().await {
+            if let Some(sender) = sender.take() {
+                let _ = sender.send(stream);
+            }
+        }
+    });
+
+    let (dialer, listener) = futures::try_join!(
+        async {
+            let stream = control.open_stream().await?;
+            let (_, stream) =
+                multistream_select::dialer_select_proto(stream, [protocol], Version::V1).await?;
+
+            anyhow::Ok(stream)
+        },
+        async {
+            let stream = receiver
+                .await
+                .context("Connection closed before substream was opened")?;
+            let (_, stream) = multistream_select::listener_select_proto(stream, [protocol]).await?;
+
+            anyhow::Ok(stream)
+        }
+    )?;
+
+    Ok(SubstreamPair {
+        peer_id: PeerId::random(),
+        dialer: Substream::new(dialer, protocol, Endpoint::Dialer),
+        listener: Substream::new(listener, protocol, Endpoint::Listener),
+        _connection: tasks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::AsyncReadExt;
+    use futures::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn bytes_written_to_one_end_are_read_from_the_other() {
+        let SubstreamPair {
+            mut dialer,
+            mut listener,
+            _connection,
+            ..
+        } = substream_pair("/test/1.0.0").await.unwrap();
+
+        dialer.write_all(b"ping").await.unwrap();
+        dialer.flush().await.unwrap();
+
+        let mut buf = [0u8; 4];
+        listener.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        listener.write_all(b"pong").await.unwrap();
+        listener.flush().await.unwrap();
+
+        dialer.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+    }
+}
@@ -1,3 +1,4 @@
+use crate::protocol_set::ProtocolSet;
 use crate::verify_peer_id::VerifyPeerId;
 use crate::Connection;
 use futures::channel::mpsc;
@@ -8,7 +9,6 @@ use futures::FutureExt;
 use futures::SinkExt;
 use futures::StreamExt;
 use libp2p_core::identity::Keypair;
-use libp2p_core::transport::timeout::TransportTimeout;
 use libp2p_core::transport::Boxed;
 use libp2p_core::upgrade;
 use libp2p_core::upgrade::Version;
@@ -26,12 +26,25 @@ use void::Void;
 /// - Noise encryption and authentication
 /// - PeerID verification for each connection
 /// - Yamux multiplexing
-/// - Connection upgrade timeout
+///
+/// Noise is the only security protocol on offer. TLS 1.3 as specified for libp2p is implemented
+/// by `libp2p-tls`, which requires a newer `libp2p-core` than the one we depend on. Offering TLS
+/// alongside noise thus has to wait until we upgrade `libp2p-core`.
+///
+/// The `connection_timeout` is applied to the protocol negotiation of inbound substreams. Timing
+/// out dials and connection upgrades is left to the caller, allowing all timers to be driven by
+/// tokio (see [`crate::Endpoint::new`]).
+///
+/// At most `max_buffered_inbound_substreams` inbound substreams per connection are buffered until
+/// the caller takes them from the stream of incoming substreams. Once the buffer is full, we stop
+/// reading from the connection until there is space again. Up to as many inbound substreams are
+/// negotiated concurrently, thus a slow negotiation does not hold up the others.
 pub fn transport<T>(
     transport: T,
     identity: &Keypair,
-    supported_inbound_protocols: Vec<&'static str>,
+    supported_inbound_protocols: ProtocolSet,
     connection_timeout: Duration,
+    max_buffered_inbound_substreams: usize,
 ) -> Boxed<Connection>
 where
     T: Transport + Send + Sync + 'static,
@@ -90,10 +103,13 @@ where
         )
     });
 
+    let supported_inbound_protocols = supported_inbound_protocols.protocols();
+
     let protocols_negotiated = multiplexed.map(move |(peer, mut connection), _| {
         let control = connection.control();
 
-        let (mut sender, receiver) = mpsc::channel(5); // Use a bounded channel to allow caller to exercise back-pressure.
+        // Use a bounded channel to allow caller to exercise back-pressure.
+        let (mut sender, receiver) = mpsc::channel(max_buffered_inbound_substreams);
 
         let worker = async move {
             while let Ok(Some(stream)) = connection.next_stream().await {
@@ -129,7 +145,7 @@ where
         .boxed();
 
         let incoming = receiver
-            .then(move |stream| {
+            .map(move |stream| {
                 let supported_protocols = supported_inbound_protocols.clone();
 
                 let fut = async move {
@@ -152,12 +168,13 @@ where
                     ?supported_inbound_protocols
                 ))
             })
+            .buffer_unordered(max_buffered_inbound_substreams.max(1))
             .boxed();
 
         (peer, control, incoming, worker)
     });
 
-    TransportTimeout::new(protocols_negotiated, connection_timeout).boxed()
+    protocols_negotiated.boxed()
 }
 
 #[derive(thiserror::Error, Debug)]
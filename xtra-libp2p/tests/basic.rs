@@ -1,31 +1,70 @@
+use crate::util::make_configured_node;
 use crate::util::make_node;
 use crate::util::make_node_with_blocklist;
 use crate::util::GetConnectedPeers;
+use crate::util::GetDialFailures;
+use crate::util::GetDisconnectReasons;
 use crate::util::GetListenAddresses;
 use crate::util::Node;
 use anyhow::Context as _;
 use anyhow::Result;
 use async_trait::async_trait;
 use asynchronous_codec::Bytes;
+use futures::AsyncRead;
+use futures::AsyncWrite;
+use futures::FutureExt;
 use futures::SinkExt;
 use futures::StreamExt;
 use libp2p_core::multiaddr::Protocol;
+use libp2p_core::transport::ListenerEvent;
 use libp2p_core::Multiaddr;
+use libp2p_core::Transport;
+use libp2p_tcp::TokioTcpConfig;
+use std::collections::HashMap;
 use std::collections::HashSet;
+use std::net::Ipv4Addr;
+use std::num::NonZeroUsize;
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
 use xtra::message_channel::MessageChannel;
 use xtra::spawn::TokioGlobalSpawnExt;
 use xtra::Actor;
 use xtra::Context;
 use xtra_libp2p::endpoint;
 use xtra_libp2p::endpoint::RegisterListenProtocols;
+use xtra_libp2p::endpoint::Subscribers;
+use xtra_libp2p::libp2p::identity::Keypair;
+use xtra_libp2p::libp2p::transport::MemoryTransport;
 use xtra_libp2p::libp2p::PeerId;
 use xtra_libp2p::Connect;
+use xtra_libp2p::ConnectAndWait;
+use xtra_libp2p::ConnectWithPeerId;
+use xtra_libp2p::ConnectionStats;
 use xtra_libp2p::Disconnect;
+use xtra_libp2p::DisconnectMany;
+use xtra_libp2p::Endpoint;
 use xtra_libp2p::GetConnectionStats;
+use xtra_libp2p::GetDebugSnapshot;
+use xtra_libp2p::GetLastSeen;
+use xtra_libp2p::GetSubstreamStats;
+use xtra_libp2p::GetSubstreamTimings;
+use xtra_libp2p::HasHandler;
+use xtra_libp2p::InboundSubstreamHandlers;
 use xtra_libp2p::ListenOn;
 use xtra_libp2p::NewInboundSubstream;
 use xtra_libp2p::OpenSubstream;
+use xtra_libp2p::OpenSubstreamExt;
+use xtra_libp2p::PauseInbound;
+use xtra_libp2p::Quarantine;
+use xtra_libp2p::ResetConnection;
+use xtra_libp2p::ResumeInbound;
+use xtra_libp2p::RotateIdentity;
+use xtra_libp2p::StartListening;
+use xtra_libp2p::StopListening;
+use xtra_libp2p::SubstreamStats;
+use xtra_libp2p::Version;
+use xtra_libp2p::WildcardListenPolicy;
 use xtra_productivity::xtra_productivity;
 
 mod util;
@@ -59,6 +98,100 @@ async fn hello_world() {
     assert_eq!(string, "Hello Bob!");
 }
 
+#[tokio::test]
+async fn opened_substream_is_delivered_to_handler_of_its_protocol() {
+    let recorder = Recorder::default().create(None).spawn_global();
+    let other_recorder = Recorder::default().create(None).spawn_global();
+    let (alice, bob, _) = alice_and_bob(
+        InboundSubstreamHandlers::new()
+            .with("/record/1.0.0", recorder.clone())
+            .with("/other/1.0.0", other_recorder.clone())
+            .build::<2>()
+            .unwrap(),
+        [],
+    )
+    .await;
+
+    let _bob_to_alice = bob
+        .endpoint
+        .send(OpenSubstream::single_protocol(
+            alice.peer_id,
+            "/record/1.0.0",
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+
+    // Delivery happens after negotiation completed
+    tokio_extras::time::sleep(Duration::from_millis(200)).await;
+
+    let received = recorder.send(GetReceivedSubstreams).await.unwrap();
+    let other_received = other_recorder.send(GetReceivedSubstreams).await.unwrap();
+
+    assert_eq!(received, vec![bob.peer_id]);
+    assert!(other_received.is_empty());
+}
+
+#[tokio::test]
+async fn hello_world_with_handlers_built_from_addresses() {
+    let alice_hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let (alice, bob, _) = alice_and_bob(
+        InboundSubstreamHandlers::new()
+            .with("/hello-world/1.0.0", alice_hello_world_handler)
+            .build::<1>()
+            .unwrap(),
+        [],
+    )
+    .await;
+
+    let bob_to_alice = bob
+        .endpoint
+        .send(OpenSubstream::single_protocol(
+            alice.peer_id,
+            "/hello-world/1.0.0",
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+
+    let string = hello_world_dialer(bob_to_alice, "Bob").await.unwrap();
+
+    assert_eq!(string, "Hello Bob!");
+}
+
+#[tokio::test]
+async fn hello_world_with_lazy_negotiation() {
+    let alice_hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let (alice, bob, _) = alice_and_bob(
+        [(
+            "/hello-world/1.0.0",
+            alice_hello_world_handler.clone().into(),
+        )],
+        [],
+    )
+    .await;
+
+    let bob_to_alice = bob
+        .endpoint
+        .send(
+            OpenSubstream::single_protocol(alice.peer_id, "/hello-world/1.0.0")
+                .with_version(Version::V1Lazy),
+        )
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+
+    let string = hello_world_dialer(bob_to_alice, "Bob").await.unwrap();
+
+    assert_eq!(string, "Hello Bob!");
+}
+
 #[tokio::test]
 async fn blocked_peers_cannot_connect() {
     let bob = make_node([]);
@@ -132,6 +265,146 @@ async fn disconnect_is_reflected_in_stats() {
     assert_eq!(bob_stats.connected_peers, HashSet::from([]));
 }
 
+#[tokio::test]
+async fn last_seen_is_updated_on_connect_and_disconnect() {
+    let before_connect = SystemTime::now();
+    let (alice, bob, _) = alice_and_bob([], []).await;
+
+    let connected_at = alice.endpoint.send(GetLastSeen).await.unwrap()[&bob.peer_id];
+    assert!(connected_at >= before_connect);
+
+    alice.endpoint.send(Disconnect(bob.peer_id)).await.unwrap();
+
+    let last_seen = alice.endpoint.send(GetLastSeen).await.unwrap();
+    assert!(last_seen[&bob.peer_id] >= connected_at);
+    assert!(!last_seen.contains_key(&alice.peer_id));
+}
+
+#[tokio::test]
+async fn disconnect_many_returns_peers_that_were_connected() {
+    let (alice, bob, _) = alice_and_bob([], []).await;
+    let unknown_peer = PeerId::random();
+
+    let disconnected = alice
+        .endpoint
+        .send(DisconnectMany(vec![bob.peer_id, unknown_peer]))
+        .await
+        .unwrap();
+
+    assert_eq!(disconnected, HashSet::from([bob.peer_id]));
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert!(alice_stats.connected_peers.is_empty());
+}
+
+#[tokio::test]
+async fn disconnect_reason_is_reported_to_subscribers() {
+    let (alice, bob, _) = alice_and_bob([], []).await;
+
+    alice.endpoint.send(Disconnect(bob.peer_id)).await.unwrap();
+
+    // Wait for Bob to notice that the connection was closed
+    tokio_extras::time::sleep(Duration::from_millis(200)).await;
+
+    let alice_reasons = alice
+        .subscriber_stats
+        .send(GetDisconnectReasons)
+        .await
+        .unwrap();
+    let bob_reasons = bob
+        .subscriber_stats
+        .send(GetDisconnectReasons)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        alice_reasons.get(&bob.peer_id),
+        Some(&endpoint::DisconnectReason::Requested)
+    );
+    assert_eq!(
+        bob_reasons.get(&alice.peer_id),
+        Some(&endpoint::DisconnectReason::ConnectionFailed)
+    );
+}
+
+#[tokio::test]
+async fn given_max_connection_lifetime_exceeded_then_connection_recycled() {
+    let recycled_before = connections_recycled_total();
+
+    let alice = make_configured_node([], |endpoint| {
+        endpoint.with_max_connection_lifetime(Duration::from_millis(200))
+    });
+    let bob = make_node([]);
+    connect(&alice, &bob).await;
+
+    tokio_extras::time::sleep(Duration::from_secs(1)).await;
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    let alice_reasons = alice
+        .subscriber_stats
+        .send(GetDisconnectReasons)
+        .await
+        .unwrap();
+    let bob_reasons = bob
+        .subscriber_stats
+        .send(GetDisconnectReasons)
+        .await
+        .unwrap();
+
+    assert!(alice_stats.connected_peers.is_empty());
+    assert_eq!(
+        alice_reasons.get(&bob.peer_id),
+        Some(&endpoint::DisconnectReason::Recycled)
+    );
+    assert!(bob_reasons.contains_key(&alice.peer_id));
+    // Other tests may recycle connections concurrently, hence not an exact comparison
+    assert!(connections_recycled_total() > recycled_before);
+}
+
+#[tokio::test]
+async fn given_open_substream_when_max_connection_lifetime_exceeded_then_recycled_once_closed() {
+    let hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let alice = make_configured_node(
+        [("/hello-world/1.0.0", hello_world_handler.into())],
+        |endpoint| endpoint.with_max_connection_lifetime(Duration::from_millis(200)),
+    );
+    let bob = make_node([]);
+    connect(&alice, &bob).await;
+
+    let bob_to_alice = bob
+        .endpoint
+        .send(OpenSubstream::single_protocol(
+            alice.peer_id,
+            "/hello-world/1.0.0",
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+
+    // Alice's handler holds on to the substream whilst waiting for Bob's name
+    tokio_extras::time::sleep(Duration::from_secs(1)).await;
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert!(alice_stats.connected_peers.contains(&bob.peer_id));
+
+    drop(bob_to_alice);
+    tokio_extras::time::sleep(Duration::from_secs(1)).await;
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    let alice_reasons = alice
+        .subscriber_stats
+        .send(GetDisconnectReasons)
+        .await
+        .unwrap();
+
+    assert!(alice_stats.connected_peers.is_empty());
+    assert_eq!(
+        alice_reasons.get(&bob.peer_id),
+        Some(&endpoint::DisconnectReason::Recycled)
+    );
+}
+
 #[tokio::test]
 async fn subscriber_stats_track_listen_addresses_properly() {
     let alice = make_node([]);
@@ -240,6 +513,33 @@ async fn subscriber_stats_track_connected_peers_properly() {
     // Peers should be disconnected now
 }
 
+#[tokio::test]
+async fn given_peer_proposes_plaintext_then_connection_upgrade_refused() {
+    let alice = make_node([]);
+
+    let port = rand::random::<u16>();
+    alice
+        .endpoint
+        .send(ListenOn(format!("/memory/{port}").parse().unwrap()))
+        .await
+        .unwrap();
+    tokio_extras::time::sleep(Duration::from_millis(200)).await;
+
+    let mut transport = MemoryTransport::default();
+    let stream = transport
+        .dial(format!("/memory/{port}").parse().unwrap())
+        .unwrap()
+        .await
+        .unwrap();
+
+    let result =
+        multistream_select::dialer_select_proto(stream, ["/plaintext/2.0.0"], Version::V1).await;
+
+    assert!(matches!(result, Err(xtra_libp2p::NegotiationError::Failed)));
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert!(alice_stats.connected_peers.is_empty());
+}
+
 #[tokio::test]
 async fn listen_address_is_reflected_in_stats() {
     let (alice, _, listen_address) = alice_and_bob([], []).await;
@@ -252,6 +552,117 @@ async fn listen_address_is_reflected_in_stats() {
     );
 }
 
+#[tokio::test]
+async fn given_two_listeners_on_same_address_when_stopping_one_then_other_keeps_listening() {
+    let alice = make_node([]);
+    let wildcard = "/memory/0".parse::<Multiaddr>().unwrap();
+
+    let first = alice
+        .endpoint
+        .send(ListenOn(wildcard.clone()))
+        .await
+        .unwrap();
+    let second = alice
+        .endpoint
+        .send(ListenOn(wildcard.clone()))
+        .await
+        .unwrap();
+    assert_ne!(first, second);
+
+    tokio_extras::time::sleep(Duration::from_millis(200)).await;
+
+    alice.endpoint.send(StopListening(first)).await.unwrap();
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert_eq!(alice_stats.listen_addresses, HashSet::from([wildcard]));
+
+    alice.endpoint.send(StopListening(second)).await.unwrap();
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert!(alice_stats.listen_addresses.is_empty());
+}
+
+#[tokio::test]
+async fn given_default_policy_then_wildcard_listen_address_expanded_to_interfaces() {
+    let (wildcard, stats) = listen_on_tcp_wildcard(|endpoint| endpoint).await;
+
+    assert!(stats.listen_addresses.contains(&loopback(&wildcard)));
+    assert!(!stats.listen_addresses.contains(&wildcard));
+    assert_eq!(stats.listening_on, HashSet::from([wildcard]));
+}
+
+#[tokio::test]
+async fn given_keep_policy_then_wildcard_listen_address_reported_as_is() {
+    let (wildcard, stats) = listen_on_tcp_wildcard(|endpoint| {
+        endpoint.with_wildcard_listen_policy(WildcardListenPolicy::Keep)
+    })
+    .await;
+
+    assert_eq!(stats.listen_addresses, HashSet::from([wildcard.clone()]));
+    assert_eq!(stats.listening_on, HashSet::from([wildcard]));
+}
+
+#[tokio::test]
+async fn given_suppress_policy_then_only_addresses_announced_by_transport_reported() {
+    let (wildcard, stats) = listen_on_tcp_wildcard(|endpoint| {
+        endpoint.with_wildcard_listen_policy(WildcardListenPolicy::Suppress)
+    })
+    .await;
+
+    assert!(stats.listen_addresses.contains(&loopback(&wildcard)));
+    assert!(!stats.listen_addresses.contains(&wildcard));
+    assert_eq!(stats.listening_on, HashSet::from([wildcard]));
+}
+
+#[tokio::test]
+async fn given_inbound_paused_then_new_connections_rejected_until_resumed() {
+    let alice = make_node([]);
+    let bob = make_node([]);
+
+    let port = rand::random::<u16>();
+    alice
+        .endpoint
+        .send(ListenOn(format!("/memory/{port}").parse().unwrap()))
+        .await
+        .unwrap();
+    let alice_address = format!("/memory/{port}/p2p/{}", alice.peer_id)
+        .parse::<Multiaddr>()
+        .unwrap();
+
+    alice.endpoint.send(PauseInbound).await.unwrap();
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert!(alice_stats.inbound_paused);
+    assert_eq!(
+        alice_stats.listen_addresses.len(),
+        1,
+        "Listener keeps running"
+    );
+
+    bob.endpoint
+        .send(Connect(alice_address.clone()))
+        .await
+        .unwrap()
+        .unwrap();
+    tokio_extras::time::sleep(Duration::from_millis(500)).await;
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert!(alice_stats.connected_peers.is_empty());
+
+    alice.endpoint.send(ResumeInbound).await.unwrap();
+
+    bob.endpoint
+        .send(Connect(alice_address))
+        .await
+        .unwrap()
+        .unwrap();
+    tokio_extras::time::sleep(Duration::from_millis(500)).await;
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert!(!alice_stats.inbound_paused);
+    assert_eq!(alice_stats.connected_peers, HashSet::from([bob.peer_id]));
+}
+
 #[tokio::test]
 async fn cannot_open_substream_for_unhandled_protocol() {
     let (alice, bob, _) = alice_and_bob([], []).await;
@@ -275,30 +686,390 @@ async fn cannot_open_substream_for_unhandled_protocol() {
 }
 
 #[tokio::test]
-async fn cannot_connect_twice() {
-    let (alice, bob, alice_listen) = alice_and_bob([], []).await;
+async fn has_handler_only_for_registered_protocols() {
+    let hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let alice = make_node([("/hello-world/1.0.0", hello_world_handler.into())]);
 
-    let error = bob
+    let has_hello_world = alice
         .endpoint
-        .send(Connect(
-            alice_listen.with(Protocol::P2p(alice.peer_id.into())),
-        ))
+        .send(HasHandler("/hello-world/1.0.0"))
         .await
-        .unwrap()
-        .unwrap_err();
+        .unwrap();
+    let has_unknown = alice
+        .endpoint
+        .send(HasHandler("/unknown/1.0.0"))
+        .await
+        .unwrap();
 
-    assert!(matches!(
-        error,
-        xtra_libp2p::Error::AlreadyTryingToConnected(twin) if twin == alice.peer_id
-    ))
+    assert!(has_hello_world);
+    assert!(!has_unknown);
 }
 
 #[tokio::test]
-async fn chooses_first_protocol_in_list_of_multiple() {
-    let alice_hello_world_handler = HelloWorld::default().create(None).spawn_global();
-    let (alice, bob, _) = alice_and_bob(
-        [(
-            "/hello-world/1.0.0",
+async fn given_bob_dialed_alice_when_resetting_connection_then_reconnected() {
+    let (alice, bob, _) = alice_and_bob([], []).await;
+
+    bob.endpoint
+        .send(ResetConnection(alice.peer_id))
+        .await
+        .unwrap()
+        .unwrap();
+
+    tokio_extras::time::sleep(Duration::from_millis(200)).await;
+
+    let bob_stats = bob.endpoint.send(GetConnectionStats).await.unwrap();
+    assert_eq!(bob_stats.connected_peers, HashSet::from([alice.peer_id]));
+}
+
+#[tokio::test]
+async fn given_alice_never_dialed_bob_when_resetting_connection_then_only_disconnected() {
+    let (alice, bob, _) = alice_and_bob([], []).await;
+
+    let error = alice
+        .endpoint
+        .send(ResetConnection(bob.peer_id))
+        .await
+        .unwrap()
+        .unwrap_err();
+
+    assert!(matches!(error, xtra_libp2p::Error::NoKnownAddress(peer_id) if peer_id == bob.peer_id));
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert!(alice_stats.connected_peers.is_empty());
+}
+
+#[tokio::test]
+async fn given_known_peer_id_when_connecting_to_bare_address_then_connected() {
+    let alice = make_node([]);
+    let bob = make_node([]);
+
+    let port = rand::random::<u16>();
+    let alice_listen = format!("/memory/{port}").parse::<Multiaddr>().unwrap();
+    alice
+        .endpoint
+        .send(ListenOn(alice_listen.clone()))
+        .await
+        .unwrap();
+
+    bob.endpoint
+        .send(ConnectWithPeerId {
+            peer_id: alice.peer_id,
+            address: alice_listen,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    tokio_extras::time::sleep(Duration::from_millis(200)).await;
+
+    let bob_stats = bob.endpoint.send(GetConnectionStats).await.unwrap();
+    assert_eq!(bob_stats.connected_peers, HashSet::from([alice.peer_id]));
+}
+
+#[tokio::test]
+async fn given_address_of_other_peer_when_connecting_with_peer_id_then_error() {
+    let alice = make_node([]);
+    let bob = make_node([]);
+
+    let address = format!("/memory/1000/p2p/{}", bob.peer_id)
+        .parse::<Multiaddr>()
+        .unwrap();
+
+    let error = bob
+        .endpoint
+        .send(ConnectWithPeerId {
+            peer_id: alice.peer_id,
+            address,
+        })
+        .await
+        .unwrap()
+        .unwrap_err();
+
+    assert!(matches!(error, xtra_libp2p::Error::PeerIdMismatch { .. }));
+}
+
+#[tokio::test]
+async fn given_endpoint_with_two_transports_then_reachable_over_both() {
+    let alice_id = Keypair::generate_ed25519();
+    let alice_peer_id = alice_id.public().to_peer_id();
+    let alice_endpoint = Endpoint::new(
+        Box::new(|| MemoryTransport::default().or_transport(TokioTcpConfig::new())),
+        alice_id,
+        Duration::from_secs(20),
+        [],
+        Subscribers::default(),
+        Arc::new(HashSet::new()),
+    )
+    .create(None)
+    .spawn_global();
+
+    let memory_port = rand::random::<u16>();
+    alice_endpoint
+        .send(ListenOn(format!("/memory/{memory_port}").parse().unwrap()))
+        .await
+        .unwrap();
+    alice_endpoint
+        .send(ListenOn("/ip4/127.0.0.1/tcp/0".parse().unwrap()))
+        .await
+        .unwrap();
+    let tcp_address = tcp_listen_address(&alice_endpoint).await;
+
+    let memory_bob = make_node([]);
+    memory_bob
+        .endpoint
+        .send(Connect(
+            format!("/memory/{memory_port}/p2p/{alice_peer_id}")
+                .parse()
+                .unwrap(),
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let (_, tcp_bob) =
+        make_configured_endpoint::<_, 0>(TokioTcpConfig::new, [], |endpoint| endpoint);
+    tcp_bob
+        .send(Connect(
+            tcp_address.with(Protocol::P2p(alice_peer_id.into())),
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+
+    tokio_extras::time::sleep(Duration::from_millis(500)).await;
+
+    let alice_stats = alice_endpoint.send(GetConnectionStats).await.unwrap();
+    assert_eq!(alice_stats.connected_peers.len(), 2);
+}
+
+#[tokio::test]
+async fn given_connection_gate_rejects_peer_when_peer_dials_then_not_connected() {
+    let (alice_peer_id, alice_endpoint) =
+        make_configured_endpoint(MemoryTransport::default, [], reject_all_connections);
+    let bob = make_node([]);
+
+    let port = rand::random::<u16>();
+    alice_endpoint
+        .send(ListenOn(format!("/memory/{port}").parse().unwrap()))
+        .await
+        .unwrap();
+
+    bob.endpoint
+        .send(Connect(
+            format!("/memory/{port}/p2p/{alice_peer_id}")
+                .parse()
+                .unwrap(),
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+    tokio_extras::time::sleep(Duration::from_millis(200)).await;
+
+    let alice_stats = alice_endpoint.send(GetConnectionStats).await.unwrap();
+    let bob_stats = bob.endpoint.send(GetConnectionStats).await.unwrap();
+
+    assert_eq!(alice_stats.connected_peers, HashSet::from([]));
+    assert_eq!(bob_stats.connected_peers, HashSet::from([]));
+}
+
+#[tokio::test]
+async fn given_connection_gate_rejects_peer_when_dialing_peer_then_connection_rejected() {
+    let (_, alice_endpoint) =
+        make_configured_endpoint(MemoryTransport::default, [], reject_all_connections);
+    let bob = make_node([]);
+
+    let port = rand::random::<u16>();
+    let bob_listen = format!("/memory/{port}").parse::<Multiaddr>().unwrap();
+    bob.endpoint
+        .send(ListenOn(bob_listen.clone()))
+        .await
+        .unwrap();
+
+    let error = alice_endpoint
+        .send(ConnectAndWait(
+            bob_listen.with(Protocol::P2p(bob.peer_id.into())),
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap_err();
+
+    assert!(matches!(
+        error,
+        xtra_libp2p::Error::ConnectionRejected { peer_id, .. } if peer_id == bob.peer_id
+    ));
+}
+
+#[tokio::test]
+async fn given_deferred_listening_then_only_reachable_after_start() {
+    let (alice_peer_id, alice_endpoint) = make_configured_endpoint(
+        MemoryTransport::default,
+        [],
+        Endpoint::with_deferred_listening,
+    );
+    let bob = make_node([]);
+
+    let port = rand::random::<u16>();
+    let alice_address = format!("/memory/{port}/p2p/{alice_peer_id}")
+        .parse::<Multiaddr>()
+        .unwrap();
+    alice_endpoint
+        .send(ListenOn(format!("/memory/{port}").parse().unwrap()))
+        .await
+        .unwrap();
+
+    let result = bob
+        .endpoint
+        .send(Connect(alice_address.clone()))
+        .await
+        .unwrap();
+    assert!(result.is_err(), "Alice should not be listening yet");
+
+    alice_endpoint.send(StartListening).await.unwrap();
+    tokio_extras::time::sleep(Duration::from_millis(200)).await;
+
+    bob.endpoint
+        .send(Connect(alice_address))
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn debug_snapshot_reflects_connections_and_listeners() {
+    let (alice, bob, alice_listen) = alice_and_bob([], []).await;
+
+    let alice_snapshot = alice.endpoint.send(GetDebugSnapshot).await.unwrap();
+    let bob_snapshot = bob.endpoint.send(GetDebugSnapshot).await.unwrap();
+
+    assert_eq!(alice_snapshot.peer_id, alice.peer_id.to_string());
+    assert_eq!(alice_snapshot.connections.len(), 1);
+    assert_eq!(
+        alice_snapshot.connections[0].peer_id,
+        bob.peer_id.to_string()
+    );
+    assert!(!alice_snapshot.connections[0].dialer);
+    assert!(alice_snapshot
+        .listen_addresses
+        .values()
+        .any(|address| address == &alice_listen.to_string()));
+
+    assert_eq!(bob_snapshot.connections.len(), 1);
+    assert!(bob_snapshot.connections[0].dialer);
+    assert!(bob_snapshot
+        .last_dialed_addresses
+        .contains_key(&alice.peer_id.to_string()));
+    assert!(bob_snapshot.inflight_connections.is_empty());
+
+    serde_json::to_string(&alice_snapshot).expect("snapshot to be serializable");
+}
+
+#[tokio::test]
+async fn given_quarantined_peer_then_refused_until_quarantine_ends() {
+    let (alice, bob, alice_listen) = alice_and_bob([], []).await;
+    let alice_address = alice_listen.with(Protocol::P2p(alice.peer_id.into()));
+    let bob_address = format!("/memory/{}/p2p/{}", rand::random::<u16>(), bob.peer_id)
+        .parse::<Multiaddr>()
+        .unwrap();
+
+    alice
+        .endpoint
+        .send(Quarantine {
+            peer: bob.peer_id,
+            duration: Duration::from_millis(500),
+        })
+        .await
+        .unwrap();
+    tokio_extras::time::sleep(Duration::from_millis(100)).await;
+
+    let alice_snapshot = alice.endpoint.send(GetDebugSnapshot).await.unwrap();
+    assert!(alice_snapshot
+        .quarantined_peers
+        .contains_key(&bob.peer_id.to_string()));
+
+    let error = alice
+        .endpoint
+        .send(Connect(bob_address))
+        .await
+        .unwrap()
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        xtra_libp2p::Error::Quarantined { peer_id, .. } if peer_id == bob.peer_id
+    ));
+
+    let _ = bob
+        .endpoint
+        .send(Connect(alice_address.clone()))
+        .await
+        .unwrap();
+    tokio_extras::time::sleep(Duration::from_millis(200)).await;
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert_eq!(alice_stats.connected_peers, HashSet::from([]));
+
+    tokio_extras::time::sleep(Duration::from_millis(300)).await;
+
+    bob.endpoint
+        .send(Connect(alice_address))
+        .await
+        .unwrap()
+        .unwrap();
+    tokio_extras::time::sleep(Duration::from_millis(200)).await;
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert_eq!(alice_stats.connected_peers, HashSet::from([bob.peer_id]));
+}
+
+#[tokio::test]
+async fn cannot_connect_twice() {
+    let (alice, bob, alice_listen) = alice_and_bob([], []).await;
+
+    let error = bob
+        .endpoint
+        .send(Connect(
+            alice_listen.with(Protocol::P2p(alice.peer_id.into())),
+        ))
+        .await
+        .unwrap()
+        .unwrap_err();
+
+    assert!(matches!(
+        error,
+        xtra_libp2p::Error::AlreadyTryingToConnected(twin) if twin == alice.peer_id
+    ))
+}
+
+#[tokio::test]
+async fn cannot_connect_to_ourselves() {
+    let alice = make_node([]);
+
+    let port = rand::random::<u16>();
+    let alice_peer_id = alice.peer_id;
+    let address = format!("/memory/{port}/p2p/{alice_peer_id}")
+        .parse::<Multiaddr>()
+        .unwrap();
+
+    let error = alice
+        .endpoint
+        .send(Connect(address.clone()))
+        .await
+        .unwrap()
+        .unwrap_err();
+
+    assert!(matches!(error, xtra_libp2p::Error::SelfDial(self_address) if self_address == address));
+
+    let stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert!(stats.connected_peers.is_empty());
+}
+
+#[tokio::test]
+async fn chooses_first_protocol_in_list_of_multiple() {
+    let alice_hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let (alice, bob, _) = alice_and_bob(
+        [(
+            "/hello-world/1.0.0",
             alice_hello_world_handler.clone().into(),
         )],
         [],
@@ -320,40 +1091,479 @@ async fn chooses_first_protocol_in_list_of_multiple() {
         .await
         .unwrap();
 
-    assert_eq!(actual_protocol, "/hello-world/1.0.0");
+    assert_eq!(actual_protocol, "/hello-world/1.0.0");
+}
+
+#[cfg_attr(debug_assertions, tokio::test)] // The assertion for duplicate handlers only runs in debug mode.
+#[should_panic(expected = "Duplicate handler declared for protocol /hello-world/1.0.0")]
+async fn disallow_duplicate_handlers() {
+    let hello_world_handler = HelloWorld::default().create(None).spawn_global();
+
+    make_node([
+        ("/hello-world/1.0.0", hello_world_handler.clone().into()),
+        ("/hello-world/1.0.0", hello_world_handler.into()),
+    ]);
+}
+
+#[tokio::test]
+async fn falls_back_to_next_protocol_if_unsupported() {
+    let alice_hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let (alice, bob, _) = alice_and_bob(
+        [(
+            "/hello-world/1.0.0",
+            alice_hello_world_handler.clone().into(),
+        )],
+        [],
+    )
+    .await;
+
+    let (actual_protocol, _) = bob
+        .endpoint
+        .send(OpenSubstream::multiple_protocols(
+            alice.peer_id,
+            vec![
+                "/foo-bar/1.0.0", // This is unsupported by Alice.
+                "/hello-world/1.0.0",
+            ],
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+
+    assert_eq!(actual_protocol, "/hello-world/1.0.0");
+}
+
+#[tokio::test]
+async fn given_alice_knows_bob_does_not_support_hello_world_when_dial_then_fail_early() {
+    let (alice, bob, _) = alice_and_bob([], []).await;
+
+    alice
+        .endpoint
+        .send(RegisterListenProtocols {
+            peer_id: bob.peer_id,
+            listen_protocols: HashSet::default(), // Bob doesn't support any protocols!
+        })
+        .await
+        .unwrap();
+
+    let res = alice
+        .endpoint
+        .send(OpenSubstream::single_protocol(
+            bob.peer_id,
+            "/hello-world/1.0.0",
+        ))
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        res,
+        Err(xtra_libp2p::Error::ProtocolNotSupportedByPeer)
+    ));
+}
+
+#[tokio::test]
+async fn given_alice_knows_bob_supports_none_of_the_protocols_when_dial_multiple_then_fail_early() {
+    let (alice, bob, _) = alice_and_bob([], []).await;
+
+    alice
+        .endpoint
+        .send(RegisterListenProtocols {
+            peer_id: bob.peer_id,
+            listen_protocols: HashSet::from(["/foo-bar/1.0.0".to_string()]),
+        })
+        .await
+        .unwrap();
+
+    let res = alice
+        .endpoint
+        .send(OpenSubstream::multiple_protocols(
+            bob.peer_id,
+            vec!["/hello-world/1.0.0", "/hello-world/2.0.0"],
+        ))
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        res,
+        Err(xtra_libp2p::Error::ProtocolNotSupportedByPeer)
+    ));
+}
+
+#[tokio::test]
+async fn given_alice_knows_bob_does_support_hello_world_when_dial_then_open_substream() {
+    let hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let (alice, bob, _) = alice_and_bob(
+        [],
+        [("/hello-world/1.0.0", hello_world_handler.clone().into())],
+    )
+    .await;
+
+    alice
+        .endpoint
+        .send(RegisterListenProtocols {
+            peer_id: bob.peer_id,
+            listen_protocols: HashSet::from_iter(["/hello-world/1.0.0".to_string()]),
+        })
+        .await
+        .unwrap();
+
+    let res = alice
+        .endpoint
+        .send(OpenSubstream::single_protocol(
+            bob.peer_id,
+            "/hello-world/1.0.0",
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await;
+
+    assert!(matches!(res, Ok(_)));
+}
+
+#[tokio::test]
+async fn given_deadline_elapses_when_opening_substream_then_deadline_error() {
+    let alice_hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let (alice, bob, _) = alice_and_bob(
+        [(
+            "/hello-world/1.0.0",
+            alice_hello_world_handler.clone().into(),
+        )],
+        [],
+    )
+    .await;
+
+    let error = bob
+        .endpoint
+        .open_substream_with_deadline(
+            OpenSubstream::single_protocol(alice.peer_id, "/hello-world/1.0.0"),
+            Duration::ZERO,
+        )
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, xtra_libp2p::Error::Deadline(_)));
+}
+
+#[tokio::test]
+async fn given_stuck_handler_when_delivery_times_out_then_other_protocols_keep_working() {
+    let stuck_handler = Stuck.create(Some(1)).spawn_global();
+    let hello_world_handler = HelloWorld::default().create(None).spawn_global();
+
+    let (alice_peer_id, alice_endpoint) = make_configured_endpoint(
+        MemoryTransport::default,
+        InboundSubstreamHandlers::new()
+            .with("/stuck/1.0.0", stuck_handler)
+            .with("/hello-world/1.0.0", hello_world_handler)
+            .build::<2>()
+            .unwrap(),
+        |endpoint| endpoint.with_inbound_substream_timeout(Duration::from_millis(200)),
+    );
+    let bob = make_node([]);
+
+    let port = rand::random::<u16>();
+    alice_endpoint
+        .send(ListenOn(format!("/memory/{port}").parse().unwrap()))
+        .await
+        .unwrap();
+    bob.endpoint
+        .send(Connect(
+            format!("/memory/{port}/p2p/{alice_peer_id}")
+                .parse()
+                .unwrap(),
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The first substream blocks the handler, the second fills its mailbox and the third cannot
+    // be delivered anymore.
+    let mut stuck_substreams = Vec::new();
+    for _ in 0..3 {
+        let substream = bob
+            .endpoint
+            .send(OpenSubstream::single_protocol(
+                alice_peer_id,
+                "/stuck/1.0.0",
+            ))
+            .await
+            .unwrap()
+            .unwrap()
+            .await
+            .unwrap();
+        stuck_substreams.push(substream);
+    }
+
+    let bob_to_alice = bob
+        .endpoint
+        .send(OpenSubstream::single_protocol(
+            alice_peer_id,
+            "/hello-world/1.0.0",
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+    let string = hello_world_dialer(bob_to_alice, "Bob").await.unwrap();
+
+    assert_eq!(string, "Hello Bob!");
+}
+
+#[tokio::test]
+async fn given_substream_timings_enabled_then_timings_recorded_per_peer_and_protocol() {
+    let hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let alice = make_node([("/hello-world/1.0.0", hello_world_handler.into())]);
+    let (_, bob_endpoint) = make_configured_endpoint(
+        MemoryTransport::default,
+        [],
+        Endpoint::with_substream_timings,
+    );
+
+    let port = rand::random::<u16>();
+    alice
+        .endpoint
+        .send(ListenOn(format!("/memory/{port}").parse().unwrap()))
+        .await
+        .unwrap();
+    bob_endpoint
+        .send(Connect(
+            format!("/memory/{port}/p2p/{}", alice.peer_id)
+                .parse()
+                .unwrap(),
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let bob_to_alice = bob_endpoint
+        .send(OpenSubstream::single_protocol(
+            alice.peer_id,
+            "/hello-world/1.0.0",
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+    hello_world_dialer(bob_to_alice, "Bob").await.unwrap();
+
+    let timings = bob_endpoint.send(GetSubstreamTimings).await.unwrap();
+    let timings = timings
+        .get(&(alice.peer_id, "/hello-world/1.0.0"))
+        .expect("timings to be recorded");
+
+    assert!(timings.time_to_first_byte.is_some());
+    assert!(timings.duration.is_some());
+}
+
+#[tokio::test]
+async fn given_connection_dropped_then_per_peer_state_forgotten_except_last_seen() {
+    let hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let alice = make_node([("/hello-world/1.0.0", hello_world_handler.into())]);
+    let (_, bob_endpoint) = make_configured_endpoint(
+        MemoryTransport::default,
+        [],
+        Endpoint::with_substream_timings,
+    );
+
+    let port = rand::random::<u16>();
+    alice
+        .endpoint
+        .send(ListenOn(format!("/memory/{port}").parse().unwrap()))
+        .await
+        .unwrap();
+    bob_endpoint
+        .send(Connect(
+            format!("/memory/{port}/p2p/{}", alice.peer_id)
+                .parse()
+                .unwrap(),
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let bob_to_alice = bob_endpoint
+        .send(OpenSubstream::single_protocol(
+            alice.peer_id,
+            "/hello-world/1.0.0",
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+    hello_world_dialer(bob_to_alice, "Bob").await.unwrap();
+
+    bob_endpoint.send(Disconnect(alice.peer_id)).await.unwrap();
+
+    let timings = bob_endpoint.send(GetSubstreamTimings).await.unwrap();
+    let bob_snapshot = bob_endpoint.send(GetDebugSnapshot).await.unwrap();
+    let last_seen = bob_endpoint.send(GetLastSeen).await.unwrap();
+
+    assert!(timings.is_empty());
+    assert!(bob_snapshot.last_dialed_addresses.is_empty());
+    assert!(last_seen.contains_key(&alice.peer_id));
+}
+
+#[tokio::test]
+async fn given_open_substream_then_counted_until_dropped() {
+    let hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let (alice, bob, _) =
+        alice_and_bob([("/hello-world/1.0.0", hello_world_handler.into())], []).await;
+
+    let bob_to_alice = bob
+        .endpoint
+        .send(OpenSubstream::single_protocol(
+            alice.peer_id,
+            "/hello-world/1.0.0",
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+
+    let bob_stats = bob.endpoint.send(GetSubstreamStats).await.unwrap();
+    assert_eq!(bob_stats.outbound_open, 1);
+    assert_eq!(bob_stats.inbound_open, 0);
+    assert_eq!(
+        bob_stats.per_protocol,
+        HashMap::from([("/hello-world/1.0.0", 1)])
+    );
+
+    // Alice's handler holds on to the substream whilst waiting for Bob's name
+    while alice
+        .endpoint
+        .send(GetSubstreamStats)
+        .await
+        .unwrap()
+        .inbound_open
+        == 0
+    {
+        tokio_extras::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    drop(bob_to_alice);
+
+    let bob_stats = bob.endpoint.send(GetSubstreamStats).await.unwrap();
+    assert_eq!(bob_stats, SubstreamStats::default());
 }
 
-#[cfg_attr(debug_assertions, tokio::test)] // The assertion for duplicate handlers only runs in debug mode.
-#[should_panic(expected = "Duplicate handler declared for protocol /hello-world/1.0.0")]
-async fn disallow_duplicate_handlers() {
-    let hello_world_handler = HelloWorld::default().create(None).spawn_global();
+#[tokio::test]
+async fn given_alice_rotates_identity_then_bob_can_only_connect_with_new_peer_id() {
+    let (alice, bob, alice_listen) = alice_and_bob([], []).await;
 
-    make_node([
-        ("/hello-world/1.0.0", hello_world_handler.clone().into()),
-        ("/hello-world/1.0.0", hello_world_handler.into()),
-    ]);
+    let new_identity = Keypair::generate_ed25519();
+    let new_alice_peer_id = new_identity.public().to_peer_id();
+    alice
+        .endpoint
+        .send(RotateIdentity(new_identity))
+        .await
+        .unwrap();
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    assert!(alice_stats.connected_peers.is_empty());
+
+    // Wait for Alice to listen again
+    tokio_extras::time::sleep(Duration::from_millis(500)).await;
+
+    let result = bob
+        .endpoint
+        .send(Connect(
+            alice_listen
+                .clone()
+                .with(Protocol::P2p(alice.peer_id.into())),
+        ))
+        .await
+        .unwrap();
+    assert!(result.is_ok(), "Dial is only attempted in the background");
+
+    tokio_extras::time::sleep(Duration::from_millis(500)).await;
+    let bob_stats = bob.endpoint.send(GetConnectionStats).await.unwrap();
+    assert!(!bob_stats.connected_peers.contains(&alice.peer_id));
+
+    bob.endpoint
+        .send(Connect(
+            alice_listen.with(Protocol::P2p(new_alice_peer_id.into())),
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    let bob_stats = bob.endpoint.send(GetConnectionStats).await.unwrap();
+
+    assert_eq!(alice_stats.connected_peers, HashSet::from([bob.peer_id]));
+    assert_eq!(
+        bob_stats.connected_peers,
+        HashSet::from([new_alice_peer_id])
+    );
 }
 
 #[tokio::test]
-async fn falls_back_to_next_protocol_if_unsupported() {
+async fn given_alice_and_bob_dial_each_other_simultaneously_then_one_connection_remains() {
     let alice_hello_world_handler = HelloWorld::default().create(None).spawn_global();
-    let (alice, bob, _) = alice_and_bob(
-        [(
-            "/hello-world/1.0.0",
-            alice_hello_world_handler.clone().into(),
-        )],
-        [],
-    )
-    .await;
+    let bob_hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let alice = make_node([(
+        "/hello-world/1.0.0",
+        alice_hello_world_handler.clone().into(),
+    )]);
+    let bob = make_node([("/hello-world/1.0.0", bob_hello_world_handler.clone().into())]);
+
+    let alice_port = rand::random::<u16>();
+    let bob_port = rand::random::<u16>();
+    for (node, port) in [(&alice, alice_port), (&bob, bob_port)] {
+        node.endpoint
+            .send(ListenOn(format!("/memory/{port}").parse().unwrap()))
+            .await
+            .unwrap();
+    }
 
-    let (actual_protocol, _) = bob
+    let alice_peer_id = alice.peer_id;
+    let bob_peer_id = bob.peer_id;
+    let (alice_dial, bob_dial) = futures::join!(
+        alice.endpoint.send(Connect(
+            format!("/memory/{bob_port}/p2p/{bob_peer_id}")
+                .parse()
+                .unwrap(),
+        )),
+        bob.endpoint.send(Connect(
+            format!("/memory/{alice_port}/p2p/{alice_peer_id}")
+                .parse()
+                .unwrap(),
+        )),
+    );
+    alice_dial.unwrap().unwrap();
+    bob_dial.unwrap().unwrap();
+
+    // Let both dials complete and the redundant connection be closed
+    tokio_extras::time::sleep(Duration::from_millis(500)).await;
+
+    let alice_stats = alice.endpoint.send(GetConnectionStats).await.unwrap();
+    let bob_stats = bob.endpoint.send(GetConnectionStats).await.unwrap();
+    assert_eq!(alice_stats.connected_peers, HashSet::from([bob.peer_id]));
+    assert_eq!(bob_stats.connected_peers, HashSet::from([alice.peer_id]));
+
+    // Both parties must have kept the same connection for it to be usable in both directions
+    let alice_to_bob = alice
         .endpoint
-        .send(OpenSubstream::multiple_protocols(
+        .send(OpenSubstream::single_protocol(
+            bob.peer_id,
+            "/hello-world/1.0.0",
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+    let bob_to_alice = bob
+        .endpoint
+        .send(OpenSubstream::single_protocol(
             alice.peer_id,
-            vec![
-                "/foo-bar/1.0.0", // This is unsupported by Alice.
-                "/hello-world/1.0.0",
-            ],
+            "/hello-world/1.0.0",
         ))
         .await
         .unwrap()
@@ -361,26 +1571,99 @@ async fn falls_back_to_next_protocol_if_unsupported() {
         .await
         .unwrap();
 
-    assert_eq!(actual_protocol, "/hello-world/1.0.0");
+    assert_eq!(
+        hello_world_dialer(alice_to_bob, "Alice").await.unwrap(),
+        "Hello Alice!"
+    );
+    assert_eq!(
+        hello_world_dialer(bob_to_alice, "Bob").await.unwrap(),
+        "Hello Bob!"
+    );
 }
 
 #[tokio::test]
-async fn given_alice_knows_bob_does_not_support_hello_world_when_dial_then_fail_early() {
-    let (alice, bob, _) = alice_and_bob([], []).await;
+async fn given_connect_and_wait_resolved_then_substream_can_be_opened_right_away() {
+    let alice_hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let alice = make_node([(
+        "/hello-world/1.0.0",
+        alice_hello_world_handler.clone().into(),
+    )]);
+    let bob = make_node([]);
 
+    let port = rand::random::<u16>();
+    let alice_listen = format!("/memory/{port}").parse::<Multiaddr>().unwrap();
     alice
         .endpoint
-        .send(RegisterListenProtocols {
-            peer_id: bob.peer_id,
-            listen_protocols: HashSet::default(), // Bob doesn't support any protocols!
-        })
+        .send(ListenOn(alice_listen.clone()))
         .await
         .unwrap();
 
-    let res = alice
+    bob.endpoint
+        .send(ConnectAndWait(
+            alice_listen.with(Protocol::P2p(alice.peer_id.into())),
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+
+    let bob_to_alice = bob
         .endpoint
         .send(OpenSubstream::single_protocol(
-            bob.peer_id,
+            alice.peer_id,
+            "/hello-world/1.0.0",
+        ))
+        .await
+        .unwrap()
+        .unwrap()
+        .await
+        .unwrap();
+
+    let string = hello_world_dialer(bob_to_alice, "Bob").await.unwrap();
+    assert_eq!(string, "Hello Bob!");
+}
+
+#[tokio::test]
+async fn given_dial_in_flight_when_opening_substream_then_still_connecting() {
+    let alice = make_node([]);
+    let (_, bob_endpoint) =
+        make_configured_endpoint::<_, 0>(MemoryTransport::default, [], |endpoint| {
+            endpoint.with_max_concurrent_dials(NonZeroUsize::new(1).unwrap())
+        });
+
+    // The only dial slot is taken by a dial to a listener which never completes the handshake,
+    // thus the dial to Alice stays queued
+    let mut black_hole = MemoryTransport::default()
+        .listen_on("/memory/0".parse().unwrap())
+        .unwrap();
+    let black_hole_address = match black_hole.next().await {
+        Some(Ok(ListenerEvent::NewAddress(address))) => address,
+        _ => panic!("Memory transport did not report listen address"),
+    };
+    let black_hole_address = format!("{black_hole_address}/p2p/{}", PeerId::random())
+        .parse::<Multiaddr>()
+        .unwrap();
+    bob_endpoint
+        .send(Connect(black_hole_address))
+        .await
+        .unwrap()
+        .unwrap();
+    let _black_hole_connection = match black_hole.next().await {
+        Some(Ok(ListenerEvent::Upgrade { upgrade, .. })) => upgrade,
+        _ => panic!("Dial did not reach the listener"),
+    };
+
+    let port = rand::random::<u16>();
+    let alice_peer_id = alice.peer_id;
+    let address = format!("/memory/{port}/p2p/{alice_peer_id}")
+        .parse::<Multiaddr>()
+        .unwrap();
+    bob_endpoint.send(Connect(address)).await.unwrap().unwrap();
+
+    let res = bob_endpoint
+        .send(OpenSubstream::single_protocol(
+            alice.peer_id,
             "/hello-world/1.0.0",
         ))
         .await
@@ -388,40 +1671,145 @@ async fn given_alice_knows_bob_does_not_support_hello_world_when_dial_then_fail_
 
     assert!(matches!(
         res,
-        Err(xtra_libp2p::Error::ProtocolNotSupportedByPeer)
+        Err(xtra_libp2p::Error::StillConnecting(peer_id)) if peer_id == alice.peer_id
     ));
 }
 
 #[tokio::test]
-async fn given_alice_knows_bob_does_support_hello_world_when_dial_then_open_substream() {
-    let hello_world_handler = HelloWorld::default().create(None).spawn_global();
-    let (alice, bob, _) = alice_and_bob(
-        [],
-        [("/hello-world/1.0.0", hello_world_handler.clone().into())],
-    )
-    .await;
+async fn given_nobody_listens_when_connect_and_wait_then_dial_failed() {
+    let alice = make_node([]);
+    let bob = make_node([]);
 
-    alice
+    let port = rand::random::<u16>();
+    let alice_peer_id = alice.peer_id;
+    let address = format!("/memory/{port}/p2p/{alice_peer_id}")
+        .parse::<Multiaddr>()
+        .unwrap();
+
+    let error = bob
         .endpoint
-        .send(RegisterListenProtocols {
-            peer_id: bob.peer_id,
-            listen_protocols: HashSet::from_iter(["/hello-world/1.0.0".to_string()]),
-        })
+        .send(ConnectAndWait(address))
+        .await
+        .unwrap()
+        .unwrap()
         .await
+        .unwrap_err();
+
+    assert!(matches!(
+        error,
+        xtra_libp2p::Error::DialFailed { peer_id, .. } if peer_id == alice.peer_id
+    ));
+}
+
+#[tokio::test]
+async fn given_nobody_listens_when_dialing_then_dial_failure_reported() {
+    let alice = make_node([]);
+    let bob = make_node([]);
+
+    let port = rand::random::<u16>();
+    let alice_peer_id = alice.peer_id;
+    let address = format!("/memory/{port}/p2p/{alice_peer_id}")
+        .parse::<Multiaddr>()
         .unwrap();
 
-    let res = alice
-        .endpoint
-        .send(OpenSubstream::single_protocol(
-            bob.peer_id,
-            "/hello-world/1.0.0",
-        ))
+    bob.endpoint
+        .send(Connect(address.clone()))
         .await
         .unwrap()
+        .unwrap();
+
+    tokio_extras::time::sleep(Duration::from_millis(500)).await;
+
+    let dial_failures = bob.subscriber_stats.send(GetDialFailures).await.unwrap();
+    assert_eq!(dial_failures, vec![(alice.peer_id, address)]);
+}
+
+/// Create an endpoint that is configured beyond what [`make_node`] offers.
+fn make_configured_endpoint<T, const N: usize>(
+    transport: fn() -> T,
+    inbound_substream_handlers: [(&'static str, MessageChannel<NewInboundSubstream, ()>); N],
+    configure: fn(Endpoint) -> Endpoint,
+) -> (PeerId, xtra::Address<Endpoint>)
+where
+    T: Transport + Send + Sync + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Send + Sync,
+    T::Listener: Send + 'static,
+    T::Dial: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+{
+    let id = Keypair::generate_ed25519();
+    let peer_id = id.public().to_peer_id();
+
+    let endpoint = configure(Endpoint::new(
+        Box::new(transport),
+        id,
+        Duration::from_secs(20),
+        inbound_substream_handlers,
+        Subscribers::default(),
+        Arc::new(HashSet::new()),
+    ))
+    .create(None)
+    .spawn_global();
+
+    (peer_id, endpoint)
+}
+
+fn reject_all_connections(endpoint: Endpoint) -> Endpoint {
+    endpoint.with_connection_gate(Box::new(|_| {
+        async { Err("Too many CFDs".to_owned()) }.boxed()
+    }))
+}
+
+/// Listen on `/ip4/0.0.0.0/tcp/<port>` and return the address alongside the resulting stats.
+async fn listen_on_tcp_wildcard(
+    configure: fn(Endpoint) -> Endpoint,
+) -> (Multiaddr, ConnectionStats) {
+    let (_, endpoint) = make_configured_endpoint::<_, 0>(TokioTcpConfig::new, [], configure);
+
+    // Listening on port 0 would leave reporting the addresses to the transport, thus we ask the
+    // OS for a free port upfront
+    let port = std::net::TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .unwrap()
+        .local_addr()
         .unwrap()
-        .await;
+        .port();
+    let wildcard = format!("/ip4/0.0.0.0/tcp/{port}")
+        .parse::<Multiaddr>()
+        .unwrap();
+    endpoint.send(ListenOn(wildcard.clone())).await.unwrap();
 
-    assert!(matches!(res, Ok(_)));
+    // Wait for the transport to announce the addresses of the local interfaces
+    tokio_extras::time::sleep(Duration::from_millis(500)).await;
+
+    let stats = endpoint.send(GetConnectionStats).await.unwrap();
+
+    (wildcard, stats)
+}
+
+/// Wait until the endpoint reports the address the transport picked for a `/tcp/0` listener.
+async fn tcp_listen_address(endpoint: &xtra::Address<Endpoint>) -> Multiaddr {
+    loop {
+        let stats = endpoint.send(GetConnectionStats).await.unwrap();
+        let tcp_address = stats.listen_addresses.into_iter().find(|address| {
+            address
+                .iter()
+                .any(|protocol| matches!(protocol, Protocol::Tcp(_)))
+        });
+
+        if let Some(tcp_address) = tcp_address {
+            return tcp_address;
+        }
+
+        tokio_extras::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// The loopback address of the given wildcard address.
+fn loopback(wildcard: &Multiaddr) -> Multiaddr {
+    wildcard
+        .replace(0, |_| Some(Protocol::Ip4(Ipv4Addr::LOCALHOST)))
+        .unwrap()
 }
 
 async fn alice_and_bob<const AN: usize, const BN: usize>(
@@ -455,6 +1843,37 @@ async fn alice_and_bob<const AN: usize, const BN: usize>(
     (alice, bob, alice_listen)
 }
 
+/// Let `dialer` connect to `listener` over a fresh memory address.
+async fn connect(listener: &Node, dialer: &Node) {
+    let port = rand::random::<u16>();
+    let listener_peer_id = listener.peer_id;
+
+    listener
+        .endpoint
+        .send(ListenOn(format!("/memory/{port}").parse().unwrap()))
+        .await
+        .unwrap();
+    dialer
+        .endpoint
+        .send(Connect(
+            format!("/memory/{port}/p2p/{listener_peer_id}")
+                .parse()
+                .unwrap(),
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+/// The current value of the `connections_recycled_total` metric.
+fn connections_recycled_total() -> f64 {
+    prometheus::gather()
+        .into_iter()
+        .find(|family| family.get_name() == "connections_recycled_total")
+        .map(|family| family.get_metric()[0].get_counter().get_value())
+        .unwrap_or_default()
+}
+
 /// A test actor subscribing to all the notifications
 #[derive(Default)]
 struct EndpointSubscriberStats {
@@ -528,6 +1947,53 @@ impl Actor for HelloWorld {
     async fn stopped(self) -> Self::Stop {}
 }
 
+/// A handler that records from which peers it received substreams.
+#[derive(Default)]
+struct Recorder {
+    received: Vec<PeerId>,
+    /// Keep the substreams open for the duration of the test.
+    substreams: Vec<xtra_libp2p::Substream>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct GetReceivedSubstreams;
+
+#[xtra_productivity]
+impl Recorder {
+    async fn handle(&mut self, msg: NewInboundSubstream) {
+        self.received.push(msg.peer_id);
+        self.substreams.push(msg.stream);
+    }
+
+    async fn handle(&mut self, _: GetReceivedSubstreams) -> Vec<PeerId> {
+        self.received.clone()
+    }
+}
+
+#[async_trait]
+impl Actor for Recorder {
+    type Stop = ();
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+/// A handler that never finishes handling a substream.
+struct Stuck;
+
+#[xtra_productivity]
+impl Stuck {
+    async fn handle(&mut self, _msg: NewInboundSubstream) {
+        futures::future::pending::<()>().await;
+    }
+}
+
+#[async_trait]
+impl Actor for Stuck {
+    type Stop = ();
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
 async fn hello_world_dialer(stream: xtra_libp2p::Substream, name: &'static str) -> Result<String> {
     let mut stream = asynchronous_codec::Framed::new(stream, asynchronous_codec::LengthCodec);
 
@@ -1,5 +1,6 @@
 use async_trait::async_trait;
 use libp2p_core::Multiaddr;
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
@@ -33,6 +34,21 @@ pub fn make_node<const N: usize>(
 pub fn make_node_with_blocklist<const N: usize>(
     substream_handlers: [(&'static str, MessageChannel<NewInboundSubstream, ()>); N],
     blocked_peers: Arc<HashSet<PeerId>>,
+) -> Node {
+    make_node_with(substream_handlers, blocked_peers, |endpoint| endpoint)
+}
+
+pub fn make_configured_node<const N: usize>(
+    substream_handlers: [(&'static str, MessageChannel<NewInboundSubstream, ()>); N],
+    configure: fn(Endpoint) -> Endpoint,
+) -> Node {
+    make_node_with(substream_handlers, Arc::new(HashSet::new()), configure)
+}
+
+fn make_node_with<const N: usize>(
+    substream_handlers: [(&'static str, MessageChannel<NewInboundSubstream, ()>); N],
+    blocked_peers: Arc<HashSet<PeerId>>,
+    configure: fn(Endpoint) -> Endpoint,
 ) -> Node {
     let id = Keypair::generate_ed25519();
     let peer_id = id.public().to_peer_id();
@@ -41,7 +57,7 @@ pub fn make_node_with_blocklist<const N: usize>(
         .create(None)
         .spawn_global();
 
-    let endpoint = Endpoint::new(
+    let endpoint = configure(Endpoint::new(
         Box::new(MemoryTransport::default),
         id,
         Duration::from_secs(20),
@@ -51,10 +67,11 @@ pub fn make_node_with_blocklist<const N: usize>(
             vec![subscriber_stats.clone().into()],
             vec![subscriber_stats.clone().into()],
             vec![subscriber_stats.clone().into()],
-        ),
+        )
+        .with_dial_failure(vec![subscriber_stats.clone().into()]),
         blocked_peers,
-    )
-    .create(None)
+    ))
+    .create(Some(endpoint::DEFAULT_MAILBOX_CAPACITY))
     .spawn_global();
 
     Node {
@@ -69,6 +86,8 @@ pub fn make_node_with_blocklist<const N: usize>(
 pub struct EndpointSubscriberStats {
     connected_peers: HashSet<PeerId>,
     listen_addresses: HashSet<Multiaddr>,
+    dial_failures: Vec<(PeerId, Multiaddr)>,
+    disconnect_reasons: HashMap<PeerId, endpoint::DisconnectReason>,
 }
 
 #[async_trait]
@@ -86,6 +105,7 @@ impl EndpointSubscriberStats {
 
     async fn handle(&mut self, msg: endpoint::ConnectionDropped) {
         self.connected_peers.remove(&msg.peer_id);
+        self.disconnect_reasons.insert(msg.peer_id, msg.reason);
     }
 
     async fn handle(&mut self, msg: endpoint::ListenAddressAdded) {
@@ -95,6 +115,10 @@ impl EndpointSubscriberStats {
     async fn handle(&mut self, msg: endpoint::ListenAddressRemoved) {
         self.listen_addresses.remove(&msg.address);
     }
+
+    async fn handle(&mut self, msg: endpoint::DialFailure) {
+        self.dial_failures.push((msg.peer_id, msg.address));
+    }
 }
 
 #[xtra_productivity]
@@ -106,6 +130,17 @@ impl EndpointSubscriberStats {
     async fn handle(&mut self, _msg: GetListenAddresses) -> HashSet<Multiaddr> {
         self.listen_addresses.clone()
     }
+
+    async fn handle(&mut self, _msg: GetDialFailures) -> Vec<(PeerId, Multiaddr)> {
+        self.dial_failures.clone()
+    }
+
+    async fn handle(
+        &mut self,
+        _msg: GetDisconnectReasons,
+    ) -> HashMap<PeerId, endpoint::DisconnectReason> {
+        self.disconnect_reasons.clone()
+    }
 }
 
 /// Returns connected peers
@@ -115,3 +150,11 @@ pub struct GetConnectedPeers;
 /// Returns current listen addressess
 #[derive(Clone, Copy)]
 pub struct GetListenAddresses;
+
+/// Returns the peers and addresses we failed to dial
+#[derive(Clone, Copy)]
+pub struct GetDialFailures;
+
+/// Returns why the connection to each peer was last dropped
+#[derive(Clone, Copy)]
+pub struct GetDisconnectReasons;